@@ -0,0 +1,67 @@
+-- Tables are created IF NOT EXISTS so databases set up before migrations
+-- existed (schema created inline at startup) can adopt this history as-is.
+
+CREATE TABLE IF NOT EXISTS users (
+    id TEXT PRIMARY KEY,
+    email TEXT UNIQUE NOT NULL,
+    password_hash TEXT NOT NULL,
+    role TEXT NOT NULL CHECK(role IN ('admin','dev','user')),
+    must_change_password BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS accounts (
+    id TEXT PRIMARY KEY,
+    email TEXT UNIQUE NOT NULL,
+    display_name TEXT NOT NULL,
+    password TEXT NOT NULL,
+    is_active BOOLEAN NOT NULL DEFAULT TRUE,
+    owner_id TEXT,
+    is_public BOOLEAN NOT NULL DEFAULT FALSE,
+    FOREIGN KEY(owner_id) REFERENCES users(id) ON DELETE SET NULL
+);
+
+CREATE TABLE IF NOT EXISTS aliases (
+    id TEXT PRIMARY KEY,
+    alias_email TEXT UNIQUE NOT NULL,
+    display_name TEXT,
+    is_active BOOLEAN NOT NULL DEFAULT TRUE,
+    account_id TEXT NOT NULL,
+    owner_id TEXT,
+    is_public BOOLEAN NOT NULL DEFAULT FALSE,
+    FOREIGN KEY(account_id) REFERENCES accounts(id) ON DELETE CASCADE,
+    FOREIGN KEY(owner_id) REFERENCES users(id) ON DELETE SET NULL
+);
+
+-- At most one row: the CHECK pins the primary key to 1
+CREATE TABLE IF NOT EXISTS default_sender (
+    singleton INTEGER PRIMARY KEY CHECK (singleton = 1),
+    sender_type TEXT NOT NULL CHECK(sender_type IN ('account','alias')),
+    sender_id TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS pending_users (
+    id TEXT PRIMARY KEY,
+    email TEXT UNIQUE NOT NULL,
+    password_hash TEXT NOT NULL,
+    verification_token TEXT UNIQUE NOT NULL,
+    expires_at BIGINT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS password_reset_tokens (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    token TEXT UNIQUE NOT NULL,
+    expires_at BIGINT NOT NULL,
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS api_tokens (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    token_hash TEXT UNIQUE NOT NULL,
+    name TEXT,
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP,
+    last_used_at TIMESTAMPTZ,
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
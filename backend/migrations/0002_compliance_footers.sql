@@ -0,0 +1,4 @@
+ALTER TABLE accounts ADD COLUMN IF NOT EXISTS compliance_footer_html TEXT;
+ALTER TABLE accounts ADD COLUMN IF NOT EXISTS compliance_footer_text TEXT;
+ALTER TABLE aliases ADD COLUMN IF NOT EXISTS compliance_footer_html TEXT;
+ALTER TABLE aliases ADD COLUMN IF NOT EXISTS compliance_footer_text TEXT;
@@ -0,0 +1,13 @@
+-- Hash of (user id, IP prefix, UA family) for new-device sign-in alerts
+CREATE TABLE IF NOT EXISTS user_devices (
+    user_id TEXT NOT NULL,
+    device_hash TEXT NOT NULL,
+    first_seen_at BIGINT NOT NULL,
+    last_seen_at BIGINT NOT NULL,
+    PRIMARY KEY(user_id, device_hash),
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+ALTER TABLE users ADD COLUMN IF NOT EXISTS failed_login_count INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE users ADD COLUMN IF NOT EXISTS failed_login_alert_at BIGINT;
+ALTER TABLE users ADD COLUMN IF NOT EXISTS security_alerts BOOLEAN NOT NULL DEFAULT TRUE;
@@ -0,0 +1,2 @@
+ALTER TABLE accounts ADD COLUMN IF NOT EXISTS cooldown_until BIGINT;
+ALTER TABLE accounts ADD COLUMN IF NOT EXISTS throttle_count BIGINT NOT NULL DEFAULT 0;
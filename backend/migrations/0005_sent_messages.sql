@@ -0,0 +1,15 @@
+-- Delivered mail, for users to audit what they sent
+CREATE TABLE IF NOT EXISTS sent_messages (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    from_email TEXT NOT NULL,
+    to_recipients TEXT NOT NULL,
+    cc_recipients TEXT,
+    bcc_recipients TEXT,
+    subject TEXT NOT NULL,
+    body TEXT NOT NULL,
+    sent_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_sent_messages_user_sent_at ON sent_messages(user_id, sent_at DESC);
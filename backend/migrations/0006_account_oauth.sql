@@ -0,0 +1,9 @@
+-- Microsoft OAuth credentials for accounts that send with XOAUTH2
+CREATE TABLE IF NOT EXISTS account_oauth (
+    account_id TEXT PRIMARY KEY,
+    refresh_token TEXT NOT NULL,
+    access_token TEXT,
+    expires_at BIGINT,
+    updated_at BIGINT NOT NULL,
+    FOREIGN KEY(account_id) REFERENCES accounts(id) ON DELETE CASCADE
+);
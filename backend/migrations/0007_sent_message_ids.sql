@@ -0,0 +1 @@
+ALTER TABLE sent_messages ADD COLUMN IF NOT EXISTS message_id TEXT;
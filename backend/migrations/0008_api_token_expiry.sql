@@ -0,0 +1,2 @@
+-- NULL keeps the original never-expires behaviour
+ALTER TABLE api_tokens ADD COLUMN IF NOT EXISTS expires_at TIMESTAMPTZ;
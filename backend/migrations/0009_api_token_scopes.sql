@@ -0,0 +1,2 @@
+-- Comma-separated scope list; NULL grants the owner's full permissions
+ALTER TABLE api_tokens ADD COLUMN IF NOT EXISTS scopes TEXT;
@@ -0,0 +1,6 @@
+-- Catch-all aliases (alias_email "*@domain") accept any address at catchall_domain
+ALTER TABLE aliases ADD COLUMN IF NOT EXISTS is_catchall BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE aliases ADD COLUMN IF NOT EXISTS catchall_domain TEXT;
+
+-- At most one catch-all per domain
+CREATE UNIQUE INDEX IF NOT EXISTS idx_aliases_catchall_domain ON aliases(catchall_domain) WHERE is_catchall = TRUE;
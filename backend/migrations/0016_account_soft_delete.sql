@@ -0,0 +1,3 @@
+-- Deleted accounts are kept (with their aliases) so history and audit references survive.
+-- NULL means live; restoring an account clears it.
+ALTER TABLE accounts ADD COLUMN IF NOT EXISTS deleted_at BIGINT;
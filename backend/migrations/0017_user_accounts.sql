@@ -0,0 +1,11 @@
+-- Extra users allowed to send from an account (and its aliases) besides its owner
+CREATE TABLE IF NOT EXISTS user_accounts (
+    user_id TEXT NOT NULL,
+    account_id TEXT NOT NULL,
+    created_at BIGINT NOT NULL,
+    PRIMARY KEY(user_id, account_id),
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE,
+    FOREIGN KEY(account_id) REFERENCES accounts(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_user_accounts_account ON user_accounts(account_id);
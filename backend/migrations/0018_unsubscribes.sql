@@ -0,0 +1,5 @@
+-- Recipients who used a List-Unsubscribe link; sends with honorUnsubscribe skip them
+CREATE TABLE IF NOT EXISTS unsubscribes (
+    email TEXT PRIMARY KEY,
+    created_at BIGINT NOT NULL
+);
@@ -0,0 +1,16 @@
+-- Unsent compose state, so a long email survives a closed tab. Fields stay empty
+-- until the user fills them in.
+CREATE TABLE IF NOT EXISTS drafts (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    from_email TEXT NOT NULL DEFAULT '',
+    to_recipients TEXT NOT NULL DEFAULT '',
+    cc_recipients TEXT,
+    bcc_recipients TEXT,
+    subject TEXT NOT NULL DEFAULT '',
+    body TEXT NOT NULL DEFAULT '',
+    updated_at BIGINT NOT NULL,
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_drafts_user_updated_at ON drafts(user_id, updated_at DESC);
@@ -0,0 +1,11 @@
+-- Per-account cap on sends per UTC day; NULL falls back to DAILY_SEND_LIMIT
+ALTER TABLE accounts ADD COLUMN IF NOT EXISTS daily_send_limit BIGINT;
+
+-- One row per account per day; a new day starts a new row, so nothing needs resetting
+CREATE TABLE IF NOT EXISTS send_counters (
+    account_id TEXT NOT NULL,
+    day DATE NOT NULL,
+    count BIGINT NOT NULL,
+    PRIMARY KEY (account_id, day),
+    FOREIGN KEY(account_id) REFERENCES accounts(id) ON DELETE CASCADE
+);
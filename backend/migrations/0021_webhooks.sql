@@ -0,0 +1,9 @@
+-- Endpoints notified about sends. The secret signs each payload and is sealed with
+-- ACCOUNT_ENC_KEY like account passwords.
+CREATE TABLE IF NOT EXISTS webhooks (
+    id TEXT PRIMARY KEY,
+    url TEXT NOT NULL,
+    secret TEXT NOT NULL,
+    events TEXT[] NOT NULL,
+    created_at BIGINT NOT NULL
+);
@@ -0,0 +1,3 @@
+-- Unix seconds, for sorting the alias list by creation. Existing aliases get the
+-- migration time since their real creation time was never recorded.
+ALTER TABLE aliases ADD COLUMN IF NOT EXISTS created_at BIGINT NOT NULL DEFAULT EXTRACT(EPOCH FROM NOW())::BIGINT;
@@ -0,0 +1,12 @@
+-- Idempotency-Key values seen on /api/send, per user. response stays NULL while the
+-- first request is still sending; afterwards it holds the JSON replayed to retries.
+CREATE TABLE IF NOT EXISTS idempotency_keys (
+    key TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    response JSONB,
+    created_at BIGINT NOT NULL,
+    PRIMARY KEY (user_id, key),
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_idempotency_keys_created_at ON idempotency_keys(created_at);
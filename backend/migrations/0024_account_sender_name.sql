@@ -0,0 +1,3 @@
+-- Name shown in From for mail sent as the account; NULL falls back to display_name,
+-- which stays the label in the UI.
+ALTER TABLE accounts ADD COLUMN IF NOT EXISTS sender_name TEXT;
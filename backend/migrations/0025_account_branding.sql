@@ -0,0 +1,3 @@
+-- When false, HTML mail sent as the account (or its aliases) keeps the template layout
+-- but drops the W9 Mail header and "Sent via W9 Mail" line.
+ALTER TABLE accounts ADD COLUMN IF NOT EXISTS branding_enabled BOOLEAN NOT NULL DEFAULT TRUE;
@@ -0,0 +1,4 @@
+-- Outcome of the most recent send through each account, for a health indicator in the
+-- admin UI. last_send_error is NULL after a successful send.
+ALTER TABLE accounts ADD COLUMN IF NOT EXISTS last_send_at BIGINT;
+ALTER TABLE accounts ADD COLUMN IF NOT EXISTS last_send_error TEXT;
@@ -0,0 +1,10 @@
+-- A user's requested login email, held until the link sent to the new address is
+-- confirmed. One pending change per user; a new request replaces it.
+CREATE TABLE IF NOT EXISTS pending_email_changes (
+    id TEXT PRIMARY KEY,
+    user_id TEXT UNIQUE NOT NULL,
+    new_email TEXT NOT NULL,
+    token TEXT UNIQUE NOT NULL,
+    expires_at BIGINT NOT NULL,
+    FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
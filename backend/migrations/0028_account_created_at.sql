@@ -0,0 +1,3 @@
+-- Unix seconds, like aliases.created_at, for sorting the account list by creation.
+-- Existing accounts get the migration time since their real creation time was never recorded.
+ALTER TABLE accounts ADD COLUMN IF NOT EXISTS created_at BIGINT NOT NULL DEFAULT EXTRACT(EPOCH FROM NOW())::BIGINT;
@@ -0,0 +1,12 @@
+-- One row per address a sent message went to, lowercased, so "what did we send to
+-- x@example.com" is an index lookup instead of a LIKE over the comma-joined columns.
+-- Rows that predate this table are filled in at startup (sent_recipients::backfill).
+CREATE TABLE IF NOT EXISTS sent_message_recipients (
+    sent_message_id TEXT NOT NULL,
+    address TEXT NOT NULL,
+    kind TEXT NOT NULL CHECK (kind IN ('to', 'cc', 'bcc')),
+    PRIMARY KEY (sent_message_id, kind, address),
+    FOREIGN KEY(sent_message_id) REFERENCES sent_messages(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_sent_message_recipients_address ON sent_message_recipients(address);
@@ -0,0 +1,7 @@
+-- Browser copy of a sent message (webVersion on /api/send), served from /api/view/:token
+-- until web_expires_at (unix seconds). The cleanup task clears both once it has passed.
+ALTER TABLE sent_messages ADD COLUMN IF NOT EXISTS web_html TEXT;
+ALTER TABLE sent_messages ADD COLUMN IF NOT EXISTS web_expires_at BIGINT;
+
+CREATE INDEX IF NOT EXISTS idx_sent_messages_web_expires_at ON sent_messages(web_expires_at)
+    WHERE web_expires_at IS NOT NULL;
@@ -0,0 +1,120 @@
+// Append-only record of administrative changes (accounts, aliases, users, default sender, webhooks)
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::Json,
+};
+use chrono::Utc;
+use serde::Serialize;
+use sqlx::{PgPool, Row};
+use uuid::Uuid;
+
+use crate::{
+    auth::{AuthUser, UserRole, SCOPE_ADMIN},
+    handlers::page_bounds,
+    AppState, ListParams, Page,
+};
+
+#[derive(Serialize)]
+pub struct AuditEntry {
+    pub id: String,
+    #[serde(rename = "actorUserId")]
+    pub actor_user_id: String,
+    // Null once the actor has been deleted
+    #[serde(rename = "actorEmail")]
+    pub actor_email: Option<String>,
+    pub action: String,
+    #[serde(rename = "targetType")]
+    pub target_type: String,
+    #[serde(rename = "targetId")]
+    pub target_id: String,
+    pub timestamp: i64,
+    pub detail: Option<serde_json::Value>,
+}
+
+/// Writes one entry. Failures are logged rather than returned: the change itself
+/// has already been committed and shouldn't be reported as failed.
+pub async fn record(
+    db: &PgPool,
+    actor: &AuthUser,
+    action: &str,
+    target_type: &str,
+    target_id: &str,
+    detail: serde_json::Value,
+) {
+    let result = sqlx::query(
+        r#"
+        INSERT INTO audit_log (id, actor_user_id, action, target_type, target_id, created_at, detail)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        "#,
+    )
+    .bind(Uuid::new_v4().to_string())
+    .bind(&actor.id)
+    .bind(action)
+    .bind(target_type)
+    .bind(target_id)
+    .bind(Utc::now().timestamp())
+    .bind(detail)
+    .execute(db)
+    .await;
+
+    if let Err(e) = result {
+        tracing::error!(error = %e, actor = %actor.id, %action, %target_id, "failed to write audit log entry");
+    }
+}
+
+pub async fn list_audit(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Query(params): Query<ListParams>,
+) -> Result<Json<Page<AuditEntry>>, StatusCode> {
+    user.ensure_password_updated()?;
+    user.ensure_scope(SCOPE_ADMIN)?;
+    if !matches!(user.role, UserRole::Admin) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    let (limit, offset) = page_bounds(params.limit, params.offset);
+
+    let total: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM audit_log")
+        .fetch_one(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let rows = sqlx::query(
+        r#"
+        SELECT audit_log.id, audit_log.actor_user_id, users.email, audit_log.action,
+               audit_log.target_type, audit_log.target_id, audit_log.created_at, audit_log.detail
+        FROM audit_log
+        LEFT JOIN users ON users.id = audit_log.actor_user_id
+        ORDER BY audit_log.created_at DESC, audit_log.id
+        LIMIT $1 OFFSET $2
+        "#,
+    )
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(&state.db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let items = rows
+        .into_iter()
+        .map(|row| AuditEntry {
+            id: row.get(0),
+            actor_user_id: row.get(1),
+            actor_email: row.get(2),
+            action: row.get(3),
+            target_type: row.get(4),
+            target_id: row.get(5),
+            timestamp: row.get(6),
+            detail: row.get(7),
+        })
+        .collect();
+
+    Ok(Json(Page {
+        items,
+        total,
+        limit,
+        offset,
+    }))
+}
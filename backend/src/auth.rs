@@ -1,4 +1,4 @@
-use std::fmt;
+use std::{fmt, net::SocketAddr};
 
 use anyhow::anyhow;
 use argon2::{
@@ -7,22 +7,28 @@ use argon2::{
 };
 use axum::{
     async_trait,
-    extract::{FromRequestParts, Path, State},
-    http::{request::Parts, StatusCode},
-    response::Json,
+    extract::{ConnectInfo, FromRequestParts, Path, State},
+    http::{request::Parts, HeaderMap, StatusCode},
+    response::{IntoResponse, Json, Response},
 };
 use chrono::{Duration, Utc};
 use rand_core::OsRng;
 use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
 use serde::{Deserialize, Serialize};
 use sha2::{Sha256, Digest};
-use sqlx::{Row, PgPool};
+use sqlx::{PgConnection, PgPool, Row};
 use uuid::Uuid;
 use rand::Rng;
 
-use crate::{email::EmailService, mailer, AppState};
-
-const TOKEN_TTL_HOURS: i64 = 12;
+use crate::{
+    audit,
+    email::{is_valid_email, OutgoingEmail, FOOTER_SLOT},
+    error::ApiError,
+    handlers::{invalid_email_error, send_via_account},
+    login_alerts,
+    mail_queue::SystemEmail,
+    mailer, telemetry, two_factor, AppState,
+};
 
 async fn verify_turnstile(secret: &str, token: &str) -> Result<bool, String> {
     let client = reqwest::Client::new();
@@ -52,6 +58,28 @@ async fn verify_turnstile(secret: &str, token: &str) -> Result<bool, String> {
     }
 }
 
+// Only enforced when TURNSTILE_SECRET_KEY is configured
+async fn check_turnstile(state: &AppState, token: Option<&str>) -> Result<(), ApiError> {
+    let Some(secret) = &state.turnstile_secret else {
+        return Ok(());
+    };
+    let Some(token) = token else {
+        return Err(ApiError::invalid("turnstile_token", "captcha_required", "Complete the captcha"));
+    };
+    match verify_turnstile(secret, token).await {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(ApiError::invalid(
+            "turnstile_token",
+            "captcha_failed",
+            "Captcha verification failed; try again",
+        )),
+        Err(e) => {
+            tracing::error!(error = %e, "Turnstile verification failed");
+            Err(StatusCode::INTERNAL_SERVER_ERROR.into())
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
 pub enum UserRole {
@@ -89,12 +117,55 @@ pub struct AuthUser {
     pub email: String,
     pub role: UserRole,
     pub must_change_password: bool,
+    // Set only for scoped API tokens; None means the user's full permissions
+    pub scopes: Option<Vec<String>>,
 }
 
+// API token scopes. Role checks still apply on top of these.
+pub const SCOPE_SEND: &str = "send";
+pub const SCOPE_SENT_READ: &str = "sent:read";
+pub const SCOPE_INBOX_READ: &str = "inbox:read";
+pub const SCOPE_ACCOUNTS_READ: &str = "accounts:read";
+pub const SCOPE_ACCOUNTS_WRITE: &str = "accounts:write";
+pub const SCOPE_ADMIN: &str = "admin";
+pub const API_TOKEN_SCOPES: &[&str] = &[
+    SCOPE_SEND,
+    SCOPE_SENT_READ,
+    SCOPE_INBOX_READ,
+    SCOPE_ACCOUNTS_READ,
+    SCOPE_ACCOUNTS_WRITE,
+    SCOPE_ADMIN,
+];
+
 impl AuthUser {
-    pub fn ensure_password_updated(&self) -> Result<(), StatusCode> {
+    pub fn ensure_password_updated(&self) -> Result<(), ApiError> {
         if self.must_change_password {
-            Err(StatusCode::FORBIDDEN)
+            Err(ApiError::forbidden(
+                "password_change_required",
+                "Change your password before continuing",
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn ensure_scope(&self, scope: &str) -> Result<(), ApiError> {
+        match &self.scopes {
+            Some(scopes) if !scopes.iter().any(|s| s == scope) => Err(ApiError::forbidden(
+                "missing_scope",
+                format!("This API token lacks the {} scope", scope),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    // For credential and security settings, which no scoped token may touch
+    pub fn ensure_full_access(&self) -> Result<(), ApiError> {
+        if self.scopes.is_some() {
+            Err(ApiError::forbidden(
+                "full_access_required",
+                "Scoped API tokens can't do this; use an unscoped credential",
+            ))
         } else {
             Ok(())
         }
@@ -115,11 +186,17 @@ pub struct LoginRequest {
     pub password: String,
     #[serde(default)]
     pub turnstile_token: Option<String>,
+    // Required once the user has enabled two-factor authentication
+    #[serde(default, rename = "totpCode")]
+    pub totp_code: Option<String>,
 }
 
 #[derive(Serialize)]
 pub struct LoginResponse {
     pub token: String,
+    // Opaque and single-use; exchange at /api/auth/refresh for a new JWT
+    #[serde(rename = "refreshToken")]
+    pub refresh_token: String,
     pub id: String,
     pub email: String,
     pub role: UserRole,
@@ -152,6 +229,14 @@ pub struct UserSummary {
     pub must_change_password: bool,
 }
 
+#[derive(Serialize)]
+pub struct PendingSignup {
+    pub id: String,
+    pub email: String,
+    #[serde(rename = "expiresAt")]
+    pub expires_at: i64,
+}
+
 #[derive(Deserialize)]
 pub struct UpdateUserRequest {
     pub password: Option<String>,
@@ -173,6 +258,11 @@ pub struct SignupVerifyRequest {
     pub token: String,
 }
 
+#[derive(Deserialize)]
+pub struct ResendVerificationRequest {
+    pub email: String,
+}
+
 #[derive(Deserialize)]
 pub struct PasswordResetRequest {
     pub email: String,
@@ -180,6 +270,19 @@ pub struct PasswordResetRequest {
     pub turnstile_token: Option<String>,
 }
 
+#[derive(Deserialize)]
+pub struct ChangeEmailRequest {
+    #[serde(rename = "newEmail")]
+    pub new_email: String,
+    #[serde(rename = "currentPassword")]
+    pub current_password: String,
+}
+
+#[derive(Deserialize)]
+pub struct ChangeEmailConfirmRequest {
+    pub token: String,
+}
+
 #[derive(Deserialize)]
 pub struct PasswordResetConfirmRequest {
     pub token: String,
@@ -195,7 +298,7 @@ where
     State<AppState>: FromRequestParts<S>,
     S: Send + Sync,
 {
-    type Rejection = (StatusCode, &'static str);
+    type Rejection = ApiError;
 
     async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
         let auth_header = parts
@@ -223,9 +326,9 @@ where
         let token_hash = format!("{:x}", hasher.finalize());
         
         let api_token_row = sqlx::query(
-            "SELECT u.id, u.email, u.role, u.must_change_password FROM api_tokens at
+            "SELECT u.id, u.email, u.role, u.must_change_password, at.expires_at, at.scopes FROM api_tokens at
              INNER JOIN users u ON at.user_id = u.id
-             WHERE at.token_hash = ?"
+             WHERE at.token_hash = $1"
         )
         .bind(&token_hash)
         .fetch_optional(&app_state.db)
@@ -233,9 +336,14 @@ where
         .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Failed to check API token"))?;
 
         if let Some(row) = api_token_row {
+            let expires_at = row.get::<Option<chrono::DateTime<Utc>>, _>(4);
+            if expires_at.is_some_and(|at| at <= Utc::now()) {
+                return Err((StatusCode::UNAUTHORIZED, "API token expired").into());
+            }
+
             // Update last_used_at
             let _ = sqlx::query(
-                "UPDATE api_tokens SET last_used_at = CURRENT_TIMESTAMP WHERE token_hash = ?"
+                "UPDATE api_tokens SET last_used_at = CURRENT_TIMESTAMP WHERE token_hash = $1"
             )
             .bind(&token_hash)
             .execute(&app_state.db)
@@ -251,6 +359,9 @@ where
                 email: row.get::<String, _>(1),
                 role,
                 must_change_password: row.get::<bool, _>(3),
+                scopes: row
+                    .get::<Option<String>, _>(5)
+                    .map(|s| s.split(',').map(str::to_string).collect()),
             });
         }
 
@@ -260,7 +371,7 @@ where
             .map_err(|_| (StatusCode::UNAUTHORIZED, "Invalid or expired token"))?;
 
         let row = sqlx::query(
-            "SELECT id, email, role, must_change_password FROM users WHERE id = ?",
+            "SELECT id, email, role, must_change_password FROM users WHERE id = $1",
         )
         .bind(&token_data.claims.sub)
         .fetch_optional(&app_state.db)
@@ -278,37 +389,101 @@ where
             email: row.get::<String, _>(1),
             role,
             must_change_password: row.get::<bool, _>(3),
+            scopes: None,
         })
     }
 }
 
+/// Bootstraps the first admin on an empty database from DEFAULT_ADMIN_EMAIL and
+/// DEFAULT_ADMIN_PASSWORD. Without a password one is generated and printed once.
 pub async fn ensure_default_admin(db: &PgPool) -> anyhow::Result<()> {
-    const ADMIN_EMAIL: &str = "shayneeo@0.id.vn";
-    const ADMIN_PASSWORD: &str = "Admin@123";
-
-    let count: i64 = sqlx::query_scalar("SELECT COUNT(1) FROM users WHERE email = ?")
-        .bind(ADMIN_EMAIL)
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(1) FROM users")
         .fetch_one(db)
         .await?;
+    if count > 0 {
+        return Ok(());
+    }
 
-    if count == 0 {
-        let password_hash = hash_password(ADMIN_PASSWORD)?;
-        sqlx::query(
-            r#"
-            INSERT INTO users (id, email, password_hash, role, must_change_password)
-            VALUES (?, ?, ?, 'admin', 1)
+    let email = std::env::var("DEFAULT_ADMIN_EMAIL")
+        .ok()
+        .map(|v| v.trim().to_lowercase())
+        .filter(|v| !v.is_empty())
+        .unwrap_or_else(|| "admin@localhost".to_string());
+    let configured_password = std::env::var("DEFAULT_ADMIN_PASSWORD")
+        .ok()
+        .filter(|v| !v.is_empty());
+    let generated = configured_password.is_none();
+    let password = configured_password.unwrap_or_else(|| generate_api_token()[..24].to_string());
+
+    let password_hash = hash_password(&password)?;
+    sqlx::query(
+        r#"
+        INSERT INTO users (id, email, password_hash, role, must_change_password)
+        VALUES ($1, $2, $3, 'admin', TRUE)
         "#,
-        )
-        .bind(Uuid::new_v4().to_string())
-        .bind(ADMIN_EMAIL)
-        .bind(password_hash)
-        .execute(db)
-        .await?;
+    )
+    .bind(Uuid::new_v4().to_string())
+    .bind(&email)
+    .bind(password_hash)
+    .execute(db)
+    .await?;
+
+    if generated {
+        // Printed to stdout rather than logged so it doesn't end up in log aggregation
+        println!(
+            "Created default admin {} with generated password: {}\n\
+             This is shown only once; you will be asked to change it at first sign-in.",
+            email, password
+        );
+    } else {
+        tracing::info!(%email, "created default admin from DEFAULT_ADMIN_PASSWORD");
     }
 
     Ok(())
 }
 
+pub(crate) fn env_flag(key: &str) -> bool {
+    std::env::var(key)
+        .map(|v| matches!(v.trim().to_ascii_lowercase().as_str(), "1" | "true" | "yes"))
+        .unwrap_or(false)
+}
+
+/// Checks a new password against the PASSWORD_* policy. The 400 lists every rule that
+/// failed, so the user can fix them in one go.
+pub fn validate_password_strength(password: &str) -> Result<(), ApiError> {
+    let min_len = std::env::var("PASSWORD_MIN_LEN")
+        .ok()
+        .and_then(|v| v.trim().parse::<usize>().ok())
+        .filter(|v| *v > 0)
+        .unwrap_or(8);
+
+    let mut failures = Vec::new();
+    if password.chars().count() < min_len {
+        failures.push(format!("at least {} characters", min_len));
+    }
+    if env_flag("PASSWORD_REQUIRE_UPPER") && !password.chars().any(|c| c.is_uppercase()) {
+        failures.push("an uppercase letter".to_string());
+    }
+    if env_flag("PASSWORD_REQUIRE_LOWER") && !password.chars().any(|c| c.is_lowercase()) {
+        failures.push("a lowercase letter".to_string());
+    }
+    if env_flag("PASSWORD_REQUIRE_DIGIT") && !password.chars().any(|c| c.is_ascii_digit()) {
+        failures.push("a digit".to_string());
+    }
+    if env_flag("PASSWORD_REQUIRE_SYMBOL") && !password.chars().any(|c| !c.is_alphanumeric() && !c.is_whitespace()) {
+        failures.push("a symbol".to_string());
+    }
+
+    if failures.is_empty() {
+        return Ok(());
+    }
+    Err(ApiError::invalid(
+        "password",
+        "weak_password",
+        format!("Password must contain {}.", failures.join(", ")),
+    ))
+}
+
 pub fn hash_password(password: &str) -> Result<String, anyhow::Error> {
     let salt = SaltString::generate(&mut OsRng);
     let argon2 = Argon2::default();
@@ -325,9 +500,9 @@ fn verify_password(password_hash: &str, password: &str) -> Result<bool, anyhow::
         .is_ok())
 }
 
-fn encode_token(user_id: &str, email: &str, role: &UserRole, secret: &str) -> anyhow::Result<String> {
+fn encode_token(user_id: &str, email: &str, role: &UserRole, secret: &str, ttl_hours: i64) -> anyhow::Result<String> {
     let exp = Utc::now()
-        .checked_add_signed(Duration::hours(TOKEN_TTL_HOURS))
+        .checked_add_signed(Duration::hours(ttl_hours))
         .ok_or_else(|| anyhow::anyhow!("Failed to calculate token expiration"))?
         .timestamp() as usize;
 
@@ -344,33 +519,35 @@ fn encode_token(user_id: &str, email: &str, role: &UserRole, secret: &str) -> an
 
 pub async fn login(
     State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     Json(payload): Json<LoginRequest>,
-) -> Result<Json<LoginResponse>, StatusCode> {
-    // Verify Turnstile token if secret is configured
-    if let Some(secret) = &state.turnstile_secret {
-        if let Some(token) = &payload.turnstile_token {
-            match verify_turnstile(secret, token).await {
-                Ok(true) => {},
-                Ok(false) => return Err(StatusCode::BAD_REQUEST),
-                Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
-            }
-        } else {
-            return Err(StatusCode::BAD_REQUEST);
-        }
-    }
+) -> Result<Response, ApiError> {
+    check_turnstile(&state, payload.turnstile_token.as_deref()).await?;
 
     let row = sqlx::query(
-        "SELECT id, email, password_hash, role, must_change_password FROM users WHERE email = ?",
+        "SELECT id, email, password_hash, role, must_change_password, locked_until FROM users WHERE email = $1",
     )
     .bind(&payload.email)
     .fetch_optional(&state.db)
     .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
-    .ok_or(StatusCode::UNAUTHORIZED)?;
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let Some(row) = row else {
+        telemetry::login_failed("unknown_user");
+        return Err(StatusCode::UNAUTHORIZED.into());
+    };
+
+    let now = Utc::now().timestamp();
+    if let Some(until) = row.get::<Option<i64>, _>(5).filter(|until| *until > now) {
+        telemetry::login_failed("locked");
+        return Ok(login_alerts::locked_out_response(until - now));
+    }
 
     let password_hash = row.get::<String, _>(2);
     if !verify_password(&password_hash, &payload.password).map_err(|_| StatusCode::UNAUTHORIZED)? {
-        return Err(StatusCode::UNAUTHORIZED);
+        telemetry::login_failed("password");
+        login_alerts::record_failed_login(&state, &row.get::<String, _>(0), &payload.email).await;
+        return Err(StatusCode::UNAUTHORIZED.into());
     }
 
     let role: UserRole = row
@@ -378,47 +555,101 @@ pub async fn login(
         .try_into()
         .map_err(|_| StatusCode::UNAUTHORIZED)?;
 
-    let token = encode_token(&row.get::<String, _>(0), &payload.email, &role, &state.jwt_secret)
+    let second_factor = two_factor::check_login(&state, &row.get::<String, _>(0), payload.totp_code.as_deref())
+        .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let totp_error = match second_factor {
+        two_factor::LoginCheck::NotEnrolled | two_factor::LoginCheck::Passed => None,
+        two_factor::LoginCheck::Missing => Some("Two-factor code required"),
+        two_factor::LoginCheck::Failed => {
+            telemetry::login_failed("totp");
+            login_alerts::record_failed_login(&state, &row.get::<String, _>(0), &payload.email).await;
+            Some("Invalid two-factor code")
+        }
+    };
+    if let Some(message) = totp_error {
+        // totpRequired tells the sign-in form to ask for a code
+        return Ok((
+            StatusCode::UNAUTHORIZED,
+            Json(serde_json::json!({
+                "status": "error",
+                "message": message,
+                "totpRequired": true
+            })),
+        )
+            .into_response());
+    }
+
+    let token = encode_token(
+        &row.get::<String, _>(0),
+        &payload.email,
+        &role,
+        &state.jwt_secret,
+        state.jwt_ttl_hours,
+    )
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let refresh_token = issue_refresh_token(&state, &row.get::<String, _>(0)).await?;
+
+    let ip = state.trusted_proxies.client_ip(&headers, peer.ip());
+    login_alerts::record_successful_login(&state, &row.get::<String, _>(0), &payload.email, ip, &headers)
+        .await;
+    telemetry::login_succeeded();
 
     Ok(Json(LoginResponse {
         token,
+        refresh_token,
         id: row.get::<String, _>(0),
         email: payload.email,
         role,
         must_change_password: row.get::<bool, _>(4),
-    }))
+    })
+    .into_response())
 }
 
 pub async fn signup(
     State(state): State<AppState>,
     Json(payload): Json<SignupRequest>,
-) -> Result<Json<serde_json::Value>, StatusCode> {
+) -> Result<Response, ApiError> {
+    check_turnstile(&state, payload.turnstile_token.as_deref()).await?;
+
     let email = normalize_email(&payload.email);
-    if email.is_empty() || payload.password.len() < 8 {
-        return Err(StatusCode::BAD_REQUEST);
+    validate_password_strength(&payload.password)?;
+    if !is_valid_email(&email) {
+        return Err(invalid_email_error("email", &email));
     }
 
-    let existing: i64 = sqlx::query_scalar("SELECT COUNT(1) FROM users WHERE email = ?")
+    let existing: i64 = sqlx::query_scalar("SELECT COUNT(1) FROM users WHERE email = $1")
         .bind(&email)
         .fetch_one(&state.db)
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
     if existing > 0 {
-        return Ok(Json(serde_json::json!({
-            "status": "error",
-            "message": "Email already registered"
-        })));
+        return Err(ApiError::new(StatusCode::CONFLICT, "email_taken", "Email already registered").with_field("email"));
     }
 
+    // Checked before anything is stored, so a refused signup leaves no pending row behind
+    let default_sender = match mailer::get_default_sender_summary(&state.db, &state.secrets).await {
+        Ok(Some(summary)) => summary,
+        Ok(None) => {
+            return Err(ApiError::new(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "no_default_sender",
+                "Registration is temporarily unavailable. Ask an admin to set a default sender.",
+            ));
+        }
+        Err(e) => {
+            tracing::error!(error = %e, "failed to load default sender for signup");
+            return Err(StatusCode::INTERNAL_SERVER_ERROR.into());
+        }
+    };
+
     let password_hash =
         hash_password(&payload.password).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
     let token = Uuid::new_v4().to_string();
-    let expires_at = (Utc::now() + Duration::minutes(30))
-        .timestamp();
+    let expires_at = (Utc::now() + Duration::minutes(VERIFICATION_TTL_MINUTES)).timestamp();
 
-    sqlx::query("DELETE FROM pending_users WHERE email = ?")
+    sqlx::query("DELETE FROM pending_users WHERE email = $1")
         .bind(&email)
         .execute(&state.db)
         .await
@@ -427,7 +658,7 @@ pub async fn signup(
     sqlx::query(
         r#"
         INSERT INTO pending_users (id, email, password_hash, verification_token, expires_at)
-        VALUES (?, ?, ?, ?, ?)
+        VALUES ($1, $2, $3, $4, $5)
         "#,
     )
     .bind(Uuid::new_v4().to_string())
@@ -439,20 +670,26 @@ pub async fn signup(
     .await
     .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    let default_sender = match mailer::get_default_sender_summary(&state.db).await {
-        Ok(Some(summary)) => summary,
-        Ok(None) => {
-            return Ok(Json(serde_json::json!({
-                "status": "error",
-                "message": "Registration is temporarily unavailable. Ask an admin to set a default sender."
-            })));
-        }
-        Err(e) => {
-            eprintln!("Failed to load default sender: {}", e);
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
-        }
-    };
+    queue_verification_email(&state, default_sender, &email, &token).await?;
+
+    Ok(Json(serde_json::json!({
+        "status": "pending",
+        "message": "Check your inbox for a verification link."
+    }))
+    .into_response())
+}
 
+// Verification links, like reset links, are good for 30 minutes
+const VERIFICATION_TTL_MINUTES: i64 = 30;
+// A resend is refused until this long after the previous email
+const VERIFICATION_RESEND_INTERVAL_SECS: i64 = 60;
+
+async fn queue_verification_email(
+    state: &AppState,
+    sender: mailer::SenderSummary,
+    email: &str,
+    token: &str,
+) -> Result<(), ApiError> {
     let base_url = state.app_base_url.trim_end_matches('/').to_string();
     let verify_url = format!("{}/signup/verify?token={}", base_url, token);
     let body_lines = vec![
@@ -465,38 +702,82 @@ pub async fn signup(
         "Verify account",
         &verify_url,
     );
+    let queued = state
+        .mail_queue
+        .enqueue(SystemEmail {
+            sender,
+            to: email.to_string(),
+            subject: "Verify your W9 Mail account".to_string(),
+            html: email_body,
+        })
+        .await;
+    if let Err(e) = queued {
+        tracing::error!(error = %e, %email, "failed to queue verification email");
+        return Err(StatusCode::INTERNAL_SERVER_ERROR.into());
+    }
+    Ok(())
+}
 
-    let email_service = EmailService::new();
-    if let Err(e) = email_service
-        .send_email(
-            &default_sender.credentials.header_from,
-            &default_sender.credentials.auth_email,
-            &default_sender.credentials.auth_password,
-            &email,
-            "Verify your W9 Mail account",
-            &email_body,
-            None,
-            None,
-            true,
-        )
-        .await
-    {
-        eprintln!("Failed to send verification email: {}", e);
-        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+pub async fn resend_verification(
+    State(state): State<AppState>,
+    Json(payload): Json<ResendVerificationRequest>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let email = normalize_email(&payload.email);
+    if email.is_empty() {
+        return Err(ApiError::invalid("email", "email_required", "Email is required"));
     }
 
+    let default_sender = match mailer::get_default_sender_summary(&state.db, &state.secrets).await {
+        Ok(Some(summary)) => summary,
+        Ok(None) => {
+            return Ok(Json(serde_json::json!({
+                "status": "error",
+                "message": "Registration is temporarily unavailable. Ask an admin to set a default sender."
+            })));
+        }
+        Err(e) => {
+            tracing::error!(error = %e, "failed to load default sender for verification resend");
+            return Err(StatusCode::INTERNAL_SERVER_ERROR.into());
+        }
+    };
+
+    // expires_at is always set TTL after the last email, so it also tells us when that
+    // email went out. Rows still inside the resend interval, or already expired, match nothing.
+    let now = Utc::now().timestamp();
+    let token = Uuid::new_v4().to_string();
+    let expires_at = now + VERIFICATION_TTL_MINUTES * 60;
+    let resent = sqlx::query(
+        r#"
+        UPDATE pending_users SET verification_token = $1, expires_at = $2
+        WHERE email = $3 AND expires_at > $4 AND expires_at <= $5
+        "#,
+    )
+    .bind(&token)
+    .bind(expires_at)
+    .bind(&email)
+    .bind(now)
+    .bind(expires_at - VERIFICATION_RESEND_INTERVAL_SECS)
+    .execute(&state.db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    .rows_affected();
+
+    if resent > 0 {
+        queue_verification_email(&state, default_sender, &email, &token).await?;
+    }
+    // Same body whether or not a signup is pending, so the response doesn't reveal who signed up
     Ok(Json(serde_json::json!({
         "status": "pending",
-        "message": "Check your inbox for a verification link."
+        "message": "If a signup is waiting for verification, a new link was sent."
     })))
 }
 
 pub async fn verify_signup(
     State(state): State<AppState>,
     Json(payload): Json<SignupVerifyRequest>,
-) -> Result<Json<serde_json::Value>, StatusCode> {
+) -> Result<Json<serde_json::Value>, ApiError> {
     let row = sqlx::query(
-        "SELECT id, email, password_hash, expires_at FROM pending_users WHERE verification_token = ?",
+        "SELECT id, email, password_hash, expires_at FROM pending_users WHERE verification_token = $1",
     )
     .bind(&payload.token)
     .fetch_optional(&state.db)
@@ -512,7 +793,7 @@ pub async fn verify_signup(
 
     let expires_at = row.get::<i64, _>(3);
     if expires_at < Utc::now().timestamp() {
-        sqlx::query("DELETE FROM pending_users WHERE id = ?")
+        sqlx::query("DELETE FROM pending_users WHERE id = $1")
             .bind(row.get::<String, _>(0))
             .execute(&state.db)
             .await
@@ -530,7 +811,7 @@ pub async fn verify_signup(
     let insert_result = sqlx::query(
         r#"
         INSERT INTO users (id, email, password_hash, role, must_change_password)
-        VALUES (?, ?, ?, 'user', 0)
+        VALUES ($1, $2, $3, 'user', FALSE)
         "#,
     )
     .bind(&user_id)
@@ -540,14 +821,14 @@ pub async fn verify_signup(
     .await;
 
     if let Err(e) = insert_result {
-        eprintln!("Failed to finalize signup: {}", e);
+        tracing::warn!(error = %e, %email, "failed to finalize signup");
         return Ok(Json(serde_json::json!({
             "status": "error",
             "message": "This email is already activated. Try signing in."
         })));
     }
 
-    sqlx::query("DELETE FROM pending_users WHERE id = ?")
+    sqlx::query("DELETE FROM pending_users WHERE id = $1")
         .bind(row.get::<String, _>(0))
         .execute(&state.db)
         .await
@@ -562,25 +843,14 @@ pub async fn verify_signup(
 pub async fn request_password_reset(
     State(state): State<AppState>,
     Json(payload): Json<PasswordResetRequest>,
-) -> Result<Json<serde_json::Value>, StatusCode> {
-    // Verify Turnstile token if secret is configured
-    if let Some(secret) = &state.turnstile_secret {
-        if let Some(token) = &payload.turnstile_token {
-            match verify_turnstile(secret, token).await {
-                Ok(true) => {},
-                Ok(false) => return Err(StatusCode::BAD_REQUEST),
-                Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
-            }
-        } else {
-            return Err(StatusCode::BAD_REQUEST);
-        }
-    }
+) -> Result<Json<serde_json::Value>, ApiError> {
+    check_turnstile(&state, payload.turnstile_token.as_deref()).await?;
     let email = normalize_email(&payload.email);
     if email.is_empty() {
-        return Err(StatusCode::BAD_REQUEST);
+        return Err(ApiError::invalid("email", "email_required", "Email is required"));
     }
 
-    let row = sqlx::query("SELECT id FROM users WHERE email = ?")
+    let row = sqlx::query("SELECT id FROM users WHERE email = $1")
         .bind(&email)
         .fetch_optional(&state.db)
         .await
@@ -598,7 +868,7 @@ pub async fn request_password_reset(
     let token = Uuid::new_v4().to_string();
     let expires_at = (Utc::now() + Duration::minutes(30)).timestamp();
 
-    sqlx::query("DELETE FROM password_reset_tokens WHERE user_id = ?")
+    sqlx::query("DELETE FROM password_reset_tokens WHERE user_id = $1")
         .bind(&user_id)
         .execute(&state.db)
         .await
@@ -607,7 +877,7 @@ pub async fn request_password_reset(
     sqlx::query(
         r#"
         INSERT INTO password_reset_tokens (id, user_id, token, expires_at)
-        VALUES (?, ?, ?, ?)
+        VALUES ($1, $2, $3, $4)
         "#,
     )
     .bind(Uuid::new_v4().to_string())
@@ -618,7 +888,7 @@ pub async fn request_password_reset(
     .await
     .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    let default_sender = match mailer::get_default_sender_summary(&state.db).await {
+    let default_sender = match mailer::get_default_sender_summary(&state.db, &state.secrets).await {
         Ok(Some(summary)) => summary,
         _ => {
             return Ok(Json(serde_json::json!({
@@ -636,26 +906,21 @@ pub async fn request_password_reset(
     ];
     let email_body =
         build_system_email_html("Reset your W9 Mail password", &body_lines, "Reset password", &reset_url);
-
-    let email_service = EmailService::new();
-    if let Err(e) = email_service
-        .send_email(
-            &default_sender.credentials.header_from,
-            &default_sender.credentials.auth_email,
-            &default_sender.credentials.auth_password,
-            &email,
-            "Reset your W9 Mail password",
-            &email_body,
-            None,
-            None,
-            true,
-        )
-        .await
-    {
-        eprintln!("Failed to send reset email: {}", e);
-        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    let queued = state
+        .mail_queue
+        .enqueue(SystemEmail {
+            sender: default_sender,
+            to: email.clone(),
+            subject: "Reset your W9 Mail password".to_string(),
+            html: email_body,
+        })
+        .await;
+    if let Err(e) = queued {
+        tracing::error!(error = %e, %email, "failed to queue password reset email");
+        return Err(StatusCode::INTERNAL_SERVER_ERROR.into());
     }
 
+    // Same body as the unknown-email branch so the response doesn't reveal who has an account
     Ok(Json(serde_json::json!({
         "status": "ok",
         "message": "If the email exists, a reset link was sent."
@@ -665,26 +930,13 @@ pub async fn request_password_reset(
 pub async fn confirm_password_reset(
     State(state): State<AppState>,
     Json(payload): Json<PasswordResetConfirmRequest>,
-) -> Result<Json<serde_json::Value>, StatusCode> {
-    // Verify Turnstile token if secret is configured
-    if let Some(secret) = &state.turnstile_secret {
-        if let Some(token) = &payload.turnstile_token {
-            match verify_turnstile(secret, token).await {
-                Ok(true) => {},
-                Ok(false) => return Err(StatusCode::BAD_REQUEST),
-                Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
-            }
-        } else {
-            return Err(StatusCode::BAD_REQUEST);
-        }
-    }
+) -> Result<Response, ApiError> {
+    check_turnstile(&state, payload.turnstile_token.as_deref()).await?;
 
-    if payload.new_password.len() < 8 {
-        return Err(StatusCode::BAD_REQUEST);
-    }
+    validate_password_strength(&payload.new_password)?;
 
     let row = sqlx::query(
-        "SELECT user_id, expires_at FROM password_reset_tokens WHERE token = ?",
+        "SELECT user_id, expires_at FROM password_reset_tokens WHERE token = $1",
     )
     .bind(&payload.token)
     .fetch_optional(&state.db)
@@ -695,11 +947,12 @@ pub async fn confirm_password_reset(
         return Ok(Json(serde_json::json!({
             "status": "error",
             "message": "Invalid or expired reset link."
-        })));
+        }))
+        .into_response());
     };
 
     if row.get::<i64, _>(1) < Utc::now().timestamp() {
-        sqlx::query("DELETE FROM password_reset_tokens WHERE token = ?")
+        sqlx::query("DELETE FROM password_reset_tokens WHERE token = $1")
             .bind(&payload.token)
             .execute(&state.db)
             .await
@@ -707,30 +960,37 @@ pub async fn confirm_password_reset(
         return Ok(Json(serde_json::json!({
             "status": "error",
             "message": "Reset link expired. Request a new one."
-        })));
+        }))
+        .into_response());
     }
 
     let user_id = row.get::<String, _>(0);
+    if password_recently_used(&state, &user_id, &payload.new_password).await? {
+        return Err(password_reused_error());
+    }
     let new_hash =
         hash_password(&payload.new_password).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    sqlx::query("UPDATE users SET password_hash = ?, must_change_password = 0 WHERE id = ?")
-        .bind(new_hash)
+    sqlx::query("UPDATE users SET password_hash = $1, must_change_password = FALSE WHERE id = $2")
+        .bind(&new_hash)
         .bind(&user_id)
         .execute(&state.db)
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    record_password_history(&state, &user_id, &new_hash).await;
 
-    sqlx::query("DELETE FROM password_reset_tokens WHERE user_id = ?")
+    sqlx::query("DELETE FROM password_reset_tokens WHERE user_id = $1")
         .bind(&user_id)
         .execute(&state.db)
         .await
         .ok();
+    revoke_refresh_tokens(&state, &user_id).await;
 
     Ok(Json(serde_json::json!({
         "status": "success",
         "message": "Password updated. You can sign in now."
-    })))
+    }))
+    .into_response())
 }
 
 fn normalize_email(input: &str) -> String {
@@ -744,7 +1004,29 @@ fn html_escape(input: &str) -> String {
         .replace('>', "&gt;")
 }
 
-fn build_system_email_html(
+// Send a branded system message through the default sender, with its compliance footer
+pub(crate) async fn send_system_email(
+    state: &AppState,
+    sender: &mailer::SenderSummary,
+    to: &str,
+    subject: &str,
+    html: &str,
+) -> anyhow::Result<()> {
+    let creds = &sender.credentials;
+    let body = creds.compliance_footer.apply(html, true);
+    let outgoing = OutgoingEmail {
+        header_from: &creds.header_from,
+        to,
+        subject,
+        body: &body,
+        as_html: true,
+        ..Default::default()
+    };
+    send_via_account(state, creds, &outgoing).await?.ensure_delivered()?;
+    Ok(())
+}
+
+pub(crate) fn build_system_email_html(
     title: &str,
     body_lines: &[String],
     button_text: &str,
@@ -781,6 +1063,7 @@ fn build_system_email_html(
               <a href="{button_url}" style="text-decoration:none;display:inline-block;border:2px solid #ffffff;padding:12px 24px;color:#ffffff;background:#000;text-transform:uppercase;font-weight:bold;font-family:'Courier New',Courier,monospace;">{button_text}</a>
             </div>
             <p style="margin:0 0 12px;color:#ffffff;font-size:12px;line-height:1.4;font-family:'Courier New',Courier,monospace;word-break:break-word;">If the button doesn't work, copy and paste this link:<br />{button_url}</p>
+            {footer_slot}
             <hr style="border:none;border-top:2px solid #ffffff;margin:32px 0;" />
             <p style="margin:0;color:#ffffff;font-size:11px;opacity:0.7;font-family:'Courier New',Courier,monospace;line-height:1.4;">Automated message from W9 Mail. Replies are not monitored.</p>
           </td></tr>
@@ -795,19 +1078,104 @@ fn build_system_email_html(
         paragraphs = paragraphs,
         button_text = html_escape(button_text),
         button_url = html_escape(button_url),
+        footer_slot = FOOTER_SLOT,
     )
 }
 
+// How many previous passwords a new one is compared against; 0 turns the check off
+fn password_history_depth() -> i64 {
+    std::env::var("PASSWORD_HISTORY_COUNT")
+        .ok()
+        .and_then(|v| v.trim().parse::<i64>().ok())
+        .filter(|v| *v >= 0)
+        .unwrap_or(3)
+}
+
+/// True if `password` matches the user's current hash or one of their last N stored ones.
+async fn password_recently_used(state: &AppState, user_id: &str, password: &str) -> Result<bool, ApiError> {
+    let depth = password_history_depth();
+    if depth == 0 {
+        return Ok(false);
+    }
+
+    // The current hash covers users whose history predates the table
+    let mut hashes: Vec<String> = sqlx::query_scalar("SELECT password_hash FROM users WHERE id = $1")
+        .bind(user_id)
+        .fetch_optional(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .into_iter()
+        .collect();
+    let history: Vec<String> = sqlx::query_scalar(
+        "SELECT password_hash FROM password_history WHERE user_id = $1 ORDER BY changed_at DESC LIMIT $2",
+    )
+    .bind(user_id)
+    .bind(depth)
+    .fetch_all(&state.db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    hashes.extend(history);
+    hashes.dedup();
+
+    for hash in &hashes {
+        if verify_password(hash, password).unwrap_or(false) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+// Records the new hash and drops entries older than the configured depth
+async fn record_password_history(state: &AppState, user_id: &str, password_hash: &str) {
+    let depth = password_history_depth();
+    if depth == 0 {
+        return;
+    }
+
+    if let Err(e) = sqlx::query(
+        "INSERT INTO password_history (id, user_id, password_hash, changed_at) VALUES ($1, $2, $3, $4)",
+    )
+    .bind(Uuid::new_v4().to_string())
+    .bind(user_id)
+    .bind(password_hash)
+    .bind(Utc::now().timestamp())
+    .execute(&state.db)
+    .await
+    {
+        tracing::error!(error = %e, %user_id, "failed to record password history");
+        return;
+    }
+
+    if let Err(e) = sqlx::query(
+        r#"
+        DELETE FROM password_history
+        WHERE user_id = $1 AND id NOT IN (
+            SELECT id FROM password_history WHERE user_id = $1 ORDER BY changed_at DESC LIMIT $2
+        )
+        "#,
+    )
+    .bind(user_id)
+    .bind(depth)
+    .execute(&state.db)
+    .await
+    {
+        tracing::error!(error = %e, %user_id, "failed to prune password history");
+    }
+}
+
+fn password_reused_error() -> ApiError {
+    ApiError::invalid("password", "password_reused", "password recently used")
+}
+
 pub async fn change_password(
     State(state): State<AppState>,
     user: AuthUser,
     Json(payload): Json<ChangePasswordRequest>,
-) -> Result<Json<serde_json::Value>, StatusCode> {
-    if payload.new_password.len() < 8 {
-        return Err(StatusCode::BAD_REQUEST);
-    }
+) -> Result<Response, ApiError> {
+    user.ensure_full_access()?;
+    validate_password_strength(&payload.new_password)?;
 
-    let current_hash = sqlx::query("SELECT password_hash FROM users WHERE id = ?")
+    let current_hash = sqlx::query("SELECT password_hash FROM users WHERE id = $1")
         .bind(&user.id)
         .fetch_one(&state.db)
         .await
@@ -817,26 +1185,283 @@ pub async fn change_password(
     if !verify_password(&current_hash, &payload.current_password)
         .map_err(|_| StatusCode::UNAUTHORIZED)?
     {
-        return Err(StatusCode::UNAUTHORIZED);
+        return Err(StatusCode::UNAUTHORIZED.into());
+    }
+    if password_recently_used(&state, &user.id, &payload.new_password).await? {
+        return Err(password_reused_error());
     }
 
     let new_hash =
         hash_password(&payload.new_password).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    sqlx::query("UPDATE users SET password_hash = ?, must_change_password = 0 WHERE id = ?")
-        .bind(new_hash)
+    sqlx::query("UPDATE users SET password_hash = $1, must_change_password = FALSE WHERE id = $2")
+        .bind(&new_hash)
         .bind(&user.id)
         .execute(&state.db)
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    record_password_history(&state, &user.id, &new_hash).await;
+    // A stolen refresh token mustn't outlive the password it was issued under
+    revoke_refresh_tokens(&state, &user.id).await;
 
     Ok(Json(serde_json::json!({
         "status": "success",
         "message": "Password updated"
+    }))
+    .into_response())
+}
+
+// The user keeps signing in with the old email until the link sent to the new one is
+// followed, so a mistyped address can't lock anyone out
+pub async fn request_email_change(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(payload): Json<ChangeEmailRequest>,
+) -> Result<Response, ApiError> {
+    user.ensure_full_access()?;
+    let new_email = normalize_email(&payload.new_email);
+    if !is_valid_email(&new_email) {
+        return Err(invalid_email_error("newEmail", &new_email));
+    }
+    if new_email == user.email.to_lowercase() {
+        return Err(ApiError::invalid("newEmail", "email_unchanged", "This is already your email"));
+    }
+
+    let current_hash = sqlx::query("SELECT password_hash FROM users WHERE id = $1")
+        .bind(&user.id)
+        .fetch_one(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .get::<String, _>(0);
+    if !verify_password(&current_hash, &payload.current_password)
+        .map_err(|_| StatusCode::UNAUTHORIZED)?
+    {
+        return Err(StatusCode::UNAUTHORIZED.into());
+    }
+
+    let taken: i64 = sqlx::query_scalar("SELECT COUNT(1) FROM users WHERE email = $1")
+        .bind(&new_email)
+        .fetch_one(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if taken > 0 {
+        return Err(ApiError::new(StatusCode::CONFLICT, "email_taken", "Email already registered").with_field("newEmail"));
+    }
+
+    let default_sender = match mailer::get_default_sender_summary(&state.db, &state.secrets).await {
+        Ok(Some(summary)) => summary,
+        Ok(None) => {
+            return Err(ApiError::new(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "no_default_sender",
+                "Email changes are temporarily unavailable. Ask an admin to set a default sender.",
+            ));
+        }
+        Err(e) => {
+            tracing::error!(error = %e, "failed to load default sender for email change");
+            return Err(StatusCode::INTERNAL_SERVER_ERROR.into());
+        }
+    };
+
+    let token = Uuid::new_v4().to_string();
+    let expires_at = (Utc::now() + Duration::minutes(VERIFICATION_TTL_MINUTES)).timestamp();
+    sqlx::query(
+        r#"
+        INSERT INTO pending_email_changes (id, user_id, new_email, token, expires_at)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT(user_id) DO UPDATE SET new_email = excluded.new_email, token = excluded.token, expires_at = excluded.expires_at
+        "#,
+    )
+    .bind(Uuid::new_v4().to_string())
+    .bind(&user.id)
+    .bind(&new_email)
+    .bind(&token)
+    .bind(expires_at)
+    .execute(&state.db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let base_url = state.app_base_url.trim_end_matches('/').to_string();
+    let confirm_url = format!("{}/change-email/confirm?token={}", base_url, token);
+    let body_lines = vec![
+        format!("Confirm that {} should become the sign-in email for the W9 Mail user {}.", new_email, user.email),
+        "This link expires in 30 minutes. If you didn't ask for this, you can ignore this email.".to_string(),
+    ];
+    let email_body = build_system_email_html("Confirm your new W9 Mail email", &body_lines, "Confirm email", &confirm_url);
+    let queued = state
+        .mail_queue
+        .enqueue(SystemEmail {
+            sender: default_sender,
+            to: new_email.clone(),
+            subject: "Confirm your new W9 Mail email".to_string(),
+            html: email_body,
+        })
+        .await;
+    if let Err(e) = queued {
+        tracing::error!(error = %e, user_id = %user.id, "failed to queue email change confirmation");
+        return Err(StatusCode::INTERNAL_SERVER_ERROR.into());
+    }
+
+    Ok(Json(serde_json::json!({
+        "status": "pending",
+        "message": format!("Check {} for a confirmation link. Keep signing in with {} until then.", new_email, user.email)
+    }))
+    .into_response())
+}
+
+pub async fn confirm_email_change(
+    State(state): State<AppState>,
+    Json(payload): Json<ChangeEmailConfirmRequest>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let row = sqlx::query("SELECT id, user_id, new_email, expires_at FROM pending_email_changes WHERE token = $1")
+        .bind(&payload.token)
+        .fetch_optional(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let Some(row) = row else {
+        return Ok(Json(serde_json::json!({
+            "status": "error",
+            "message": "Invalid or expired confirmation link."
+        })));
+    };
+
+    let pending_id = row.get::<String, _>(0);
+    if row.get::<i64, _>(3) < Utc::now().timestamp() {
+        sqlx::query("DELETE FROM pending_email_changes WHERE id = $1")
+            .bind(&pending_id)
+            .execute(&state.db)
+            .await
+            .ok();
+        return Ok(Json(serde_json::json!({
+            "status": "error",
+            "message": "Confirmation link expired. Request the change again."
+        })));
+    }
+
+    let user_id = row.get::<String, _>(1);
+    let new_email = row.get::<String, _>(2);
+    // users.email is unique, so an address registered since the request fails here
+    let updated = sqlx::query("UPDATE users SET email = $1 WHERE id = $2")
+        .bind(&new_email)
+        .bind(&user_id)
+        .execute(&state.db)
+        .await;
+    sqlx::query("DELETE FROM pending_email_changes WHERE id = $1")
+        .bind(&pending_id)
+        .execute(&state.db)
+        .await
+        .ok();
+    if let Err(e) = updated {
+        tracing::warn!(error = %e, %user_id, "failed to apply email change");
+        return Ok(Json(serde_json::json!({
+            "status": "error",
+            "message": "This email was registered by someone else in the meantime."
+        })));
+    }
+
+    Ok(Json(serde_json::json!({
+        "status": "success",
+        "message": format!("Email updated. Sign in with {} from now on.", new_email),
+        "email": new_email
     })))
 }
 
-pub async fn me(user: AuthUser) -> Result<Json<UserSummary>, StatusCode> {
+fn hash_refresh_token(token: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(token.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+async fn issue_refresh_token(state: &AppState, user_id: &str) -> Result<String, ApiError> {
+    let token = generate_api_token();
+    let expires_at = (Utc::now() + Duration::days(state.refresh_ttl_days)).timestamp();
+
+    sqlx::query("INSERT INTO refresh_tokens (id, user_id, token_hash, expires_at) VALUES ($1, $2, $3, $4)")
+        .bind(Uuid::new_v4().to_string())
+        .bind(user_id)
+        .bind(hash_refresh_token(&token))
+        .bind(expires_at)
+        .execute(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(token)
+}
+
+// Signs out everywhere; used when the password changes
+async fn revoke_refresh_tokens(state: &AppState, user_id: &str) {
+    if let Err(e) = sqlx::query("DELETE FROM refresh_tokens WHERE user_id = $1")
+        .bind(user_id)
+        .execute(&state.db)
+        .await
+    {
+        tracing::error!(error = %e, %user_id, "failed to revoke refresh tokens");
+    }
+}
+
+#[derive(Deserialize)]
+pub struct RefreshRequest {
+    #[serde(rename = "refreshToken")]
+    pub refresh_token: String,
+}
+
+pub async fn refresh_session(
+    State(state): State<AppState>,
+    Json(payload): Json<RefreshRequest>,
+) -> Result<Json<LoginResponse>, ApiError> {
+    // Deleting on use is the rotation: a replayed token finds nothing
+    let user_id: String = sqlx::query_scalar(
+        "DELETE FROM refresh_tokens WHERE token_hash = $1 AND expires_at > $2 RETURNING user_id",
+    )
+    .bind(hash_refresh_token(&payload.refresh_token))
+    .bind(Utc::now().timestamp())
+    .fetch_optional(&state.db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    .ok_or(StatusCode::UNAUTHORIZED)?;
+
+    let row = sqlx::query("SELECT id, email, role, must_change_password FROM users WHERE id = $1")
+        .bind(&user_id)
+        .fetch_optional(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+
+    let email = row.get::<String, _>(1);
+    let role: UserRole = row
+        .get::<String, _>(2)
+        .try_into()
+        .map_err(|_| StatusCode::UNAUTHORIZED)?;
+    let token = encode_token(&user_id, &email, &role, &state.jwt_secret, state.jwt_ttl_hours)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let refresh_token = issue_refresh_token(&state, &user_id).await?;
+
+    Ok(Json(LoginResponse {
+        token,
+        refresh_token,
+        id: user_id,
+        email,
+        role,
+        must_change_password: row.get::<bool, _>(3),
+    }))
+}
+
+// Revokes the given refresh token; the JWT itself simply runs out
+pub async fn logout(
+    State(state): State<AppState>,
+    Json(payload): Json<RefreshRequest>,
+) -> Result<StatusCode, ApiError> {
+    sqlx::query("DELETE FROM refresh_tokens WHERE token_hash = $1")
+        .bind(hash_refresh_token(&payload.refresh_token))
+        .execute(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn me(user: AuthUser) -> Result<Json<UserSummary>, ApiError> {
     Ok(Json(UserSummary {
         id: user.id,
         email: user.email,
@@ -845,21 +1470,60 @@ pub async fn me(user: AuthUser) -> Result<Json<UserSummary>, StatusCode> {
     }))
 }
 
+#[derive(Serialize, Deserialize)]
+pub struct SecurityPreferences {
+    #[serde(rename = "securityAlerts")]
+    pub security_alerts: bool,
+}
+
+pub async fn get_preferences(
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> Result<Json<SecurityPreferences>, ApiError> {
+    let security_alerts: bool = sqlx::query_scalar("SELECT security_alerts FROM users WHERE id = $1")
+        .bind(&user.id)
+        .fetch_one(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(SecurityPreferences { security_alerts }))
+}
+
+pub async fn update_preferences(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(payload): Json<SecurityPreferences>,
+) -> Result<Json<SecurityPreferences>, ApiError> {
+    user.ensure_full_access()?;
+    sqlx::query("UPDATE users SET security_alerts = $1 WHERE id = $2")
+        .bind(payload.security_alerts)
+        .bind(&user.id)
+        .execute(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(payload))
+}
+
 pub async fn create_user(
     State(state): State<AppState>,
     user: AuthUser,
     Json(payload): Json<CreateUserRequest>,
-) -> Result<Json<UserSummary>, StatusCode> {
+) -> Result<Response, ApiError> {
     user.ensure_password_updated()?;
+    user.ensure_scope(SCOPE_ADMIN)?;
     if !matches!(user.role, UserRole::Admin) {
-        return Err(StatusCode::FORBIDDEN);
+        return Err(StatusCode::FORBIDDEN.into());
     }
 
-    if payload.password.len() < 8 {
-        return Err(StatusCode::BAD_REQUEST);
+    validate_password_strength(&payload.password)?;
+    let email = normalize_email(&payload.email);
+    if !is_valid_email(&email) {
+        return Err(invalid_email_error("email", &email));
     }
 
     let role = payload.role.unwrap_or(UserRole::User);
+    ensure_may_grant(&user, &role)?;
     let password_hash =
         hash_password(&payload.password).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
     let id = Uuid::new_v4().to_string();
@@ -867,32 +1531,35 @@ pub async fn create_user(
     sqlx::query(
         r#"
         INSERT INTO users (id, email, password_hash, role, must_change_password)
-        VALUES (?, ?, ?, ?, 0)
+        VALUES ($1, $2, $3, $4, FALSE)
     "#,
     )
     .bind(&id)
-    .bind(&payload.email)
+    .bind(&email)
     .bind(password_hash)
     .bind(role.as_str())
     .execute(&state.db)
     .await
     .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    audit::record(&state.db, &user, "user.create", "user", &id, serde_json::json!({ "email": email, "role": role })).await;
 
     Ok(Json(UserSummary {
         id,
-        email: payload.email,
+        email,
         role,
         must_change_password: false,
-    }))
+    })
+    .into_response())
 }
 
 pub async fn list_users(
     State(state): State<AppState>,
     user: AuthUser,
-) -> Result<Json<Vec<UserSummary>>, StatusCode> {
+) -> Result<Json<Vec<UserSummary>>, ApiError> {
     user.ensure_password_updated()?;
+    user.ensure_scope(SCOPE_ADMIN)?;
     if !matches!(user.role, UserRole::Admin) {
-        return Err(StatusCode::FORBIDDEN);
+        return Err(StatusCode::FORBIDDEN.into());
     }
 
     let rows = sqlx::query("SELECT id, email, role, must_change_password FROM users ORDER BY created_at DESC")
@@ -919,32 +1586,108 @@ pub async fn list_users(
     Ok(Json(users))
 }
 
+// Signups still waiting on their verification link; expired ones are left out
+pub async fn list_pending_signups(
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> Result<Json<Vec<PendingSignup>>, ApiError> {
+    user.ensure_password_updated()?;
+    user.ensure_scope(SCOPE_ADMIN)?;
+    if !matches!(user.role, UserRole::Admin) {
+        return Err(StatusCode::FORBIDDEN.into());
+    }
+
+    let rows = sqlx::query(
+        "SELECT id, email, expires_at FROM pending_users WHERE expires_at >= $1 ORDER BY expires_at DESC",
+    )
+    .bind(Utc::now().timestamp())
+    .fetch_all(&state.db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let pending = rows
+        .into_iter()
+        .map(|row| PendingSignup {
+            id: row.get::<String, _>(0),
+            email: row.get::<String, _>(1),
+            expires_at: row.get::<i64, _>(2),
+        })
+        .collect();
+
+    Ok(Json(pending))
+}
+
+// Cancels a signup; its verification link stops working
+pub async fn delete_pending_signup(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(pending_id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    user.ensure_password_updated()?;
+    user.ensure_scope(SCOPE_ADMIN)?;
+    if !matches!(user.role, UserRole::Admin) {
+        return Err(StatusCode::FORBIDDEN.into());
+    }
+
+    let deleted: Option<String> =
+        sqlx::query_scalar("DELETE FROM pending_users WHERE id = $1 RETURNING email")
+            .bind(&pending_id)
+            .fetch_optional(&state.db)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let Some(email) = deleted else {
+        return Err(StatusCode::NOT_FOUND.into());
+    };
+    audit::record(&state.db, &user, "signup.cancel", "pending_user", &pending_id, serde_json::json!({ "email": email })).await;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
 pub async fn update_user(
     State(state): State<AppState>,
     user: AuthUser,
     Path(target_id): Path<String>,
     Json(payload): Json<UpdateUserRequest>,
-) -> Result<Json<UserSummary>, StatusCode> {
+) -> Result<Response, ApiError> {
     user.ensure_password_updated()?;
+    user.ensure_scope(SCOPE_ADMIN)?;
     if !matches!(user.role, UserRole::Admin) {
-        return Err(StatusCode::FORBIDDEN);
+        return Err(StatusCode::FORBIDDEN.into());
     }
 
     if payload.password.is_none() && payload.role.is_none() && payload.must_change_password.is_none() {
-        return Err(StatusCode::BAD_REQUEST);
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            "nothing_to_update",
+            "Provide password, role or mustChangePassword",
+        ));
+    }
+    // Checked before any field is written so a weak password doesn't leave a half-applied update
+    if let Some(password) = &payload.password {
+        validate_password_strength(password)?;
+        if password_recently_used(&state, &target_id, password).await? {
+            return Err(password_reused_error());
+        }
     }
 
     if let Some(role) = &payload.role {
-        sqlx::query("UPDATE users SET role = ? WHERE id = ?")
+        ensure_may_grant(&user, role)?;
+        let mut tx = state.db.begin().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        if !matches!(role, UserRole::Admin) && is_last_admin(&mut tx, &target_id).await? {
+            return Err(last_admin_error());
+        }
+        sqlx::query("UPDATE users SET role = $1 WHERE id = $2")
             .bind(role.as_str())
             .bind(&target_id)
-            .execute(&state.db)
+            .execute(&mut *tx)
             .await
             .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        tx.commit().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
     }
 
     if let Some(flag) = payload.must_change_password {
-        sqlx::query("UPDATE users SET must_change_password = ? WHERE id = ?")
+        sqlx::query("UPDATE users SET must_change_password = $1 WHERE id = $2")
             .bind(flag)
             .bind(&target_id)
             .execute(&state.db)
@@ -953,20 +1696,19 @@ pub async fn update_user(
     }
 
     if let Some(password) = &payload.password {
-        if password.len() < 8 {
-            return Err(StatusCode::BAD_REQUEST);
-        }
         let new_hash =
             hash_password(password).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-        sqlx::query("UPDATE users SET password_hash = ?, must_change_password = 0 WHERE id = ?")
-            .bind(new_hash)
+        sqlx::query("UPDATE users SET password_hash = $1, must_change_password = FALSE WHERE id = $2")
+            .bind(&new_hash)
             .bind(&target_id)
             .execute(&state.db)
             .await
             .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        record_password_history(&state, &target_id, &new_hash).await;
+        revoke_refresh_tokens(&state, &target_id).await;
     }
 
-    let row = sqlx::query("SELECT id, email, role, must_change_password FROM users WHERE id = ?")
+    let row = sqlx::query("SELECT id, email, role, must_change_password FROM users WHERE id = $1")
         .bind(&target_id)
         .fetch_one(&state.db)
         .await
@@ -977,40 +1719,80 @@ pub async fn update_user(
         .try_into()
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
+    // The password itself is never logged, only that it was reset
+    let detail = serde_json::json!({
+        "email": row.get::<String, _>(1),
+        "role": payload.role,
+        "mustChangePassword": payload.must_change_password,
+        "passwordReset": payload.password.is_some(),
+    });
+    audit::record(&state.db, &user, "user.update", "user", &target_id, detail).await;
+
     Ok(Json(UserSummary {
         id: row.get::<String, _>(0),
         email: row.get::<String, _>(1),
         role,
         must_change_password: row.get::<bool, _>(3),
-    }))
+    })
+    .into_response())
 }
 
 pub async fn delete_user(
     State(state): State<AppState>,
     user: AuthUser,
     Path(target_id): Path<String>,
-) -> Result<StatusCode, StatusCode> {
+) -> Result<StatusCode, ApiError> {
     user.ensure_password_updated()?;
+    user.ensure_scope(SCOPE_ADMIN)?;
     if !matches!(user.role, UserRole::Admin) {
-        return Err(StatusCode::FORBIDDEN);
+        return Err(StatusCode::FORBIDDEN.into());
     }
     if user.id == target_id {
-        return Err(StatusCode::BAD_REQUEST);
+        return Err(ApiError::new(StatusCode::BAD_REQUEST, "cannot_delete_self", "You can't delete your own user"));
     }
 
-    let result = sqlx::query("DELETE FROM users WHERE id = ?")
+    let mut tx = state.db.begin().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if is_last_admin(&mut tx, &target_id).await? {
+        return Err(last_admin_error());
+    }
+    let deleted: Option<String> = sqlx::query_scalar("DELETE FROM users WHERE id = $1 RETURNING email")
         .bind(&target_id)
-        .execute(&state.db)
+        .fetch_optional(&mut *tx)
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    if result.rows_affected() == 0 {
-        return Err(StatusCode::NOT_FOUND);
-    }
+    let Some(email) = deleted else {
+        return Err(StatusCode::NOT_FOUND.into());
+    };
+    tx.commit().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    audit::record(&state.db, &user, "user.delete", "user", &target_id, serde_json::json!({ "email": email })).await;
 
     Ok(StatusCode::NO_CONTENT)
 }
 
+// Only admins may hand out the admin role. The user handlers are admin-only today; this
+// keeps it that way if other roles are ever let in to manage users.
+fn ensure_may_grant(actor: &AuthUser, role: &UserRole) -> Result<(), ApiError> {
+    if matches!(role, UserRole::Admin) && !matches!(actor.role, UserRole::Admin) {
+        return Err(ApiError::forbidden("cannot_grant_admin", "Only admins can assign the admin role").with_field("role"));
+    }
+    Ok(())
+}
+
+// Whether target_id is the only admin. Every admin row stays locked until the
+// transaction ends, so two concurrent demotions can't each leave the other as the last.
+async fn is_last_admin(conn: &mut PgConnection, target_id: &str) -> Result<bool, ApiError> {
+    let admins: Vec<String> = sqlx::query_scalar("SELECT id FROM users WHERE role = 'admin' FOR UPDATE")
+        .fetch_all(conn)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(admins.len() <= 1 && admins.iter().any(|id| id == target_id))
+}
+
+fn last_admin_error() -> ApiError {
+    ApiError::new(StatusCode::BAD_REQUEST, "cannot_remove_last_admin", "Cannot remove the last admin")
+}
+
 impl fmt::Display for UserRole {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}", self.as_str())
@@ -1028,12 +1810,20 @@ pub struct ApiTokenSummary {
     pub created_at: String,
     #[serde(rename = "lastUsedAt")]
     pub last_used_at: Option<String>,
+    #[serde(rename = "expiresAt")]
+    pub expires_at: Option<String>,
+    pub scopes: Option<Vec<String>>,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct CreateApiTokenRequest {
     #[serde(rename = "name")]
     pub name: Option<String>,
+    // Omitted or null means the token never expires
+    #[serde(rename = "expiresInDays")]
+    pub expires_in_days: Option<i64>,
+    // Omitted or null grants the owner's full permissions
+    pub scopes: Option<Vec<String>>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -1045,10 +1835,16 @@ pub struct CreateApiTokenResponse {
     pub name: Option<String>,
     #[serde(rename = "createdAt")]
     pub created_at: String,
+    #[serde(rename = "expiresAt")]
+    pub expires_at: Option<String>,
+    pub scopes: Option<Vec<String>>,
     #[serde(rename = "message")]
     pub message: String,
 }
 
+// Ten years; longer than that is better expressed as no expiry
+const MAX_API_TOKEN_DAYS: i64 = 3650;
+
 fn generate_api_token() -> String {
     const CHARSET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
     let mut rng = rand::thread_rng();
@@ -1064,8 +1860,38 @@ pub async fn create_api_token(
     State(state): State<AppState>,
     user: AuthUser,
     Json(payload): Json<CreateApiTokenRequest>,
-) -> Result<Json<CreateApiTokenResponse>, StatusCode> {
+) -> Result<Json<CreateApiTokenResponse>, ApiError> {
     user.ensure_password_updated()?;
+    // A scoped token must not be able to mint a broader one
+    user.ensure_full_access()?;
+
+    if payload
+        .expires_in_days
+        .is_some_and(|days| !(1..=MAX_API_TOKEN_DAYS).contains(&days))
+    {
+        return Err(ApiError::invalid(
+            "expiresInDays",
+            "invalid_expiry",
+            format!("expiresInDays must be between 1 and {}", MAX_API_TOKEN_DAYS),
+        ));
+    }
+
+    let scopes = match &payload.scopes {
+        Some(requested) => {
+            let mut scopes: Vec<String> = requested.iter().map(|s| s.trim().to_string()).collect();
+            scopes.sort();
+            scopes.dedup();
+            if scopes.is_empty() || scopes.iter().any(|s| !API_TOKEN_SCOPES.contains(&s.as_str())) {
+                return Err(ApiError::invalid(
+                    "scopes",
+                    "invalid_scopes",
+                    format!("scopes must be a non-empty list of: {}", API_TOKEN_SCOPES.join(", ")),
+                ));
+            }
+            Some(scopes)
+        }
+        None => None,
+    };
     
     // Generate a random token
     let token = generate_api_token();
@@ -1076,16 +1902,19 @@ pub async fn create_api_token(
     let token_hash = format!("{:x}", hasher.finalize());
     
     let token_id = Uuid::new_v4().to_string();
-    let created_at = Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
+    let created_at = Utc::now();
+    let expires_at = payload.expires_in_days.map(|days| created_at + Duration::days(days));
     
     sqlx::query(
-        "INSERT INTO api_tokens (id, user_id, token_hash, name, created_at) VALUES (?, ?, ?, ?, ?)"
+        "INSERT INTO api_tokens (id, user_id, token_hash, name, created_at, expires_at, scopes) VALUES ($1, $2, $3, $4, $5, $6, $7)"
     )
     .bind(&token_id)
     .bind(&user.id)
     .bind(&token_hash)
     .bind(payload.name.as_deref())
-    .bind(&created_at)
+    .bind(created_at)
+    .bind(expires_at)
+    .bind(scopes.as_ref().map(|s| s.join(",")))
     .execute(&state.db)
     .await
     .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
@@ -1094,19 +1923,84 @@ pub async fn create_api_token(
         id: token_id,
         token,
         name: payload.name,
-        created_at,
+        created_at: created_at.to_rfc3339(),
+        expires_at: expires_at.map(|t| t.to_rfc3339()),
+        scopes,
         message: "API token created. Save this token now - you won't be able to see it again!".to_string(),
     }))
 }
 
+/// Replaces a token's secret while keeping its id, name, scopes and expiry, so a CI
+/// credential can be rotated without breaking references to it. Like creation, the
+/// new secret is only ever returned here.
+pub async fn rotate_api_token(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(token_id): Path<String>,
+) -> Result<Json<CreateApiTokenResponse>, ApiError> {
+    user.ensure_password_updated()?;
+    user.ensure_full_access()?;
+
+    let token = generate_api_token();
+    let mut hasher = Sha256::new();
+    hasher.update(token.as_bytes());
+    let token_hash = format!("{:x}", hasher.finalize());
+
+    // The owner or an admin; anyone else gets the same 404 as an unknown id
+    let row = sqlx::query(
+        r#"
+        UPDATE api_tokens SET token_hash = $1, last_used_at = NULL
+        WHERE id = $2 AND (user_id = $3 OR $4)
+        RETURNING user_id, name, created_at, expires_at, scopes
+        "#,
+    )
+    .bind(&token_hash)
+    .bind(&token_id)
+    .bind(&user.id)
+    .bind(matches!(user.role, UserRole::Admin))
+    .fetch_optional(&state.db)
+    .await
+    .map_err(|e| {
+        tracing::error!(error = %e, user_id = %user.id, %token_id, "failed to rotate api token");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?
+    .ok_or(StatusCode::NOT_FOUND)?;
+
+    let owner_id = row.get::<String, _>(0);
+    audit::record(
+        &state.db,
+        &user,
+        "token.rotate",
+        "api_token",
+        &token_id,
+        serde_json::json!({ "ownerId": owner_id }),
+    )
+    .await;
+
+    Ok(Json(CreateApiTokenResponse {
+        id: token_id,
+        token,
+        name: row.get::<Option<String>, _>(1),
+        created_at: row.get::<chrono::DateTime<Utc>, _>(2).to_rfc3339(),
+        expires_at: row
+            .get::<Option<chrono::DateTime<Utc>>, _>(3)
+            .map(|t| t.to_rfc3339()),
+        scopes: row
+            .get::<Option<String>, _>(4)
+            .map(|s| s.split(',').map(str::to_string).collect()),
+        message: "API token rotated. The previous token no longer works. Save this token now - you won't be able to see it again!".to_string(),
+    }))
+}
+
 pub async fn list_api_tokens(
     State(state): State<AppState>,
     user: AuthUser,
-) -> Result<Json<Vec<ApiTokenSummary>>, StatusCode> {
+) -> Result<Json<Vec<ApiTokenSummary>>, ApiError> {
     user.ensure_password_updated()?;
+    user.ensure_full_access()?;
     
     let rows = sqlx::query(
-        "SELECT id, name, created_at, last_used_at FROM api_tokens WHERE user_id = ? ORDER BY created_at DESC"
+        "SELECT id, name, created_at, last_used_at, expires_at, scopes FROM api_tokens WHERE user_id = $1 ORDER BY created_at DESC"
     )
     .bind(&user.id)
     .fetch_all(&state.db)
@@ -1118,8 +2012,16 @@ pub async fn list_api_tokens(
         .map(|row| ApiTokenSummary {
             id: row.get::<String, _>(0),
             name: row.get::<Option<String>, _>(1),
-            created_at: row.get::<String, _>(2),
-            last_used_at: row.get::<Option<String>, _>(3),
+            created_at: row.get::<chrono::DateTime<Utc>, _>(2).to_rfc3339(),
+            last_used_at: row
+                .get::<Option<chrono::DateTime<Utc>>, _>(3)
+                .map(|t| t.to_rfc3339()),
+            expires_at: row
+                .get::<Option<chrono::DateTime<Utc>>, _>(4)
+                .map(|t| t.to_rfc3339()),
+            scopes: row
+                .get::<Option<String>, _>(5)
+                .map(|s| s.split(',').map(str::to_string).collect()),
         })
         .collect();
     
@@ -1130,11 +2032,12 @@ pub async fn delete_api_token(
     State(state): State<AppState>,
     user: AuthUser,
     Path(token_id): Path<String>,
-) -> Result<StatusCode, StatusCode> {
+) -> Result<StatusCode, ApiError> {
     user.ensure_password_updated()?;
+    user.ensure_full_access()?;
     
     let result = sqlx::query(
-        "DELETE FROM api_tokens WHERE id = ? AND user_id = ?"
+        "DELETE FROM api_tokens WHERE id = $1 AND user_id = $2"
     )
     .bind(&token_id)
     .bind(&user.id)
@@ -1143,9 +2046,70 @@ pub async fn delete_api_token(
     .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
     
     if result.rows_affected() == 0 {
-        return Err(StatusCode::NOT_FOUND);
+        return Err(StatusCode::NOT_FOUND.into());
     }
     
     Ok(StatusCode::NO_CONTENT)
 }
 
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{Method, StatusCode};
+
+    use super::*;
+    use crate::test_support;
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn changing_the_password_revokes_refresh_tokens() {
+        let state = test_support::state().await;
+        let user = test_support::user(&state.db, "user").await;
+        sqlx::query("UPDATE users SET password_hash = $1 WHERE id = $2")
+            .bind(hash_password("old-password-1").unwrap())
+            .bind(&user.id)
+            .execute(&state.db)
+            .await
+            .unwrap();
+        let refresh_token = issue_refresh_token(&state, &user.id).await.unwrap();
+        let app = crate::router(state, 1024 * 1024);
+
+        let body = serde_json::json!({
+            "currentPassword": "old-password-1",
+            "newPassword": "new-password-2"
+        })
+        .to_string();
+        let (status, _) =
+            test_support::call(app.clone(), Method::POST, "/api/auth/change-password", &user.token, body).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let body = serde_json::json!({ "refreshToken": refresh_token }).to_string();
+        let (status, _) = test_support::call(app, Method::POST, "/api/auth/refresh", "", body).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn signing_up_with_a_registered_email_is_a_conflict() {
+        let state = test_support::state().await;
+        let user = test_support::user(&state.db, "user").await;
+        let email: String = sqlx::query_scalar("SELECT email FROM users WHERE id = $1")
+            .bind(&user.id)
+            .fetch_one(&state.db)
+            .await
+            .unwrap();
+        let app = crate::router(state.clone(), 1024 * 1024);
+
+        let body = serde_json::json!({ "email": email, "password": "long-enough-1" }).to_string();
+        let (status, json) = test_support::call(app, Method::POST, "/api/auth/signup", "", body).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(json["code"], "email_taken");
+
+        let pending: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM pending_users WHERE email = $1")
+            .bind(&email)
+            .fetch_one(&state.db)
+            .await
+            .unwrap();
+        assert_eq!(pending, 0);
+    }
+}
@@ -0,0 +1,72 @@
+// Periodic purge of expired signup, password-reset and email-change tokens, and of send
+// idempotency keys past their TTL. Those rows are otherwise only deleted when someone
+// follows an expired link or reuses a key, so abandoned ones pile up. Browser copies of
+// sent mail (webVersion) are dropped here too once their links expire.
+
+use std::time::Duration;
+
+use chrono::Utc;
+use sqlx::PgPool;
+
+use crate::workers::{self, Workers};
+
+/// Runs `purge_expired` every `every`, starting one interval after startup.
+pub fn spawn(workers: &Workers, db: PgPool, every: Duration, idempotency_ttl_secs: i64) {
+    // Missing one run is harmless, so this one never holds up readiness
+    let heartbeat = workers.register("token_cleanup", every * 2, false);
+    workers::supervise(heartbeat, move |heartbeat| {
+        let db = db.clone();
+        async move {
+            let mut interval = tokio::time::interval(every);
+            // The first tick fires immediately; skip it so startup isn't slowed by a purge
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                match purge_expired(&db, idempotency_ttl_secs).await {
+                    Ok(purged) => heartbeat.processed(purged),
+                    Err(e) => {
+                        tracing::error!(error = %e, "failed to purge expired tokens");
+                        heartbeat.failed(&e);
+                    }
+                }
+            }
+        }
+    });
+}
+
+// Returns how many rows were deleted
+async fn purge_expired(db: &PgPool, idempotency_ttl_secs: i64) -> anyhow::Result<u64> {
+    let now = Utc::now().timestamp();
+    let pending_users = sqlx::query("DELETE FROM pending_users WHERE expires_at < $1")
+        .bind(now)
+        .execute(db)
+        .await?
+        .rows_affected();
+    let reset_tokens = sqlx::query("DELETE FROM password_reset_tokens WHERE expires_at < $1")
+        .bind(now)
+        .execute(db)
+        .await?
+        .rows_affected();
+    let email_changes = sqlx::query("DELETE FROM pending_email_changes WHERE expires_at < $1")
+        .bind(now)
+        .execute(db)
+        .await?
+        .rows_affected();
+    let idempotency_keys = sqlx::query("DELETE FROM idempotency_keys WHERE created_at < $1")
+        .bind(now - idempotency_ttl_secs)
+        .execute(db)
+        .await?
+        .rows_affected();
+    // The sent message itself stays; only its browser copy goes
+    let web_copies = sqlx::query(
+        "UPDATE sent_messages SET web_html = NULL, web_expires_at = NULL WHERE web_expires_at < $1",
+    )
+    .bind(now)
+    .execute(db)
+    .await?
+    .rows_affected();
+    if pending_users > 0 || reset_tokens > 0 || email_changes > 0 || idempotency_keys > 0 || web_copies > 0 {
+        tracing::info!(pending_users, reset_tokens, email_changes, idempotency_keys, web_copies, "purged expired tokens");
+    }
+    Ok(pending_users + reset_tokens + email_changes + idempotency_keys + web_copies)
+}
@@ -0,0 +1,855 @@
+// Export/import of sender configuration so staging and production can be kept in sync.
+// Rows are matched on natural keys (account email, alias email) rather than ids, which
+// differ between instances.
+
+use std::collections::{HashMap, HashSet};
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::Json,
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sqlx::{PgExecutor, Postgres, Row, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    audit,
+    auth::{AuthUser, UserRole, SCOPE_ADMIN},
+    error::ApiError,
+    mailer::{catchall_domain, SenderKind},
+    secrets::SecretBox,
+    AppState,
+};
+
+const BUNDLE_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct AccountConfig {
+    pub email: String,
+    #[serde(rename = "displayName")]
+    pub display_name: String,
+    #[serde(rename = "isActive")]
+    pub is_active: bool,
+    #[serde(rename = "isPublic")]
+    pub is_public: bool,
+    #[serde(rename = "complianceFooterHtml", default)]
+    pub compliance_footer_html: Option<String>,
+    #[serde(rename = "complianceFooterText", default)]
+    pub compliance_footer_text: Option<String>,
+    #[serde(rename = "dailySendLimit", default)]
+    pub daily_send_limit: Option<i64>,
+    #[serde(rename = "senderName", default)]
+    pub sender_name: Option<String>,
+    #[serde(rename = "brandingEnabled", default = "default_true")]
+    pub branding_enabled: bool,
+    // Filled in by hand; never exported
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    // Exported with includeSecrets: the password sealed with ACCOUNT_ENC_KEY, which the
+    // importing instance needs to share
+    #[serde(rename = "sealedPassword", default, skip_serializing_if = "Option::is_none")]
+    pub sealed_password: Option<String>,
+}
+
+// Bundles exported before brandingEnabled existed keep branding on
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct AliasConfig {
+    #[serde(rename = "aliasEmail")]
+    pub alias_email: String,
+    #[serde(rename = "accountEmail")]
+    pub account_email: String,
+    #[serde(rename = "displayName")]
+    pub display_name: Option<String>,
+    #[serde(rename = "isActive")]
+    pub is_active: bool,
+    #[serde(rename = "isPublic")]
+    pub is_public: bool,
+    #[serde(rename = "complianceFooterHtml", default)]
+    pub compliance_footer_html: Option<String>,
+    #[serde(rename = "complianceFooterText", default)]
+    pub compliance_footer_text: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct DefaultSenderConfig {
+    #[serde(rename = "senderType")]
+    pub sender_type: SenderKind,
+    pub email: String,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct SettingsConfig {
+    #[serde(rename = "defaultSender", default)]
+    pub default_sender: Option<DefaultSenderConfig>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ConfigBundle {
+    pub version: u32,
+    #[serde(rename = "exportedAt", default)]
+    pub exported_at: Option<String>,
+    #[serde(default)]
+    pub accounts: Vec<AccountConfig>,
+    #[serde(default)]
+    pub aliases: Vec<AliasConfig>,
+    #[serde(default)]
+    pub settings: SettingsConfig,
+}
+
+#[derive(Deserialize)]
+pub struct ExportQuery {
+    #[serde(rename = "includeSecrets", default)]
+    pub include_secrets: bool,
+}
+
+#[derive(Deserialize)]
+pub struct ImportQuery {
+    #[serde(rename = "dryRun", default)]
+    pub dry_run: bool,
+    #[serde(default)]
+    pub force: bool,
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeAction {
+    Create,
+    Update,
+    Unchanged,
+    Conflict,
+}
+
+#[derive(Serialize)]
+pub struct ConfigChange {
+    pub kind: &'static str,
+    pub key: String,
+    pub action: ChangeAction,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ImportReport {
+    #[serde(rename = "dryRun")]
+    pub dry_run: bool,
+    pub applied: bool,
+    pub changes: Vec<ConfigChange>,
+}
+
+fn ensure_admin(user: &AuthUser) -> Result<(), StatusCode> {
+    user.ensure_password_updated()?;
+    user.ensure_scope(SCOPE_ADMIN)?;
+    if !matches!(user.role, UserRole::Admin) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    Ok(())
+}
+
+// An import reads with `lock` set, inside its transaction, so the rows it diffed against
+// can't change before its writes land
+fn locking(lock: bool) -> &'static str {
+    if lock {
+        " FOR UPDATE"
+    } else {
+        ""
+    }
+}
+
+async fn load_accounts<'e>(db: impl PgExecutor<'e>, lock: bool) -> Result<Vec<AccountConfig>, StatusCode> {
+    let sql = format!(
+        "SELECT email, display_name, is_active, is_public, compliance_footer_html, compliance_footer_text, daily_send_limit, sender_name, branding_enabled FROM accounts WHERE deleted_at IS NULL ORDER BY email ASC{}",
+        locking(lock)
+    );
+    let rows = sqlx::query(&sql)
+        .fetch_all(db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| AccountConfig {
+            email: row.get::<String, _>(0),
+            display_name: row.get::<String, _>(1),
+            is_active: row.get::<bool, _>(2),
+            is_public: row.get::<bool, _>(3),
+            compliance_footer_html: row.get::<Option<String>, _>(4),
+            compliance_footer_text: row.get::<Option<String>, _>(5),
+            daily_send_limit: row.get::<Option<i64>, _>(6),
+            sender_name: row.get::<Option<String>, _>(7),
+            branding_enabled: row.get::<bool, _>(8),
+            password: None,
+            sealed_password: None,
+        })
+        .collect())
+}
+
+async fn load_aliases<'e>(db: impl PgExecutor<'e>, lock: bool) -> Result<Vec<AliasConfig>, StatusCode> {
+    let sql = format!(
+        r#"
+        SELECT
+            aliases.alias_email,
+            accounts.email,
+            aliases.display_name,
+            aliases.is_active,
+            aliases.is_public,
+            aliases.compliance_footer_html,
+            aliases.compliance_footer_text
+        FROM aliases
+        JOIN accounts ON aliases.account_id = accounts.id
+        WHERE accounts.deleted_at IS NULL
+        ORDER BY aliases.alias_email ASC{}
+        "#,
+        locking(lock)
+    );
+    let rows = sqlx::query(&sql)
+        .fetch_all(db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| AliasConfig {
+            alias_email: row.get::<String, _>(0),
+            account_email: row.get::<String, _>(1),
+            display_name: row.get::<Option<String>, _>(2),
+            is_active: row.get::<bool, _>(3),
+            is_public: row.get::<bool, _>(4),
+            compliance_footer_html: row.get::<Option<String>, _>(5),
+            compliance_footer_text: row.get::<Option<String>, _>(6),
+        })
+        .collect())
+}
+
+async fn load_default_sender<'e>(db: impl PgExecutor<'e>, lock: bool) -> Result<Option<DefaultSenderConfig>, StatusCode> {
+    // Only default_sender itself can be locked; the joins are outer
+    let sql = format!(
+        r#"
+        SELECT default_sender.sender_type,
+               COALESCE(accounts.email, aliases.alias_email)
+        FROM default_sender
+        LEFT JOIN accounts ON default_sender.sender_type = 'account' AND accounts.id = default_sender.sender_id
+        LEFT JOIN aliases ON default_sender.sender_type = 'alias' AND aliases.id = default_sender.sender_id
+        WHERE default_sender.singleton = 1{}
+        "#,
+        if lock { " FOR UPDATE OF default_sender" } else { "" }
+    );
+    let row = sqlx::query(&sql)
+        .fetch_optional(db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let Some(row) = row else {
+        return Ok(None);
+    };
+    let Some(email) = row.get::<Option<String>, _>(1) else {
+        return Ok(None);
+    };
+    let sender_type: SenderKind = row
+        .get::<String, _>(0)
+        .try_into()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Some(DefaultSenderConfig { sender_type, email }))
+}
+
+pub async fn export_config(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Query(params): Query<ExportQuery>,
+) -> Result<Json<ConfigBundle>, ApiError> {
+    ensure_admin(&user)?;
+
+    let mut accounts = load_accounts(&state.db, false).await?;
+    if params.include_secrets {
+        seal_passwords(&state, &mut accounts).await?;
+    }
+
+    let bundle = ConfigBundle {
+        version: BUNDLE_VERSION,
+        exported_at: Some(Utc::now().to_rfc3339()),
+        accounts,
+        aliases: load_aliases(&state.db, false).await?,
+        settings: SettingsConfig {
+            default_sender: load_default_sender(&state.db, false).await?,
+        },
+    };
+
+    Ok(Json(bundle))
+}
+
+// Passwords only leave the server sealed, so a bundle file never holds them in the clear
+async fn seal_passwords(state: &AppState, accounts: &mut [AccountConfig]) -> Result<(), ApiError> {
+    if !state.secrets.is_enabled() {
+        return Err(ApiError::invalid(
+            "includeSecrets",
+            "encryption_key_required",
+            "includeSecrets needs ACCOUNT_ENC_KEY, so exported passwords can be sealed",
+        ));
+    }
+    let rows = sqlx::query("SELECT email, password FROM accounts WHERE deleted_at IS NULL")
+        .fetch_all(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let stored: HashMap<String, String> = rows
+        .into_iter()
+        .map(|row| (row.get::<String, _>(0), row.get::<String, _>(1)))
+        .collect();
+    for account in accounts {
+        let Some(password) = stored.get(&account.email) else {
+            continue;
+        };
+        let sealed = state
+            .secrets
+            .open(password)
+            .and_then(|plain| state.secrets.seal(&plain))
+            .map_err(|e| {
+                tracing::error!(error = %e, email = %account.email, "failed to seal password for export");
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+        account.sealed_password = Some(sealed);
+    }
+    Ok(())
+}
+
+fn account_diff(local: &AccountConfig, incoming: &AccountConfig) -> Vec<&'static str> {
+    let mut fields = Vec::new();
+    if local.display_name != incoming.display_name {
+        fields.push("displayName");
+    }
+    if local.is_active != incoming.is_active {
+        fields.push("isActive");
+    }
+    if local.is_public != incoming.is_public {
+        fields.push("isPublic");
+    }
+    if local.compliance_footer_html != incoming.compliance_footer_html {
+        fields.push("complianceFooterHtml");
+    }
+    if local.compliance_footer_text != incoming.compliance_footer_text {
+        fields.push("complianceFooterText");
+    }
+    if local.daily_send_limit != incoming.daily_send_limit {
+        fields.push("dailySendLimit");
+    }
+    if local.sender_name != incoming.sender_name {
+        fields.push("senderName");
+    }
+    if local.branding_enabled != incoming.branding_enabled {
+        fields.push("brandingEnabled");
+    }
+    if incoming.password.is_some() {
+        fields.push("password");
+    }
+    fields
+}
+
+fn alias_diff(local: &AliasConfig, incoming: &AliasConfig) -> Vec<&'static str> {
+    let mut fields = Vec::new();
+    if local.account_email != incoming.account_email {
+        fields.push("accountEmail");
+    }
+    if local.display_name != incoming.display_name {
+        fields.push("displayName");
+    }
+    if local.is_active != incoming.is_active {
+        fields.push("isActive");
+    }
+    if local.is_public != incoming.is_public {
+        fields.push("isPublic");
+    }
+    if local.compliance_footer_html != incoming.compliance_footer_html {
+        fields.push("complianceFooterHtml");
+    }
+    if local.compliance_footer_text != incoming.compliance_footer_text {
+        fields.push("complianceFooterText");
+    }
+    fields
+}
+
+// Rows carry no modification timestamps, so any divergence from local state is treated
+// as a local change that only `force` may overwrite.
+fn classify(fields: Vec<&'static str>, force: bool) -> (ChangeAction, Vec<&'static str>, Option<String>) {
+    if fields.is_empty() {
+        (ChangeAction::Unchanged, fields, None)
+    } else if force {
+        (ChangeAction::Update, fields, None)
+    } else {
+        (
+            ChangeAction::Conflict,
+            fields,
+            Some("Local values differ; re-run with force=true to overwrite".to_string()),
+        )
+    }
+}
+
+pub async fn import_config(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Query(params): Query<ImportQuery>,
+    Json(mut bundle): Json<ConfigBundle>,
+) -> Result<(StatusCode, Json<ImportReport>), StatusCode> {
+    ensure_admin(&user)?;
+    if bundle.version != BUNDLE_VERSION {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    // Sealed passwords are opened up front and from then on treated like plain ones
+    let mut unsealable = HashSet::new();
+    for account in &mut bundle.accounts {
+        let Some(sealed) = account.sealed_password.take() else {
+            continue;
+        };
+        match state.secrets.open_sealed(&sealed) {
+            Ok(password) => account.password = Some(password),
+            Err(_) => {
+                unsealable.insert(account.email.clone());
+            }
+        }
+    }
+
+    // Dropped without a commit (dry run, conflicts, errors) it just rolls back
+    let mut tx = state
+        .db
+        .begin()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let local_accounts: HashMap<String, AccountConfig> = load_accounts(&mut *tx, true)
+        .await?
+        .into_iter()
+        .map(|a| (a.email.clone(), a))
+        .collect();
+    let local_aliases: HashMap<String, AliasConfig> = load_aliases(&mut *tx, true)
+        .await?
+        .into_iter()
+        .map(|a| (a.alias_email.clone(), a))
+        .collect();
+    let local_default = load_default_sender(&mut *tx, true).await?;
+
+    let mut changes = Vec::new();
+
+    for account in &bundle.accounts {
+        let (action, fields, reason) = match local_accounts.get(&account.email) {
+            _ if unsealable.contains(&account.email) => (
+                ChangeAction::Conflict,
+                Vec::new(),
+                Some("sealedPassword can't be opened with this server's ACCOUNT_ENC_KEY".to_string()),
+            ),
+            None if account.password.is_none() => (
+                ChangeAction::Conflict,
+                Vec::new(),
+                Some("A password is required to create a new account".to_string()),
+            ),
+            None => (ChangeAction::Create, Vec::new(), None),
+            Some(local) => classify(account_diff(local, account), params.force),
+        };
+        changes.push(ConfigChange {
+            kind: "account",
+            key: account.email.clone(),
+            action,
+            fields,
+            reason,
+        });
+    }
+
+    for alias in &bundle.aliases {
+        let account_known = local_accounts.contains_key(&alias.account_email)
+            || bundle.accounts.iter().any(|a| a.email == alias.account_email);
+        let (action, fields, reason) = if !account_known {
+            (
+                ChangeAction::Conflict,
+                Vec::new(),
+                Some(format!("Account {} does not exist", alias.account_email)),
+            )
+        } else {
+            match local_aliases.get(&alias.alias_email) {
+                None => (ChangeAction::Create, Vec::new(), None),
+                Some(local) => classify(alias_diff(local, alias), params.force),
+            }
+        };
+        changes.push(ConfigChange {
+            kind: "alias",
+            key: alias.alias_email.clone(),
+            action,
+            fields,
+            reason,
+        });
+    }
+
+    if let Some(incoming) = &bundle.settings.default_sender {
+        let sender_known = match incoming.sender_type {
+            SenderKind::Account => {
+                local_accounts.contains_key(&incoming.email)
+                    || bundle.accounts.iter().any(|a| a.email == incoming.email)
+            }
+            SenderKind::Alias => {
+                local_aliases.contains_key(&incoming.email)
+                    || bundle.aliases.iter().any(|a| a.alias_email == incoming.email)
+            }
+        };
+        let (action, fields, reason) = if !sender_known {
+            (
+                ChangeAction::Conflict,
+                Vec::new(),
+                Some(format!("Sender {} does not exist", incoming.email)),
+            )
+        } else {
+            match &local_default {
+                None => (ChangeAction::Create, Vec::new(), None),
+                Some(local) if local == incoming => (ChangeAction::Unchanged, Vec::new(), None),
+                Some(_) => classify(vec!["defaultSender"], params.force),
+            }
+        };
+        changes.push(ConfigChange {
+            kind: "setting",
+            key: "defaultSender".to_string(),
+            action,
+            fields,
+            reason,
+        });
+    }
+
+    let has_conflicts = changes.iter().any(|c| c.action == ChangeAction::Conflict);
+    if params.dry_run || has_conflicts {
+        let status = if has_conflicts && !params.dry_run {
+            StatusCode::CONFLICT
+        } else {
+            StatusCode::OK
+        };
+        return Ok((
+            status,
+            Json(ImportReport {
+                dry_run: params.dry_run,
+                applied: false,
+                changes,
+            }),
+        ));
+    }
+
+    let applied = apply_changes(&mut tx, &state.secrets, &user, &bundle, &changes)
+        .await
+        .map_err(|e| {
+            tracing::error!(error = %e, user_id = %user.id, "config import failed");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    tx.commit()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    for change in &applied {
+        let detail = serde_json::json!({ "key": change.key, "action": change.action, "fields": change.fields });
+        audit::record(&state.db, &user, "config.import", change.kind, &change.target_id, detail).await;
+    }
+
+    tracing::info!(
+        user_id = %user.id,
+        email = %user.email,
+        changes = changes
+            .iter()
+            .filter(|c| matches!(c.action, ChangeAction::Create | ChangeAction::Update))
+            .count(),
+        "config import applied"
+    );
+
+    Ok((
+        StatusCode::OK,
+        Json(ImportReport {
+            dry_run: false,
+            applied: true,
+            changes,
+        }),
+    ))
+}
+
+// A create or update that was written, for the audit log
+struct AppliedChange<'a> {
+    kind: &'static str,
+    key: &'a str,
+    action: ChangeAction,
+    fields: &'a [&'static str],
+    target_id: String,
+}
+
+async fn apply_changes<'a>(
+    tx: &mut Transaction<'_, Postgres>,
+    secrets: &SecretBox,
+    user: &AuthUser,
+    bundle: &ConfigBundle,
+    changes: &'a [ConfigChange],
+) -> anyhow::Result<Vec<AppliedChange<'a>>> {
+    let change_for = |kind: &str, key: &str| changes.iter().find(|c| c.kind == kind && c.key == key);
+    let action_for = |kind: &str, key: &str| change_for(kind, key).map(|c| c.action);
+    let mut applied = Vec::new();
+    let mut record = |kind: &'static str, key: &str, target_id: String| {
+        if let Some(change) = change_for(kind, key) {
+            applied.push(AppliedChange {
+                kind,
+                key: &change.key,
+                action: change.action,
+                fields: &change.fields,
+                target_id,
+            });
+        }
+    };
+
+    for account in &bundle.accounts {
+        match action_for("account", &account.email) {
+            Some(ChangeAction::Create) => {
+                // A soft-deleted account with the same email is revived rather than duplicated
+                let id: String = sqlx::query_scalar(
+                    r#"
+                    INSERT INTO accounts (id, email, display_name, password, is_active, owner_id, is_public, compliance_footer_html, compliance_footer_text, daily_send_limit, sender_name, branding_enabled)
+                    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $11, $12, $13)
+                    ON CONFLICT (email) DO UPDATE SET
+                        display_name = EXCLUDED.display_name,
+                        password = CASE WHEN $10 THEN EXCLUDED.password ELSE accounts.password END,
+                        is_active = EXCLUDED.is_active,
+                        is_public = EXCLUDED.is_public,
+                        compliance_footer_html = EXCLUDED.compliance_footer_html,
+                        compliance_footer_text = EXCLUDED.compliance_footer_text,
+                        daily_send_limit = EXCLUDED.daily_send_limit,
+                        sender_name = EXCLUDED.sender_name,
+                        branding_enabled = EXCLUDED.branding_enabled,
+                        deleted_at = NULL
+                    RETURNING id
+                    "#,
+                )
+                .bind(Uuid::new_v4().to_string())
+                .bind(&account.email)
+                .bind(&account.display_name)
+                .bind(secrets.seal(account.password.as_deref().unwrap_or_default())?)
+                .bind(account.is_active)
+                .bind(&user.id)
+                .bind(account.is_public)
+                .bind(&account.compliance_footer_html)
+                .bind(&account.compliance_footer_text)
+                .bind(account.password.is_some())
+                .bind(account.daily_send_limit.filter(|limit| *limit > 0))
+                .bind(&account.sender_name)
+                .bind(account.branding_enabled)
+                .fetch_one(&mut **tx)
+                .await?;
+                record("account", &account.email, id);
+            }
+            Some(ChangeAction::Update) => {
+                let id: String = sqlx::query_scalar(
+                    "UPDATE accounts SET display_name = $1, is_active = $2, is_public = $3, compliance_footer_html = $4, compliance_footer_text = $5, password = COALESCE($6, password), daily_send_limit = $8, sender_name = $9, branding_enabled = $10 WHERE email = $7 RETURNING id",
+                )
+                .bind(&account.display_name)
+                .bind(account.is_active)
+                .bind(account.is_public)
+                .bind(&account.compliance_footer_html)
+                .bind(&account.compliance_footer_text)
+                .bind(account.password.as_deref().map(|p| secrets.seal(p)).transpose()?)
+                .bind(&account.email)
+                .bind(account.daily_send_limit.filter(|limit| *limit > 0))
+                .bind(&account.sender_name)
+                .bind(account.branding_enabled)
+                .fetch_one(&mut **tx)
+                .await?;
+                record("account", &account.email, id);
+            }
+            _ => {}
+        }
+    }
+
+    for alias in &bundle.aliases {
+        match action_for("alias", &alias.alias_email) {
+            Some(ChangeAction::Create) => {
+                let id: Option<String> = sqlx::query_scalar(
+                    r#"
+                    INSERT INTO aliases (id, alias_email, display_name, is_active, account_id, owner_id, is_public, compliance_footer_html, compliance_footer_text, is_catchall, catchall_domain)
+                    SELECT $1, $2, $3, $4, accounts.id, $5, $6, $7, $8, $10, $11 FROM accounts WHERE accounts.email = $9
+                    ON CONFLICT (alias_email) DO UPDATE SET
+                        account_id = EXCLUDED.account_id,
+                        display_name = EXCLUDED.display_name,
+                        is_active = EXCLUDED.is_active,
+                        is_public = EXCLUDED.is_public,
+                        compliance_footer_html = EXCLUDED.compliance_footer_html,
+                        compliance_footer_text = EXCLUDED.compliance_footer_text
+                    RETURNING id
+                    "#,
+                )
+                .bind(Uuid::new_v4().to_string())
+                .bind(&alias.alias_email)
+                .bind(&alias.display_name)
+                .bind(alias.is_active)
+                .bind(&user.id)
+                .bind(alias.is_public)
+                .bind(&alias.compliance_footer_html)
+                .bind(&alias.compliance_footer_text)
+                .bind(&alias.account_email)
+                .bind(catchall_domain(&alias.alias_email).is_some())
+                .bind(catchall_domain(&alias.alias_email))
+                .fetch_optional(&mut **tx)
+                .await?;
+                if let Some(id) = id {
+                    record("alias", &alias.alias_email, id);
+                }
+            }
+            Some(ChangeAction::Update) => {
+                let id: String = sqlx::query_scalar(
+                    r#"
+                    UPDATE aliases
+                    SET account_id = (SELECT id FROM accounts WHERE email = $1),
+                        display_name = $2,
+                        is_active = $3,
+                        is_public = $4,
+                        compliance_footer_html = $5,
+                        compliance_footer_text = $6
+                    WHERE alias_email = $7
+                    RETURNING id
+                    "#,
+                )
+                .bind(&alias.account_email)
+                .bind(&alias.display_name)
+                .bind(alias.is_active)
+                .bind(alias.is_public)
+                .bind(&alias.compliance_footer_html)
+                .bind(&alias.compliance_footer_text)
+                .bind(&alias.alias_email)
+                .fetch_one(&mut **tx)
+                .await?;
+                record("alias", &alias.alias_email, id);
+            }
+            _ => {}
+        }
+    }
+
+    if let Some(sender) = &bundle.settings.default_sender {
+        if matches!(
+            action_for("setting", "defaultSender"),
+            Some(ChangeAction::Create | ChangeAction::Update)
+        ) {
+            let lookup = match sender.sender_type {
+                SenderKind::Account => "SELECT id FROM accounts WHERE email = $1",
+                SenderKind::Alias => "SELECT id FROM aliases WHERE alias_email = $1",
+            };
+            let sender_id: String = sqlx::query_scalar(lookup)
+                .bind(&sender.email)
+                .fetch_one(&mut **tx)
+                .await?;
+            sqlx::query(
+                r#"
+                INSERT INTO default_sender (singleton, sender_type, sender_id)
+                VALUES (1, $1, $2)
+                ON CONFLICT(singleton) DO UPDATE SET sender_type = excluded.sender_type, sender_id = excluded.sender_id
+                "#,
+            )
+            .bind(sender.sender_type.as_str())
+            .bind(&sender_id)
+            .execute(&mut **tx)
+            .await?;
+            record("setting", "defaultSender", sender_id);
+        }
+    }
+
+    Ok(applied)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::Method;
+
+    use super::*;
+    use crate::test_support;
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn applied_imports_are_audited() {
+        let state = test_support::state().await;
+        let admin = test_support::user(&state.db, "admin").await;
+        let sender = test_support::account(&state.db, None).await;
+        let app = crate::router(state.clone(), 1024 * 1024);
+
+        let bundle = serde_json::json!({
+            "version": 1,
+            "accounts": [{
+                "email": sender.email,
+                "displayName": "Renamed",
+                "isActive": true,
+                "isPublic": false
+            }]
+        })
+        .to_string();
+        let (status, json) =
+            test_support::call(app, Method::POST, "/api/admin/config/import?force=true", &admin.token, bundle).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(json["applied"], true);
+
+        let detail: serde_json::Value = sqlx::query_scalar(
+            "SELECT detail FROM audit_log WHERE action = 'config.import' AND target_type = 'account' AND target_id = $1",
+        )
+        .bind(&sender.id)
+        .fetch_one(&state.db)
+        .await
+        .unwrap();
+        assert_eq!(detail["action"], "update");
+        assert_eq!(detail["fields"], serde_json::json!(["displayName"]));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn sealed_passwords_survive_an_export_and_import() {
+        let mut state = test_support::state().await;
+        state.secrets = SecretBox::with_key([7; 32]);
+        let admin = test_support::user(&state.db, "admin").await;
+        let sender = test_support::account(&state.db, None).await;
+        let app = crate::router(state.clone(), 1024 * 1024);
+
+        let (status, json) = test_support::call(
+            app.clone(),
+            Method::GET,
+            "/api/admin/config/export?includeSecrets=true",
+            &admin.token,
+            "",
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let mut exported = json["accounts"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|a| a["email"] == sender.email.as_str())
+            .cloned()
+            .unwrap();
+        assert!(exported["sealedPassword"].as_str().unwrap().starts_with("enc:v1:"));
+
+        // Import it back as a new account under another address
+        let email = format!("copy-{}", sender.email);
+        exported["email"] = email.clone().into();
+        let bundle = serde_json::json!({ "version": 1, "accounts": [exported] }).to_string();
+        let (status, _) =
+            test_support::call(app, Method::POST, "/api/admin/config/import", &admin.token, bundle.clone()).await;
+        assert_eq!(status, StatusCode::OK);
+        let stored: String = sqlx::query_scalar("SELECT password FROM accounts WHERE email = $1")
+            .bind(&email)
+            .fetch_one(&state.db)
+            .await
+            .unwrap();
+        assert_eq!(state.secrets.open(&stored).unwrap(), "secret");
+
+        // An instance with a different key can't use it
+        let mut other = state.clone();
+        other.secrets = SecretBox::with_key([8; 32]);
+        let (status, json) = test_support::call(
+            crate::router(other, 1024 * 1024),
+            Method::POST,
+            "/api/admin/config/import?force=true",
+            &admin.token,
+            bundle,
+        )
+        .await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(json["changes"][0]["action"], "conflict");
+    }
+}
@@ -0,0 +1,248 @@
+// Admin spreadsheets of every account and alias. Rows are written to the response as the
+// database returns them, so an export never sits in memory as a whole. Also parses the
+// CSV form of POST /api/accounts/import.
+
+use axum::{
+    body::Body,
+    extract::State,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+use futures::StreamExt;
+use sqlx::{postgres::PgRow, PgPool, Row};
+use tokio::sync::mpsc;
+
+use crate::{
+    auth::{AuthUser, UserRole, SCOPE_ACCOUNTS_READ},
+    error::ApiError,
+    AppState, CreateAccountRequest,
+};
+
+// Rows buffered between the query and a slow client
+const ROW_BUFFER: usize = 64;
+
+const ACCOUNTS_HEADER: &str =
+    "id,email,displayName,senderName,isActive,isPublic,ownerEmail,dailySendLimit,brandingEnabled,createdAt\r\n";
+const ACCOUNTS_QUERY: &str = r#"
+    SELECT accounts.id, accounts.email, accounts.display_name, accounts.sender_name, accounts.is_active,
+           accounts.is_public, users.email, accounts.daily_send_limit, accounts.branding_enabled,
+           accounts.created_at
+    FROM accounts
+    LEFT JOIN users ON users.id = accounts.owner_id
+    WHERE accounts.deleted_at IS NULL
+    ORDER BY accounts.email ASC
+"#;
+
+const ALIASES_HEADER: &str = "id,aliasEmail,displayName,isActive,isPublic,isCatchall,ownerEmail,accountEmail,accountDisplayName,accountIsActive,createdAt\r\n";
+const ALIASES_QUERY: &str = r#"
+    SELECT aliases.id, aliases.alias_email, aliases.display_name, aliases.is_active, aliases.is_public,
+           aliases.is_catchall, users.email, accounts.email, accounts.display_name, accounts.is_active,
+           aliases.created_at
+    FROM aliases
+    JOIN accounts ON aliases.account_id = accounts.id
+    LEFT JOIN users ON users.id = aliases.owner_id
+    WHERE accounts.deleted_at IS NULL
+    ORDER BY aliases.alias_email ASC
+"#;
+
+fn ensure_admin(user: &AuthUser) -> Result<(), ApiError> {
+    user.ensure_password_updated()?;
+    user.ensure_scope(SCOPE_ACCOUNTS_READ)?;
+    if !matches!(user.role, UserRole::Admin) {
+        return Err(StatusCode::FORBIDDEN.into());
+    }
+    Ok(())
+}
+
+pub async fn export_accounts(State(state): State<AppState>, user: AuthUser) -> Result<Response, ApiError> {
+    ensure_admin(&user)?;
+    Ok(csv_response("accounts.csv", ACCOUNTS_HEADER, state.db, ACCOUNTS_QUERY, |row| {
+        csv_line(&[
+            row.get::<String, _>(0),
+            row.get::<String, _>(1),
+            row.get::<String, _>(2),
+            row.get::<Option<String>, _>(3).unwrap_or_default(),
+            row.get::<bool, _>(4).to_string(),
+            row.get::<bool, _>(5).to_string(),
+            row.get::<Option<String>, _>(6).unwrap_or_default(),
+            row.get::<Option<i64>, _>(7).map(|limit| limit.to_string()).unwrap_or_default(),
+            row.get::<bool, _>(8).to_string(),
+            row.get::<i64, _>(9).to_string(),
+        ])
+    }))
+}
+
+pub async fn export_aliases(State(state): State<AppState>, user: AuthUser) -> Result<Response, ApiError> {
+    ensure_admin(&user)?;
+    Ok(csv_response("aliases.csv", ALIASES_HEADER, state.db, ALIASES_QUERY, |row| {
+        csv_line(&[
+            row.get::<String, _>(0),
+            row.get::<String, _>(1),
+            row.get::<Option<String>, _>(2).unwrap_or_default(),
+            row.get::<bool, _>(3).to_string(),
+            row.get::<bool, _>(4).to_string(),
+            row.get::<bool, _>(5).to_string(),
+            row.get::<Option<String>, _>(6).unwrap_or_default(),
+            row.get::<String, _>(7),
+            row.get::<String, _>(8),
+            row.get::<bool, _>(9).to_string(),
+            row.get::<i64, _>(10).to_string(),
+        ])
+    }))
+}
+
+// The query runs in its own task and hands lines over a bounded channel, so it only reads
+// ahead of the client by ROW_BUFFER rows. A database error mid-way ends the body with an
+// error, which aborts the download instead of leaving a silently truncated file.
+fn csv_response(
+    filename: &'static str,
+    header_line: &'static str,
+    db: PgPool,
+    query: &'static str,
+    line: fn(&PgRow) -> String,
+) -> Response {
+    let (tx, rx) = mpsc::channel::<Result<String, sqlx::Error>>(ROW_BUFFER);
+    tokio::spawn(async move {
+        let mut rows = sqlx::query(query).fetch(&db);
+        while let Some(row) = rows.next().await {
+            let item = row.map(|row| line(&row));
+            if let Err(e) = &item {
+                tracing::error!(error = %e, file = filename, "CSV export failed");
+            }
+            let failed = item.is_err();
+            // A closed channel means the client went away
+            if tx.send(item).await.is_err() || failed {
+                break;
+            }
+        }
+    });
+
+    let rows = futures::stream::unfold(rx, |mut rx| async move { rx.recv().await.map(|item| (item, rx)) });
+    let body = futures::stream::once(async move { Ok(header_line.to_string()) }).chain(rows);
+    (
+        [
+            (header::CONTENT_TYPE, "text/csv; charset=utf-8".to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", filename)),
+        ],
+        Body::from_stream(body),
+    )
+        .into_response()
+}
+
+// RFC 4180 line. Values a spreadsheet would run as a formula get a leading quote.
+fn csv_line(fields: &[String]) -> String {
+    let mut line = fields.iter().map(|field| csv_field(field)).collect::<Vec<_>>().join(",");
+    line.push_str("\r\n");
+    line
+}
+
+fn csv_field(value: &str) -> String {
+    let value = if value.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        format!("'{}", value)
+    } else {
+        value.to_string()
+    };
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
+
+// Column positions in an import's header row
+struct AccountColumns {
+    email: usize,
+    display_name: usize,
+    password: usize,
+    is_active: Option<usize>,
+    is_public: Option<usize>,
+    sender_name: Option<usize>,
+}
+
+/// Accounts from an import CSV whose header row names the columns (in any order, any
+/// case): email, displayName and password are required; isActive (default true),
+/// isPublic (default false) and senderName are optional. A malformed row becomes an
+/// error for that row only; a missing column or broken quoting fails the whole file.
+pub fn parse_account_rows(text: &str) -> Result<Vec<Result<CreateAccountRequest, String>>, String> {
+    let mut records = parse_records(text)?.into_iter();
+    let header = records.next().ok_or("The CSV is empty")?;
+    let column = |name: &str| header.iter().position(|h| h.trim().eq_ignore_ascii_case(name));
+    let (Some(email), Some(display_name), Some(password)) =
+        (column("email"), column("displayName"), column("password"))
+    else {
+        return Err("The header row needs email, displayName and password columns".to_string());
+    };
+    let columns = AccountColumns {
+        email,
+        display_name,
+        password,
+        is_active: column("isActive"),
+        is_public: column("isPublic"),
+        sender_name: column("senderName"),
+    };
+    Ok(records.map(|record| account_from_record(&record, &columns)).collect())
+}
+
+fn account_from_record(record: &[String], columns: &AccountColumns) -> Result<CreateAccountRequest, String> {
+    let cell = |index: Option<usize>| index.and_then(|i| record.get(i)).map(String::as_str).unwrap_or_default();
+    Ok(CreateAccountRequest {
+        email: cell(Some(columns.email)).trim().to_string(),
+        display_name: cell(Some(columns.display_name)).trim().to_string(),
+        // Taken as written; spaces can be part of a password
+        password: cell(Some(columns.password)).to_string(),
+        is_active: parse_bool("isActive", cell(columns.is_active), true)?,
+        is_public: parse_bool("isPublic", cell(columns.is_public), false)?,
+        sender_name: Some(cell(columns.sender_name).trim().to_string()).filter(|name| !name.is_empty()),
+    })
+}
+
+fn parse_bool(column: &str, value: &str, default: bool) -> Result<bool, String> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "" => Ok(default),
+        "true" | "yes" | "1" => Ok(true),
+        "false" | "no" | "0" => Ok(false),
+        _ => Err(format!("{} must be true or false, got {:?}", column, value)),
+    }
+}
+
+// RFC 4180 records. Quoted fields may hold commas, doubled quotes and line breaks; blank
+// lines and a leading byte order mark are ignored.
+fn parse_records(text: &str) -> Result<Vec<Vec<String>>, String> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = text.trim_start_matches('\u{feff}').chars().peekable();
+    while let Some(c) = chars.next() {
+        if in_quotes {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    field.push('"');
+                    chars.next();
+                }
+                '"' => in_quotes = false,
+                _ => field.push(c),
+            }
+            continue;
+        }
+        match c {
+            '"' => in_quotes = true,
+            ',' => record.push(std::mem::take(&mut field)),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\r' | '\n' => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            _ => field.push(c),
+        }
+    }
+    if in_quotes {
+        return Err("The CSV has an unterminated quoted field".to_string());
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    records.retain(|record| !(record.len() == 1 && record[0].trim().is_empty()));
+    Ok(records)
+}
@@ -0,0 +1,237 @@
+// Server-side drafts for the compose form. A draft is only ever visible to the user
+// who saved it; sending one through /api/send (draftId) deletes it.
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::Json,
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sqlx::{postgres::PgRow, PgPool, Row};
+use uuid::Uuid;
+
+use crate::{
+    auth::{AuthUser, UserRole, SCOPE_SEND},
+    handlers::page_bounds,
+    AppState, ListParams, Page,
+};
+
+const DRAFT_COLUMNS: &str =
+    "id, from_email, to_recipients, cc_recipients, bcc_recipients, subject, body, updated_at";
+
+#[derive(Serialize)]
+pub struct Draft {
+    pub id: String,
+    pub from: String,
+    pub to: String,
+    pub cc: Option<String>,
+    pub bcc: Option<String>,
+    pub subject: String,
+    pub body: String,
+    // Unix seconds
+    #[serde(rename = "updatedAt")]
+    pub updated_at: i64,
+}
+
+// Used for both create and update; on PATCH only the fields present change.
+// An empty cc or bcc clears it.
+#[derive(Deserialize)]
+pub struct DraftRequest {
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub cc: Option<String>,
+    pub bcc: Option<String>,
+    pub subject: Option<String>,
+    pub body: Option<String>,
+}
+
+fn draft_from_row(row: &PgRow) -> Draft {
+    Draft {
+        id: row.get(0),
+        from: row.get(1),
+        to: row.get(2),
+        cc: row.get(3),
+        bcc: row.get(4),
+        subject: row.get(5),
+        body: row.get(6),
+        updated_at: row.get(7),
+    }
+}
+
+fn ensure_composer(user: &AuthUser) -> Result<(), StatusCode> {
+    user.ensure_password_updated()?;
+    user.ensure_scope(SCOPE_SEND)?;
+    if !matches!(user.role, UserRole::Dev | UserRole::Admin) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    Ok(())
+}
+
+/// One of `user_id`'s drafts, or None if it doesn't exist or belongs to someone else.
+pub async fn find(db: &PgPool, user_id: &str, id: &str) -> sqlx::Result<Option<Draft>> {
+    let row = sqlx::query(&format!(
+        "SELECT {} FROM drafts WHERE id = $1 AND user_id = $2",
+        DRAFT_COLUMNS
+    ))
+    .bind(id)
+    .bind(user_id)
+    .fetch_optional(db)
+    .await?;
+    Ok(row.as_ref().map(draft_from_row))
+}
+
+/// Called once a draft has been sent. Failures are logged: the mail has already gone out.
+pub async fn discard_sent(db: &PgPool, user_id: &str, id: &str) {
+    if let Err(e) = sqlx::query("DELETE FROM drafts WHERE id = $1 AND user_id = $2")
+        .bind(id)
+        .bind(user_id)
+        .execute(db)
+        .await
+    {
+        tracing::error!(error = %e, %user_id, draft_id = %id, "failed to delete sent draft");
+    }
+}
+
+pub async fn list_drafts(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Query(params): Query<ListParams>,
+) -> Result<Json<Page<Draft>>, StatusCode> {
+    ensure_composer(&user)?;
+    let (limit, offset) = page_bounds(params.limit, params.offset);
+
+    let total: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM drafts WHERE user_id = $1")
+        .bind(&user.id)
+        .fetch_one(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let rows = sqlx::query(&format!(
+        "SELECT {} FROM drafts WHERE user_id = $1 ORDER BY updated_at DESC, id LIMIT $2 OFFSET $3",
+        DRAFT_COLUMNS
+    ))
+    .bind(&user.id)
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(&state.db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(Page {
+        items: rows.iter().map(draft_from_row).collect(),
+        total,
+        limit,
+        offset,
+    }))
+}
+
+pub async fn get_draft(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+) -> Result<Json<Draft>, StatusCode> {
+    ensure_composer(&user)?;
+    find(&state.db, &user.id, &id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+pub async fn create_draft(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(req): Json<DraftRequest>,
+) -> Result<(StatusCode, Json<Draft>), StatusCode> {
+    ensure_composer(&user)?;
+
+    let row = sqlx::query(&format!(
+        r#"
+        INSERT INTO drafts (id, user_id, from_email, to_recipients, cc_recipients, bcc_recipients, subject, body, updated_at)
+        VALUES ($1, $2, $3, $4, NULLIF($5, ''), NULLIF($6, ''), $7, $8, $9)
+        RETURNING {}
+        "#,
+        DRAFT_COLUMNS
+    ))
+    .bind(Uuid::new_v4().to_string())
+    .bind(&user.id)
+    .bind(req.from.unwrap_or_default())
+    .bind(req.to.unwrap_or_default())
+    .bind(req.cc)
+    .bind(req.bcc)
+    .bind(req.subject.unwrap_or_default())
+    .bind(req.body.unwrap_or_default())
+    .bind(Utc::now().timestamp())
+    .fetch_one(&state.db)
+    .await
+    .map_err(|e| {
+        tracing::error!(error = %e, user_id = %user.id, "failed to create draft");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok((StatusCode::CREATED, Json(draft_from_row(&row))))
+}
+
+pub async fn update_draft(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+    Json(req): Json<DraftRequest>,
+) -> Result<Json<Draft>, StatusCode> {
+    ensure_composer(&user)?;
+
+    let row = sqlx::query(&format!(
+        r#"
+        UPDATE drafts SET
+            from_email = COALESCE($3, from_email),
+            to_recipients = COALESCE($4, to_recipients),
+            cc_recipients = CASE WHEN $5::TEXT IS NULL THEN cc_recipients ELSE NULLIF($5, '') END,
+            bcc_recipients = CASE WHEN $6::TEXT IS NULL THEN bcc_recipients ELSE NULLIF($6, '') END,
+            subject = COALESCE($7, subject),
+            body = COALESCE($8, body),
+            updated_at = $9
+        WHERE id = $1 AND user_id = $2
+        RETURNING {}
+        "#,
+        DRAFT_COLUMNS
+    ))
+    .bind(&id)
+    .bind(&user.id)
+    .bind(req.from)
+    .bind(req.to)
+    .bind(req.cc)
+    .bind(req.bcc)
+    .bind(req.subject)
+    .bind(req.body)
+    .bind(Utc::now().timestamp())
+    .fetch_optional(&state.db)
+    .await
+    .map_err(|e| {
+        tracing::error!(error = %e, user_id = %user.id, draft_id = %id, "failed to update draft");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?
+    .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(draft_from_row(&row)))
+}
+
+pub async fn delete_draft(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+) -> Result<StatusCode, StatusCode> {
+    ensure_composer(&user)?;
+
+    let result = sqlx::query("DELETE FROM drafts WHERE id = $1 AND user_id = $2")
+        .bind(&id)
+        .bind(&user.id)
+        .execute(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if result.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
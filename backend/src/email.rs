@@ -1,16 +1,37 @@
-// Email service implementation using Microsoft SMTP/IMAP/POP3
-// This module will handle email operations
+// Outbound mail over SMTP (Microsoft by default); IMAP reads live in inbox.rs
 
+use std::{error::Error as _, fmt, sync::Arc, time::Duration};
+
+use anyhow::anyhow;
 use lettre::{
-    message::{header::ContentType, Attachment, Mailbox, Message, MultiPart, SinglePart},
-    transport::smtp::authentication::Credentials,
-    AsyncSmtpTransport, AsyncTransport, Tokio1Executor,
+    address::Envelope,
+    message::{
+        dkim::{
+            DkimCanonicalization, DkimCanonicalizationType, DkimConfig, DkimSigningAlgorithm,
+            DkimSigningKey,
+        },
+        header::{ContentType, HeaderName, HeaderValue},
+        Attachment, Mailbox, Message, MultiPart, SinglePart,
+    },
+    transport::smtp::{
+        authentication::{Credentials, Mechanism},
+        client::{AsyncSmtpConnection, TlsParameters},
+        Error as SmtpError,
+        commands::{Data, Mail, Rcpt, Rset},
+        extension::{ClientId, MailBodyParameter, MailParameter},
+    },
+};
+use base64::{
+    engine::general_purpose::{STANDARD as Base64, STANDARD_NO_PAD, URL_SAFE_NO_PAD},
+    Engine,
 };
-use base64::{engine::general_purpose::STANDARD as Base64, Engine};
 use regex::Regex;
+use serde::Serialize;
+
+use crate::smtp_pool::{PoolKey, SmtpPool};
 
 // Simple HTML escape function
-fn html_escape(input: &str) -> String {
+pub fn html_escape(input: &str) -> String {
     input
         .replace('&', "&amp;")
         .replace('<', "&lt;")
@@ -19,13 +40,661 @@ fn html_escape(input: &str) -> String {
         .replace('\'', "&#x27;")
 }
 
-pub struct EmailService;
+pub struct EmailService {
+    smtp: SmtpConfig,
+    pool: SmtpPool,
+}
 
-// Render email body with W9 Mail branding template (matching w9-tools design)
-pub fn render_email_template(body: &str) -> String {
-    // Check if body is already a complete HTML document
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmtpTlsMode {
+    // Plain connection upgraded with STARTTLS (submission, port 587)
+    StartTls,
+    // TLS from the first byte (SMTPS, port 465)
+    Tls,
+    // No encryption; only for relays on a trusted network
+    None,
+}
+
+impl SmtpTlsMode {
+    // SMTP_TLS as set, if at all. Blank means the usual mode for the port: implicit TLS
+    // on 465, STARTTLS anywhere else.
+    fn resolve(port: u16, setting: Option<&str>) -> anyhow::Result<Self> {
+        match setting.map(str::trim) {
+            None | Some("") => Ok(if port == 465 { Self::Tls } else { Self::StartTls }),
+            Some(v) => match v.to_ascii_lowercase().as_str() {
+                "starttls" => Ok(Self::StartTls),
+                "tls" | "ssl" | "implicit" => Ok(Self::Tls),
+                "none" | "off" => Ok(Self::None),
+                _ => Err(anyhow!("SMTP_TLS must be one of starttls, tls, none; got {:?}", v)),
+            },
+        }
+    }
+}
+
+// How the sending account authenticates to the SMTP server
+#[derive(Debug, Clone)]
+pub enum SmtpAuth {
+    Password(String),
+    // Microsoft access token, sent with the XOAUTH2 mechanism
+    OAuth2(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct SmtpConfig {
+    pub host: String,
+    pub port: u16,
+    pub tls: SmtpTlsMode,
+    // Attempts per send, including the first
+    pub max_attempts: u32,
+    // Bound on connecting and on each whole SMTP session (SMTP_TIMEOUT_SECS)
+    pub timeout: Duration,
+    // None unless DKIM_PRIVATE_KEY_PATH is set
+    pub dkim: Option<Arc<DkimSigner>>,
+}
+
+impl SmtpConfig {
+    // SMTP_HOST / SMTP_PORT / SMTP_TLS, defaulting to Outlook submission. Without an
+    // explicit SMTP_TLS, port 465 means implicit TLS and anything else STARTTLS.
+    pub fn from_env() -> anyhow::Result<Self> {
+        let host = std::env::var("SMTP_HOST")
+            .ok()
+            .filter(|v| !v.trim().is_empty())
+            .unwrap_or_else(|| "smtp-mail.outlook.com".to_string());
+        let port = match std::env::var("SMTP_PORT") {
+            Ok(v) if !v.trim().is_empty() => v
+                .trim()
+                .parse()
+                .map_err(|_| anyhow!("SMTP_PORT must be a port number, got {:?}", v))?,
+            _ => 587,
+        };
+        let tls = SmtpTlsMode::resolve(port, std::env::var("SMTP_TLS").ok().as_deref())?;
+        // Either mismatch makes every session stall until SMTP_TIMEOUT_SECS, one side
+        // waiting for a greeting and the other for a TLS handshake
+        if matches!((port, tls), (465, SmtpTlsMode::StartTls) | (587, SmtpTlsMode::Tls)) {
+            tracing::warn!(port, tls = ?tls, "SMTP_TLS doesn't match the usual mode for SMTP_PORT (465 is implicit TLS, 587 STARTTLS)");
+        }
+        // SMTP_MAX_RETRIES counts retries after the first attempt
+        let retries: u32 = match std::env::var("SMTP_MAX_RETRIES") {
+            Ok(v) if !v.trim().is_empty() => v
+                .trim()
+                .parse()
+                .map_err(|_| anyhow!("SMTP_MAX_RETRIES must be a number, got {:?}", v))?,
+            _ => 2,
+        };
+        let timeout_secs: u64 = match std::env::var("SMTP_TIMEOUT_SECS") {
+            Ok(v) if !v.trim().is_empty() => v
+                .trim()
+                .parse()
+                .ok()
+                .filter(|secs| *secs > 0)
+                .ok_or_else(|| anyhow!("SMTP_TIMEOUT_SECS must be a positive number of seconds, got {:?}", v))?,
+            _ => 30,
+        };
+        Ok(Self {
+            host,
+            port,
+            tls,
+            max_attempts: retries + 1,
+            timeout: Duration::from_secs(timeout_secs),
+            dkim: DkimSigner::from_env()?.map(Arc::new),
+        })
+    }
+}
+
+// Headers covered by the signature. Ones a message doesn't carry are signed as empty,
+// which also stops them being added in transit.
+const DKIM_SIGNED_HEADERS: &[&str] = &[
+    "From",
+    "To",
+    "Cc",
+    "Reply-To",
+    "Subject",
+    "Date",
+    "Message-ID",
+    "In-Reply-To",
+    "References",
+    "List-Unsubscribe",
+    "List-Unsubscribe-Post",
+    "MIME-Version",
+    "Content-Type",
+];
+
+/// Signs outbound mail whose From domain is `DKIM_DOMAIN`; other senders go out unsigned.
+pub struct DkimSigner {
+    domain: String,
+    selector: String,
+    config: DkimConfig,
+}
+
+impl fmt::Debug for DkimSigner {
+    // Keeps the private key out of logs
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DkimSigner")
+            .field("domain", &self.domain)
+            .field("selector", &self.selector)
+            .finish_non_exhaustive()
+    }
+}
+
+impl DkimSigner {
+    // DKIM_PRIVATE_KEY_PATH (PKCS#1 RSA PEM), DKIM_SELECTOR and DKIM_DOMAIN. Without a
+    // key path signing is off; with one, a missing selector/domain or unreadable key
+    // fails startup rather than silently sending unsigned mail.
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        let Some(key_path) = env_non_empty("DKIM_PRIVATE_KEY_PATH") else {
+            return Ok(None);
+        };
+        let selector = env_non_empty("DKIM_SELECTOR")
+            .ok_or_else(|| anyhow!("DKIM_SELECTOR is required when DKIM_PRIVATE_KEY_PATH is set"))?;
+        let domain = env_non_empty("DKIM_DOMAIN")
+            .ok_or_else(|| anyhow!("DKIM_DOMAIN is required when DKIM_PRIVATE_KEY_PATH is set"))?
+            .to_ascii_lowercase();
+
+        let pem = std::fs::read_to_string(&key_path)
+            .map_err(|e| anyhow!("failed to read DKIM key {}: {}", key_path, e))?;
+        let key = DkimSigningKey::new(&pem, DkimSigningAlgorithm::Rsa).map_err(|e| {
+            anyhow!(
+                "DKIM key {} is not a PKCS#1 RSA PEM (\"BEGIN RSA PRIVATE KEY\"): {}",
+                key_path,
+                e
+            )
+        })?;
+
+        // Relaxed on both sides survives the whitespace and case changes relays make
+        let config = DkimConfig::new(
+            selector.clone(),
+            domain.clone(),
+            key,
+            DKIM_SIGNED_HEADERS
+                .iter()
+                .map(|name| HeaderName::new_from_ascii_str(name))
+                .collect(),
+            DkimCanonicalization {
+                header: DkimCanonicalizationType::Relaxed,
+                body: DkimCanonicalizationType::Relaxed,
+            },
+        );
+        Ok(Some(Self { domain, selector, config }))
+    }
+
+    /// Adds a DKIM-Signature when the From address is in the signing domain.
+    fn sign_if_matching(&self, message: &mut Message, from: &Mailbox) {
+        if from.email.domain().eq_ignore_ascii_case(&self.domain) {
+            message.sign(&self.config);
+        }
+    }
+}
+
+fn env_non_empty(key: &str) -> Option<String> {
+    std::env::var(key)
+        .ok()
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+}
+
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(500);
+// Used when a throttling reply doesn't say how long to back off
+const THROTTLE_BACKOFF: Duration = Duration::from_secs(60);
+
+// Outlook's "slow down" replies (421, or 4xx with a 4.7.x / too-many-requests text).
+// Kept distinct from other failures so callers can back off instead of retrying.
+#[derive(Debug)]
+pub struct Throttled {
+    pub retry_after: Duration,
+    pub message: String,
+}
+
+impl fmt::Display for Throttled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SMTP server is throttling: {}", self.message)
+    }
+}
+
+impl std::error::Error for Throttled {}
+
+// The SMTP server stopped answering for longer than SmtpConfig::timeout
+#[derive(Debug)]
+pub struct SmtpTimedOut {
+    pub after: Duration,
+}
+
+impl fmt::Display for SmtpTimedOut {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SMTP server did not finish within {:?}", self.after)
+    }
+}
+
+impl std::error::Error for SmtpTimedOut {}
+
+// The account's login couldn't be turned into SMTP credentials, e.g. a revoked Microsoft token
+#[derive(Debug)]
+pub struct SenderAuthFailed(pub anyhow::Error);
+
+impl fmt::Display for SenderAuthFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Could not authorize the account: {}", self.0)
+    }
+}
+
+impl std::error::Error for SenderAuthFailed {}
+
+fn throttle_from(err: &SmtpError) -> Option<Throttled> {
+    let code = err.status().map(u16::from)?;
+    let message = err
+        .source()
+        .map(|s| s.to_string())
+        .unwrap_or_else(|| err.to_string());
+    let throttle_text =
+        Regex::new(r"(?i)\b4\.7\.\d+|too many|throttl|rate limit|server busy|thread limit").unwrap();
+    let is_throttle = code == 421 || code == 432 || (code / 100 == 4 && throttle_text.is_match(&message));
+    if !is_throttle {
+        return None;
+    }
+
+    // e.g. "try again in 30 seconds" / "retry after 5 minutes"
+    let hint = Regex::new(r"(?i)(\d+)\s*(seconds?|secs?|minutes?|mins?)\b").unwrap();
+    let retry_after = hint
+        .captures(&message)
+        .and_then(|c| {
+            let n: u64 = c[1].parse().ok()?;
+            Some(if c[2].to_ascii_lowercase().starts_with('m') { n * 60 } else { n })
+        })
+        .map(Duration::from_secs)
+        .unwrap_or(THROTTLE_BACKOFF);
+
+    Some(Throttled {
+        retry_after,
+        message,
+    })
+}
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RecipientStatus {
+    Accepted,
+    Rejected,
+}
+
+// Outcome of the RCPT TO for a single envelope recipient
+#[derive(Debug, Clone, Serialize)]
+pub struct RecipientResult {
+    pub address: String,
+    pub status: RecipientStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SendReport {
+    // False when no recipient was accepted, or when an all-or-nothing send was aborted
+    pub delivered: bool,
+    pub recipients: Vec<RecipientResult>,
+    // Message-ID header of the message, e.g. "<uuid@example.com>"
+    pub message_id: Option<String>,
+}
+
+impl SendReport {
+    pub fn rejected(&self) -> impl Iterator<Item = &RecipientResult> {
+        self.recipients
+            .iter()
+            .filter(|r| r.status == RecipientStatus::Rejected)
+    }
+
+    // Turn an undelivered report into an error, for callers that only care whether mail went out
+    pub fn ensure_delivered(self) -> anyhow::Result<Self> {
+        if self.delivered {
+            return Ok(self);
+        }
+        let reasons = self
+            .rejected()
+            .map(|r| format!("{} ({})", r.address, r.message.as_deref().unwrap_or("rejected")))
+            .collect::<Vec<_>>()
+            .join(", ");
+        Err(anyhow!("Message was not delivered: {}", reasons))
+    }
+}
+
+// A decoded file to send with the message
+#[derive(Debug, Clone)]
+pub struct EmailAttachment {
+    pub filename: String,
+    pub content_type: String,
+    pub data: Vec<u8>,
+    // Lets an HTML body show the file inline with <img src="cid:...">
+    pub content_id: Option<String>,
+}
+
+impl EmailAttachment {
+    // An explicit Content-ID wins; otherwise the filename can be referenced directly
+    fn matches_cid(&self, cid: &str) -> bool {
+        match &self.content_id {
+            Some(content_id) => content_id == cid,
+            None => self.filename == cid,
+        }
+    }
+}
+
+// Everything about one outgoing message except which account sends it
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OutgoingEmail<'a> {
+    pub header_from: &'a str,
+    // Comma-separated address lists
+    pub to: &'a str,
+    pub cc: Option<&'a str>,
+    pub bcc: Option<&'a str>,
+    pub reply_to: Option<&'a str>,
+    // Threading headers; already-validated message ids, References space-separated
+    pub in_reply_to: Option<&'a str>,
+    pub references: Option<&'a str>,
+    // Full List-Unsubscribe value; also marks the message as one-click (RFC 8058)
+    pub list_unsubscribe: Option<&'a str>,
+    pub subject: &'a str,
+    pub body: &'a str,
+    pub as_html: bool,
+    // Abort the whole send if any recipient is rejected
+    pub all_or_nothing: bool,
+    pub attachments: &'a [EmailAttachment],
+    // Extra headers such as X-Campaign-Id; check them with validate_custom_header first
+    pub headers: &'a [(String, String)],
+}
+
+// Headers the service sets itself (or that describe the MIME structure); callers can't override them
+const PROTECTED_HEADERS: &[&str] = &[
+    "from",
+    "to",
+    "cc",
+    "bcc",
+    "subject",
+    "date",
+    "reply-to",
+    "in-reply-to",
+    "references",
+    "sender",
+    "message-id",
+    "mime-version",
+    "content-type",
+    "content-transfer-encoding",
+    "return-path",
+    "received",
+    "dkim-signature",
+];
+
+// RFC 5322 msg-id: "<" id-left "@" id-right ">", no whitespace or nested brackets
+pub fn is_valid_message_id(value: &str) -> bool {
+    let Some(inner) = value.strip_prefix('<').and_then(|v| v.strip_suffix('>')) else {
+        return false;
+    };
+    let Some((left, right)) = inner.split_once('@') else {
+        return false;
+    };
+    let valid_part = |part: &str| {
+        !part.is_empty() && part.chars().all(|c| c.is_ascii_graphic() && !matches!(c, '<' | '>' | '@'))
+    };
+    valid_part(left) && valid_part(right)
+}
+
+// The same parser lettre uses when building the message, so anything stored here
+// is guaranteed to work as a From/To address later; IDN domains are accepted
+pub fn is_valid_email(value: &str) -> bool {
+    value.parse::<lettre::Address>().is_ok()
+}
+
+/// Entries of a comma-separated recipient list. Commas inside a quoted display name
+/// or inside <...> don't split, so `"Doe, Jane" <jane@x.com>` stays one entry.
+pub fn split_recipients(list: &str) -> Vec<&str> {
+    let mut entries = Vec::new();
+    let (mut start, mut quoted, mut escaped, mut angle) = (0, false, false, false);
+    for (i, c) in list.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            '<' if !quoted => angle = true,
+            '>' if !quoted => angle = false,
+            ',' if !quoted && !angle => {
+                entries.push(&list[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    entries.push(&list[start..]);
+    entries
+        .into_iter()
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .collect()
+}
+
+/// One recipient given on its own (an element of a JSON array), formatted so that
+/// `split_recipients` keeps it whole. Unlike the parser, this accepts an unquoted
+/// display name with commas, e.g. `Doe, Jane <jane@x.com>`. None if there's no valid address.
+pub fn single_recipient(entry: &str) -> Option<String> {
+    let entry = entry.trim();
+    let mailbox = match entry.parse::<Mailbox>() {
+        Ok(mailbox) => mailbox,
+        Err(_) => {
+            let (name, rest) = entry.rsplit_once('<')?;
+            let address = rest.strip_suffix('>')?.trim().parse::<lettre::Address>().ok()?;
+            let name: String = name
+                .trim()
+                .trim_matches('"')
+                .chars()
+                .filter(|c| !c.is_control())
+                .collect();
+            let name = name.trim();
+            Mailbox::new((!name.is_empty()).then(|| name.to_string()), address)
+        }
+    };
+    Some(mailbox.to_string())
+}
+
+pub fn validate_custom_header(name: &str, value: &str) -> Result<(), String> {
+    if name.is_empty() || !name.bytes().all(|b| b.is_ascii_graphic() && b != b':') {
+        return Err(format!("Header name {:?} is not a valid header name", name));
+    }
+    if PROTECTED_HEADERS.contains(&name.to_ascii_lowercase().as_str()) {
+        return Err(format!("Header {} is set by W9 Mail and can't be overridden", name));
+    }
+    if value.contains(['\r', '\n']) {
+        return Err(format!("Header {} must be a single line", name));
+    }
+    Ok(())
+}
+
+// Marker left in branded templates where sender compliance footers are inserted,
+// after the message content and before the template's own footer
+pub const FOOTER_SLOT: &str = "<!-- w9:compliance-footer -->";
+
+// Admin-managed disclaimer attached to an account or alias
+#[derive(Debug, Clone, Default)]
+pub struct ComplianceFooter {
+    pub html: Option<String>,
+    pub text: Option<String>,
+}
+
+// Marks the footer block in HTML bodies. Sanitizing drops the comment but keeps the
+// class, so either one means the footer is already there.
+const FOOTER_MARKER: &str = "<!-- w9-compliance-footer -->";
+const FOOTER_CLASS: &str = "class=\"w9-compliance-footer\"";
+
+impl ComplianceFooter {
+    // Append the footer in the body's content type. A body that already carries
+    // the footer (e.g. a resent draft) is returned unchanged.
+    pub fn apply(&self, body: &str, as_html: bool) -> String {
+        if as_html {
+            let footer = match (&self.html, &self.text) {
+                (Some(html), _) => html.clone(),
+                (None, Some(text)) => html_escape(text).replace('\n', "<br />"),
+                (None, None) => return body.to_string(),
+            };
+            if body.contains(FOOTER_MARKER) || body.contains(FOOTER_CLASS) {
+                return body.to_string();
+            }
+
+            let block = format!(
+                "<div {} style=\"margin-top:24px;font-size:12px;line-height:1.5;\">{}{}</div>\n",
+                FOOTER_CLASS, FOOTER_MARKER, footer
+            );
+            let insert_at = body.find(FOOTER_SLOT).or_else(|| body.rfind("</body>"));
+            match insert_at {
+                Some(idx) => format!("{}{}{}", &body[..idx], block, &body[idx..]),
+                None => format!("{}{}", body, block),
+            }
+        } else {
+            let footer = match (&self.text, &self.html) {
+                (Some(text), _) => text.clone(),
+                (None, Some(html)) => html_to_text(html),
+                (None, None) => return body.to_string(),
+            };
+            // Plain text has nowhere to hide a marker; the footer as appended, closing the
+            // body, is the text equivalent. A quoted copy ("> ...") doesn't count.
+            let block = format!("\n\n{}", footer.trim());
+            if body.trim_end().ends_with(&block) {
+                return body.to_string();
+            }
+            format!("{}{}", body.trim_end(), block)
+        }
+    }
+}
+
+// Plain-text rendering of an HTML body for the text/plain alternative: drops head/style/script,
+// turns block elements into line breaks, keeps link targets, and decodes entities
+pub fn html_to_text(html: &str) -> String {
+    let hidden = Regex::new(r"(?is)<(head|style|script|title)\b.*?</\s*(head|style|script|title)\s*>").unwrap();
+    let comments = Regex::new(r"(?s)<!--.*?-->").unwrap();
+    let links = Regex::new(r#"(?is)<a\b[^>]*?href\s*=\s*["']([^"']+)["'][^>]*>(.*?)</a\s*>"#).unwrap();
+    let breaks = Regex::new(r"(?i)<br\s*/?>|</(p|div|tr|h[1-6]|li|table|blockquote)\s*>|<hr[^>]*>").unwrap();
+    let bullets = Regex::new(r"(?i)<li\b[^>]*>").unwrap();
+    let tags = Regex::new(r"<[^>]*>").unwrap();
+    let numeric = Regex::new(r"&#(x[0-9a-fA-F]+|[0-9]+);").unwrap();
+
+    let text = hidden.replace_all(html, "");
+    let text = comments.replace_all(&text, "");
+    let text = links.replace_all(&text, |caps: &regex::Captures| {
+        let label = tags.replace_all(&caps[2], "").trim().to_string();
+        let href = &caps[1];
+        if label.is_empty() || label == href || href.starts_with('#') {
+            if label.is_empty() { href.to_string() } else { label }
+        } else {
+            format!("{} ({})", label, href)
+        }
+    });
+    let text = breaks.replace_all(&text, "\n");
+    let text = bullets.replace_all(&text, "\n- ");
+    let text = tags.replace_all(&text, "");
+    let text = numeric.replace_all(&text, |caps: &regex::Captures| {
+        let code = &caps[1];
+        let value = match code.strip_prefix('x') {
+            Some(hex) => u32::from_str_radix(hex, 16).ok(),
+            None => code.parse().ok(),
+        };
+        value
+            .and_then(char::from_u32)
+            .map(String::from)
+            .unwrap_or_default()
+    });
+    let text = text
+        .replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&");
+
+    // Collapse the template's indentation and runs of blank lines
+    let mut out = String::new();
+    let mut blank = 0;
+    for line in text.lines().map(|l| l.split_whitespace().collect::<Vec<_>>().join(" ")) {
+        if line.is_empty() {
+            blank += 1;
+            continue;
+        }
+        if !out.is_empty() {
+            out.push_str(if blank > 0 { "\n\n" } else { "\n" });
+        }
+        out.push_str(&line);
+        blank = 0;
+    }
+    out
+}
+
+// A complete HTML document, which the template passes through instead of escaping
+fn is_html_document(body: &str) -> bool {
     let trimmed = body.trim();
-    if trimmed.starts_with("<!DOCTYPE") || trimmed.starts_with("<html") {
+    trimmed.starts_with("<!DOCTYPE") || trimmed.starts_with("<html")
+}
+
+/// Removes scripts, event handlers and `javascript:` (or other non-web) URLs from a
+/// caller's HTML, keeping formatting, inline styles, `<style>` blocks, tables and images.
+/// `data:` URIs survive only as image sources, so inline images still become attachments.
+/// A full document comes back as a minimal one, so the template still passes it through.
+pub fn sanitize_html(html: &str) -> String {
+    let mut builder = ammonia::Builder::default();
+    builder
+        .add_tags(["style", "font"])
+        .rm_clean_content_tags(["style"])
+        .add_clean_content_tags(["title"])
+        .add_tag_attributes("font", ["color", "face", "size"])
+        .add_generic_attributes([
+            "style", "class", "dir", "align", "valign", "width", "height", "bgcolor", "border",
+            "cellpadding", "cellspacing", "role",
+        ])
+        .add_url_schemes(["data", "cid"])
+        .attribute_filter(|element, attribute, value| {
+            let lower = value.trim_start().to_ascii_lowercase();
+            let image = element == "img" && attribute == "src" && lower.starts_with("data:image/");
+            if lower.starts_with("data:") && !image {
+                None
+            } else {
+                Some(value.into())
+            }
+        });
+    let clean = builder.clean(html).to_string();
+
+    if is_html_document(html) {
+        format!("<!DOCTYPE html>\n<html>\n<body>\n{}\n</body>\n</html>", clean)
+    } else {
+        clean
+    }
+}
+
+/// The HTML sent for a caller's body: sanitized unless `sanitize` is off, then wrapped in
+/// the template unless `use_template` is off. Fragments the template escapes are left alone.
+/// `branded` is the sending account's branding flag.
+pub fn render_html_body(body: &str, use_template: bool, sanitize: bool, branded: bool) -> String {
+    let as_written = !use_template || is_html_document(body);
+    let body = if sanitize && as_written { sanitize_html(body) } else { body.to_string() };
+    if use_template {
+        render_email_template(&body, branded)
+    } else {
+        body
+    }
+}
+
+// W9 logo and name above the body
+const BRAND_HEADER: &str = r#"<table role="presentation" cellpadding="0" cellspacing="0" style="margin-bottom:24px;">
+              <tr>
+                <td style="width:42px;height:42px;border:2px solid #fdfdfd;text-align:center;vertical-align:middle;font-weight:bold;color:#fdfdfd;line-height:42px;font-size:16px;padding:0;margin:0;">W9</td>
+                <td style="padding-left:12px;vertical-align:middle;">
+                  <div style="color:#fdfdfd;font-size:18px;letter-spacing:0.1em;text-transform:uppercase;">W9 Mail</div>
+                  <div style="color:#9a9a9a;font-size:12px;">Open-source mail rail</div>
+                </td>
+              </tr>
+            </table>"#;
+
+// "Sent via W9 Mail" line closing the message
+const BRAND_SIGNOFF: &str = r#"<hr style="border:none;border-top:2px solid #1a1a1a;margin:32px 0;" />
+            <p style="margin:0;color:#686868;font-size:11px;line-height:1.4;">Sent via W9 Mail. Open-source mail rail for teams.</p>"#;
+
+// Render email body with W9 Mail branding template (matching w9-tools design). Accounts
+// with branding turned off keep the layout and compliance footer but lose the W9 header
+// and sign-off.
+pub fn render_email_template(body: &str, branded: bool) -> String {
+    // Check if body is already a complete HTML document
+    if is_html_document(body) {
         // Already a complete HTML document, return as-is
         return body.to_string();
     }
@@ -49,20 +718,12 @@ pub fn render_email_template(body: &str) -> String {
       <td align="center">
         <table role="presentation" cellpadding="0" cellspacing="0" width="100%" style="max-width:640px;border:2px solid #fdfdfd;padding:28px;background:#000;">
           <tr><td style="text-align:left;">
-            <table role="presentation" cellpadding="0" cellspacing="0" style="margin-bottom:24px;">
-              <tr>
-                <td style="width:42px;height:42px;border:2px solid #fdfdfd;text-align:center;vertical-align:middle;font-weight:bold;color:#fdfdfd;line-height:42px;font-size:16px;padding:0;margin:0;">W9</td>
-                <td style="padding-left:12px;vertical-align:middle;">
-                  <div style="color:#fdfdfd;font-size:18px;letter-spacing:0.1em;text-transform:uppercase;">W9 Mail</div>
-                  <div style="color:#9a9a9a;font-size:12px;">Open-source mail rail</div>
-                </td>
-              </tr>
-            </table>
+            {brand_header}
             <div style="color:#fdfdfd;font-size:15px;line-height:1.6;font-family:'Courier New',Courier,monospace;">
               {html_body}
             </div>
-            <hr style="border:none;border-top:2px solid #1a1a1a;margin:32px 0;" />
-            <p style="margin:0;color:#686868;font-size:11px;line-height:1.4;">Sent via W9 Mail. Open-source mail rail for teams.</p>
+            {footer_slot}
+            {brand_signoff}
           </td></tr>
         </table>
       </td>
@@ -70,65 +731,130 @@ pub fn render_email_template(body: &str) -> String {
   </table>
 </body>
 </html>"#,
-        html_body = html_body
+        html_body = html_body,
+        footer_slot = FOOTER_SLOT,
+        brand_header = if branded { BRAND_HEADER } else { "" },
+        brand_signoff = if branded { BRAND_SIGNOFF } else { "" },
     )
 }
 
 impl EmailService {
-    pub fn new() -> Self {
-        EmailService
+    pub fn new(smtp: SmtpConfig, pool: SmtpPool) -> Self {
+        EmailService { smtp, pool }
     }
 
     pub async fn send_email(
         &self,
-        header_from: &str,
         auth_email: &str,
-        auth_password: &str,
-        to: &str,
-        subject: &str,
-        body: &str,
-        cc: Option<&str>,
-        bcc: Option<&str>,
-        as_html: bool,
-    ) -> anyhow::Result<()> {
-        // Parse email addresses
-        let from_addr: Mailbox = header_from.parse()?;
-        
-        // Build recipients list
-        let mut to_addresses = Vec::new();
-        for addr in to.split(',') {
-            let trimmed = addr.trim();
-            if !trimmed.is_empty() {
-                to_addresses.push(trimmed.parse::<Mailbox>()?);
-            }
-        }
+        auth: &SmtpAuth,
+        outgoing: &OutgoingEmail<'_>,
+    ) -> anyhow::Result<SendReport> {
+        let (email, message_id) = self.build_message(outgoing)?;
+        let all_or_nothing = outgoing.all_or_nothing;
+
+        // The SMTP conversation is driven by hand so each RCPT TO response is visible.
+        let (creds, mechanisms) = credentials(auth_email, auth);
+        let envelope = email.envelope();
+        let formatted = email.formatted();
 
-        // Build CC list
-        let mut cc_addresses = Vec::new();
-        if let Some(cc_str) = cc {
-            for addr in cc_str.split(',') {
-                let trimmed = addr.trim();
-                if !trimmed.is_empty() {
-                    cc_addresses.push(trimmed.parse::<Mailbox>()?);
+        // Transient failures are retried with exponential backoff, but only while nothing has
+        // been handed over with DATA, so a retry can never deliver the message twice
+        // A session that runs past the timeout is abandoned like a transient failure
+        let mut attempt = 1;
+        loop {
+            let mut data_started = false;
+            let session = self.attempt(
+                auth_email,
+                &creds,
+                mechanisms,
+                envelope,
+                &formatted,
+                all_or_nothing,
+                &mut data_started,
+            );
+            let err = match tokio::time::timeout(self.smtp.timeout, session).await {
+                Ok(Ok(report)) => {
+                    return Ok(SendReport {
+                        message_id: Some(message_id),
+                        ..report
+                    })
                 }
-            }
-        }
+                Ok(Err(err)) => Some(err),
+                Err(_) => None,
+            };
 
-        // Build BCC list
-        let mut bcc_addresses = Vec::new();
-        if let Some(bcc_str) = bcc {
-            for addr in bcc_str.split(',') {
-                let trimmed = addr.trim();
-                if !trimmed.is_empty() {
-                    bcc_addresses.push(trimmed.parse::<Mailbox>()?);
+            match &err {
+                Some(err) => {
+                    if let Some(throttled) = throttle_from(err) {
+                        return Err(anyhow::Error::new(throttled));
+                    }
+                    if data_started || !is_retryable(err) || attempt >= self.smtp.max_attempts {
+                        return Err(anyhow!("{} (after {} attempt{})", err, attempt, if attempt == 1 { "" } else { "s" }));
+                    }
+                }
+                // Once DATA has begun the message may have been accepted, so it's not retried
+                None if data_started || attempt >= self.smtp.max_attempts => {
+                    return Err(anyhow::Error::new(SmtpTimedOut { after: self.smtp.timeout }));
                 }
+                None => {}
             }
+            let err = err.map_or_else(|| "timed out".to_string(), |err| err.to_string());
+
+            let backoff = RETRY_BASE_DELAY * 2u32.pow(attempt - 1);
+            tracing::warn!(
+                attempt,
+                sender = %auth_email,
+                ?backoff,
+                error = %err,
+                "SMTP attempt failed, retrying"
+            );
+            tokio::time::sleep(backoff).await;
+            attempt += 1;
         }
+    }
+
+    /// The finished, signed message and its Message-ID, without contacting the SMTP
+    /// server. Dry-run sends stop here.
+    pub fn build_message(&self, outgoing: &OutgoingEmail<'_>) -> anyhow::Result<(Message, String)> {
+        let OutgoingEmail {
+            header_from,
+            to,
+            cc,
+            bcc,
+            reply_to,
+            in_reply_to,
+            references,
+            list_unsubscribe,
+            subject,
+            body,
+            as_html,
+            all_or_nothing: _,
+            attachments: files,
+            headers,
+        } = *outgoing;
+
+        // Parse email addresses
+        let from_addr: Mailbox = header_from.parse()?;
+        
+        // Build recipient lists
+        let parse_list = |list: &str| {
+            split_recipients(list)
+                .into_iter()
+                .map(|addr| addr.parse::<Mailbox>())
+                .collect::<Result<Vec<_>, _>>()
+        };
+        let to_addresses = parse_list(to)?;
+        let cc_addresses = cc.map(parse_list).transpose()?.unwrap_or_default();
+        let bcc_addresses = bcc.map(parse_list).transpose()?.unwrap_or_default();
 
         // Build email message
+        // Generated here rather than by lettre so callers can correlate with delivery logs;
+        // the sender's domain keeps it recognisable and unique
+        let message_id = format!("<{}@{}>", uuid::Uuid::new_v4(), from_addr.email.domain());
         let mut message_builder = Message::builder()
             .from(from_addr.clone())
-            .subject(subject);
+            .subject(subject)
+            .message_id(Some(message_id.clone()));
 
         // Add To recipients
         for addr in &to_addresses {
@@ -145,97 +871,846 @@ impl EmailService {
             message_builder = message_builder.bcc(addr.clone());
         }
 
+        if let Some(reply_to) = reply_to.map(str::trim).filter(|v| !v.is_empty()) {
+            message_builder = message_builder.reply_to(reply_to.parse::<Mailbox>()?);
+        }
+        if let Some(in_reply_to) = in_reply_to {
+            message_builder = message_builder.in_reply_to(in_reply_to.to_string());
+        }
+        if let Some(references) = references {
+            message_builder = message_builder.references(references.to_string());
+        }
+        if let Some(list_unsubscribe) = list_unsubscribe {
+            message_builder = message_builder
+                .raw_header(HeaderValue::new(
+                    HeaderName::new_from_ascii_str("List-Unsubscribe"),
+                    list_unsubscribe.to_string(),
+                ))
+                .raw_header(HeaderValue::new(
+                    HeaderName::new_from_ascii_str("List-Unsubscribe-Post"),
+                    "List-Unsubscribe=One-Click".to_string(),
+                ));
+        }
+
+        for (name, value) in headers {
+            validate_custom_header(name, value).map_err(|e| anyhow!(e))?;
+            message_builder = message_builder.raw_header(HeaderValue::new(
+                HeaderName::new_from_ascii(name.clone())?,
+                value.clone(),
+            ));
+        }
+
         // Handle inline images: convert data URIs to CID attachments
-        let (final_body, attachments) = if as_html {
+        let (final_body, mut attachments) = if as_html {
             extract_inline_images(body)
         } else {
             (body.to_string(), Vec::new())
         };
 
-        let content_type = if as_html {
-            ContentType::TEXT_HTML
+        // Uploaded files the HTML refers to by cid: go in the related part, inline;
+        // everything else stays a regular attachment
+        let mut regular_files = Vec::with_capacity(files.len());
+        let referenced = if as_html { referenced_cids(&final_body) } else { Vec::new() };
+        for file in files {
+            match referenced.iter().find(|cid| file.matches_cid(cid)) {
+                Some(cid) => attachments.push((cid.clone(), file.content_type.clone(), file.data.clone())),
+                None => regular_files.push(file.clone()),
+            }
+        }
+        let files = regular_files.as_slice();
+
+        // Build email: plain text stays a single part; HTML always gets a text/plain
+        // alternative, with inline images kept next to the HTML in a related part.
+        // File attachments wrap either form in multipart/mixed.
+        let mut email = if !as_html {
+            let text_part = SinglePart::builder()
+                .header(ContentType::TEXT_PLAIN)
+                .body(final_body);
+            if files.is_empty() {
+                message_builder.singlepart(text_part)?
+            } else {
+                message_builder.multipart(attach_files(MultiPart::mixed().singlepart(text_part), files))?
+            }
         } else {
-            ContentType::TEXT_PLAIN
+            let text_part = SinglePart::builder()
+                .header(ContentType::TEXT_PLAIN)
+                .body(html_to_text(&final_body));
+            let html_part = SinglePart::builder()
+                .header(ContentType::TEXT_HTML)
+                .body(final_body);
+
+            let alternative = MultiPart::alternative().singlepart(text_part);
+            let alternative = if attachments.is_empty() {
+                alternative.singlepart(html_part)
+            } else {
+                let mut related = MultiPart::related().singlepart(html_part);
+                for (cid, mime_type, data) in attachments {
+                    let content_type = ContentType::parse(&mime_type)
+                        .unwrap_or(ContentType::TEXT_PLAIN);
+                    let attachment = Attachment::new_inline(cid.clone())
+                        .body(data, content_type);
+                    related = related.singlepart(attachment);
+                }
+                alternative.multipart(related)
+            };
+
+            if files.is_empty() {
+                message_builder.multipart(alternative)?
+            } else {
+                message_builder.multipart(attach_files(MultiPart::mixed().multipart(alternative), files))?
+            }
         };
 
-        // Build email with or without attachments
-        let email = if attachments.is_empty() {
-            // Simple singlepart email
-            message_builder.singlepart(
-                SinglePart::builder()
-                .header(content_type)
-                    .body(final_body),
-            )?
-        } else {
-            // Multipart email with inline attachments
-            let mut multipart = MultiPart::related()
-                .singlepart(
-                    SinglePart::builder()
-                        .header(content_type)
-                        .body(final_body),
-                );
-
-            for (cid, mime_type, data) in attachments {
-                let content_type = ContentType::parse(&mime_type)
-                    .unwrap_or(ContentType::TEXT_PLAIN);
-                let attachment = Attachment::new_inline(cid.clone())
-                    .body(data, content_type);
-                multipart = multipart.singlepart(attachment);
+        // Signed last: the signature covers the finished headers and body
+        if let Some(dkim) = &self.smtp.dkim {
+            dkim.sign_if_matching(&mut email, &from_addr);
+        }
+
+        Ok((email, message_id))
+    }
+
+    // One SMTP transaction. A pooled connection for the account is used if it still
+    // answers; otherwise connect, secure and authenticate. After a successful delivery the
+    // connection goes back to the pool instead of being closed.
+    #[allow(clippy::too_many_arguments)]
+    async fn attempt(
+        &self,
+        auth_email: &str,
+        creds: &Credentials,
+        mechanisms: &[Mechanism],
+        envelope: &Envelope,
+        formatted: &[u8],
+        all_or_nothing: bool,
+        data_started: &mut bool,
+    ) -> Result<SendReport, SmtpError> {
+        let smtp = &self.smtp;
+        let key = (smtp.host.clone(), smtp.port, auth_email.to_string());
+        if let Some(mut conn) = self.pool.take(&key) {
+            if conn.test_connected().await {
+                return self.finish(key, conn, envelope, formatted, all_or_nothing, data_started).await;
             }
+        }
 
-            message_builder.multipart(multipart)?
+        let hello = ClientId::default();
+        let implicit_tls = match smtp.tls {
+            SmtpTlsMode::Tls => Some(TlsParameters::new(smtp.host.clone())?),
+            _ => None,
         };
+        let mut conn = AsyncSmtpConnection::connect_tokio1(
+            (smtp.host.as_str(), smtp.port),
+            Some(smtp.timeout),
+            &hello,
+            implicit_tls,
+            None,
+        )
+        .await?;
+        if smtp.tls == SmtpTlsMode::StartTls {
+            conn.starttls(TlsParameters::new(smtp.host.clone())?, &hello)
+                .await?;
+        }
+        conn.auth(mechanisms, creds)
+            .await?;
 
-        // Create SMTP transport for Microsoft/Outlook
-        // Port 587 requires STARTTLS (not direct TLS)
-        let creds = Credentials::new(auth_email.to_string(), auth_password.to_string());
-        
-        let mailer = AsyncSmtpTransport::<Tokio1Executor>::starttls_relay("smtp-mail.outlook.com")?
-            .port(587)
-            .credentials(creds)
-            .build();
+        self.finish(key, conn, envelope, formatted, all_or_nothing, data_started).await
+    }
+
+    // Delivers on an authenticated connection, then pools it or, after an error, closes it
+    async fn finish(
+        &self,
+        key: PoolKey,
+        mut conn: AsyncSmtpConnection,
+        envelope: &Envelope,
+        formatted: &[u8],
+        all_or_nothing: bool,
+        data_started: &mut bool,
+    ) -> Result<SendReport, SmtpError> {
+        let report = deliver(&mut conn, envelope, formatted, all_or_nothing, data_started).await;
+        if report.is_ok() {
+            self.pool.put(key, conn);
+        } else {
+            let _ = conn.quit().await;
+        }
+        report
+    }
+
+    /// Connects, secures and authenticates, then QUITs without sending anything.
+    pub async fn verify_credentials(&self, auth_email: &str, auth: &SmtpAuth) -> Result<(), CredentialCheckError> {
+        let (creds, mechanisms) = credentials(auth_email, auth);
+        match tokio::time::timeout(VERIFY_TIMEOUT, self.check_session(&creds, mechanisms)).await {
+            Ok(result) => result,
+            Err(_) => Err(CredentialCheckError {
+                kind: CredentialFailure::Timeout,
+                message: format!("No answer from {} within {:?}", self.smtp.host, VERIFY_TIMEOUT),
+            }),
+        }
+    }
+
+    async fn check_session(&self, creds: &Credentials, mechanisms: &[Mechanism]) -> Result<(), CredentialCheckError> {
+        let hello = ClientId::default();
+        let smtp = &self.smtp;
+        let stage = |kind: CredentialFailure| move |err: SmtpError| CredentialCheckError::from_smtp(kind, err);
+
+        let implicit_tls = match smtp.tls {
+            SmtpTlsMode::Tls => Some(TlsParameters::new(smtp.host.clone()).map_err(stage(CredentialFailure::Tls))?),
+            _ => None,
+        };
+        let mut conn = AsyncSmtpConnection::connect_tokio1(
+            (smtp.host.as_str(), smtp.port),
+            Some(VERIFY_TIMEOUT),
+            &hello,
+            implicit_tls,
+            None,
+        )
+        .await
+        .map_err(stage(CredentialFailure::Connection))?;
+        if smtp.tls == SmtpTlsMode::StartTls {
+            let params = TlsParameters::new(smtp.host.clone()).map_err(stage(CredentialFailure::Tls))?;
+            conn.starttls(params, &hello)
+                .await
+                .map_err(stage(CredentialFailure::Tls))?;
+        }
+        let result = conn
+            .auth(mechanisms, creds)
+            .await
+            .map(|_| ())
+            .map_err(stage(CredentialFailure::Auth));
+        let _ = conn.quit().await;
+        result
+    }
+}
+
+const VERIFY_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CredentialFailure {
+    Connection,
+    Tls,
+    Auth,
+    Timeout,
+}
+
+// Why a credential check failed, in a shape the manage UI can show as-is
+#[derive(Debug, Serialize)]
+pub struct CredentialCheckError {
+    pub kind: CredentialFailure,
+    pub message: String,
+}
+
+impl CredentialCheckError {
+    // TLS and timeout errors can surface from any stage, so they override the stage's kind
+    fn from_smtp(kind: CredentialFailure, err: SmtpError) -> Self {
+        let kind = if err.is_timeout() {
+            CredentialFailure::Timeout
+        } else if err.is_tls() {
+            CredentialFailure::Tls
+        } else {
+            kind
+        };
+        Self {
+            kind,
+            message: err.to_string(),
+        }
+    }
+}
+
+fn credentials(auth_email: &str, auth: &SmtpAuth) -> (Credentials, &'static [Mechanism]) {
+    match auth {
+        SmtpAuth::Password(password) => (
+            Credentials::new(auth_email.to_string(), password.clone()),
+            &[Mechanism::Plain, Mechanism::Login][..],
+        ),
+        SmtpAuth::OAuth2(token) => (
+            Credentials::new(auth_email.to_string(), token.clone()),
+            &[Mechanism::Xoauth2][..],
+        ),
+    }
+}
+
+// 4xx replies, timeouts and dropped connections; never 5xx, TLS or auth problems
+fn is_retryable(err: &SmtpError) -> bool {
+    if err.is_transient() || err.is_timeout() {
+        return true;
+    }
+    err.status().is_none()
+        && !err.is_permanent()
+        && !err.is_tls()
+        && !err.is_client()
+        && !err.is_response()
+}
+
+fn attach_files(mut mixed: MultiPart, files: &[EmailAttachment]) -> MultiPart {
+    for file in files {
+        let content_type = ContentType::parse(&file.content_type)
+            .unwrap_or_else(|_| ContentType::parse("application/octet-stream").unwrap());
+        mixed = mixed.singlepart(
+            Attachment::new(file.filename.clone()).body(file.data.clone(), content_type),
+        );
+    }
+    mixed
+}
 
-        // Send email
-        mailer.send(email).await?;
+// Run MAIL/RCPT/DATA on an authenticated connection, recording every RCPT TO outcome.
+// Rejected recipients are skipped and the rest still receive the message, unless
+// `all_or_nothing` is set, in which case the transaction is reset and nothing is sent.
+async fn deliver(
+    conn: &mut AsyncSmtpConnection,
+    envelope: &Envelope,
+    email: &[u8],
+    all_or_nothing: bool,
+    data_started: &mut bool,
+) -> Result<SendReport, SmtpError> {
+    let mut mail_options = Vec::new();
+    if !email.is_ascii() {
+        mail_options.push(MailParameter::Body(MailBodyParameter::EightBitMime));
+    }
+    conn.command(Mail::new(envelope.from().cloned(), mail_options))
+        .await?;
 
-        Ok(())
+    let mut recipients = Vec::with_capacity(envelope.to().len());
+    for address in envelope.to() {
+        let result = match conn.command(Rcpt::new(address.clone(), vec![])).await {
+            Ok(_) => RecipientResult {
+                address: address.to_string(),
+                status: RecipientStatus::Accepted,
+                code: None,
+                message: None,
+            },
+            // Only reply codes are per-recipient; anything else means the connection is unusable.
+            // A throttling reply isn't about this recipient either, so it aborts the send.
+            Err(e) if e.status().is_some() && throttle_from(&e).is_none() => RecipientResult {
+                address: address.to_string(),
+                status: RecipientStatus::Rejected,
+                code: e.status().map(u16::from),
+                message: e.source().map(|s| s.to_string()),
+            },
+            Err(e) => {
+                let _ = conn.command(Rset).await;
+                return Err(e);
+            }
+        };
+        recipients.push(result);
     }
 
-    #[allow(dead_code)]
-    pub async fn fetch_inbox(&self, _account: &str, _limit: Option<u32>) -> anyhow::Result<Vec<serde_json::Value>> {
-        // TODO: Implement IMAP inbox fetching
-        Ok(vec![])
+    let accepted = recipients
+        .iter()
+        .filter(|r| r.status == RecipientStatus::Accepted)
+        .count();
+    if accepted == 0 || (all_or_nothing && accepted < recipients.len()) {
+        conn.command(Rset).await?;
+        return Ok(SendReport {
+            delivered: false,
+            recipients,
+            message_id: None,
+        });
     }
+
+    *data_started = true;
+    conn.command(Data).await?;
+    conn.message(email).await?;
+
+    Ok(SendReport {
+        delivered: true,
+        recipients,
+        message_id: None,
+    })
+}
+
+/// What an HTML send would produce, for viewing in a browser. Inline images go through the
+/// same CID extraction as a real send and are then put back as data URIs, since a browser
+/// can't resolve cid: links. Returns the HTML and how many images were extracted.
+pub fn render_preview(body: &str, use_template: bool, sanitize: bool, branded: bool) -> (String, usize) {
+    let body = render_html_body(body, use_template, sanitize, branded);
+    let (mut html, images) = extract_inline_images(&body);
+    for (cid, mime_type, data) in &images {
+        let data_uri = format!("data:{};base64,{}", mime_type, Base64.encode(data));
+        html = html.replacen(&format!("cid:{}", cid), &data_uri, 1);
+    }
+    (html, images.len())
 }
 
 // Extract data URIs from HTML and convert them to CID attachments
 // Returns (modified_html, vec of (cid, mime_type, data))
+//
+// A data URI runs to the closing quote when it sits in a quoted attribute (so wrapped
+// base64 with line breaks is fine), otherwise to whitespace, `>` or a CSS `)`. Anything
+// after the base64 run, such as `?v=2`, is dropped together with it. URIs that don't
+// decode are left untouched rather than turned into broken cid: links.
 fn extract_inline_images(html: &str) -> (String, Vec<(String, String, Vec<u8>)>) {
-    // Pattern to match data URIs: data:image/type;base64,data
-    let re = Regex::new(r#"data:([^;]+);base64,([^"'\s>]+)"#).unwrap();
+    // The mime type may carry parameters before ;base64 (e.g. ;name=logo.png)
+    let re = Regex::new(r#"data:([A-Za-z0-9.+-]+/[A-Za-z0-9.+-]+)(?:;[A-Za-z0-9.+-]+=[^;,\s"'>]*)*;base64,"#).unwrap();
     let mut attachments = Vec::new();
-    let mut cid_counter = 0;
-    let mut modified_html = html.to_string();
+    let mut modified_html = String::with_capacity(html.len());
+    let mut copied_to = 0;
 
-    // Find all matches and collect them
     for cap in re.captures_iter(html) {
-        let full_match = cap.get(0).unwrap();
+        let prefix = cap.get(0).unwrap();
+        if prefix.start() < copied_to {
+            continue;
+        }
         let mime_type = cap.get(1).map(|m| m.as_str()).unwrap_or("image/png");
-        let base64_data = cap.get(2).map(|m| m.as_str()).unwrap_or("");
 
-        // Decode base64 data
-        if let Ok(data) = Base64.decode(base64_data) {
-            cid_counter += 1;
-            let cid = format!("image{}", cid_counter);
-            
-            // Store attachment info
-            attachments.push((cid.clone(), mime_type.to_string(), data));
-            
-            // Replace in HTML
-            let cid_ref = format!("cid:{}", cid);
-            modified_html = modified_html.replacen(full_match.as_str(), &cid_ref, 1);
-        }
+        let quote = html[..prefix.start()].chars().next_back().filter(|c| *c == '"' || *c == '\'');
+        let rest = &html[prefix.end()..];
+        let uri_len = rest
+            .find(|c: char| match quote {
+                Some(q) => c == q,
+                None => c.is_whitespace() || matches!(c, '>' | ')' | '"' | '\''),
+            })
+            .unwrap_or(rest.len());
+        let uri_rest = &rest[..uri_len];
+        let payload_len = uri_rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c.is_ascii_whitespace() || matches!(c, '+' | '/' | '-' | '_' | '=')))
+            .unwrap_or(uri_rest.len());
+
+        let Some(data) = decode_base64_lenient(&uri_rest[..payload_len]) else {
+            continue;
+        };
+        // Prefixed so generated ids can't collide with cid: names the sender chose
+        let cid = format!("w9-inline-{}", attachments.len() + 1);
+        modified_html.push_str(&html[copied_to..prefix.start()]);
+        modified_html.push_str("cid:");
+        modified_html.push_str(&cid);
+        copied_to = prefix.end() + uri_len;
+        attachments.push((cid, mime_type.to_string(), data));
     }
+    modified_html.push_str(&html[copied_to..]);
 
     (modified_html, attachments)
 }
+
+// Distinct cid: references in an HTML body, in order of first appearance
+fn referenced_cids(html: &str) -> Vec<String> {
+    let re = Regex::new(r#"(?i)cid:([^"'\s>)]+)"#).unwrap();
+    let mut cids: Vec<String> = Vec::new();
+    for cap in re.captures_iter(html) {
+        let cid = cap[1].to_string();
+        if !cids.contains(&cid) {
+            cids.push(cid);
+        }
+    }
+    cids
+}
+
+// Accepts whitespace, missing or extra `=` padding, and the URL-safe alphabet
+fn decode_base64_lenient(raw: &str) -> Option<Vec<u8>> {
+    let compact: String = raw.chars().filter(|c| !c.is_ascii_whitespace()).collect();
+    let unpadded = compact.trim_end_matches('=');
+    // Padding only ever appears at the end
+    if unpadded.is_empty() || unpadded.contains('=') {
+        return None;
+    }
+    let decoded = if unpadded.contains(['-', '_']) {
+        URL_SAFE_NO_PAD.decode(unpadded)
+    } else {
+        STANDARD_NO_PAD.decode(unpadded)
+    };
+    decoded.ok().filter(|data| !data.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use mail_parser::{MessageParser, MimeHeaders};
+
+    use super::*;
+    use crate::test_support::MockSmtp;
+
+    fn service(smtp: &MockSmtp) -> EmailService {
+        EmailService::new(smtp.config(), SmtpPool::from_env().unwrap())
+    }
+
+    fn outgoing(to: &str) -> OutgoingEmail<'_> {
+        OutgoingEmail {
+            header_from: "sender@example.com",
+            to,
+            subject: "Hello",
+            body: "Hello there",
+            ..Default::default()
+        }
+    }
+
+    // For build_message, which never connects
+    fn offline() -> EmailService {
+        let smtp = SmtpConfig {
+            host: "localhost".to_string(),
+            port: 25,
+            tls: SmtpTlsMode::None,
+            max_attempts: 1,
+            timeout: Duration::from_secs(1),
+            dkim: None,
+        };
+        EmailService::new(smtp, SmtpPool::from_env().unwrap())
+    }
+
+    async fn send(smtp: &MockSmtp, outgoing: &OutgoingEmail<'_>) -> anyhow::Result<SendReport> {
+        let auth = SmtpAuth::Password("secret".to_string());
+        service(smtp).send_email("sender@example.com", &auth, outgoing).await
+    }
+
+    #[tokio::test]
+    async fn delivers_to_accepted_recipients_and_reports_rejected_ones() {
+        let smtp = MockSmtp::start(&[("RCPT TO:<bad@", "550 5.1.1 User unknown")]).await;
+
+        let report = send(&smtp, &outgoing("good@example.com, bad@example.com")).await.unwrap();
+
+        assert!(report.delivered);
+        assert!(report.message_id.is_some());
+        let good = &report.recipients[0];
+        assert_eq!(good.address, "good@example.com");
+        assert_eq!(good.status, RecipientStatus::Accepted);
+        assert_eq!(good.code, None);
+        let bad = &report.recipients[1];
+        assert_eq!(bad.address, "bad@example.com");
+        assert_eq!(bad.status, RecipientStatus::Rejected);
+        assert_eq!(bad.code, Some(550));
+        assert!(bad.message.as_deref().unwrap_or_default().contains("User unknown"));
+        assert!(smtp.commands().iter().any(|c| c == "DATA"));
+    }
+
+    #[tokio::test]
+    async fn all_or_nothing_resets_instead_of_sending() {
+        let smtp = MockSmtp::start(&[("RCPT TO:<bad@", "550 5.1.1 User unknown")]).await;
+        let email = OutgoingEmail {
+            all_or_nothing: true,
+            ..outgoing("good@example.com, bad@example.com")
+        };
+
+        let report = send(&smtp, &email).await.unwrap();
+
+        assert!(!report.delivered);
+        assert_eq!(report.rejected().count(), 1);
+        let commands = smtp.commands();
+        assert!(commands.iter().any(|c| c == "RSET"));
+        assert!(!commands.iter().any(|c| c == "DATA"));
+    }
+
+    #[tokio::test]
+    async fn nothing_is_sent_when_every_recipient_is_rejected() {
+        let smtp = MockSmtp::start(&[("RCPT TO:", "550 5.1.1 User unknown")]).await;
+
+        let report = send(&smtp, &outgoing("a@example.com, b@example.com")).await.unwrap();
+
+        assert!(!report.delivered);
+        assert_eq!(report.rejected().count(), 2);
+        assert!(report.ensure_delivered().is_err());
+        assert!(!smtp.commands().iter().any(|c| c == "DATA"));
+    }
+
+    fn compliance_footer() -> ComplianceFooter {
+        ComplianceFooter {
+            html: Some("<p>Acme Ltd is registered in England.</p>".to_string()),
+            text: Some("Acme Ltd is registered in England.".to_string()),
+        }
+    }
+
+    #[test]
+    fn compliance_footer_sits_between_the_content_and_the_template_footer() {
+        for branded in [true, false] {
+            let body = compliance_footer().apply(&render_email_template("Quarterly numbers", branded), true);
+
+            let content_at = body.find("Quarterly numbers").unwrap();
+            let footer_at = body.find("<p>Acme Ltd is registered in England.</p>").unwrap();
+            let slot_at = body.find(FOOTER_SLOT).unwrap();
+            assert!(content_at < footer_at);
+            assert!(footer_at < slot_at);
+            if branded {
+                assert!(footer_at < body.find("Sent via W9 Mail").unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn compliance_footer_uses_the_body_content_type() {
+        let text = compliance_footer().apply("Quarterly numbers", false);
+        assert_eq!(text, "Quarterly numbers\n\nAcme Ltd is registered in England.");
+
+        // Only a text footer set: HTML bodies get it escaped
+        let footer = ComplianceFooter {
+            html: None,
+            text: Some("Fish & Chips Ltd\nLondon".to_string()),
+        };
+        let html = footer.apply("<html><body><p>Hi</p></body></html>", true);
+        assert!(html.contains("Fish &amp; Chips Ltd<br />London</div>\n</body>"));
+    }
+
+    #[test]
+    fn compliance_footer_is_not_added_twice_to_a_resent_draft() {
+        let html = compliance_footer().apply(&render_email_template("Quarterly numbers", true), true);
+        assert_eq!(compliance_footer().apply(&html, true), html);
+        assert_eq!(html.matches("Acme Ltd").count(), 1);
+
+        let text = compliance_footer().apply("Quarterly numbers", false);
+        assert_eq!(compliance_footer().apply(&text, false), text);
+    }
+
+    #[test]
+    fn compliance_footer_is_recognised_after_escaping_and_sanitizing() {
+        let footer = ComplianceFooter {
+            html: Some("<p>Fish & Chips Ltd, \"London\"</p>".to_string()),
+            text: None,
+        };
+        // Sanitizing reserializes the footer (& becomes &amp;) and drops the comment
+        let sent = footer.apply("<html><body><p>Hi</p></body></html>", true);
+        let resent = sanitize_html(&sent);
+        assert!(!resent.contains("Fish & Chips"));
+        assert_eq!(footer.apply(&resent, true).matches("Chips Ltd").count(), 1);
+
+        // A text-only footer is escaped on its way into HTML
+        let footer = ComplianceFooter {
+            html: None,
+            text: Some("Fish & Chips Ltd\nLondon".to_string()),
+        };
+        let sent = footer.apply("<p>Hi</p>", true);
+        assert_eq!(footer.apply(&sent, true), sent);
+        assert_eq!(footer.apply(&sanitize_html(&sent), true).matches("Chips Ltd").count(), 1);
+
+        // Quoting an earlier message doesn't stand in for this one's footer
+        let text = compliance_footer().apply("Thanks!\n\n> Acme Ltd is registered in England.", false);
+        assert!(text.ends_with("\n\nAcme Ltd is registered in England."));
+    }
+
+    #[tokio::test]
+    async fn throttling_replies_become_throttled_errors() {
+        let smtp = MockSmtp::start(&[(
+            "RCPT TO:",
+            "451 4.7.500 Server busy. Please try again later in 30 seconds",
+        )])
+        .await;
+        let err = send(&smtp, &outgoing("a@example.com")).await.unwrap_err();
+        let throttled = err.downcast_ref::<Throttled>().expect("451 4.7.x should be a throttle");
+        assert_eq!(throttled.retry_after, Duration::from_secs(30));
+        assert!(throttled.message.contains("Server busy"));
+
+        // 421 throttles whatever it says, and without a hint the default backoff applies
+        let smtp = MockSmtp::start(&[("MAIL FROM:", "421 4.3.2 Service not available")]).await;
+        let err = send(&smtp, &outgoing("a@example.com")).await.unwrap_err();
+        let throttled = err.downcast_ref::<Throttled>().expect("421 should be a throttle");
+        assert_eq!(throttled.retry_after, THROTTLE_BACKOFF);
+
+        // Other temporary failures are plain errors
+        let smtp = MockSmtp::start(&[("MAIL FROM:", "451 4.3.0 Temporary local problem")]).await;
+        let err = send(&smtp, &outgoing("a@example.com")).await.unwrap_err();
+        assert!(err.downcast_ref::<Throttled>().is_none());
+    }
+
+    #[test]
+    fn html_message_has_text_and_html_alternatives() {
+        let email = OutgoingEmail {
+            body: r#"<p>Hello <b>there</b></p><p><a href="https://w9.nu">Site</a></p>"#,
+            as_html: true,
+            ..outgoing("to@example.com")
+        };
+        let (message, message_id) = offline().build_message(&email).unwrap();
+        let raw = message.formatted();
+        let parsed = MessageParser::default().parse(&raw[..]).unwrap();
+
+        assert!(parsed.is_content_type("multipart", "alternative"));
+        assert_eq!(parsed.message_id(), message_id.strip_prefix('<').and_then(|id| id.strip_suffix('>')));
+        let parts: Vec<_> = parsed
+            .root_part()
+            .sub_parts()
+            .unwrap()
+            .iter()
+            .map(|id| parsed.part(*id).unwrap())
+            .collect();
+        assert_eq!(parts.len(), 2);
+        assert!(parts[0].is_content_type("text", "plain"));
+        let text = parts[0].text_contents().unwrap();
+        assert!(text.contains("Hello there"));
+        assert!(text.contains("Site (https://w9.nu)"));
+        assert!(!text.contains('<'));
+        assert!(parts[1].is_content_type("text", "html"));
+        assert!(parts[1].text_contents().unwrap().contains("<b>there</b>"));
+    }
+
+    #[test]
+    fn plain_message_is_a_single_text_part() {
+        let (message, _) = offline().build_message(&outgoing("to@example.com")).unwrap();
+        let raw = message.formatted();
+        let parsed = MessageParser::default().parse(&raw[..]).unwrap();
+
+        assert!(parsed.is_content_type("text", "plain"));
+        assert_eq!(parsed.body_text(0).as_deref().map(str::trim_end), Some("Hello there"));
+    }
+
+    #[test]
+    fn reply_to_header_is_set_when_given() {
+        let email = OutgoingEmail {
+            reply_to: Some("Support Desk <tickets@example.com>"),
+            ..outgoing("to@example.com")
+        };
+        let (message, _) = offline().build_message(&email).unwrap();
+        let raw = message.formatted();
+        let parsed = MessageParser::default().parse(&raw[..]).unwrap();
+
+        let reply_to = parsed.reply_to().and_then(|a| a.first()).unwrap();
+        assert_eq!(reply_to.address(), Some("tickets@example.com"));
+        assert_eq!(reply_to.name(), Some("Support Desk"));
+
+        let (message, _) = offline().build_message(&outgoing("to@example.com")).unwrap();
+        let raw = message.formatted();
+        assert!(MessageParser::default().parse(&raw[..]).unwrap().reply_to().is_none());
+    }
+
+    #[test]
+    fn email_validation() {
+        let valid = [
+            "foo@bar.com",
+            "first.last+tag@sub.example.co.uk",
+            "user@bücher.de",
+            "user@xn--bcher-kva.de",
+            "josé@example.com",
+            "用户@例子.广告",
+        ];
+        for address in valid {
+            assert!(is_valid_email(address), "{:?} should be valid", address);
+        }
+
+        let invalid = [
+            "",
+            "foo",
+            "foo@",
+            "@bar.com",
+            "foo@@bar",
+            "a b@c.de",
+            "foo@bar..com",
+            "Jane <jane@example.com>",
+        ];
+        for address in invalid {
+            assert!(!is_valid_email(address), "{:?} should be invalid", address);
+        }
+    }
+
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+    const JPEG: &[u8] = &[0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x10, b'J', b'F', b'I', b'F'];
+
+    #[test]
+    fn inline_png_and_jpeg_become_cid_attachments() {
+        let jpeg = Base64.encode(JPEG);
+        let html = format!(
+            r#"<img src="data:image/png;base64,{}"><img src='data:image/jpeg;name=photo.jpg;base64,{}
+              {}'><div style="background:url(data:image/png;base64,{})">"#,
+            Base64.encode(PNG),
+            &jpeg[..8],
+            &jpeg[8..],
+            Base64.encode(PNG).trim_end_matches('='),
+        );
+
+        let (out, images) = extract_inline_images(&html);
+
+        assert_eq!(
+            out,
+            r#"<img src="cid:w9-inline-1"><img src='cid:w9-inline-2'><div style="background:url(cid:w9-inline-3)">"#
+        );
+        let image = |n: usize, mime: &str, data: &[u8]| (format!("w9-inline-{}", n), mime.to_string(), data.to_vec());
+        assert_eq!(
+            images,
+            [image(1, "image/png", PNG), image(2, "image/jpeg", JPEG), image(3, "image/png", PNG)]
+        );
+    }
+
+    #[test]
+    fn malformed_data_uris_are_left_in_place() {
+        for html in [
+            r#"<img src="data:image/png;base64,">"#,
+            r#"<img src="data:image/png;base64,!!!!">"#,
+            r#"<img src="data:image/png;base64,iVBO=Rw0KGgo">"#,
+            r#"<img src="data:image/png;base64,A">"#,
+        ] {
+            let (out, images) = extract_inline_images(html);
+            assert_eq!(out, html);
+            assert!(images.is_empty());
+        }
+    }
+
+    #[test]
+    fn lenient_base64_decoding() {
+        let hello = Some(b"hello".to_vec());
+        assert_eq!(decode_base64_lenient("aGVsbG8="), hello);
+        assert_eq!(decode_base64_lenient("aGVs\r\n  bG8="), hello);
+        assert_eq!(decode_base64_lenient("aGVsbG8"), hello);
+        assert_eq!(decode_base64_lenient("aGVsbG8==="), hello);
+        assert_eq!(decode_base64_lenient("-_8"), Some(vec![0xFB, 0xFF]));
+
+        for malformed in ["", "====", "a", "aGV=sbG8", "aGVs*bG8"] {
+            assert_eq!(decode_base64_lenient(malformed), None, "{:?}", malformed);
+        }
+    }
+
+    #[test]
+    fn threading_headers_are_set_when_given() {
+        let email = OutgoingEmail {
+            in_reply_to: Some("<second@example.com>"),
+            references: Some("<first@example.com> <second@example.com>"),
+            ..outgoing("to@example.com")
+        };
+        let (message, _) = offline().build_message(&email).unwrap();
+        let raw = message.formatted();
+        let parsed = MessageParser::default().parse(&raw[..]).unwrap();
+
+        assert_eq!(parsed.in_reply_to().as_text_list(), Some(vec!["second@example.com"]));
+        assert_eq!(
+            parsed.references().as_text_list(),
+            Some(vec!["first@example.com", "second@example.com"])
+        );
+        let headers = String::from_utf8_lossy(&raw);
+        assert!(headers.contains("In-Reply-To: <second@example.com>\r\n"));
+        assert!(headers.contains("References: <first@example.com> <second@example.com>\r\n"));
+    }
+
+    #[test]
+    fn quoted_display_names_keep_their_commas() {
+        assert_eq!(
+            split_recipients(r#""Doe, Jane" <jane@x.com>, bob@x.com"#),
+            [r#""Doe, Jane" <jane@x.com>"#, "bob@x.com"]
+        );
+        assert_eq!(
+            split_recipients(r#""Say \"hi\", Jane" <jane@x.com>,bob@x.com"#),
+            [r#""Say \"hi\", Jane" <jane@x.com>"#, "bob@x.com"]
+        );
+        assert_eq!(split_recipients("a@x.com, , b@x.com,"), ["a@x.com", "b@x.com"]);
+        // Unquoted, the comma still separates entries as it always has
+        assert_eq!(split_recipients("Doe, Jane <jane@x.com>"), ["Doe", "Jane <jane@x.com>"]);
+    }
+
+    #[test]
+    fn single_recipient_quotes_a_display_name_with_a_comma() {
+        for entry in [r#""Doe, Jane" <jane@x.com>"#, "Doe, Jane <jane@x.com>", "  Doe, Jane <jane@x.com>  "] {
+            assert_eq!(single_recipient(entry).as_deref(), Some(r#""Doe, Jane" <jane@x.com>"#), "{:?}", entry);
+        }
+        assert_eq!(single_recipient("jane@x.com").as_deref(), Some("jane@x.com"));
+        assert_eq!(single_recipient("Jane <not an address>"), None);
+
+        let list = format!("{}, bob@x.com", single_recipient("Doe, Jane <jane@x.com>").unwrap());
+        assert_eq!(split_recipients(&list), [r#""Doe, Jane" <jane@x.com>"#, "bob@x.com"]);
+    }
+
+    #[test]
+    fn tls_mode_defaults_to_the_usual_one_for_the_port() {
+        for setting in [None, Some(""), Some("  ")] {
+            assert_eq!(SmtpTlsMode::resolve(587, setting).unwrap(), SmtpTlsMode::StartTls);
+            assert_eq!(SmtpTlsMode::resolve(25, setting).unwrap(), SmtpTlsMode::StartTls);
+            assert_eq!(SmtpTlsMode::resolve(465, setting).unwrap(), SmtpTlsMode::Tls);
+        }
+    }
+
+    #[test]
+    fn tls_mode_setting_overrides_the_port() {
+        let cases = [
+            ("starttls", SmtpTlsMode::StartTls),
+            ("STARTTLS", SmtpTlsMode::StartTls),
+            ("tls", SmtpTlsMode::Tls),
+            ("ssl", SmtpTlsMode::Tls),
+            (" Implicit ", SmtpTlsMode::Tls),
+            ("none", SmtpTlsMode::None),
+            ("off", SmtpTlsMode::None),
+        ];
+        for (setting, mode) in cases {
+            assert_eq!(SmtpTlsMode::resolve(465, Some(setting)).unwrap(), mode, "{:?}", setting);
+            assert_eq!(SmtpTlsMode::resolve(587, Some(setting)).unwrap(), mode, "{:?}", setting);
+        }
+
+        let error = SmtpTlsMode::resolve(587, Some("yes")).unwrap_err();
+        assert_eq!(error.to_string(), r#"SMTP_TLS must be one of starttls, tls, none; got "yes""#);
+    }
+}
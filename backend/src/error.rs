@@ -0,0 +1,126 @@
+// JSON error bodies for handlers. Same shape as the hand-built 400s elsewhere, plus a
+// machine-readable code:
+//   { "status": "error", "code": "invalid_email", "message": "…", "field": "email" }
+
+use axum::{
+    extract::Request,
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Json, Response},
+};
+
+#[derive(Debug)]
+pub struct ApiError {
+    status: StatusCode,
+    code: &'static str,
+    message: String,
+    field: Option<&'static str>,
+    retry_after_secs: Option<u64>,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            code,
+            message: message.into(),
+            field: None,
+            retry_after_secs: None,
+        }
+    }
+
+    /// 400 about one request field.
+    pub fn invalid(field: &'static str, code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, code, message).with_field(field)
+    }
+
+    pub fn forbidden(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(StatusCode::FORBIDDEN, code, message)
+    }
+
+    pub fn with_field(mut self, field: &'static str) -> Self {
+        self.field = Some(field);
+        self
+    }
+
+    /// Sets `Retry-After` and repeats it as `retryAfter` in the body.
+    pub fn with_retry_after(mut self, secs: u64) -> Self {
+        self.retry_after_secs = Some(secs);
+        self
+    }
+
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+}
+
+// Bare status codes (`?` on the many helpers that still return one) get a generic body
+impl From<StatusCode> for ApiError {
+    fn from(status: StatusCode) -> Self {
+        let (code, message) = match status {
+            StatusCode::BAD_REQUEST => ("bad_request", "The request is invalid"),
+            StatusCode::UNAUTHORIZED => ("unauthorized", "Authentication required"),
+            StatusCode::FORBIDDEN => ("forbidden", "You don't have permission to do this"),
+            StatusCode::NOT_FOUND => ("not_found", "Not found"),
+            StatusCode::CONFLICT => ("conflict", "This conflicts with an existing record"),
+            StatusCode::PAYLOAD_TOO_LARGE => ("payload_too_large", "The request is too large"),
+            StatusCode::TOO_MANY_REQUESTS => ("rate_limited", "Too many requests"),
+            StatusCode::BAD_GATEWAY => ("upstream_error", "The mail server returned an error"),
+            StatusCode::SERVICE_UNAVAILABLE => ("unavailable", "Temporarily unavailable"),
+            _ if status.is_server_error() => ("internal_error", "Internal server error"),
+            _ => ("error", status.canonical_reason().unwrap_or("Request failed")),
+        };
+        Self::new(status, code, message)
+    }
+}
+
+// Auth extractor failures carry their own explanation
+impl From<(StatusCode, &'static str)> for ApiError {
+    fn from((status, message): (StatusCode, &'static str)) -> Self {
+        Self {
+            message: message.to_string(),
+            ..Self::from(status)
+        }
+    }
+}
+
+// For modules whose handlers still return bare status codes
+impl From<ApiError> for StatusCode {
+    fn from(error: ApiError) -> Self {
+        error.status
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let mut body = serde_json::json!({
+            "status": "error",
+            "code": self.code,
+            "message": self.message,
+        });
+        if let Some(field) = self.field {
+            body["field"] = field.into();
+        }
+        let Some(secs) = self.retry_after_secs else {
+            return (self.status, Json(body)).into_response();
+        };
+        body["retryAfter"] = secs.into();
+        (self.status, [(header::RETRY_AFTER, secs.to_string())], Json(body)).into_response()
+    }
+}
+
+// Axum rejects a body over the route's DefaultBodyLimit with a plain-text 413 before the
+// handler runs; give it the same JSON shape as every other error. Handlers' own 413s are
+// already JSON and pass through.
+pub async fn payload_too_large_as_json(request: Request, next: Next) -> Response {
+    let response = next.run(request).await;
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/json"));
+    if response.status() != StatusCode::PAYLOAD_TOO_LARGE || is_json {
+        return response;
+    }
+    ApiError::from(StatusCode::PAYLOAD_TOO_LARGE).into_response()
+}
@@ -1,166 +1,585 @@
+use std::collections::HashSet;
+
 use axum::{
+    body::{Body, Bytes},
     extract::{Path, Query, State},
-    http::StatusCode,
-    response::Json,
+    http::{header, HeaderMap, HeaderName, StatusCode},
+    response::{IntoResponse, Json, Response},
 };
-use sqlx::Row;
+use base64::{engine::general_purpose::STANDARD as Base64, Engine};
+use sqlx::{postgres::PgRow, Acquire, PgConnection, Postgres, QueryBuilder, Row};
 use uuid::Uuid;
 
 use crate::{
-    auth::{AuthUser, UserRole},
+    audit,
+    csv_export,
+    auth::{
+        build_system_email_html, send_system_email, AuthUser, UserRole, SCOPE_ACCOUNTS_READ, SCOPE_ACCOUNTS_WRITE, SCOPE_ADMIN, SCOPE_INBOX_READ,
+        SCOPE_SEND, SCOPE_SENT_READ,
+    },
+    drafts,
+    idempotency::{self, Claim},
+    inbox,
     mailer::{self, SenderKind, SenderSummary},
-    AppState, CreateAccountRequest, CreateAliasRequest, DefaultSenderResponse, EmailAccount,
-    EmailAlias, InboxQuery, SendEmailRequest, UpdateAccountRequest, UpdateAliasRequest,
-    UpdateDefaultSenderRequest,
+    oauth,
+    secrets::SecretBox,
+    sent_recipients,
+    telemetry,
+    unsubscribe,
+    web_view,
+    webhooks,
+    AccountListParams, AccountUser, ActivationResponse, AliasListParams, AppState, AttachmentRequest, BulkAccountResult, BulkAliasResult, CreateAccountRequest, CreateAliasRequest, DefaultSenderResponse, EmailAccount,
+    EmailAlias, EmailPreviewRequest, GrantAccountRequest, StringOrList, InboxMessageQuery, InboxQuery, InboxSearchQuery, ListSort, Page, SendEmailRequest, SenderListParams, SenderOption, SentMessage, SentMessageDetail, SentQuery, TestDefaultSenderRequest, UpdateAccountRequest,
+    UpdateAliasRequest, UpdateDefaultSenderRequest, UpdateInboxMessageRequest,
+};
+use crate::email::{
+    is_valid_email, is_valid_message_id, split_recipients, validate_custom_header, CredentialCheckError, CredentialFailure, EmailAttachment, EmailService,
+    OutgoingEmail, SendReport, SenderAuthFailed, SmtpTimedOut, Throttled,
 };
-use crate::email::EmailService;
+use crate::error::ApiError;
+use crate::send_limits::{QuotaCheck, RETRY_AFTER_SECS};
+
+const ACCOUNT_COLUMNS: &str = "id, email, display_name, is_active, owner_id, is_public, compliance_footer_html, compliance_footer_text, cooldown_until, throttle_count, deleted_at, daily_send_limit, sender_name, branding_enabled, last_send_at, last_send_error, created_at";
+
+const ALIAS_SELECT: &str = r#"
+        SELECT 
+            aliases.id,
+            aliases.alias_email,
+            aliases.display_name,
+            aliases.is_active,
+            aliases.account_id,
+            accounts.email,
+            accounts.display_name,
+            accounts.is_active,
+            aliases.owner_id,
+            aliases.is_public,
+            aliases.compliance_footer_html,
+            aliases.compliance_footer_text,
+            aliases.is_catchall,
+            aliases.created_at
+        FROM aliases
+        JOIN accounts ON aliases.account_id = accounts.id
+"#;
+
+// Binds the viewer as $1: NULL (admins) sees every alias, anyone else their own and
+// public ones. Aliases of a soft-deleted account are hidden until the account is restored.
+const ALIAS_VISIBILITY: &str = "WHERE accounts.deleted_at IS NULL AND ($1::TEXT IS NULL OR aliases.owner_id = $1 OR aliases.is_public = TRUE)";
+
+const DEFAULT_PAGE_SIZE: u32 = 100;
+const MAX_PAGE_SIZE: u32 = 200;
+
+pub(crate) fn page_bounds(limit: Option<u32>, offset: Option<u32>) -> (i64, i64) {
+    let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+    (limit as i64, offset.unwrap_or(0) as i64)
+}
+
+// Escapes LIKE wildcards so a search for "a_b" doesn't match "axb"
+fn like_pattern(search: &str) -> String {
+    let escaped = search
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+// Shared by the count and the page query so both see the same rows
+fn push_account_filters(
+    builder: &mut QueryBuilder<'_, Postgres>,
+    owner: Option<&str>,
+    search: Option<&str>,
+    active: Option<bool>,
+    include_deleted: bool,
+) {
+    builder.push(" WHERE TRUE");
+    if !include_deleted {
+        builder.push(" AND deleted_at IS NULL");
+    }
+    if let Some(owner) = owner {
+        builder.push(" AND (owner_id = ");
+        builder.push_bind(owner.to_string());
+        builder.push(" OR is_public = TRUE)");
+    }
+    if let Some(search) = search {
+        let pattern = like_pattern(search);
+        builder.push(" AND (email ILIKE ");
+        builder.push_bind(pattern.clone());
+        builder.push(" OR display_name ILIKE ");
+        builder.push_bind(pattern);
+        builder.push(")");
+    }
+    if let Some(active) = active {
+        builder.push(" AND is_active = ");
+        builder.push_bind(active);
+    }
+}
+
+// Same visibility as ALIAS_VISIBILITY, built with bound params for the list filters
+fn push_alias_filters(
+    builder: &mut QueryBuilder<'_, Postgres>,
+    owner: Option<&str>,
+    active: Option<bool>,
+    account_id: Option<&str>,
+) {
+    builder.push(" WHERE accounts.deleted_at IS NULL");
+    if let Some(owner) = owner {
+        builder.push(" AND (aliases.owner_id = ");
+        builder.push_bind(owner.to_string());
+        builder.push(" OR aliases.is_public = TRUE)");
+    }
+    if let Some(active) = active {
+        builder.push(" AND aliases.is_active = ");
+        builder.push_bind(active);
+    }
+    if let Some(account_id) = account_id {
+        builder.push(" AND aliases.account_id = ");
+        builder.push_bind(account_id.to_string());
+    }
+}
+
+fn account_from_row(row: &PgRow) -> EmailAccount {
+    EmailAccount {
+        id: row.get::<String, _>(0),
+        email: row.get::<String, _>(1),
+        display_name: row.get::<String, _>(2),
+        is_active: row.get::<bool, _>(3),
+        owner_id: row.get::<Option<String>, _>(4),
+        is_public: row.get::<bool, _>(5),
+        compliance_footer_html: row.get::<Option<String>, _>(6),
+        compliance_footer_text: row.get::<Option<String>, _>(7),
+        cooldown_until: row.get::<Option<i64>, _>(8),
+        throttle_count: row.get::<i64, _>(9),
+        deleted_at: row.get::<Option<i64>, _>(10),
+        daily_send_limit: row.get::<Option<i64>, _>(11),
+        sender_name: row.get::<Option<String>, _>(12),
+        branding_enabled: row.get::<bool, _>(13),
+        last_send_at: row.get::<Option<i64>, _>(14),
+        last_send_error: row.get::<Option<String>, _>(15),
+        created_at: row.get::<i64, _>(16),
+    }
+}
+
+fn alias_from_row(row: &PgRow) -> EmailAlias {
+    EmailAlias {
+        id: row.get::<String, _>(0),
+        alias_email: row.get::<String, _>(1),
+        display_name: row.get::<Option<String>, _>(2),
+        is_active: row.get::<bool, _>(3),
+        account_id: row.get::<String, _>(4),
+        account_email: row.get::<String, _>(5),
+        account_display_name: row.get::<String, _>(6),
+        account_is_active: row.get::<bool, _>(7),
+        owner_id: row.get::<Option<String>, _>(8),
+        is_public: row.get::<bool, _>(9),
+        compliance_footer_html: row.get::<Option<String>, _>(10),
+        compliance_footer_text: row.get::<Option<String>, _>(11),
+        is_catchall: row.get::<bool, _>(12),
+        created_at: row.get::<i64, _>(13),
+    }
+}
+
+pub fn invalid_email_error(field: &'static str, value: &str) -> ApiError {
+    ApiError::invalid(field, "invalid_email", format!("{:?} is not a valid email address", value))
+}
+
+// Empty strings clear an optional text field (footers, sender name) rather than storing a blank
+fn non_blank(value: String) -> Option<String> {
+    let trimmed = value.trim();
+    if trimmed.is_empty() {
+        None
+    } else {
+        Some(trimmed.to_string())
+    }
+}
 
 pub async fn get_accounts(
     State(state): State<AppState>,
     user: AuthUser,
-) -> Result<Json<Vec<EmailAccount>>, StatusCode> {
+    Query(params): Query<AccountListParams>,
+) -> Result<Json<Page<EmailAccount>>, ApiError> {
     user.ensure_password_updated()?;
+    user.ensure_scope(SCOPE_ACCOUNTS_READ)?;
     if !matches!(user.role, UserRole::Admin | UserRole::Dev) {
-        return Err(StatusCode::FORBIDDEN);
+        return Err(StatusCode::FORBIDDEN.into());
     }
-    
-    // Admin sees all, others see their own + public
-    let query = if matches!(user.role, UserRole::Admin) {
-        "SELECT id, email, display_name, is_active, owner_id, is_public FROM accounts"
-    } else {
-        "SELECT id, email, display_name, is_active, owner_id, is_public FROM accounts WHERE owner_id = ? OR is_public = 1"
-    };
-    
-    let mut query_builder = sqlx::query(query);
-    if !matches!(user.role, UserRole::Admin) {
-        query_builder = query_builder.bind(&user.id);
+    let (limit, offset) = page_bounds(params.limit, params.offset);
+    let include_deleted = params.include_deleted.unwrap_or(false);
+    if include_deleted && !matches!(user.role, UserRole::Admin) {
+        return Err(StatusCode::FORBIDDEN.into());
     }
-    
-    let rows = query_builder
+
+    // Admin sees all, others see their own + public
+    let owner = (!matches!(user.role, UserRole::Admin)).then_some(user.id.as_str());
+    let search = params.search.as_deref().map(str::trim).filter(|s| !s.is_empty());
+
+    let mut count = QueryBuilder::new("SELECT COUNT(*) FROM accounts");
+    push_account_filters(&mut count, owner, search, params.active, include_deleted);
+    let total: i64 = count
+        .build_query_scalar()
+        .fetch_one(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let mut select = QueryBuilder::new(format!("SELECT {} FROM accounts", ACCOUNT_COLUMNS));
+    push_account_filters(&mut select, owner, search, params.active, include_deleted);
+    select.push(match params.sort.unwrap_or_default() {
+        ListSort::Email => " ORDER BY email ASC",
+        ListSort::Created => " ORDER BY created_at DESC, email ASC",
+    });
+    select.push(" LIMIT ");
+    select.push_bind(limit);
+    select.push(" OFFSET ");
+    select.push_bind(offset);
+    let rows = select
+        .build()
         .fetch_all(&state.db)
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    let accounts: Vec<EmailAccount> = rows
+    let items = rows
         .into_iter()
-        .map(|row| EmailAccount {
-            id: row.get::<String, _>(0),
-            email: row.get::<String, _>(1),
-            display_name: row.get::<String, _>(2),
-            is_active: row.get::<bool, _>(3),
-            owner_id: row.get::<Option<String>, _>(4),
-            is_public: row.get::<bool, _>(5),
-        })
+        .map(|row| account_from_row(&row))
         .collect();
 
-    Ok(Json(accounts))
+    Ok(Json(Page {
+        items,
+        total,
+        limit,
+        offset,
+    }))
 }
 
-pub async fn create_account(
+// Same visibility as the list: admins see every account, including soft-deleted ones,
+// others only their own and public ones. Anything hidden is a 404.
+pub async fn get_account(
     State(state): State<AppState>,
+    Path(id): Path<String>,
     user: AuthUser,
-    Json(req): Json<CreateAccountRequest>,
-) -> Result<Json<serde_json::Value>, StatusCode> {
+) -> Result<Json<EmailAccount>, ApiError> {
     user.ensure_password_updated()?;
+    user.ensure_scope(SCOPE_ACCOUNTS_READ)?;
     if !matches!(user.role, UserRole::Admin | UserRole::Dev) {
-        return Err(StatusCode::FORBIDDEN);
+        return Err(StatusCode::FORBIDDEN.into());
     }
 
-    // Check if email already exists
-    let existing = sqlx::query("SELECT email FROM accounts WHERE email = ?")
-        .bind(&req.email)
+    let mut select = QueryBuilder::new(format!("SELECT {} FROM accounts", ACCOUNT_COLUMNS));
+    let is_admin = matches!(user.role, UserRole::Admin);
+    let owner = (!is_admin).then_some(user.id.as_str());
+    push_account_filters(&mut select, owner, None, None, is_admin);
+    select.push(" AND id = ");
+    select.push_bind(&id);
+
+    let row = select
+        .build()
         .fetch_optional(&state.db)
         .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(account_from_row(&row)))
+}
 
-    if existing.is_some() {
-        return Ok(Json(serde_json::json!({
-            "status": "error",
-            "message": "Email address already exists"
-        })));
+pub async fn create_account(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(req): Json<CreateAccountRequest>,
+) -> Result<Response, ApiError> {
+    user.ensure_password_updated()?;
+    user.ensure_scope(SCOPE_ACCOUNTS_WRITE)?;
+    if !matches!(user.role, UserRole::Admin | UserRole::Dev) {
+        return Err(StatusCode::FORBIDDEN.into());
     }
 
-    let id = Uuid::new_v4().to_string();
-    
-    match sqlx::query(
-        "INSERT INTO accounts (id, email, display_name, password, is_active, owner_id, is_public) VALUES (?, ?, ?, ?, ?, ?, ?)"
-    )
-    .bind(&id)
-    .bind(&req.email)
-    .bind(&req.display_name)
-    .bind(&req.password)
-    .bind(req.is_active)
-    .bind(&user.id)
-    .bind(req.is_public)
-    .execute(&state.db)
-    .await {
-        Ok(_) => {
-            let account = EmailAccount {
-                id,
-                email: req.email,
-                display_name: req.display_name,
-                is_active: req.is_active,
-                owner_id: Some(user.id),
-                is_public: req.is_public,
-            };
+    let mut conn = state.db.acquire().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let error_response =
+        |message: String| Json(serde_json::json!({ "status": "error", "message": message })).into_response();
+    match insert_account(&mut conn, &state.secrets, &user.id, req).await {
+        Ok(account) => {
+            audit::record(&state.db, &user, "account.create", "account", &account.id, serde_json::json!({ "email": account.email })).await;
             Ok(Json(serde_json::json!({
                 "status": "success",
                 "message": "Account created successfully",
                 "account": account
-            })))
+            }))
+            .into_response())
         }
-        Err(e) => {
-            eprintln!("Database error: {}", e);
-            Ok(Json(serde_json::json!({
-                "status": "error",
-                "message": format!("Failed to create account: {}", e)
-            })))
+        Err(AccountInsertError::InvalidEmail(email)) => Err(invalid_email_error("email", &email)),
+        Err(e @ (AccountInsertError::Duplicate | AccountInsertError::Deleted)) => Ok(error_response(e.message())),
+        Err(AccountInsertError::Seal(e)) => {
+            tracing::error!(error = %e, user_id = %user.id, "failed to encrypt account password");
+            Err(StatusCode::INTERNAL_SERVER_ERROR.into())
+        }
+        Err(AccountInsertError::Database(e)) => {
+            tracing::error!(error = %e, user_id = %user.id, "failed to create account");
+            Ok(error_response(format!("Failed to create account: {}", e)))
+        }
+    }
+}
+
+enum AccountInsertError {
+    InvalidEmail(String),
+    Duplicate,
+    // Same email as a soft-deleted account, which has to be restored instead
+    Deleted,
+    Seal(anyhow::Error),
+    Database(sqlx::Error),
+}
+
+impl AccountInsertError {
+    fn message(&self) -> String {
+        match self {
+            Self::InvalidEmail(email) => format!("{:?} is not a valid email address", email),
+            Self::Duplicate => "Email address already exists".to_string(),
+            Self::Deleted => "An account with this email was deleted; ask an admin to restore it".to_string(),
+            Self::Seal(_) | Self::Database(_) => "Failed to create account".to_string(),
         }
     }
 }
 
+impl From<sqlx::Error> for AccountInsertError {
+    fn from(e: sqlx::Error) -> Self {
+        Self::Database(e)
+    }
+}
+
+// Validates and inserts one account owned by `owner_id`; shared by create and import
+async fn insert_account(
+    conn: &mut PgConnection,
+    secrets: &SecretBox,
+    owner_id: &str,
+    req: CreateAccountRequest,
+) -> Result<EmailAccount, AccountInsertError> {
+    let email = req.email.trim().to_string();
+    if !is_valid_email(&email) {
+        return Err(AccountInsertError::InvalidEmail(email));
+    }
+
+    // Check if email already exists
+    let existing = sqlx::query("SELECT deleted_at FROM accounts WHERE email = $1")
+        .bind(&email)
+        .fetch_optional(&mut *conn)
+        .await?;
+    if let Some(row) = existing {
+        return Err(if row.get::<Option<i64>, _>(0).is_some() {
+            AccountInsertError::Deleted
+        } else {
+            AccountInsertError::Duplicate
+        });
+    }
+
+    let id = Uuid::new_v4().to_string();
+    let sealed_password = secrets.seal(&req.password).map_err(AccountInsertError::Seal)?;
+    let sender_name = req.sender_name.and_then(non_blank);
+
+    let created_at: i64 = sqlx::query_scalar(
+        "INSERT INTO accounts (id, email, display_name, password, is_active, owner_id, is_public, sender_name) VALUES ($1, $2, $3, $4, $5, $6, $7, $8) RETURNING created_at"
+    )
+    .bind(&id)
+    .bind(&email)
+    .bind(&req.display_name)
+    .bind(&sealed_password)
+    .bind(req.is_active)
+    .bind(owner_id)
+    .bind(req.is_public)
+    .bind(&sender_name)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok(EmailAccount {
+        id,
+        email,
+        display_name: req.display_name,
+        is_active: req.is_active,
+        owner_id: Some(owner_id.to_string()),
+        is_public: req.is_public,
+        compliance_footer_html: None,
+        compliance_footer_text: None,
+        cooldown_until: None,
+        throttle_count: 0,
+        deleted_at: None,
+        daily_send_limit: None,
+        sender_name,
+        branding_enabled: true,
+        last_send_at: None,
+        last_send_error: None,
+        created_at,
+    })
+}
+
+const MAX_ACCOUNT_IMPORT: usize = 100;
+
+// Admin onboarding: a JSON array of create requests, or CSV with a header row naming
+// email, displayName, password and optionally isActive, isPublic, senderName. Each row is
+// inserted under its own savepoint, so a bad or duplicate row is reported and skipped.
+pub async fn import_accounts(
+    State(state): State<AppState>,
+    user: AuthUser,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<Vec<BulkAccountResult>>, ApiError> {
+    user.ensure_password_updated()?;
+    user.ensure_scope(SCOPE_ACCOUNTS_WRITE)?;
+    if !matches!(user.role, UserRole::Admin) {
+        return Err(StatusCode::FORBIDDEN.into());
+    }
+
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    let rows: Vec<Result<CreateAccountRequest, String>> = if content_type.starts_with("application/json") {
+        let items: Vec<serde_json::Value> = serde_json::from_slice(&body).map_err(|e| {
+            ApiError::new(StatusCode::BAD_REQUEST, "invalid_import", format!("Expected a JSON array of accounts: {}", e))
+        })?;
+        items
+            .into_iter()
+            .map(|item| serde_json::from_value(item).map_err(|e| format!("Invalid account: {}", e)))
+            .collect()
+    } else if content_type.starts_with("text/csv") {
+        let text = std::str::from_utf8(&body)
+            .map_err(|_| ApiError::new(StatusCode::BAD_REQUEST, "invalid_import", "CSV must be UTF-8"))?;
+        csv_export::parse_account_rows(text).map_err(|message| ApiError::new(StatusCode::BAD_REQUEST, "invalid_import", message))?
+    } else {
+        return Err(ApiError::new(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "unsupported_media_type",
+            "Send application/json or text/csv",
+        ));
+    };
+    if rows.is_empty() || rows.len() > MAX_ACCOUNT_IMPORT {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            "invalid_batch_size",
+            format!("Send between 1 and {} accounts", MAX_ACCOUNT_IMPORT),
+        ));
+    }
+
+    let mut tx = state.db.begin().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut results = Vec::with_capacity(rows.len());
+
+    for (index, row) in rows.into_iter().enumerate() {
+        let req = match row {
+            Ok(req) => req,
+            Err(message) => {
+                results.push(BulkAccountResult::failed(index, "error", message));
+                continue;
+            }
+        };
+        let mut savepoint = tx.begin().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        let outcome = insert_account(&mut savepoint, &state.secrets, &user.id, req).await;
+        let result = match outcome {
+            Ok(account) => {
+                savepoint.commit().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+                results.push(BulkAccountResult {
+                    index,
+                    status: "created",
+                    account: Some(account),
+                    message: None,
+                });
+                continue;
+            }
+            Err(e @ (AccountInsertError::Duplicate | AccountInsertError::Deleted)) => {
+                BulkAccountResult::failed(index, "skipped", e.message())
+            }
+            Err(e) => {
+                match &e {
+                    AccountInsertError::Seal(err) => {
+                        tracing::error!(error = %err, user_id = %user.id, index, "failed to encrypt imported account password")
+                    }
+                    AccountInsertError::Database(err) => {
+                        tracing::error!(error = %err, user_id = %user.id, index, "failed to insert imported account")
+                    }
+                    _ => {}
+                }
+                BulkAccountResult::failed(index, "error", e.message())
+            }
+        };
+        savepoint.rollback().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        results.push(result);
+    }
+
+    tx.commit().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    for account in results.iter().filter_map(|r| r.account.as_ref()) {
+        let detail = serde_json::json!({ "email": account.email, "import": true });
+        audit::record(&state.db, &user, "account.create", "account", &account.id, detail).await;
+    }
+    Ok(Json(results))
+}
+
 pub async fn update_account(
     State(state): State<AppState>,
     Path(id): Path<String>,
     user: AuthUser,
     Json(req): Json<UpdateAccountRequest>,
-) -> Result<Json<EmailAccount>, StatusCode> {
+) -> Result<Json<EmailAccount>, ApiError> {
     user.ensure_password_updated()?;
+    user.ensure_scope(SCOPE_ACCOUNTS_WRITE)?;
     
-    // Check ownership or admin
-    let owner_row = sqlx::query("SELECT owner_id FROM accounts WHERE id = ?")
+    // Check ownership or admin; deleted accounts must be restored before editing
+    let owner_row = sqlx::query("SELECT owner_id FROM accounts WHERE id = $1 AND deleted_at IS NULL")
         .bind(&id)
         .fetch_optional(&state.db)
         .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
     
-    let owner_id = owner_row.and_then(|row| row.get::<Option<String>, _>(0));
+    let owner_id = owner_row.get::<Option<String>, _>(0);
     let is_owner = owner_id.as_ref().map(|oid| oid == &user.id).unwrap_or(false);
     let is_admin = matches!(user.role, UserRole::Admin);
     
     if !is_owner && !is_admin {
-        return Err(StatusCode::FORBIDDEN);
+        return Err(StatusCode::FORBIDDEN.into());
     }
 
     // Return error if no field was provided
-    if req.is_active.is_none() && req.password.is_none() && req.owner_id.is_none() && req.is_public.is_none() {
-        return Err(StatusCode::BAD_REQUEST);
+    if req.is_active.is_none()
+        && req.password.is_none()
+        && req.owner_id.is_none()
+        && req.is_public.is_none()
+        && req.compliance_footer_html.is_none()
+        && req.compliance_footer_text.is_none()
+        && req.daily_send_limit.is_none()
+        && req.sender_name.is_none()
+        && req.branding_enabled.is_none()
+    {
+        return Err(ApiError::new(StatusCode::BAD_REQUEST, "nothing_to_update", "No account fields were provided"));
     }
 
     // Only admin can change ownership
     if req.owner_id.is_some() && !is_admin {
-        return Err(StatusCode::FORBIDDEN);
+        return Err(StatusCode::FORBIDDEN.into());
+    }
+
+    // Compliance footers are set by admins so owners can't opt out of them
+    if (req.compliance_footer_html.is_some() || req.compliance_footer_text.is_some()) && !is_admin {
+        return Err(StatusCode::FORBIDDEN.into());
+    }
+
+    // Branding is part of what the service provides, so turning it off is up to admins too
+    if req.branding_enabled.is_some() && !is_admin {
+        return Err(StatusCode::FORBIDDEN.into());
+    }
+
+    // Likewise the send quota, which exists to contain a leaked owner token
+    if req.daily_send_limit.is_some() && !is_admin {
+        return Err(StatusCode::FORBIDDEN.into());
+    }
+    if req.daily_send_limit.is_some_and(|limit| limit < 0) {
+        return Err(ApiError::invalid(
+            "dailySendLimit",
+            "invalid_limit",
+            "dailySendLimit can't be negative; use 0 to remove it",
+        ));
     }
 
     // Update is_active if provided
     if let Some(is_active) = req.is_active {
-        sqlx::query("UPDATE accounts SET is_active = ? WHERE id = ?")
+        sqlx::query("UPDATE accounts SET is_active = $1 WHERE id = $2")
             .bind(is_active)
             .bind(&id)
             .execute(&state.db)
             .await
             .map_err(|e| {
-                eprintln!("Database update error: {}", e);
+                tracing::error!(error = %e, user_id = %user.id, account_id = %id, "failed to update account");
                 StatusCode::INTERNAL_SERVER_ERROR
             })?;
     }
@@ -168,232 +587,567 @@ pub async fn update_account(
     // Update password if provided
     if let Some(password) = req.password {
         if password.is_empty() {
-            return Err(StatusCode::BAD_REQUEST);
+            return Err(ApiError::invalid("password", "password_required", "Password can't be empty"));
         }
-        sqlx::query("UPDATE accounts SET password = ? WHERE id = ?")
-            .bind(&password)
+        let sealed_password = state
+            .secrets
+            .seal(&password)
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        sqlx::query("UPDATE accounts SET password = $1 WHERE id = $2")
+            .bind(&sealed_password)
             .bind(&id)
             .execute(&state.db)
             .await
             .map_err(|e| {
-                eprintln!("Database update error: {}", e);
+                tracing::error!(error = %e, user_id = %user.id, account_id = %id, "failed to update account");
                 StatusCode::INTERNAL_SERVER_ERROR
             })?;
     }
 
     // Update owner_id if provided (admin only)
     if let Some(owner_id) = req.owner_id {
-        sqlx::query("UPDATE accounts SET owner_id = ? WHERE id = ?")
+        sqlx::query("UPDATE accounts SET owner_id = $1 WHERE id = $2")
             .bind(&owner_id)
             .bind(&id)
             .execute(&state.db)
             .await
             .map_err(|e| {
-                eprintln!("Database update error: {}", e);
+                tracing::error!(error = %e, user_id = %user.id, account_id = %id, "failed to update account");
                 StatusCode::INTERNAL_SERVER_ERROR
             })?;
     }
 
     // Update is_public if provided
     if let Some(is_public) = req.is_public {
-        sqlx::query("UPDATE accounts SET is_public = ? WHERE id = ?")
+        sqlx::query("UPDATE accounts SET is_public = $1 WHERE id = $2")
             .bind(is_public)
             .bind(&id)
             .execute(&state.db)
             .await
             .map_err(|e| {
-                eprintln!("Database update error: {}", e);
+                tracing::error!(error = %e, user_id = %user.id, account_id = %id, "failed to update account");
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+    }
+
+    // Update compliance footers if provided (admin only)
+    if let Some(footer_html) = req.compliance_footer_html {
+        sqlx::query("UPDATE accounts SET compliance_footer_html = $1 WHERE id = $2")
+            .bind(non_blank(footer_html))
+            .bind(&id)
+            .execute(&state.db)
+            .await
+            .map_err(|e| {
+                tracing::error!(error = %e, user_id = %user.id, account_id = %id, "failed to update account");
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+    }
+
+    if let Some(footer_text) = req.compliance_footer_text {
+        sqlx::query("UPDATE accounts SET compliance_footer_text = $1 WHERE id = $2")
+            .bind(non_blank(footer_text))
+            .bind(&id)
+            .execute(&state.db)
+            .await
+            .map_err(|e| {
+                tracing::error!(error = %e, user_id = %user.id, account_id = %id, "failed to update account");
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+    }
+
+    if let Some(limit) = req.daily_send_limit {
+        sqlx::query("UPDATE accounts SET daily_send_limit = $1 WHERE id = $2")
+            .bind((limit > 0).then_some(limit))
+            .bind(&id)
+            .execute(&state.db)
+            .await
+            .map_err(|e| {
+                tracing::error!(error = %e, user_id = %user.id, account_id = %id, "failed to update account");
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+    }
+
+    if let Some(sender_name) = req.sender_name {
+        sqlx::query("UPDATE accounts SET sender_name = $1 WHERE id = $2")
+            .bind(non_blank(sender_name))
+            .bind(&id)
+            .execute(&state.db)
+            .await
+            .map_err(|e| {
+                tracing::error!(error = %e, user_id = %user.id, account_id = %id, "failed to update account");
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+    }
+
+    if let Some(branding_enabled) = req.branding_enabled {
+        sqlx::query("UPDATE accounts SET branding_enabled = $1 WHERE id = $2")
+            .bind(branding_enabled)
+            .bind(&id)
+            .execute(&state.db)
+            .await
+            .map_err(|e| {
+                tracing::error!(error = %e, user_id = %user.id, account_id = %id, "failed to update account");
                 StatusCode::INTERNAL_SERVER_ERROR
             })?;
     }
 
-    // Fetch and return updated account
-    let row = sqlx::query("SELECT id, email, display_name, is_active, owner_id, is_public FROM accounts WHERE id = ?")
+    // Fetch and return updated account; only a racing delete can make it vanish now
+    let row = sqlx::query(&format!("SELECT {} FROM accounts WHERE id = $1", ACCOUNT_COLUMNS))
         .bind(&id)
-        .fetch_one(&state.db)
+        .fetch_optional(&state.db)
         .await
-        .map_err(|_| StatusCode::NOT_FOUND)?;
-
-    let account = EmailAccount {
-        id: row.get::<String, _>(0),
-        email: row.get::<String, _>(1),
-        display_name: row.get::<String, _>(2),
-        is_active: row.get::<bool, _>(3),
-        owner_id: row.get::<Option<String>, _>(4),
-        is_public: row.get::<bool, _>(5),
-    };
+        .map_err(|e| {
+            tracing::error!(error = %e, user_id = %user.id, account_id = %id, "failed to load updated account");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
 
-    Ok(Json(account))
+    Ok(Json(account_from_row(&row)))
 }
 
 pub async fn delete_account(
     State(state): State<AppState>,
     Path(id): Path<String>,
     user: AuthUser,
-) -> Result<StatusCode, StatusCode> {
+) -> Result<StatusCode, ApiError> {
     user.ensure_password_updated()?;
+    user.ensure_scope(SCOPE_ACCOUNTS_WRITE)?;
     
     // Check ownership or admin
-    let owner_row = sqlx::query("SELECT owner_id FROM accounts WHERE id = ?")
+    let owner_row = sqlx::query("SELECT owner_id FROM accounts WHERE id = $1 AND deleted_at IS NULL")
         .bind(&id)
         .fetch_optional(&state.db)
         .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
     
-    let owner_id = owner_row.and_then(|row| row.get::<Option<String>, _>(0));
+    let owner_id = owner_row.get::<Option<String>, _>(0);
     let is_owner = owner_id.as_ref().map(|oid| oid == &user.id).unwrap_or(false);
     let is_admin = matches!(user.role, UserRole::Admin);
     
     if !is_owner && !is_admin {
-        return Err(StatusCode::FORBIDDEN);
+        return Err(StatusCode::FORBIDDEN.into());
     }
 
-    let result = sqlx::query("DELETE FROM accounts WHERE id = ?")
-        .bind(&id)
-        .execute(&state.db)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    // Soft delete: aliases, OAuth tokens and sent history stay attached for a restore
+    let deleted: Option<String> = sqlx::query_scalar(
+        "UPDATE accounts SET deleted_at = $2 WHERE id = $1 AND deleted_at IS NULL RETURNING email",
+    )
+    .bind(&id)
+    .bind(chrono::Utc::now().timestamp())
+    .fetch_optional(&state.db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    if result.rows_affected() == 0 {
-        return Err(StatusCode::NOT_FOUND);
-    }
+    let Some(email) = deleted else {
+        return Err(StatusCode::NOT_FOUND.into());
+    };
+    audit::record(&state.db, &user, "account.delete", "account", &id, serde_json::json!({ "email": email })).await;
 
     if let Err(e) = mailer::delete_default_if_matches(&state.db, SenderKind::Account, &id).await {
-        eprintln!("Failed to clear default sender after account deletion: {}", e);
+        tracing::warn!(error = %e, account_id = %id, "failed to clear default sender after account deletion");
     }
 
     Ok(StatusCode::NO_CONTENT)
 }
 
-pub async fn get_aliases(
+// Sharing an account is up to its owner or an admin; deleted accounts are not found
+async fn ensure_account_manager(state: &AppState, user: &AuthUser, account_id: &str) -> Result<(), ApiError> {
+    let owner_id: Option<String> =
+        sqlx::query_scalar("SELECT owner_id FROM accounts WHERE id = $1 AND deleted_at IS NULL")
+            .bind(account_id)
+            .fetch_optional(&state.db)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+            .ok_or(StatusCode::NOT_FOUND)?;
+    if owner_id.as_deref() == Some(user.id.as_str()) || matches!(user.role, UserRole::Admin) {
+        Ok(())
+    } else {
+        Err(StatusCode::FORBIDDEN.into())
+    }
+}
+
+pub async fn list_account_users(
     State(state): State<AppState>,
+    Path(id): Path<String>,
     user: AuthUser,
-) -> Result<Json<Vec<EmailAlias>>, StatusCode> {
+) -> Result<Json<Vec<AccountUser>>, ApiError> {
     user.ensure_password_updated()?;
-    if !matches!(user.role, UserRole::Admin | UserRole::Dev) {
-        return Err(StatusCode::FORBIDDEN);
-    }
+    user.ensure_scope(SCOPE_ACCOUNTS_READ)?;
+    ensure_account_manager(&state, &user, &id).await?;
 
-    // Admin sees all, others see their own + public
-    let query = if matches!(user.role, UserRole::Admin) {
-        r#"
-        SELECT 
-            aliases.id,
-            aliases.alias_email,
-            aliases.display_name,
-            aliases.is_active,
-            aliases.account_id,
-            accounts.email,
-            accounts.display_name,
-            accounts.is_active,
-            aliases.owner_id,
-            aliases.is_public
-        FROM aliases
-        JOIN accounts ON aliases.account_id = accounts.id
-        ORDER BY aliases.alias_email ASC
-        "#
-    } else {
+    let rows = sqlx::query(
         r#"
-        SELECT 
-            aliases.id,
-            aliases.alias_email,
-            aliases.display_name,
-            aliases.is_active,
-            aliases.account_id,
-            accounts.email,
-            accounts.display_name,
-            accounts.is_active,
-            aliases.owner_id,
-            aliases.is_public
-        FROM aliases
-        JOIN accounts ON aliases.account_id = accounts.id
-        WHERE aliases.owner_id = ? OR aliases.is_public = 1
-        ORDER BY aliases.alias_email ASC
-        "#
-    };
+        SELECT users.id, users.email, user_accounts.created_at
+        FROM user_accounts
+        JOIN users ON users.id = user_accounts.user_id
+        WHERE user_accounts.account_id = $1
+        ORDER BY users.email ASC
+        "#,
+    )
+    .bind(&id)
+    .fetch_all(&state.db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(
+        rows.into_iter()
+            .map(|row| AccountUser {
+                user_id: row.get::<String, _>(0),
+                email: row.get::<String, _>(1),
+                granted_at: row.get::<i64, _>(2),
+            })
+            .collect(),
+    ))
+}
+
+pub async fn grant_account_user(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    user: AuthUser,
+    Json(req): Json<GrantAccountRequest>,
+) -> Result<StatusCode, ApiError> {
+    user.ensure_password_updated()?;
+    user.ensure_scope(SCOPE_ACCOUNTS_WRITE)?;
+    ensure_account_manager(&state, &user, &id).await?;
+
+    let exists: i64 = sqlx::query_scalar("SELECT COUNT(1) FROM users WHERE id = $1")
+        .bind(&req.user_id)
+        .fetch_one(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if exists == 0 {
+        return Err(ApiError::invalid("userId", "unknown_user", "No user has this id"));
+    }
+
+    let inserted = sqlx::query(
+        "INSERT INTO user_accounts (user_id, account_id, created_at) VALUES ($1, $2, $3) ON CONFLICT DO NOTHING",
+    )
+    .bind(&req.user_id)
+    .bind(&id)
+    .bind(chrono::Utc::now().timestamp())
+    .execute(&state.db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    .rows_affected();
+
+    if inserted > 0 {
+        audit::record(&state.db, &user, "account.grant", "account", &id, serde_json::json!({ "userId": req.user_id })).await;
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn revoke_account_user(
+    State(state): State<AppState>,
+    Path((id, user_id)): Path<(String, String)>,
+    user: AuthUser,
+) -> Result<StatusCode, ApiError> {
+    user.ensure_password_updated()?;
+    user.ensure_scope(SCOPE_ACCOUNTS_WRITE)?;
+    ensure_account_manager(&state, &user, &id).await?;
+
+    let result = sqlx::query("DELETE FROM user_accounts WHERE account_id = $1 AND user_id = $2")
+        .bind(&id)
+        .bind(&user_id)
+        .execute(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if result.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND.into());
+    }
+
+    audit::record(&state.db, &user, "account.revoke", "account", &id, serde_json::json!({ "userId": user_id })).await;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn restore_account(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    user: AuthUser,
+) -> Result<Json<EmailAccount>, ApiError> {
+    user.ensure_password_updated()?;
+    user.ensure_scope(SCOPE_ACCOUNTS_WRITE)?;
+    if !matches!(user.role, UserRole::Admin) {
+        return Err(StatusCode::FORBIDDEN.into());
+    }
+
+    let row = sqlx::query(&format!(
+        "UPDATE accounts SET deleted_at = NULL WHERE id = $1 AND deleted_at IS NOT NULL RETURNING {}",
+        ACCOUNT_COLUMNS
+    ))
+    .bind(&id)
+    .fetch_optional(&state.db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    .ok_or(StatusCode::NOT_FOUND)?;
+
+    let account = account_from_row(&row);
+    audit::record(&state.db, &user, "account.restore", "account", &id, serde_json::json!({ "email": account.email })).await;
+    Ok(Json(account))
+}
+
+pub async fn activate_account(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    user: AuthUser,
+) -> Result<Json<ActivationResponse<EmailAccount>>, ApiError> {
+    set_account_active(&state, &user, &id, true).await
+}
+
+pub async fn deactivate_account(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    user: AuthUser,
+) -> Result<Json<ActivationResponse<EmailAccount>>, ApiError> {
+    set_account_active(&state, &user, &id, false).await
+}
 
-    let mut query_builder = sqlx::query(query);
+// Flips is_active alone, so the password and other fields can't be touched by accident
+async fn set_account_active(
+    state: &AppState,
+    user: &AuthUser,
+    id: &str,
+    active: bool,
+) -> Result<Json<ActivationResponse<EmailAccount>>, ApiError> {
+    user.ensure_password_updated()?;
+    user.ensure_scope(SCOPE_ACCOUNTS_WRITE)?;
     if !matches!(user.role, UserRole::Admin) {
-        query_builder = query_builder.bind(&user.id);
+        return Err(StatusCode::FORBIDDEN.into());
+    }
+
+    let row = sqlx::query(&format!(
+        "UPDATE accounts SET is_active = $1 WHERE id = $2 AND deleted_at IS NULL RETURNING {}",
+        ACCOUNT_COLUMNS
+    ))
+    .bind(active)
+    .bind(id)
+    .fetch_optional(&state.db)
+    .await
+    .map_err(|e| {
+        tracing::error!(error = %e, user_id = %user.id, account_id = %id, "failed to update account");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?
+    .ok_or(StatusCode::NOT_FOUND)?;
+    let account = account_from_row(&row);
+
+    let default_sender_cleared = !active
+        && mailer::delete_default_for_account(&state.db, id).await.unwrap_or_else(|e| {
+            tracing::warn!(error = %e, account_id = %id, "failed to clear default sender after account deactivation");
+            false
+        });
+    let action = if active { "account.activate" } else { "account.deactivate" };
+    let detail = serde_json::json!({ "email": account.email, "defaultSenderCleared": default_sender_cleared });
+    audit::record(&state.db, user, action, "account", id, detail).await;
+
+    Ok(Json(ActivationResponse { item: account, default_sender_cleared }))
+}
+
+// Checks the stored credentials against the SMTP server without sending anything
+pub async fn test_account(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    user: AuthUser,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    user.ensure_password_updated()?;
+    user.ensure_scope(SCOPE_ACCOUNTS_WRITE)?;
+
+    let row = sqlx::query("SELECT email, password, owner_id FROM accounts WHERE id = $1 AND deleted_at IS NULL")
+        .bind(&id)
+        .fetch_optional(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let is_owner = row.get::<Option<String>, _>(2).as_deref() == Some(user.id.as_str());
+    if !is_owner && !matches!(user.role, UserRole::Admin) {
+        return Err(StatusCode::FORBIDDEN.into());
+    }
+
+    let email = row.get::<String, _>(0);
+    let password = state
+        .secrets
+        .open(&row.get::<String, _>(1))
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    // An OAuth account whose refresh token no longer works fails here, before SMTP
+    let auth = match oauth::smtp_auth(&state, &email, &password).await {
+        Ok(auth) => auth,
+        Err(e) => {
+            return Ok(Json(serde_json::json!({
+                "ok": false,
+                "error": CredentialCheckError {
+                    kind: CredentialFailure::Auth,
+                    message: e.to_string(),
+                }
+            })))
+        }
+    };
+
+    match EmailService::new(state.smtp.clone(), state.smtp_pool.clone()).verify_credentials(&email, &auth).await {
+        Ok(()) => Ok(Json(serde_json::json!({ "ok": true }))),
+        Err(error) => Ok(Json(serde_json::json!({ "ok": false, "error": error }))),
+    }
+}
+
+pub async fn get_aliases(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Query(params): Query<AliasListParams>,
+) -> Result<Json<Page<EmailAlias>>, ApiError> {
+    user.ensure_password_updated()?;
+    user.ensure_scope(SCOPE_ACCOUNTS_READ)?;
+    if !matches!(user.role, UserRole::Admin | UserRole::Dev) {
+        return Err(StatusCode::FORBIDDEN.into());
     }
+    let (limit, offset) = page_bounds(params.limit, params.offset);
+
+    let owner = (!matches!(user.role, UserRole::Admin)).then_some(user.id.as_str());
+    let account_id = params.account_id.as_deref().map(str::trim).filter(|s| !s.is_empty());
+
+    let mut count = QueryBuilder::new(
+        "SELECT COUNT(*) FROM aliases JOIN accounts ON aliases.account_id = accounts.id",
+    );
+    push_alias_filters(&mut count, owner, params.active, account_id);
+    let total: i64 = count
+        .build_query_scalar()
+        .fetch_one(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    let rows = query_builder
+    let mut select = QueryBuilder::new(ALIAS_SELECT);
+    push_alias_filters(&mut select, owner, params.active, account_id);
+    select.push(match params.sort.unwrap_or_default() {
+        ListSort::Email => " ORDER BY aliases.alias_email ASC",
+        ListSort::Created => " ORDER BY aliases.created_at DESC, aliases.alias_email ASC",
+    });
+    select.push(" LIMIT ");
+    select.push_bind(limit);
+    select.push(" OFFSET ");
+    select.push_bind(offset);
+    let rows = select
+        .build()
         .fetch_all(&state.db)
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    let aliases = rows
+    let items = rows
         .into_iter()
-        .map(|row| EmailAlias {
-            id: row.get::<String, _>(0),
-            alias_email: row.get::<String, _>(1),
-            display_name: row.get::<Option<String>, _>(2),
-            is_active: row.get::<bool, _>(3),
-            account_id: row.get::<String, _>(4),
-            account_email: row.get::<String, _>(5),
-            account_display_name: row.get::<String, _>(6),
-            account_is_active: row.get::<bool, _>(7),
-            owner_id: row.get::<Option<String>, _>(8),
-            is_public: row.get::<bool, _>(9),
-        })
+        .map(|row| alias_from_row(&row))
         .collect();
 
-    Ok(Json(aliases))
+    Ok(Json(Page {
+        items,
+        total,
+        limit,
+        offset,
+    }))
 }
 
-pub async fn create_alias(
+pub async fn get_alias(
     State(state): State<AppState>,
+    Path(id): Path<String>,
     user: AuthUser,
-    Json(req): Json<CreateAliasRequest>,
-) -> Result<Json<EmailAlias>, StatusCode> {
+) -> Result<Json<EmailAlias>, ApiError> {
     user.ensure_password_updated()?;
+    user.ensure_scope(SCOPE_ACCOUNTS_READ)?;
     if !matches!(user.role, UserRole::Admin | UserRole::Dev) {
-        return Err(StatusCode::FORBIDDEN);
+        return Err(StatusCode::FORBIDDEN.into());
+    }
+
+    let owner = (!matches!(user.role, UserRole::Admin)).then_some(user.id.as_str());
+    let row = sqlx::query(&format!("{} {} AND aliases.id = $2", ALIAS_SELECT, ALIAS_VISIBILITY))
+        .bind(owner)
+        .bind(&id)
+        .fetch_optional(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(alias_from_row(&row)))
+}
+
+fn unknown_account_error() -> ApiError {
+    ApiError::invalid("accountId", "unknown_account", "No account has this id")
+}
+
+fn account_forbidden_error() -> ApiError {
+    ApiError::forbidden("account_forbidden", "You don't have access to this account").with_field("accountId")
+}
+
+enum AliasInsertError {
+    InvalidEmail(String),
+    UnknownAccount,
+    // The caller neither owns nor was granted the account
+    AccountForbidden,
+    Duplicate,
+    Database(sqlx::Error),
+}
+
+impl From<sqlx::Error> for AliasInsertError {
+    fn from(e: sqlx::Error) -> Self {
+        Self::Database(e)
     }
+}
 
+// Shared by create_alias and the bulk endpoint; runs on whatever connection or
+// transaction the caller hands in
+async fn insert_alias(
+    conn: &mut PgConnection,
+    user: &AuthUser,
+    req: CreateAliasRequest,
+) -> Result<EmailAlias, AliasInsertError> {
+    let owner_id = user.id.as_str();
     let CreateAliasRequest {
         account_id,
         alias_email,
         display_name,
         is_active,
         is_public,
+        is_catchall,
     } = req;
 
-    let account_row = sqlx::query(
-        "SELECT id, email, display_name, is_active FROM accounts WHERE id = ?",
-    )
-    .bind(&account_id)
-        .fetch_optional(&state.db)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut alias_email = alias_email.trim().to_string();
+    if is_catchall && !alias_email.contains('@') {
+        alias_email = format!("*@{}", alias_email);
+    }
+    let catchall_domain = mailer::catchall_domain(&alias_email);
+    // "*" is reserved for catch-alls so a plain alias can't shadow one
+    if !is_valid_email(&alias_email) || is_catchall != catchall_domain.is_some() {
+        return Err(AliasInsertError::InvalidEmail(alias_email));
+    }
 
-    let account = match account_row {
-        Some(row) => (
-            row.get::<String, _>(0),
-            row.get::<String, _>(1),
-            row.get::<String, _>(2),
-            row.get::<bool, _>(3),
-        ),
-        None => {
-            return Err(StatusCode::BAD_REQUEST);
-        }
-    };
+    let account = sqlx::query("SELECT id, email, display_name, is_active FROM accounts WHERE id = $1 AND deleted_at IS NULL")
+        .bind(&account_id)
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or(AliasInsertError::UnknownAccount)?;
+    // An alias sends with its account's credentials, so it takes access to the account
+    if !matches!(user.role, UserRole::Admin) && !mailer::has_account_access(&mut *conn, owner_id, &account_id).await? {
+        return Err(AliasInsertError::AccountForbidden);
+    }
 
-    let existing = sqlx::query("SELECT alias_email FROM aliases WHERE alias_email = ?")
+    let existing = sqlx::query("SELECT alias_email FROM aliases WHERE alias_email = $1")
         .bind(&alias_email)
-        .fetch_optional(&state.db)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
+        .fetch_optional(&mut *conn)
+        .await?;
     if existing.is_some() {
-        return Err(StatusCode::CONFLICT);
+        return Err(AliasInsertError::Duplicate);
+    }
+    if let Some(domain) = &catchall_domain {
+        let claimed = sqlx::query("SELECT id FROM aliases WHERE is_catchall = TRUE AND catchall_domain = $1")
+            .bind(domain)
+            .fetch_optional(&mut *conn)
+            .await?;
+        if claimed.is_some() {
+            return Err(AliasInsertError::Duplicate);
+        }
     }
 
     let id = Uuid::new_v4().to_string();
-    sqlx::query(
+    let created_at: i64 = sqlx::query_scalar(
         r#"
-        INSERT INTO aliases (id, alias_email, display_name, is_active, account_id, owner_id, is_public)
-        VALUES (?, ?, ?, ?, ?, ?, ?)
+        INSERT INTO aliases (id, alias_email, display_name, is_active, account_id, owner_id, is_public, is_catchall, catchall_domain)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+        RETURNING created_at
         "#,
     )
     .bind(&id)
@@ -401,26 +1155,132 @@ pub async fn create_alias(
     .bind(&display_name)
     .bind(is_active)
     .bind(&account_id)
-    .bind(&user.id)
-    .bind(req.is_public)
-    .execute(&state.db)
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    let alias = EmailAlias {
+    .bind(owner_id)
+    .bind(is_public)
+    .bind(is_catchall)
+    .bind(&catchall_domain)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok(EmailAlias {
         id,
         alias_email,
         display_name,
         is_active,
-        account_id: account.0,
-        account_email: account.1,
-        account_display_name: account.2,
-        account_is_active: account.3,
-        owner_id: Some(user.id),
-        is_public: req.is_public,
-    };
+        account_id: account.get::<String, _>(0),
+        account_email: account.get::<String, _>(1),
+        account_display_name: account.get::<String, _>(2),
+        account_is_active: account.get::<bool, _>(3),
+        owner_id: Some(owner_id.to_string()),
+        is_public,
+        is_catchall,
+        compliance_footer_html: None,
+        compliance_footer_text: None,
+        created_at,
+    })
+}
+
+pub async fn create_alias(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(req): Json<CreateAliasRequest>,
+) -> Result<Response, ApiError> {
+    user.ensure_password_updated()?;
+    user.ensure_scope(SCOPE_ACCOUNTS_WRITE)?;
+    if !matches!(user.role, UserRole::Admin | UserRole::Dev) {
+        return Err(StatusCode::FORBIDDEN.into());
+    }
+
+    let mut conn = state.db.acquire().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    match insert_alias(&mut conn, &user, req).await {
+        Ok(alias) => {
+            record_alias_created(&state, &user, &alias).await;
+            Ok(Json(alias).into_response())
+        }
+        Err(AliasInsertError::InvalidEmail(email)) => Err(invalid_email_error("aliasEmail", &email)),
+        Err(AliasInsertError::UnknownAccount) => Err(unknown_account_error()),
+        Err(AliasInsertError::AccountForbidden) => Err(account_forbidden_error()),
+        Err(AliasInsertError::Duplicate) => Err(ApiError::new(
+            StatusCode::CONFLICT,
+            "duplicate_alias",
+            "An alias with this address already exists",
+        )
+        .with_field("aliasEmail")),
+        Err(AliasInsertError::Database(_)) => Err(StatusCode::INTERNAL_SERVER_ERROR.into()),
+    }
+}
+
+const MAX_BULK_ALIASES: usize = 100;
+
+// Each alias is inserted under its own savepoint inside one transaction: a failing
+// row is rolled back and reported, the rest of the batch still commits together
+pub async fn create_aliases_bulk(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(requests): Json<Vec<CreateAliasRequest>>,
+) -> Result<Json<Vec<BulkAliasResult>>, ApiError> {
+    user.ensure_password_updated()?;
+    user.ensure_scope(SCOPE_ACCOUNTS_WRITE)?;
+    if !matches!(user.role, UserRole::Admin | UserRole::Dev) {
+        return Err(StatusCode::FORBIDDEN.into());
+    }
+    if requests.is_empty() || requests.len() > MAX_BULK_ALIASES {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            "invalid_batch_size",
+            format!("Send between 1 and {} aliases", MAX_BULK_ALIASES),
+        ));
+    }
+
+    let mut tx = state.db.begin().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut results = Vec::with_capacity(requests.len());
+
+    for (index, req) in requests.into_iter().enumerate() {
+        let mut savepoint = tx.begin().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        let outcome = insert_alias(&mut savepoint, &user, req).await;
+        let message = match outcome {
+            Ok(alias) => {
+                savepoint.commit().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+                results.push(BulkAliasResult {
+                    index,
+                    status: "created",
+                    alias: Some(alias),
+                    message: None,
+                });
+                continue;
+            }
+            Err(AliasInsertError::InvalidEmail(email)) => format!("{:?} is not a valid email address", email),
+            Err(AliasInsertError::UnknownAccount) => "Account not found".to_string(),
+            Err(AliasInsertError::AccountForbidden) => "You don't have access to this account".to_string(),
+            Err(AliasInsertError::Duplicate) => "Alias email already exists".to_string(),
+            Err(AliasInsertError::Database(e)) => {
+                tracing::error!(error = %e, user_id = %user.id, index, "failed to insert alias from bulk request");
+                "Failed to create alias".to_string()
+            }
+        };
+        savepoint.rollback().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        results.push(BulkAliasResult {
+            index,
+            status: "error",
+            alias: None,
+            message: Some(message),
+        });
+    }
+
+    tx.commit().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    for alias in results.iter().filter_map(|r| r.alias.as_ref()) {
+        record_alias_created(&state, &user, alias).await;
+    }
+    Ok(Json(results))
+}
 
-    Ok(Json(alias))
+async fn record_alias_created(state: &AppState, user: &AuthUser, alias: &EmailAlias) {
+    let detail = serde_json::json!({
+        "aliasEmail": alias.alias_email,
+        "accountId": alias.account_id,
+        "isCatchall": alias.is_catchall,
+    });
+    audit::record(&state.db, user, "alias.create", "alias", &alias.id, detail).await;
 }
 
 pub async fn update_alias(
@@ -428,22 +1288,24 @@ pub async fn update_alias(
     Path(id): Path<String>,
     user: AuthUser,
     Json(req): Json<UpdateAliasRequest>,
-) -> Result<Json<EmailAlias>, StatusCode> {
+) -> Result<Json<EmailAlias>, ApiError> {
     user.ensure_password_updated()?;
+    user.ensure_scope(SCOPE_ACCOUNTS_WRITE)?;
     
-    // Check ownership or admin
-    let owner_row = sqlx::query("SELECT owner_id FROM aliases WHERE id = ?")
+    // Check ownership or admin; an unknown id is a 404 before any update runs
+    let owner_row = sqlx::query("SELECT owner_id FROM aliases WHERE id = $1")
         .bind(&id)
         .fetch_optional(&state.db)
         .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
     
-    let owner_id = owner_row.and_then(|row| row.get::<Option<String>, _>(0));
+    let owner_id = owner_row.get::<Option<String>, _>(0);
     let is_owner = owner_id.as_ref().map(|oid| oid == &user.id).unwrap_or(false);
     let is_admin = matches!(user.role, UserRole::Admin);
     
     if !is_owner && !is_admin {
-        return Err(StatusCode::FORBIDDEN);
+        return Err(StatusCode::FORBIDDEN.into());
     }
 
     let UpdateAliasRequest {
@@ -452,28 +1314,49 @@ pub async fn update_alias(
         is_active,
         owner_id: req_owner_id,
         is_public,
+        compliance_footer_html,
+        compliance_footer_text,
     } = req;
 
-    if account_id.is_none() && display_name.is_none() && is_active.is_none() && req_owner_id.is_none() && is_public.is_none() {
-        return Err(StatusCode::BAD_REQUEST);
+    if account_id.is_none()
+        && display_name.is_none()
+        && is_active.is_none()
+        && req_owner_id.is_none()
+        && is_public.is_none()
+        && compliance_footer_html.is_none()
+        && compliance_footer_text.is_none()
+    {
+        return Err(ApiError::new(StatusCode::BAD_REQUEST, "nothing_to_update", "No alias fields were provided"));
     }
 
     // Only admin can change ownership
     if req_owner_id.is_some() && !is_admin {
-        return Err(StatusCode::FORBIDDEN);
+        return Err(StatusCode::FORBIDDEN.into());
+    }
+
+    // Compliance footers are set by admins so owners can't opt out of them
+    if (compliance_footer_html.is_some() || compliance_footer_text.is_some()) && !is_admin {
+        return Err(StatusCode::FORBIDDEN.into());
     }
 
     if let Some(account_id) = &account_id {
-        let exists = sqlx::query("SELECT id FROM accounts WHERE id = ?")
+        let exists = sqlx::query("SELECT id FROM accounts WHERE id = $1 AND deleted_at IS NULL")
             .bind(account_id)
             .fetch_optional(&state.db)
             .await
             .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
         if exists.is_none() {
-            return Err(StatusCode::BAD_REQUEST);
+            return Err(unknown_account_error());
+        }
+        let allowed = is_admin
+            || mailer::has_account_access(&state.db, &user.id, account_id)
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        if !allowed {
+            return Err(account_forbidden_error());
         }
 
-        sqlx::query("UPDATE aliases SET account_id = ? WHERE id = ?")
+        sqlx::query("UPDATE aliases SET account_id = $1 WHERE id = $2")
             .bind(account_id)
             .bind(&id)
             .execute(&state.db)
@@ -482,7 +1365,7 @@ pub async fn update_alias(
     }
 
     if let Some(display_name) = &display_name {
-        sqlx::query("UPDATE aliases SET display_name = ? WHERE id = ?")
+        sqlx::query("UPDATE aliases SET display_name = $1 WHERE id = $2")
             .bind(display_name)
             .bind(&id)
             .execute(&state.db)
@@ -491,7 +1374,7 @@ pub async fn update_alias(
     }
 
     if let Some(is_active) = is_active {
-        sqlx::query("UPDATE aliases SET is_active = ? WHERE id = ?")
+        sqlx::query("UPDATE aliases SET is_active = $1 WHERE id = $2")
             .bind(is_active)
             .bind(&id)
             .execute(&state.db)
@@ -501,7 +1384,7 @@ pub async fn update_alias(
 
     // Update owner_id if provided (admin only)
     if let Some(owner_id) = req_owner_id {
-        sqlx::query("UPDATE aliases SET owner_id = ? WHERE id = ?")
+        sqlx::query("UPDATE aliases SET owner_id = $1 WHERE id = $2")
             .bind(&owner_id)
             .bind(&id)
             .execute(&state.db)
@@ -511,7 +1394,7 @@ pub async fn update_alias(
 
     // Update is_public if provided
     if let Some(is_public) = is_public {
-        sqlx::query("UPDATE aliases SET is_public = ? WHERE id = ?")
+        sqlx::query("UPDATE aliases SET is_public = $1 WHERE id = $2")
             .bind(is_public)
             .bind(&id)
             .execute(&state.db)
@@ -519,54 +1402,49 @@ pub async fn update_alias(
             .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
     }
 
-    let row = sqlx::query(
-        r#"
-        SELECT 
-            aliases.id,
-            aliases.alias_email,
-            aliases.display_name,
-            aliases.is_active,
-            aliases.account_id,
-            accounts.email,
-            accounts.display_name,
-            accounts.is_active,
-            aliases.owner_id,
-            aliases.is_public
-        FROM aliases
-        JOIN accounts ON aliases.account_id = accounts.id
-        WHERE aliases.id = ?
-        "#,
-    )
+    // Update compliance footers if provided (admin only)
+    if let Some(footer_html) = compliance_footer_html {
+        sqlx::query("UPDATE aliases SET compliance_footer_html = $1 WHERE id = $2")
+            .bind(non_blank(footer_html))
+            .bind(&id)
+            .execute(&state.db)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    }
+
+    if let Some(footer_text) = compliance_footer_text {
+        sqlx::query("UPDATE aliases SET compliance_footer_text = $1 WHERE id = $2")
+            .bind(non_blank(footer_text))
+            .bind(&id)
+            .execute(&state.db)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    }
+
+    // Only a delete racing this request can make the row disappear now
+    let row = sqlx::query(&format!("{} WHERE aliases.id = $1", ALIAS_SELECT))
     .bind(&id)
-    .fetch_one(&state.db)
+    .fetch_optional(&state.db)
     .await
-    .map_err(|_| StatusCode::NOT_FOUND)?;
-
-    let alias = EmailAlias {
-        id: row.get::<String, _>(0),
-        alias_email: row.get::<String, _>(1),
-        display_name: row.get::<Option<String>, _>(2),
-        is_active: row.get::<bool, _>(3),
-        account_id: row.get::<String, _>(4),
-        account_email: row.get::<String, _>(5),
-        account_display_name: row.get::<String, _>(6),
-        account_is_active: row.get::<bool, _>(7),
-        owner_id: row.get::<Option<String>, _>(8),
-        is_public: row.get::<bool, _>(9),
-    };
+    .map_err(|e| {
+        tracing::error!(error = %e, user_id = %user.id, alias_id = %id, "failed to load updated alias");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?
+    .ok_or(StatusCode::NOT_FOUND)?;
 
-    Ok(Json(alias))
+    Ok(Json(alias_from_row(&row)))
 }
 
 pub async fn delete_alias(
     State(state): State<AppState>,
     Path(id): Path<String>,
     user: AuthUser,
-) -> Result<StatusCode, StatusCode> {
+) -> Result<StatusCode, ApiError> {
     user.ensure_password_updated()?;
+    user.ensure_scope(SCOPE_ACCOUNTS_WRITE)?;
     
     // Check ownership or admin
-    let owner_row = sqlx::query("SELECT owner_id FROM aliases WHERE id = ?")
+    let owner_row = sqlx::query("SELECT owner_id FROM aliases WHERE id = $1")
         .bind(&id)
         .fetch_optional(&state.db)
         .await
@@ -577,41 +1455,106 @@ pub async fn delete_alias(
     let is_admin = matches!(user.role, UserRole::Admin);
     
     if !is_owner && !is_admin {
-        return Err(StatusCode::FORBIDDEN);
+        return Err(StatusCode::FORBIDDEN.into());
     }
 
-    let result = sqlx::query("DELETE FROM aliases WHERE id = ?")
+    let deleted: Option<String> = sqlx::query_scalar("DELETE FROM aliases WHERE id = $1 RETURNING alias_email")
         .bind(&id)
-        .execute(&state.db)
+        .fetch_optional(&state.db)
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    if result.rows_affected() == 0 {
-        return Err(StatusCode::NOT_FOUND);
-    }
+    let Some(alias_email) = deleted else {
+        return Err(StatusCode::NOT_FOUND.into());
+    };
+    audit::record(&state.db, &user, "alias.delete", "alias", &id, serde_json::json!({ "aliasEmail": alias_email })).await;
 
     if let Err(e) = mailer::delete_default_if_matches(&state.db, SenderKind::Alias, &id).await {
-        eprintln!("Failed to clear default sender after alias deletion: {}", e);
+        tracing::warn!(error = %e, alias_id = %id, "failed to clear default sender after alias deletion");
     }
 
     Ok(StatusCode::NO_CONTENT)
 }
 
+pub async fn activate_alias(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    user: AuthUser,
+) -> Result<Json<ActivationResponse<EmailAlias>>, ApiError> {
+    set_alias_active(&state, &user, &id, true).await
+}
+
+pub async fn deactivate_alias(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    user: AuthUser,
+) -> Result<Json<ActivationResponse<EmailAlias>>, ApiError> {
+    set_alias_active(&state, &user, &id, false).await
+}
+
+async fn set_alias_active(
+    state: &AppState,
+    user: &AuthUser,
+    id: &str,
+    active: bool,
+) -> Result<Json<ActivationResponse<EmailAlias>>, ApiError> {
+    user.ensure_password_updated()?;
+    user.ensure_scope(SCOPE_ACCOUNTS_WRITE)?;
+    if !matches!(user.role, UserRole::Admin) {
+        return Err(StatusCode::FORBIDDEN.into());
+    }
+
+    let updated = sqlx::query("UPDATE aliases SET is_active = $1 WHERE id = $2")
+        .bind(active)
+        .bind(id)
+        .execute(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .rows_affected();
+    if updated == 0 {
+        return Err(StatusCode::NOT_FOUND.into());
+    }
+
+    // Only a delete racing this request can make the row disappear now
+    let row = sqlx::query(&format!("{} WHERE aliases.id = $1", ALIAS_SELECT))
+        .bind(id)
+        .fetch_optional(&state.db)
+        .await
+        .map_err(|e| {
+            tracing::error!(error = %e, user_id = %user.id, alias_id = %id, "failed to load updated alias");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let alias = alias_from_row(&row);
+
+    let default_sender_cleared = !active
+        && mailer::delete_default_if_matches(&state.db, SenderKind::Alias, id).await.unwrap_or_else(|e| {
+            tracing::warn!(error = %e, alias_id = %id, "failed to clear default sender after alias deactivation");
+            false
+        });
+    let action = if active { "alias.activate" } else { "alias.deactivate" };
+    let detail = serde_json::json!({ "aliasEmail": alias.alias_email, "defaultSenderCleared": default_sender_cleared });
+    audit::record(&state.db, user, action, "alias", id, detail).await;
+
+    Ok(Json(ActivationResponse { item: alias, default_sender_cleared }))
+}
+
 pub async fn get_default_sender(
     State(state): State<AppState>,
     user: AuthUser,
-) -> Result<Json<Option<DefaultSenderResponse>>, StatusCode> {
+) -> Result<Json<Option<DefaultSenderResponse>>, ApiError> {
     user.ensure_password_updated()?;
+    user.ensure_scope(SCOPE_ACCOUNTS_READ)?;
     if !matches!(user.role, UserRole::Admin) {
-        return Err(StatusCode::FORBIDDEN);
+        return Err(StatusCode::FORBIDDEN.into());
     }
 
-    match mailer::get_default_sender_summary(&state.db).await {
+    match mailer::get_default_sender_summary(&state.db, &state.secrets).await {
         Ok(Some(summary)) => Ok(Json(Some(sender_summary_to_response(&summary)))),
         Ok(None) => Ok(Json(None)),
         Err(e) => {
-            eprintln!("Failed to load default sender: {}", e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
+            tracing::error!(error = %e, user_id = %user.id, "failed to load default sender");
+            Err(StatusCode::INTERNAL_SERVER_ERROR.into())
         }
     }
 }
@@ -620,17 +1563,108 @@ pub async fn update_default_sender(
     State(state): State<AppState>,
     user: AuthUser,
     Json(req): Json<UpdateDefaultSenderRequest>,
-) -> Result<Json<DefaultSenderResponse>, StatusCode> {
+) -> Result<Json<DefaultSenderResponse>, ApiError> {
     user.ensure_password_updated()?;
+    user.ensure_scope(SCOPE_ADMIN)?;
     if !matches!(user.role, UserRole::Admin) {
-        return Err(StatusCode::FORBIDDEN);
+        return Err(StatusCode::FORBIDDEN.into());
     }
 
-    match mailer::upsert_default_sender(&state.db, req.sender_type, &req.sender_id).await {
-        Ok(summary) => Ok(Json(sender_summary_to_response(&summary))),
+    match mailer::upsert_default_sender(&state.db, &state.secrets, req.sender_type, &req.sender_id).await {
+        Ok(summary) => {
+            let detail = serde_json::json!({ "senderType": summary.sender_type, "email": summary.email });
+            audit::record(&state.db, &user, "default_sender.update", "default_sender", &summary.sender_id, detail).await;
+            Ok(Json(sender_summary_to_response(&summary)))
+        }
+        Err(e) => {
+            tracing::warn!(error = %e, user_id = %user.id, sender_id = %req.sender_id, "failed to set default sender");
+            Err(ApiError::invalid(
+                "senderId",
+                "invalid_sender",
+                "No active account or alias has this id",
+            ))
+        }
+    }
+}
+
+// Sends a short branded message through the default sender right away, so a broken
+// sender shows up here rather than as a signup whose verification email never arrives
+pub async fn test_default_sender(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(req): Json<TestDefaultSenderRequest>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    user.ensure_password_updated()?;
+    user.ensure_scope(SCOPE_ADMIN)?;
+    if !matches!(user.role, UserRole::Admin) {
+        return Err(StatusCode::FORBIDDEN.into());
+    }
+
+    let to = req.to.trim().to_string();
+    if !is_valid_email(&to) {
+        return Err(ApiError::invalid("to", "invalid_email", "Enter a valid email address"));
+    }
+    let sender = match mailer::get_default_sender_summary(&state.db, &state.secrets).await {
+        Ok(Some(summary)) => summary,
+        Ok(None) => {
+            return Err(ApiError::new(
+                StatusCode::CONFLICT,
+                "no_default_sender",
+                "No default sender is set",
+            ))
+        }
+        Err(e) => {
+            tracing::warn!(error = %e, user_id = %user.id, "default sender can't be loaded for a test send");
+            return Err(ApiError::new(
+                StatusCode::CONFLICT,
+                "invalid_default_sender",
+                format!("The default sender can't be used: {}", e),
+            ));
+        }
+    };
+
+    let body_lines = vec![
+        format!("This is a test message from {} requested by {}.", sender.email, user.email),
+        "If it arrived, verification and password reset emails can be delivered.".to_string(),
+    ];
+    let html = build_system_email_html("W9 Mail test message", &body_lines, "Open W9 Mail", &state.app_base_url);
+    match send_system_email(&state, &sender, &to, "W9 Mail test message", &html).await {
+        Ok(()) => {
+            let detail = serde_json::json!({ "email": sender.email, "to": to });
+            audit::record(&state.db, &user, "default_sender.test", "default_sender", &sender.sender_id, detail).await;
+            Ok(Json(serde_json::json!({
+                "status": "sent",
+                "message": format!("Test email sent from {} to {}", sender.email, to),
+                "from": sender.email,
+                "to": to
+            })))
+        }
+        Err(e) => {
+            tracing::warn!(error = %e, user_id = %user.id, sender = %sender.email, "default sender test send failed");
+            Err(ApiError::new(StatusCode::BAD_GATEWAY, "smtp_error", e.to_string()))
+        }
+    }
+}
+
+// Every "from" the caller may pick in compose, accounts and aliases together
+pub async fn list_senders(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Query(params): Query<SenderListParams>,
+) -> Result<Json<Vec<SenderOption>>, ApiError> {
+    user.ensure_password_updated()?;
+    user.ensure_scope(SCOPE_SEND)?;
+    if !matches!(user.role, UserRole::Dev | UserRole::Admin) {
+        return Err(StatusCode::FORBIDDEN.into());
+    }
+
+    let viewer = (!matches!(user.role, UserRole::Admin)).then_some(user.id.as_str());
+    let include_inactive = params.include_inactive.unwrap_or(false);
+    match mailer::list_senders(&state.db, viewer, include_inactive).await {
+        Ok(senders) => Ok(Json(senders)),
         Err(e) => {
-            eprintln!("Failed to set default sender: {}", e);
-            Err(StatusCode::BAD_REQUEST)
+            tracing::error!(error = %e, user_id = %user.id, "failed to list senders");
+            Err(StatusCode::INTERNAL_SERVER_ERROR.into())
         }
     }
 }
@@ -646,102 +1680,1285 @@ fn sender_summary_to_response(summary: &SenderSummary) -> DefaultSenderResponse
     }
 }
 
+// Renders a body exactly as /api/send would, without touching SMTP
+pub async fn preview_email(user: AuthUser, Json(req): Json<EmailPreviewRequest>) -> Result<Response, ApiError> {
+    user.ensure_password_updated()?;
+
+    if !req.as_html {
+        return Ok(([(header::CONTENT_TYPE, "text/plain; charset=utf-8")], req.body).into_response());
+    }
+    let (html, inline_images) = crate::email::render_preview(
+        &req.body,
+        req.use_template.unwrap_or(true),
+        req.sanitize_html.unwrap_or(true),
+        true,
+    );
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/html; charset=utf-8".to_string()),
+            (HeaderName::from_static("x-inline-images"), inline_images.to_string()),
+        ],
+        html,
+    )
+        .into_response())
+}
+
 pub async fn send_email(
     State(state): State<AppState>,
     user: AuthUser,
+    headers: HeaderMap,
     Json(req): Json<SendEmailRequest>,
-) -> Result<Json<serde_json::Value>, StatusCode> {
+) -> Response {
+    // A dry run delivers nothing, so there is nothing for a key to protect
+    let key = match idempotency::key_from_headers(&headers) {
+        Ok(key) => key.filter(|_| !req.dry_run),
+        Err(error) => return error.into_response(),
+    };
+    let user_id = user.id.clone();
+    if let Some(key) = &key {
+        match idempotency::claim(&state.db, &user_id, key, state.idempotency_ttl_secs).await {
+            Ok(Claim::New) => {}
+            Ok(Claim::Replay(body)) => {
+                return (
+                    [(HeaderName::from_static("idempotent-replayed"), "true")],
+                    Json(body),
+                )
+                    .into_response();
+            }
+            Ok(Claim::InProgress) => {
+                return ApiError::new(
+                    StatusCode::CONFLICT,
+                    "idempotency_key_in_use",
+                    "A send with this Idempotency-Key is still in progress",
+                )
+                .into_response();
+            }
+            Err(e) => {
+                tracing::error!(error = %e, %user_id, "failed to claim idempotency key");
+                return ApiError::from(StatusCode::INTERNAL_SERVER_ERROR).into_response();
+            }
+        }
+    }
+
+    let response = send_with_permit(&state, user, req).await;
+    match key {
+        Some(key) => settle_idempotency_key(&state, &user_id, &key, response).await,
+        None => response,
+    }
+}
+
+async fn send_with_permit(state: &AppState, user: AuthUser, req: SendEmailRequest) -> Response {
+    // Held until the send finishes, whichever way the handler exits.
+    let Some(_permit) = state.send_limiter.acquire(&user.id, &user.role).await else {
+        return ApiError::new(
+            StatusCode::TOO_MANY_REQUESTS,
+            "too_many_sends",
+            "Too many sends in progress for this user",
+        )
+        .with_retry_after(RETRY_AFTER_SECS)
+        .into_response();
+    };
+
+    dispatch_email(state.clone(), user, req).await.into_response()
+}
+
+// Keeps the result of a send that went out (sent, partial or skipped) for replay.
+// Anything else, including rejections before SMTP, frees the key for a retry.
+async fn settle_idempotency_key(state: &AppState, user_id: &str, key: &str, response: Response) -> Response {
+    let (parts, body) = response.into_parts();
+    let Ok(bytes) = axum::body::to_bytes(body, usize::MAX).await else {
+        idempotency::release(&state.db, user_id, key).await;
+        return ApiError::from(StatusCode::INTERNAL_SERVER_ERROR).into_response();
+    };
+    let stored = serde_json::from_slice::<serde_json::Value>(&bytes)
+        .ok()
+        .filter(|body| parts.status.is_success() && body["status"] != "error");
+    match stored {
+        Some(body) => idempotency::complete(&state.db, user_id, key, &body).await,
+        None => idempotency::release(&state.db, user_id, key).await,
+    }
+    Response::from_parts(parts, Body::from(bytes))
+}
+
+// fromAccountId / fromAliasId: the sender's current email becomes from. A from given as
+// well must name the same address, and may add a display name.
+fn sender_forbidden_error() -> ApiError {
+    ApiError::forbidden("sender_forbidden", "You are not allowed to send from this address").with_field("from")
+}
+
+async fn sender_from_id(
+    state: &AppState,
+    user: &AuthUser,
+    from: String,
+    account_id: Option<String>,
+    alias_id: Option<String>,
+) -> Result<(String, Option<mailer::ResolvedSender>), ApiError> {
+    let account_id = account_id.map(|id| id.trim().to_string()).filter(|id| !id.is_empty());
+    let alias_id = alias_id.map(|id| id.trim().to_string()).filter(|id| !id.is_empty());
+    let (field, kind, id) = match (account_id, alias_id) {
+        (None, None) => return Ok((from, None)),
+        (Some(_), Some(_)) => {
+            return Err(ApiError::invalid(
+                "fromAliasId",
+                "conflicting_sender",
+                "Give fromAccountId or fromAliasId, not both",
+            ))
+        }
+        (Some(id), None) => ("fromAccountId", SenderKind::Account, id),
+        (None, Some(id)) => ("fromAliasId", SenderKind::Alias, id),
+    };
+
+    let summary = match mailer::summarize_sender(&state.db, &state.secrets, kind, &id).await {
+        Ok(summary) => summary,
+        Err(e) => {
+            tracing::warn!(error = %e, sender_id = %id, "send refused for an unusable sender id");
+            // Same answer as a sender that exists but isn't theirs
+            if !matches!(user.role, UserRole::Admin) {
+                return Err(sender_forbidden_error());
+            }
+            return Err(ApiError::invalid(
+                field,
+                "invalid_sender",
+                "No active account or alias has this id",
+            ));
+        }
+    };
+    if from.trim().is_empty() {
+        return Ok((summary.email, Some(summary.credentials)));
+    }
+    match from.trim().parse::<lettre::message::Mailbox>() {
+        Ok(mailbox) if mailbox.email.to_string().eq_ignore_ascii_case(&summary.email) => {
+            Ok((from, Some(summary.credentials)))
+        }
+        _ => Err(ApiError::invalid(
+            "from",
+            "sender_mismatch",
+            format!("from doesn't match {}, the address of {}", summary.email, field),
+        )),
+    }
+}
+
+/// Hands a built message to the account's SMTP server the way every send should go out:
+/// not while the account is cooling down, logged in with its OAuth token or password,
+/// and with the outcome and any throttling recorded on the account. Besides the SMTP
+/// error itself, failures are `Throttled`, `SmtpTimedOut` or `SenderAuthFailed`.
+pub(crate) async fn send_via_account(
+    state: &AppState,
+    sender: &mailer::ResolvedSender,
+    outgoing: &OutgoingEmail<'_>,
+) -> anyhow::Result<SendReport> {
+    let account = &sender.auth_email;
+    if let Ok(Some(until)) = mailer::account_cooldown(&state.db, account).await {
+        let wait = (until - chrono::Utc::now().timestamp()).max(1) as u64;
+        return Err(Throttled {
+            retry_after: std::time::Duration::from_secs(wait),
+            message: "the account is cooling down after its server throttled it".to_string(),
+        }
+        .into());
+    }
+
+    let smtp_auth = match oauth::smtp_auth(state, account, &sender.auth_password).await {
+        Ok(auth) => auth,
+        Err(e) => {
+            let error = SenderAuthFailed(e);
+            mailer::record_send_outcome(&state.db, account, Some(&error.to_string())).await;
+            return Err(error.into());
+        }
+    };
+
+    let started = std::time::Instant::now();
+    let result = EmailService::new(state.smtp.clone(), state.smtp_pool.clone())
+        .send_email(account, &smtp_auth, outgoing)
+        .await;
+    telemetry::smtp_latency(started.elapsed());
+    // The SMTP session itself is what says whether the mailbox works, so a send whose
+    // recipients were all rejected still counts as healthy
+    let send_error = result.as_ref().err().map(|e| e.to_string());
+    mailer::record_send_outcome(&state.db, account, send_error.as_deref()).await;
+
+    if let Some(throttled) = result.as_ref().err().and_then(|e| e.downcast_ref::<Throttled>()) {
+        let until = chrono::Utc::now().timestamp() + throttled.retry_after.as_secs().max(1) as i64;
+        tracing::warn!(sender = %account, reply = %throttled.message, "SMTP server is throttling sender");
+        if let Err(err) = mailer::record_throttle(&state.db, account, until).await {
+            tracing::error!(error = %err, sender = %account, "failed to record throttle");
+        }
+    }
+    result
+}
+
+async fn dispatch_email(
+    state: AppState,
+    user: AuthUser,
+    req: SendEmailRequest,
+) -> Result<Response, ApiError> {
     user.ensure_password_updated()?;
+    user.ensure_scope(SCOPE_SEND)?;
     if !matches!(user.role, UserRole::Dev | UserRole::Admin) {
-        return Err(StatusCode::FORBIDDEN);
+        return Err(StatusCode::FORBIDDEN.into());
     }
 
     let SendEmailRequest {
         from,
+        from_account_id,
+        from_alias_id,
         to,
         subject,
         body,
         cc,
         bcc,
         is_html,
+        all_or_nothing,
+        attachments,
+        reply_to,
+        headers,
+        use_template,
+        in_reply_to,
+        references,
+        list_unsubscribe,
+        honor_unsubscribe,
+        draft_id,
+        bcc_self,
+        dry_run,
+        sanitize_html,
+        web_version,
     } = req;
 
-    let from_address = from.trim().to_string();
-    if from_address.is_empty() {
-        return Err(StatusCode::BAD_REQUEST);
+    let recipient_list = |field: &'static str, list: StringOrList| {
+        list.into_recipient_list().map_err(|item| {
+            ApiError::invalid(field, "invalid_recipient", format!("{:?} is not a valid email address", item))
+        })
+    };
+    let to = recipient_list("to", to)?;
+    let cc = cc.map(|list| recipient_list("cc", list)).transpose()?;
+    let bcc = bcc.map(|list| recipient_list("bcc", list)).transpose()?;
+
+    let (from, sender_by_id) = sender_from_id(&state, &user, from, from_account_id, from_alias_id).await?;
+
+    // Fields left out of the request come from the draft
+    let draft_id = draft_id.map(|id| id.trim().to_string()).filter(|id| !id.is_empty());
+    let (from, to, subject, body, cc, bcc) = match &draft_id {
+        Some(id) => {
+            let draft = drafts::find(&state.db, &user.id, id)
+                .await
+                .map_err(|e| {
+                    tracing::error!(error = %e, user_id = %user.id, draft_id = %id, "failed to load draft");
+                    StatusCode::INTERNAL_SERVER_ERROR
+                })?;
+            let Some(draft) = draft else {
+                return Err(ApiError::new(StatusCode::NOT_FOUND, "draft_not_found", "Draft not found")
+                    .with_field("draftId"));
+            };
+            let or_draft = |value: String, saved: String| if value.trim().is_empty() { saved } else { value };
+            (
+                or_draft(from, draft.from),
+                or_draft(to, draft.to),
+                or_draft(subject, draft.subject),
+                or_draft(body, draft.body),
+                cc.or(draft.cc),
+                bcc.or(draft.bcc),
+            )
+        }
+        None => (from, to, subject, body, cc, bcc),
+    };
+
+    if from.trim().is_empty() {
+        return Err(ApiError::invalid("from", "from_required", "A sender address is required"));
+    }
+    // "Name <addr>" keeps its name; a bare address gets the sender's display name below
+    let Ok(from_mailbox) = from.trim().parse::<lettre::message::Mailbox>() else {
+        return Err(ApiError::invalid(
+            "from",
+            "invalid_from",
+            format!("{:?} is not a valid sender address", from.trim()),
+        ));
+    };
+    let from_address = from_mailbox.email.to_string();
+    let from_name = from_mailbox.name.filter(|name| !name.trim().is_empty());
+
+    // Before anything else looks the sender up, so a user gets the same refusal for an
+    // address that doesn't exist as for one they can't use, and learns nothing about who
+    // has unsubscribed
+    if !matches!(user.role, UserRole::Admin) {
+        match mailer::may_send_as(&state.db, &user.id, &from_address).await {
+            Ok(true) => {}
+            Ok(false) => {
+                tracing::warn!(user_id = %user.id, from = %from_address, "refused send from a sender the user may not use");
+                return Err(sender_forbidden_error());
+            }
+            Err(e) => {
+                tracing::error!(error = %e, user_id = %user.id, "failed to check sender permissions");
+                return Err(StatusCode::INTERNAL_SERVER_ERROR.into());
+            }
+        }
+    }
+
+    let reply_to = reply_to.map(|v| v.trim().to_string()).filter(|v| !v.is_empty());
+    if let Some(address) = &reply_to {
+        if address.parse::<lettre::message::Mailbox>().is_err() {
+            return Err(ApiError::invalid(
+                "replyTo",
+                "invalid_reply_to",
+                format!("Reply-To address {:?} is not a valid email address", address),
+            ));
+        }
+    }
+
+    let in_reply_to = in_reply_to.map(|v| v.trim().to_string()).filter(|v| !v.is_empty());
+    let references = references.map(StringOrList::into_items).unwrap_or_default();
+    let threading_ids = in_reply_to
+        .iter()
+        .map(|id| ("inReplyTo", id))
+        .chain(references.iter().map(|id| ("references", id)));
+    for (field, id) in threading_ids {
+        if !is_valid_message_id(id) {
+            return Err(ApiError::invalid(
+                field,
+                "invalid_message_id",
+                format!("{:?} is not a message id like <id@domain>", id),
+            ));
+        }
+    }
+    // RFC 5322 separates References entries with whitespace
+    let references = (!references.is_empty()).then(|| references.join(" "));
+
+    // Sorted so the header order in the message is stable
+    let mut headers: Vec<(String, String)> = headers
+        .into_iter()
+        .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+        .collect();
+    headers.sort();
+    for (name, value) in &headers {
+        let mut check = validate_custom_header(name, value);
+        if list_unsubscribe
+            && (name.eq_ignore_ascii_case("list-unsubscribe")
+                || name.eq_ignore_ascii_case("list-unsubscribe-post"))
+        {
+            check = Err(format!("Header {} is set by listUnsubscribe; send one or the other", name));
+        }
+        if let Err(message) = check {
+            return Err(ApiError::invalid("headers", "invalid_header", message));
+        }
+    }
+
+    let recipients = recipient_entries(&to, cc.as_deref(), bcc.as_deref());
+    if recipients.is_empty() {
+        return Err(ApiError::invalid("to", "recipients_required", "At least one recipient is required"));
+    }
+    // bccSelf's copy is added later, but it is still one more address on the message
+    let self_copy = bcc_self
+        && !recipients
+            .iter()
+            .any(|entry| bare_address(entry) == from_address.to_ascii_lowercase());
+    check_recipient_count(recipients.len() + usize::from(self_copy), state.max_recipients)?;
+    if web_version && !is_html {
+        return Err(ApiError::invalid(
+            "webVersion",
+            "web_version_requires_html",
+            "webVersion needs an HTML body (isHtml: true)",
+        ));
+    }
+    // The unsubscribe link is signed for one address, so it can't be shared by several
+    if list_unsubscribe && recipients.len() != 1 {
+        return Err(ApiError::invalid(
+            "listUnsubscribe",
+            "list_unsubscribe_recipients",
+            "listUnsubscribe needs exactly one recipient across to, cc and bcc",
+        ));
     }
 
-    let resolved = match mailer::resolve_sender_by_email(&state.db, &from_address).await {
+    let resolved = match sender_by_id {
+        Some(sender) => Ok(sender),
+        None => mailer::resolve_sender_by_email(&state.db, &state.secrets, &from_address).await,
+    };
+    let resolved = match resolved {
         Ok(sender) => sender,
         Err(_) => {
+            return Err(ApiError::invalid(
+                "from",
+                "unknown_sender",
+                "Sender account or alias not found or inactive",
+            ));
+        }
+    };
+
+    let mut skipped = Vec::new();
+    let (to, cc, bcc) = if honor_unsubscribe {
+        let addresses: Vec<String> = recipients.iter().map(|entry| bare_address(entry)).collect();
+        let unsubscribed = unsubscribe::unsubscribed_among(&state.db, &addresses)
+            .await
+            .map_err(|e| {
+                tracing::error!(error = %e, user_id = %user.id, "failed to check unsubscribes");
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+        let mut keep = |list: &str| without_unsubscribed(list, &unsubscribed, &mut skipped);
+        let to = keep(&to);
+        let cc = cc.map(|list| keep(&list)).filter(|list| !list.is_empty());
+        let bcc = bcc.map(|list| keep(&list)).filter(|list| !list.is_empty());
+        (to, cc, bcc)
+    } else {
+        (to, cc, bcc)
+    };
+    // Things a dry run lets through that could stop or change the real send
+    let mut warnings = Vec::new();
+    if !skipped.is_empty() {
+        warnings.push(format!("{} recipient(s) have unsubscribed and will be skipped", skipped.len()));
+        tracing::info!(user_id = %user.id, skipped = ?skipped, "dropped unsubscribed recipients");
+        if to.is_empty() && cc.is_none() && bcc.is_none() {
             return Ok(Json(serde_json::json!({
-                "status": "error",
-                "message": "Sender account or alias not found or inactive"
-            })));
+                "status": "skipped",
+                "message": "Every recipient has unsubscribed; nothing was sent",
+                "skipped": skipped
+            }))
+            .into_response());
+        }
+    }
+
+    // Added after the unsubscribe filter so the sender's own copy is never dropped
+    let bcc = if bcc_self && !recipient_entries(&to, cc.as_deref(), bcc.as_deref())
+        .iter()
+        .any(|entry| bare_address(entry) == from_address.to_ascii_lowercase())
+    {
+        Some(match bcc {
+            Some(list) => format!("{}, {}", list, from_address),
+            None => from_address.clone(),
+        })
+    } else {
+        bcc
+    };
+
+    let files = decode_attachments(attachments, state.max_attachment_bytes)?;
+
+    // Don't add to the pile while the account's SMTP server has asked us to back off
+    if let Ok(Some(until)) = mailer::account_cooldown(&state.db, &resolved.auth_email).await {
+        let wait = (until - chrono::Utc::now().timestamp()).max(1);
+        if !dry_run {
+            return Err(throttled_error(wait as u64));
+        }
+        warnings.push(format!("{} is throttled by its mail server for another {} seconds", resolved.auth_email, wait));
+    }
+
+    // Counted before SMTP, so a send the server goes on to refuse still uses up a slot.
+    // A dry run doesn't use one.
+    let quota = if dry_run {
+        Ok(QuotaCheck::Allowed)
+    } else {
+        state.send_quota.try_consume(&state.db, &resolved.auth_email).await
+    };
+    match quota {
+        Ok(QuotaCheck::Allowed) => {}
+        Ok(QuotaCheck::Exceeded { limit, retry_after_secs }) => {
+            tracing::warn!(user_id = %user.id, sender = %resolved.auth_email, limit, "daily send limit reached");
+            telemetry::send_failed("quota");
+            return Err(ApiError::new(
+                StatusCode::TOO_MANY_REQUESTS,
+                "daily_limit_reached",
+                format!(
+                    "Daily send limit of {} reached for {}. It resets at 00:00 UTC.",
+                    limit, resolved.auth_email
+                ),
+            )
+            .with_retry_after(retry_after_secs));
+        }
+        Err(e) => {
+            tracing::error!(error = %e, user_id = %user.id, sender = %resolved.auth_email, "failed to check daily send limit");
+            return Err(StatusCode::INTERNAL_SERVER_ERROR.into());
         }
+    }
+
+    let list_unsubscribe = if list_unsubscribe {
+        match unsubscribe::list_unsubscribe_header(&state, &from_address, &bare_address(&recipients[0])) {
+            Ok(value) => Some(value),
+            Err(e) => {
+                tracing::error!(error = %e, user_id = %user.id, "failed to build unsubscribe link");
+                return Err(StatusCode::INTERNAL_SERVER_ERROR.into());
+            }
+        }
+    } else {
+        None
     };
 
+    let header_from = lettre::message::Mailbox::new(
+        from_name.or_else(|| resolved.display_name.clone()),
+        from_mailbox.email,
+    )
+    .to_string();
+
     // Create email service and send email
-    let email_service = EmailService::new();
+    let email_service = EmailService::new(state.smtp.clone(), state.smtp_pool.clone());
     
-    // If HTML, wrap body in W9 Mail template (matching w9-tools design)
+    // If HTML, sanitize it and wrap it in the W9 Mail template (matching w9-tools design),
+    // unless the caller opted out of either
     let final_body = if is_html {
-        crate::email::render_email_template(&body)
+        crate::email::render_html_body(
+            &body,
+            use_template.unwrap_or(true),
+            sanitize_html.unwrap_or(true),
+            resolved.branding_enabled,
+        )
     } else {
         body.clone()
     };
+    // Sender compliance footers are always appended; there is no per-request opt-out
+    let final_body = resolved.compliance_footer.apply(&final_body, is_html);
+    let sent_message_id = Uuid::new_v4().to_string();
+    let (final_body, web_copy) = if web_version {
+        match web_view::prepare(&state, &sent_message_id, &final_body) {
+            Ok((body, copy)) => (body, Some(copy)),
+            Err(e) => {
+                tracing::error!(error = %e, user_id = %user.id, "failed to build view-in-browser link");
+                return Err(StatusCode::INTERNAL_SERVER_ERROR.into());
+            }
+        }
+    } else {
+        (final_body, None)
+    };
     
-    match email_service.send_email(
-        &from_address,
-        &resolved.auth_email,
-        &resolved.auth_password,
-        &to,
-        &subject,
-        &final_body,
-        cc.as_deref(),
-        bcc.as_deref(),
-        is_html,
-    ).await {
-        Ok(_) => {
-            Ok(Json(serde_json::json!({
-                "status": "sent",
-                "message": "Email sent successfully"
-            })))
+    let outgoing = OutgoingEmail {
+        header_from: &header_from,
+        to: &to,
+        cc: cc.as_deref(),
+        bcc: bcc.as_deref(),
+        reply_to: reply_to.as_deref(),
+        in_reply_to: in_reply_to.as_deref(),
+        references: references.as_deref(),
+        list_unsubscribe: list_unsubscribe.as_deref(),
+        subject: &subject,
+        body: &final_body,
+        as_html: is_html,
+        all_or_nothing,
+        attachments: &files,
+        headers: &headers,
+    };
+
+    if dry_run {
+        return match email_service.build_message(&outgoing) {
+            Ok((message, message_id)) => Ok(Json(serde_json::json!({
+                "status": "validated",
+                "message": "Email is valid; it was not sent",
+                "messageId": message_id,
+                "recipients": message.envelope().to().iter().map(ToString::to_string).collect::<Vec<_>>(),
+                "warnings": warnings,
+                "skipped": skipped
+            }))
+            .into_response()),
+            Err(e) => Err(ApiError::new(
+                StatusCode::BAD_REQUEST,
+                "invalid_message",
+                format!("Email could not be built: {}", e),
+            )),
+        };
+    }
+
+    let result = send_via_account(&state, &resolved, &outgoing).await;
+    if let Some(failed) = result.as_ref().err().and_then(|e| e.downcast_ref::<SenderAuthFailed>()) {
+        tracing::error!(error = %failed.0, user_id = %user.id, sender = %resolved.auth_email, "failed to get SMTP credentials");
+        telemetry::send_failed("auth");
+        return Err(ApiError::new(
+            StatusCode::BAD_GATEWAY,
+            "sender_auth_failed",
+            "Could not authorize the sending account. Reconnect its Microsoft login.",
+        ));
+    }
+
+    let (event, status) = match &result {
+        Ok(report) if report.delivered && report.rejected().count() == 0 => (webhooks::EVENT_SENT, "sent"),
+        Ok(report) if report.delivered => (webhooks::EVENT_SENT, "partial"),
+        _ => (webhooks::EVENT_FAILED, "failed"),
+    };
+    webhooks::notify(
+        &state,
+        webhooks::SendEvent {
+            event,
+            from: from_address.clone(),
+            to: to.clone(),
+            subject: subject.clone(),
+            status,
+        },
+    );
+
+    match result {
+        Ok(report) if report.delivered => {
+            telemetry::email_sent();
+            record_sent_message(
+                &state,
+                &sent_message_id,
+                report.message_id.as_deref(),
+                &user.id,
+                &from_address,
+                &to,
+                cc.as_deref(),
+                bcc.as_deref(),
+                &subject,
+                &final_body,
+                web_copy.as_ref(),
+            )
+            .await;
+            if let Some(id) = &draft_id {
+                drafts::discard_sent(&state.db, &user.id, id).await;
+            }
+            let rejected = report.rejected().count();
+            if rejected == 0 {
+                Ok(Json(serde_json::json!({
+                    "status": "sent",
+                    "message": "Email sent successfully",
+                    "messageId": report.message_id,
+                    "recipients": report.recipients,
+                    "skipped": skipped
+                }))
+                .into_response())
+            } else {
+                Ok(Json(serde_json::json!({
+                    "status": "partial",
+                    "message": format!("Email sent, but {} recipient(s) were rejected", rejected),
+                    "messageId": report.message_id,
+                    "recipients": report.recipients,
+                    "skipped": skipped
+                }))
+                .into_response())
+            }
         }
-        Err(e) => {
-            eprintln!("Failed to send email: {}", e);
+        Ok(report) => {
+            telemetry::send_failed("rejected");
+            let message = if all_or_nothing && report.rejected().count() < report.recipients.len() {
+                "Send aborted because some recipients were rejected"
+            } else {
+                "No recipients were accepted"
+            };
             Ok(Json(serde_json::json!({
                 "status": "error",
-                "message": format!("Failed to send email: {}", e)
-            })))
+                "message": message,
+                "recipients": report.recipients
+            }))
+            .into_response())
+        }
+        Err(e) => {
+            if let Some(throttled) = e.downcast_ref::<Throttled>() {
+                telemetry::send_failed("throttled");
+                return Err(throttled_error(throttled.retry_after.as_secs().max(1)));
+            }
+            if let Some(timed_out) = e.downcast_ref::<SmtpTimedOut>() {
+                tracing::error!(user_id = %user.id, sender = %resolved.auth_email, after = ?timed_out.after, "SMTP send timed out");
+                telemetry::send_failed("timeout");
+                return Err(ApiError::new(
+                    StatusCode::GATEWAY_TIMEOUT,
+                    "smtp_timeout",
+                    format!("The mail server did not respond within {} seconds", timed_out.after.as_secs()),
+                ));
+            }
+            tracing::error!(error = %e, user_id = %user.id, sender = %resolved.auth_email, "failed to send email");
+            telemetry::send_failed("smtp");
+            Err(ApiError::new(
+                StatusCode::BAD_GATEWAY,
+                "smtp_error",
+                format!("Failed to send email: {}", e),
+            ))
+        }
+    }
+}
+
+// Non-empty entries of the comma-separated to, cc and bcc lists
+fn recipient_entries(to: &str, cc: Option<&str>, bcc: Option<&str>) -> Vec<String> {
+    [Some(to), cc, bcc]
+        .into_iter()
+        .flatten()
+        .flat_map(split_recipients)
+        .map(str::to_string)
+        .collect()
+}
+
+fn check_recipient_count(count: usize, max: usize) -> Result<(), ApiError> {
+    if count > max {
+        return Err(ApiError::invalid(
+            "to",
+            "too_many_recipients",
+            format!(
+                "{} recipients across to, cc and bcc; at most {} are allowed per message",
+                count, max
+            ),
+        ));
+    }
+    Ok(())
+}
+
+// "Name <a@b.com>" -> "a@b.com", lowercased; unparseable entries are kept as written
+// and left for the send itself to reject
+pub(crate) fn bare_address(entry: &str) -> String {
+    entry
+        .parse::<lettre::message::Mailbox>()
+        .map(|mailbox| mailbox.email.to_string())
+        .unwrap_or_else(|_| entry.trim().to_string())
+        .to_ascii_lowercase()
+}
+
+fn without_unsubscribed(list: &str, unsubscribed: &HashSet<String>, skipped: &mut Vec<String>) -> String {
+    let mut kept = Vec::new();
+    for entry in split_recipients(list) {
+        let address = bare_address(entry);
+        if unsubscribed.contains(&address) {
+            skipped.push(address);
+        } else {
+            kept.push(entry);
+        }
+    }
+    kept.join(", ")
+}
+
+// Decode request attachments, enforcing the total size cap (413) and valid base64 (400)
+fn decode_attachments(
+    attachments: Vec<AttachmentRequest>,
+    max_total_bytes: usize,
+) -> Result<Vec<EmailAttachment>, ApiError> {
+    let mut total = 0usize;
+    let mut files = Vec::with_capacity(attachments.len());
+    for attachment in attachments {
+        // Only the last path segment, and no header-breaking characters
+        let filename = attachment
+            .filename
+            .rsplit(['/', '\\'])
+            .next()
+            .unwrap_or_default()
+            .chars()
+            .filter(|c| !c.is_control())
+            .collect::<String>();
+        if filename.trim().is_empty() {
+            return Err(ApiError::invalid(
+                "attachments",
+                "attachment_filename_required",
+                "Attachment filename is required",
+            ));
+        }
+
+        let Ok(data) = Base64.decode(attachment.content_base64.trim()) else {
+            return Err(ApiError::invalid(
+                "attachments",
+                "invalid_attachment",
+                format!("Attachment {} is not valid base64", filename),
+            ));
+        };
+        total += data.len();
+        if total > max_total_bytes {
+            return Err(ApiError::new(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                "attachments_too_large",
+                format!("Attachments exceed the {} byte limit", max_total_bytes),
+            )
+            .with_field("attachments"));
         }
+
+        files.push(EmailAttachment {
+            filename,
+            content_type: attachment
+                .content_type
+                .filter(|v| !v.trim().is_empty())
+                .unwrap_or_else(|| "application/octet-stream".to_string()),
+            data,
+            content_id: attachment
+                .content_id
+                .map(|id| id.trim().trim_start_matches('<').trim_end_matches('>').to_string())
+                .filter(|id| !id.is_empty() && !id.chars().any(|c| c.is_control() || c.is_whitespace())),
+        });
+    }
+    Ok(files)
+}
+
+// Keep a copy of delivered mail for /api/sent, plus the browser copy for a webVersion
+// send. A failed insert is logged, not surfaced: the message has already gone out.
+#[allow(clippy::too_many_arguments)]
+async fn record_sent_message(
+    state: &AppState,
+    id: &str,
+    message_id: Option<&str>,
+    user_id: &str,
+    from: &str,
+    to: &str,
+    cc: Option<&str>,
+    bcc: Option<&str>,
+    subject: &str,
+    body: &str,
+    web_copy: Option<&web_view::WebCopy>,
+) {
+    let cc = cc.filter(|v| !v.trim().is_empty());
+    let bcc = bcc.filter(|v| !v.trim().is_empty());
+    let result = async {
+        let mut tx = state.db.begin().await?;
+        sqlx::query(
+            r#"
+            INSERT INTO sent_messages (id, message_id, user_id, from_email, to_recipients, cc_recipients, bcc_recipients, subject, body, web_html, web_expires_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+            "#,
+        )
+        .bind(id)
+        .bind(message_id)
+        .bind(user_id)
+        .bind(from)
+        .bind(to)
+        .bind(cc)
+        .bind(bcc)
+        .bind(subject)
+        .bind(body)
+        .bind(web_copy.map(|copy| copy.html.as_str()))
+        .bind(web_copy.map(|copy| copy.expires_at))
+        .execute(&mut *tx)
+        .await?;
+        sent_recipients::record(&mut *tx, id, to, cc, bcc).await?;
+        tx.commit().await
+    }
+    .await;
+    if let Err(e) = result {
+        tracing::error!(error = %e, %user_id, %from, "failed to record sent message");
     }
 }
 
+const SENT_COLUMNS: &str =
+    "id, from_email, to_recipients, cc_recipients, bcc_recipients, subject, body, sent_at, message_id";
+
+fn sent_message_from_row(row: &PgRow) -> SentMessage {
+    SentMessage {
+        id: row.get::<String, _>(0),
+        from: row.get::<String, _>(1),
+        to: row.get::<String, _>(2),
+        cc: row.get::<Option<String>, _>(3),
+        bcc: row.get::<Option<String>, _>(4),
+        subject: row.get::<String, _>(5),
+        body: row.get::<String, _>(6),
+        sent_at: row.get::<chrono::DateTime<chrono::Utc>, _>(7).to_rfc3339(),
+        message_id: row.get::<Option<String>, _>(8),
+    }
+}
+
+pub async fn list_sent_messages(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Query(params): Query<SentQuery>,
+) -> Result<Json<Vec<SentMessage>>, ApiError> {
+    user.ensure_password_updated()?;
+    user.ensure_scope(SCOPE_SENT_READ)?;
+    let limit = params.limit.unwrap_or(50).clamp(1, 200) as i64;
+    // Matched against the indexed, lowercased addresses, whatever the position or
+    // casing in the original to/cc/bcc lists
+    let recipient = match params.recipient.as_deref().map(str::trim).filter(|v| !v.is_empty()) {
+        Some(value) if is_valid_email(value) => Some(value.to_ascii_lowercase()),
+        Some(value) => return Err(invalid_email_error("recipient", value)),
+        None => None,
+    };
+
+    let mut select = QueryBuilder::new(format!("SELECT {} FROM sent_messages WHERE user_id = ", SENT_COLUMNS));
+    select.push_bind(&user.id);
+    if let Some(recipient) = recipient {
+        select.push(" AND id IN (SELECT sent_message_id FROM sent_message_recipients WHERE address = ");
+        select.push_bind(recipient);
+        select.push(")");
+    }
+    select.push(" ORDER BY sent_at DESC LIMIT ");
+    select.push_bind(limit);
+
+    let rows = select
+        .build()
+        .fetch_all(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(rows.iter().map(sent_message_from_row).collect()))
+}
+
+pub async fn get_sent_message(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+) -> Result<Json<SentMessageDetail>, ApiError> {
+    user.ensure_password_updated()?;
+    user.ensure_scope(SCOPE_SENT_READ)?;
+
+    let row = sqlx::query(&format!("SELECT {} FROM sent_messages WHERE id = $1 AND user_id = $2", SENT_COLUMNS))
+        .bind(&id)
+        .bind(&user.id)
+        .fetch_optional(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, "sent_message_not_found", "Sent message not found"))?;
+    let recipients = sent_recipients::for_message(&state.db, &id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(SentMessageDetail {
+        message: sent_message_from_row(&row),
+        recipients,
+    }))
+}
+
+fn throttled_error(retry_after_secs: u64) -> ApiError {
+    ApiError::new(
+        StatusCode::SERVICE_UNAVAILABLE,
+        "sender_throttled",
+        "The mail server is throttling this sender. Try again later.",
+    )
+    .with_retry_after(retry_after_secs)
+}
+
 pub async fn get_inbox(
-    State(_state): State<AppState>,
+    State(state): State<AppState>,
+    user: AuthUser,
+    Query(params): Query<InboxQuery>,
+) -> Result<Json<Vec<inbox::InboxMessage>>, ApiError> {
+    user.ensure_password_updated()?;
+    user.ensure_scope(SCOPE_INBOX_READ)?;
+    if !matches!(user.role, UserRole::Dev | UserRole::Admin) {
+        return Err(StatusCode::FORBIDDEN.into());
+    }
+
+    let limit = params
+        .limit
+        .unwrap_or(inbox::DEFAULT_LIMIT)
+        .min(inbox::MAX_LIMIT);
+
+    let folder = inbox_folder(&params.folder)?;
+
+    let (mut session, account) = open_inbox(&state, &user, &params.account).await?;
+    let messages = inbox::fetch_recent(&mut session, folder, limit).await;
+    let _ = session.logout().await;
+
+    let messages = messages.map_err(|e| {
+        tracing::error!(error = %e, user_id = %user.id, %account, %folder, "IMAP fetch failed");
+        imap_failure_status(&e)
+    })?;
+    Ok(Json(messages))
+}
+
+pub async fn list_inbox_folders(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Query(params): Query<InboxMessageQuery>,
+) -> Result<Json<Vec<inbox::Folder>>, ApiError> {
+    user.ensure_password_updated()?;
+    user.ensure_scope(SCOPE_INBOX_READ)?;
+    if !matches!(user.role, UserRole::Dev | UserRole::Admin) {
+        return Err(StatusCode::FORBIDDEN.into());
+    }
+
+    let (mut session, account) = open_inbox(&state, &user, &params.account).await?;
+    let folders = inbox::list_folders(&mut session).await;
+    let _ = session.logout().await;
+
+    let folders = folders.map_err(|e| {
+        tracing::error!(error = %e, user_id = %user.id, %account, "IMAP folder list failed");
+        StatusCode::BAD_GATEWAY
+    })?;
+    Ok(Json(folders))
+}
+
+// The folder query parameter, INBOX when omitted. Names go to the server as given
+// (quoted by the IMAP client) but can't carry control characters.
+fn inbox_folder(folder: &Option<String>) -> Result<&str, ApiError> {
+    match folder.as_deref().filter(|f| !f.trim().is_empty()) {
+        None => Ok(inbox::DEFAULT_FOLDER),
+        Some(name) if name.chars().any(char::is_control) => Err(ApiError::invalid(
+            "folder",
+            "invalid_folder",
+            "Folder names can't contain control characters",
+        )),
+        Some(name) => Ok(name),
+    }
+}
+
+// 404 when the server has no such folder, 502 for any other IMAP failure
+fn imap_failure_status(error: &anyhow::Error) -> StatusCode {
+    if error.is::<inbox::FolderNotFound>() {
+        StatusCode::NOT_FOUND
+    } else {
+        StatusCode::BAD_GATEWAY
+    }
+}
+
+pub async fn search_inbox(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Query(params): Query<InboxSearchQuery>,
+) -> Result<Response, ApiError> {
+    user.ensure_password_updated()?;
+    user.ensure_scope(SCOPE_INBOX_READ)?;
+    if !matches!(user.role, UserRole::Dev | UserRole::Admin) {
+        return Err(StatusCode::FORBIDDEN.into());
+    }
+
+    let query = params.q.as_deref().unwrap_or_default().trim().to_string();
+    if query.is_empty() {
+        return Err(ApiError::invalid("q", "query_required", "Search query is required"));
+    }
+    if query.chars().any(char::is_control) {
+        return Err(ApiError::invalid("q", "invalid_query", "Search query must be a single line"));
+    }
+    let field_param = params.field.as_deref().unwrap_or_default();
+    let Some(field) = inbox::SearchField::parse(field_param) else {
+        return Err(ApiError::invalid(
+            "field",
+            "unknown_search_field",
+            format!("Unknown search field {:?}; use text, subject, from, to or body", field_param),
+        ));
+    };
+
+    let parse_date = |name: &'static str, value: &Option<String>| {
+        match value.as_deref().map(str::trim).filter(|v| !v.is_empty()) {
+            None => Ok(None),
+            Some(value) => chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                .map(Some)
+                .map_err(|_| {
+                    ApiError::invalid(name, "invalid_date", format!("{} must be a date like 2024-01-31", name))
+                }),
+        }
+    };
+    let since = parse_date("since", &params.since)?;
+    let before = parse_date("before", &params.before)?;
+    if let (Some(since), Some(before)) = (since, before) {
+        if before <= since {
+            return Err(ApiError::invalid("before", "invalid_date_range", "before must be later than since"));
+        }
+    }
+
+    let criteria = inbox::SearchCriteria { field, query, since, before };
+    let limit = params
+        .limit
+        .unwrap_or(inbox::DEFAULT_LIMIT)
+        .min(inbox::MAX_LIMIT);
+
+    let folder = inbox_folder(&params.folder)?;
+
+    let (mut session, account) = open_inbox(&state, &user, &params.account).await?;
+    let messages = inbox::search(&mut session, folder, &criteria, limit).await;
+    let _ = session.logout().await;
+
+    let messages = messages.map_err(|e| {
+        tracing::error!(error = %e, user_id = %user.id, %account, %folder, "IMAP search failed");
+        imap_failure_status(&e)
+    })?;
+    Ok(Json(messages).into_response())
+}
+
+// One full message; attachments are listed here and downloaded separately
+pub async fn get_inbox_message(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(uid): Path<u32>,
+    Query(params): Query<InboxMessageQuery>,
+) -> Result<Json<inbox::InboxMessageDetail>, ApiError> {
+    user.ensure_password_updated()?;
+    user.ensure_scope(SCOPE_INBOX_READ)?;
+    if !matches!(user.role, UserRole::Dev | UserRole::Admin) {
+        return Err(StatusCode::FORBIDDEN.into());
+    }
+
+    let (raw, account) = fetch_inbox_message(&state, &user, &params, uid).await?;
+    let detail = inbox::message_detail(uid, &raw).map_err(|e| {
+        tracing::error!(error = %e, user_id = %user.id, %account, uid, "failed to parse inbox message");
+        StatusCode::BAD_GATEWAY
+    })?;
+    Ok(Json(detail))
+}
+
+pub async fn download_inbox_attachment(
+    State(state): State<AppState>,
     user: AuthUser,
-    Query(_params): Query<InboxQuery>,
-) -> Result<Json<serde_json::Value>, StatusCode> {
+    Path((uid, index)): Path<(u32, usize)>,
+    Query(params): Query<InboxMessageQuery>,
+) -> Result<Response, ApiError> {
     user.ensure_password_updated()?;
+    user.ensure_scope(SCOPE_INBOX_READ)?;
     if !matches!(user.role, UserRole::Dev | UserRole::Admin) {
-        return Err(StatusCode::FORBIDDEN);
+        return Err(StatusCode::FORBIDDEN.into());
+    }
+
+    let (raw, _) = fetch_inbox_message(&state, &user, &params, uid).await?;
+    let (meta, data) = inbox::attachment(&raw, index).ok_or(StatusCode::NOT_FOUND)?;
+
+    // The type comes from the remote message; anything that isn't a valid header value
+    // is served as opaque bytes
+    let content_type = header::HeaderValue::from_str(&meta.content_type)
+        .unwrap_or(header::HeaderValue::from_static("application/octet-stream"));
+    let filename = meta.filename.as_deref().unwrap_or("attachment");
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type),
+            (header::CONTENT_DISPOSITION, attachment_disposition(filename)),
+            (header::X_CONTENT_TYPE_OPTIONS, header::HeaderValue::from_static("nosniff")),
+        ],
+        data,
+    )
+        .into_response())
+}
+
+// `attachment` with an ASCII fallback name plus the exact UTF-8 name as filename* (RFC 6266)
+fn attachment_disposition(filename: &str) -> header::HeaderValue {
+    let fallback: String = filename
+        .chars()
+        .map(|c| if (c.is_ascii_graphic() && c != '"' && c != '\\') || c == ' ' { c } else { '_' })
+        .collect();
+    let mut value = format!("attachment; filename=\"{}\"", fallback);
+    if fallback != filename {
+        value.push_str("; filename*=UTF-8''");
+        for byte in filename.bytes() {
+            if byte.is_ascii_alphanumeric() || b"!#$&+-.^_`|~".contains(&byte) {
+                value.push(byte as char);
+            } else {
+                value.push_str(&format!("%{:02X}", byte));
+            }
+        }
     }
-    // TODO: Implement IMAP inbox retrieval
-    Ok(Json(serde_json::json!([])))
+    // Only visible ASCII was written above
+    header::HeaderValue::from_str(&value).unwrap_or(header::HeaderValue::from_static("attachment"))
+}
+
+// Raw RFC 822 bytes of one message (404 for an unknown UID) and the login address
+async fn fetch_inbox_message(
+    state: &AppState,
+    user: &AuthUser,
+    params: &InboxMessageQuery,
+    uid: u32,
+) -> Result<(Vec<u8>, String), ApiError> {
+    let folder = inbox_folder(&params.folder)?;
+
+    let (mut session, account) = open_inbox(state, user, &params.account).await?;
+    let raw = inbox::fetch_raw(&mut session, folder, uid).await;
+    let _ = session.logout().await;
+
+    let raw = raw
+        .map_err(|e| {
+            tracing::error!(error = %e, user_id = %user.id, %account, %folder, uid, "IMAP message fetch failed");
+            imap_failure_status(&e)
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    Ok((raw, account))
+}
+
+// Marks one message read or unread
+pub async fn update_inbox_message(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(uid): Path<u32>,
+    Query(params): Query<InboxMessageQuery>,
+    Json(req): Json<UpdateInboxMessageRequest>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    user.ensure_password_updated()?;
+    user.ensure_scope(SCOPE_INBOX_READ)?;
+    if !matches!(user.role, UserRole::Dev | UserRole::Admin) {
+        return Err(StatusCode::FORBIDDEN.into());
+    }
+
+    let folder = inbox_folder(&params.folder)?;
+
+    let (mut session, account) = open_inbox(&state, &user, &params.account).await?;
+    let result = inbox::set_seen(&mut session, folder, uid, req.seen).await;
+    let _ = session.logout().await;
+    state.unread_cache.invalidate(&account, folder);
+
+    let seen = result
+        .map_err(|e| {
+            tracing::error!(error = %e, user_id = %user.id, %account, %folder, uid, "IMAP flag update failed");
+            imap_failure_status(&e)
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(serde_json::json!({ "uid": uid, "seen": seen })))
+}
+
+// Served from a short per-account, per-folder cache so a polling badge stays cheap
+pub async fn get_unread_count(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Query(params): Query<InboxMessageQuery>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    user.ensure_password_updated()?;
+    user.ensure_scope(SCOPE_INBOX_READ)?;
+    if !matches!(user.role, UserRole::Dev | UserRole::Admin) {
+        return Err(StatusCode::FORBIDDEN.into());
+    }
+
+    let folder = inbox_folder(&params.folder)?;
+
+    // Authorized before the cache is consulted, so a cached count never leaks
+    let resolved = authorize_inbox(&state, &user, &params.account).await?;
+    if let Some(unread) = state.unread_cache.get(&resolved.auth_email, folder) {
+        return Ok(Json(serde_json::json!({ "unread": unread })));
+    }
+
+    let mut session = connect_inbox(&state, &user, &resolved).await?;
+    let result = inbox::unread_count(&mut session, folder).await;
+    let _ = session.logout().await;
+
+    let unread = result.map_err(|e| {
+        tracing::error!(error = %e, user_id = %user.id, account = %resolved.auth_email, %folder, "IMAP unread count failed");
+        imap_failure_status(&e)
+    })?;
+    state.unread_cache.put(&resolved.auth_email, folder, unread);
+    Ok(Json(serde_json::json!({ "unread": unread })))
+}
+
+// Resolves the account (or alias), checks the caller may use its mailbox, and logs in
+// over IMAP; returns the login address too
+async fn open_inbox(
+    state: &AppState,
+    user: &AuthUser,
+    account: &str,
+) -> Result<(inbox::ImapSession, String), ApiError> {
+    let resolved = authorize_inbox(state, user, account).await?;
+    let session = connect_inbox(state, user, &resolved).await?;
+    Ok((session, resolved.auth_email))
+}
+
+async fn authorize_inbox(
+    state: &AppState,
+    user: &AuthUser,
+    account: &str,
+) -> Result<mailer::ResolvedSender, ApiError> {
+    let resolved = mailer::resolve_sender_by_email(&state.db, &state.secrets, account.trim())
+        .await
+        .map_err(|_| StatusCode::NOT_FOUND)?;
+
+    if !matches!(user.role, UserRole::Admin) {
+        let allowed = mailer::may_access_mailbox(&state.db, &user.id, &resolved.auth_email)
+            .await
+            .map_err(|e| {
+                tracing::error!(error = %e, user_id = %user.id, "failed to check mailbox access");
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+        if !allowed {
+            tracing::warn!(user_id = %user.id, account = %resolved.auth_email, "refused mailbox access");
+            return Err(StatusCode::FORBIDDEN.into());
+        }
+    }
+    Ok(resolved)
+}
+
+async fn connect_inbox(
+    state: &AppState,
+    user: &AuthUser,
+    resolved: &mailer::ResolvedSender,
+) -> Result<inbox::ImapSession, ApiError> {
+    let auth = oauth::smtp_auth(state, &resolved.auth_email, &resolved.auth_password)
+        .await
+        .map_err(|e| {
+            tracing::error!(error = %e, user_id = %user.id, account = %resolved.auth_email, "failed to get IMAP credentials");
+            StatusCode::BAD_GATEWAY
+        })?;
+
+    inbox::connect(&state.imap, &resolved.auth_email, &auth)
+        .await
+        .map_err(|e| {
+            tracing::error!(error = %e, user_id = %user.id, account = %resolved.auth_email, "IMAP connection failed");
+            ApiError::new(StatusCode::BAD_GATEWAY, "imap_unavailable", "Could not sign in to the mailbox over IMAP")
+        })
 }
 
 // Get public accounts (for compose - visible to all authenticated users)
 pub async fn get_public_accounts(
     State(state): State<AppState>,
     user: AuthUser,
-) -> Result<Json<Vec<EmailAccount>>, StatusCode> {
+) -> Result<Json<Vec<EmailAccount>>, ApiError> {
     user.ensure_password_updated()?;
+    user.ensure_scope(SCOPE_ACCOUNTS_READ)?;
     
-    // Get public accounts + accounts owned by the user
-    let rows = sqlx::query(
-        "SELECT id, email, display_name, is_active, owner_id, is_public FROM accounts WHERE (is_public = 1 OR owner_id = ?) AND is_active = 1"
-    )
+    // Public accounts + accounts the user owns or has been granted
+    let rows = sqlx::query(&format!(
+        "SELECT {} FROM accounts WHERE (is_public = TRUE OR owner_id = $1 OR id IN (SELECT account_id FROM user_accounts WHERE user_id = $1)) AND is_active = TRUE AND deleted_at IS NULL",
+        ACCOUNT_COLUMNS
+    ))
     .bind(&user.id)
     .fetch_all(&state.db)
     .await
@@ -749,14 +2966,7 @@ pub async fn get_public_accounts(
 
     let accounts: Vec<EmailAccount> = rows
         .into_iter()
-        .map(|row| EmailAccount {
-            id: row.get::<String, _>(0),
-            email: row.get::<String, _>(1),
-            display_name: row.get::<String, _>(2),
-            is_active: row.get::<bool, _>(3),
-            owner_id: row.get::<Option<String>, _>(4),
-            is_public: row.get::<bool, _>(5),
-        })
+        .map(|row| account_from_row(&row))
         .collect();
 
     Ok(Json(accounts))
@@ -766,29 +2976,15 @@ pub async fn get_public_accounts(
 pub async fn get_public_aliases(
     State(state): State<AppState>,
     user: AuthUser,
-) -> Result<Json<Vec<EmailAlias>>, StatusCode> {
+) -> Result<Json<Vec<EmailAlias>>, ApiError> {
     user.ensure_password_updated()?;
+    user.ensure_scope(SCOPE_ACCOUNTS_READ)?;
     
-    // Get public aliases + aliases owned by the user
-    let rows = sqlx::query(
-        r#"
-        SELECT 
-            aliases.id,
-            aliases.alias_email,
-            aliases.display_name,
-            aliases.is_active,
-            aliases.account_id,
-            accounts.email,
-            accounts.display_name,
-            accounts.is_active,
-            aliases.owner_id,
-            aliases.is_public
-        FROM aliases
-        JOIN accounts ON aliases.account_id = accounts.id
-        WHERE (aliases.is_public = 1 OR aliases.owner_id = ?) AND aliases.is_active = 1 AND accounts.is_active = 1
-        ORDER BY aliases.alias_email ASC
-        "#
-    )
+    // Public aliases + aliases the user owns or can use through their account
+    let rows = sqlx::query(&format!(
+        "{} WHERE (aliases.is_public = TRUE OR aliases.owner_id = $1 OR accounts.owner_id = $1 OR accounts.id IN (SELECT account_id FROM user_accounts WHERE user_id = $1)) AND aliases.is_active = TRUE AND accounts.is_active = TRUE AND accounts.deleted_at IS NULL ORDER BY aliases.alias_email ASC",
+        ALIAS_SELECT
+    ))
     .bind(&user.id)
     .fetch_all(&state.db)
     .await
@@ -796,20 +2992,422 @@ pub async fn get_public_aliases(
 
     let aliases = rows
         .into_iter()
-        .map(|row| EmailAlias {
-            id: row.get::<String, _>(0),
-            alias_email: row.get::<String, _>(1),
-            display_name: row.get::<Option<String>, _>(2),
-            is_active: row.get::<bool, _>(3),
-            account_id: row.get::<String, _>(4),
-            account_email: row.get::<String, _>(5),
-            account_display_name: row.get::<String, _>(6),
-            account_is_active: row.get::<bool, _>(7),
-            owner_id: row.get::<Option<String>, _>(8),
-            is_public: row.get::<bool, _>(9),
-        })
+        .map(|row| alias_from_row(&row))
         .collect();
 
     Ok(Json(aliases))
 }
 
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use axum::{
+        http::{Method, StatusCode},
+        response::IntoResponse,
+    };
+
+    use super::{check_recipient_count, recipient_entries};
+    use crate::{
+        mailer,
+        test_support::{self, MockSmtp},
+    };
+
+    #[tokio::test]
+    async fn recipient_limit_allows_exactly_the_maximum() {
+        let recipients = recipient_entries("a@x.com, b@x.com", Some("c@x.com"), Some(" , "));
+        assert_eq!(recipients.len(), 3);
+        assert!(check_recipient_count(recipients.len(), 3).is_ok());
+
+        let error = check_recipient_count(recipients.len(), 2).unwrap_err();
+        assert_eq!(error.status(), StatusCode::BAD_REQUEST);
+        let body = axum::body::to_bytes(error.into_response().into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["code"], "too_many_recipients");
+        assert_eq!(body["field"], "to");
+        assert_eq!(
+            body["message"],
+            "3 recipients across to, cc and bcc; at most 2 are allowed per message"
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn bcc_self_counts_toward_the_recipient_limit() {
+        let mut state = test_support::state().await;
+        state.max_recipients = 2;
+        let admin = test_support::user(&state.db, "admin").await;
+        let sender = test_support::account(&state.db, None).await;
+        let app = crate::router(state, 1024 * 1024);
+        let dry_run = |to: &str, bcc_self: bool| {
+            let body = serde_json::json!({
+                "from": sender.email,
+                "to": to,
+                "subject": "Hello",
+                "body": "Hello",
+                "bccSelf": bcc_self,
+                "dryRun": true
+            })
+            .to_string();
+            test_support::call(app.clone(), Method::POST, "/api/send", &admin.token, body)
+        };
+
+        let (status, _) = dry_run("a@example.com, b@example.com", false).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, json) = dry_run("a@example.com, b@example.com", true).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(json["code"], "too_many_recipients");
+
+        // Already a recipient, so there is no extra copy to count
+        let (status, _) = dry_run(&format!("a@example.com, {}", sender.email), true).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn honor_unsubscribe_checks_the_sender_first() {
+        let state = test_support::state().await;
+        let owner = test_support::user(&state.db, "dev").await;
+        let stranger = test_support::user(&state.db, "dev").await;
+        let sender = test_support::account(&state.db, Some(&owner.id)).await;
+        let recipient = format!("{}@recipients.test", uuid::Uuid::new_v4());
+        sqlx::query("INSERT INTO unsubscribes (email, created_at) VALUES ($1, 0)")
+            .bind(&recipient)
+            .execute(&state.db)
+            .await
+            .unwrap();
+        let body = serde_json::json!({
+            "from": sender.email,
+            "to": recipient,
+            "subject": "Hello",
+            "body": "Hello",
+            "honorUnsubscribe": true
+        })
+        .to_string();
+
+        let app = crate::router(state.clone(), 1024 * 1024);
+        let (status, json) =
+            test_support::call(app.clone(), Method::POST, "/api/send", &stranger.token, body.clone()).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(json["field"], "from");
+        assert!(json.get("skipped").is_none());
+
+        let (status, json) = test_support::call(app, Method::POST, "/api/send", &owner.token, body).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(json["status"], "skipped");
+        assert_eq!(json["skipped"], serde_json::json!([recipient]));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn concurrent_sends_past_the_limit_get_429_until_permits_free_up() {
+        let smtp = MockSmtp::start_slow(&[], Duration::from_secs(3)).await;
+        let mut state = test_support::state().await;
+        state.smtp = smtp.config();
+        state.send_limiter = crate::send_limits::SendLimiter::with_limit(2);
+        let admin = test_support::user(&state.db, "admin").await;
+        let sender = test_support::account(&state.db, None).await;
+        let body = serde_json::json!({
+            "from": sender.email,
+            "to": "someone@example.com",
+            "subject": "Hello",
+            "body": "Hello"
+        })
+        .to_string();
+        let app = crate::router(state, 1024 * 1024);
+        let send = || {
+            let (app, token, body) = (app.clone(), admin.token.clone(), body.clone());
+            async move { test_support::call(app, Method::POST, "/api/send", &token, body).await }
+        };
+
+        let slow = [tokio::spawn(send()), tokio::spawn(send())];
+        // Both slow sends hold their permits before the third one asks
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        let (status, json) = send().await;
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(json["code"], "too_many_sends");
+        assert_eq!(json["retryAfter"], crate::send_limits::RETRY_AFTER_SECS);
+
+        for handle in slow {
+            let (status, _) = handle.await.unwrap();
+            assert_eq!(status, StatusCode::OK);
+        }
+        let (status, json) = send().await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(json["status"], "sent");
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn sent_messages_can_be_filtered_by_any_recipient() {
+        let smtp = MockSmtp::start(&[]).await;
+        let mut state = test_support::state().await;
+        state.smtp = smtp.config();
+        let admin = test_support::user(&state.db, "admin").await;
+        let sender = test_support::account(&state.db, None).await;
+        let app = crate::router(state.clone(), 1024 * 1024);
+        let send = |to: &str, cc: Option<&str>| {
+            let body = serde_json::json!({
+                "from": sender.email,
+                "to": to,
+                "cc": cc,
+                "subject": "Hello",
+                "body": "Hello"
+            })
+            .to_string();
+            test_support::call(app.clone(), Method::POST, "/api/send", &admin.token, body)
+        };
+        let sent = |query: &str| {
+            let uri = format!("/api/sent?{}", query);
+            let (app, token) = (app.clone(), admin.token.clone());
+            async move { test_support::call(app, Method::GET, &uri, &token, "").await }
+        };
+
+        let (status, _) = send("first@example.com, Customer <Customer@Example.com>", None).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = send("other@example.com", Some("customer@example.com")).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = send("other@example.com", None).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, json) = sent("recipient=CUSTOMER@example.com").await;
+        assert_eq!(status, StatusCode::OK);
+        let found = json.as_array().unwrap();
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0]["cc"], "customer@example.com");
+
+        let (_, json) = sent("recipient=first@example.com").await;
+        assert_eq!(json.as_array().unwrap().len(), 1);
+        let (_, json) = sent("").await;
+        assert_eq!(json.as_array().unwrap().len(), 3);
+
+        let (status, json) = sent("recipient=not-an-address").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(json["field"], "recipient");
+
+        let uri = format!("/api/sent/{}", found[1]["id"].as_str().unwrap());
+        let (status, json) = test_support::call(app.clone(), Method::GET, &uri, &admin.token, "").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(json["recipients"]["to"], serde_json::json!(["customer@example.com", "first@example.com"]));
+        assert_eq!(json["recipients"]["cc"], serde_json::json!([]));
+
+
+        // Another user's message looks like a missing one
+        let stranger = test_support::user(&state.db, "admin").await;
+        let (status, json) = test_support::call(app, Method::GET, &uri, &stranger.token, "").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(json["code"], "sent_message_not_found");
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn a_web_version_send_links_to_its_stored_copy() {
+        let smtp = MockSmtp::start(&[]).await;
+        let mut state = test_support::state().await;
+        state.smtp = smtp.config();
+        let admin = test_support::user(&state.db, "admin").await;
+        let sender = test_support::account(&state.db, None).await;
+        let app = crate::router(state, 1024 * 1024);
+        let send = |is_html: bool| {
+            let body = serde_json::json!({
+                "from": sender.email,
+                "to": "reader@example.com",
+                "subject": "Newsletter",
+                "body": "<p>Hello</p><img src=\"https://t.example/open.gif\" width=\"1\" height=\"1\">",
+                "isHtml": is_html,
+                "useTemplate": false,
+                "webVersion": true
+            })
+            .to_string();
+            test_support::call(app.clone(), Method::POST, "/api/send", &admin.token, body)
+        };
+
+        let (status, json) = send(false).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(json["code"], "web_version_requires_html");
+
+        let (status, _) = send(true).await;
+        assert_eq!(status, StatusCode::OK);
+        let (_, json) = test_support::call(app.clone(), Method::GET, "/api/sent?limit=1", &admin.token, "").await;
+        let sent_body = json[0]["body"].as_str().unwrap();
+        let link = regex::Regex::new(r#"href="https://mail\.test(/api/view/[^"]+)""#)
+            .unwrap()
+            .captures(sent_body)
+            .expect("the sent message links to its web version")[1]
+            .to_string();
+
+        let response = tower::ServiceExt::oneshot(
+            app,
+            axum::http::Request::get(link).body(axum::body::Body::empty()).unwrap(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let page = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let page = String::from_utf8(page.to_vec()).unwrap();
+        assert!(page.contains("<p>Hello</p>"));
+        assert!(!page.contains("open.gif"));
+        assert!(!page.contains("View in browser"));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn throttled_sender_cools_down_for_the_window() {
+        let smtp = MockSmtp::start(&[(
+            "RCPT TO:",
+            "451 4.7.500 Server busy. Please try again later in 1 seconds",
+        )])
+        .await;
+        let mut state = test_support::state().await;
+        state.smtp = smtp.config();
+        let admin = test_support::user(&state.db, "admin").await;
+        let sender = test_support::account(&state.db, None).await;
+        let body = serde_json::json!({
+            "from": sender.email,
+            "to": "someone@example.com",
+            "subject": "Hello",
+            "body": "Hello"
+        })
+        .to_string();
+        let app = crate::router(state.clone(), 1024 * 1024);
+        let sessions = || smtp.commands().iter().filter(|c| c.starts_with("MAIL FROM:")).count();
+
+        let (status, json) =
+            test_support::call(app.clone(), Method::POST, "/api/send", &admin.token, body.clone()).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(json["retryAfter"], 1);
+        assert!(mailer::account_cooldown(&state.db, &sender.email).await.unwrap().is_some());
+        assert_eq!(sessions(), 1);
+
+        // Refused without contacting the server while the cooldown lasts
+        let (status, _) =
+            test_support::call(app.clone(), Method::POST, "/api/send", &admin.token, body.clone()).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(sessions(), 1);
+
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        assert!(mailer::account_cooldown(&state.db, &sender.email).await.unwrap().is_none());
+        test_support::call(app, Method::POST, "/api/send", &admin.token, body).await;
+        assert_eq!(sessions(), 2);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn updating_a_missing_account_or_alias_is_a_404() {
+        let state = test_support::state().await;
+        let admin = test_support::user(&state.db, "admin").await;
+        let app = crate::router(state.clone(), 1024 * 1024);
+        let body = serde_json::json!({ "isPublic": true }).to_string();
+        let missing = uuid::Uuid::new_v4();
+
+        for uri in [format!("/api/accounts/{}", missing), format!("/api/aliases/{}", missing)] {
+            let (status, json) =
+                test_support::call(app.clone(), Method::PATCH, &uri, &admin.token, body.clone()).await;
+            assert_eq!(status, StatusCode::NOT_FOUND, "{}", uri);
+            assert_eq!(json["code"], "not_found");
+        }
+
+        // A deleted account counts as missing, and is left as it was
+        let account = test_support::account(&state.db, None).await;
+        sqlx::query("UPDATE accounts SET deleted_at = 1 WHERE id = $1")
+            .bind(&account.id)
+            .execute(&state.db)
+            .await
+            .unwrap();
+        let uri = format!("/api/accounts/{}", account.id);
+        let (status, _) = test_support::call(app, Method::PATCH, &uri, &admin.token, body).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let is_public: bool = sqlx::query_scalar("SELECT is_public FROM accounts WHERE id = $1")
+            .bind(&account.id)
+            .fetch_one(&state.db)
+            .await
+            .unwrap();
+        assert!(!is_public);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn aliases_need_access_to_their_account() {
+        let state = test_support::state().await;
+        let owner = test_support::user(&state.db, "dev").await;
+        let stranger = test_support::user(&state.db, "dev").await;
+        let theirs = test_support::account(&state.db, Some(&owner.id)).await;
+        let mine = test_support::account(&state.db, Some(&stranger.id)).await;
+        let app = crate::router(state.clone(), 1024 * 1024);
+        let alias = |account_id: &str| {
+            serde_json::json!({
+                "accountId": account_id,
+                "aliasEmail": format!("{}@aliases.test", uuid::Uuid::new_v4()),
+                "isActive": true
+            })
+        };
+
+        let (status, json) =
+            test_support::call(app.clone(), Method::POST, "/api/aliases", &stranger.token, alias(&theirs.id).to_string())
+                .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(json["code"], "account_forbidden");
+
+        let batch = serde_json::json!([alias(&theirs.id), alias(&mine.id)]).to_string();
+        let (status, json) = test_support::call(app.clone(), Method::POST, "/api/aliases/bulk", &stranger.token, batch).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(json[0]["status"], "error");
+        assert_eq!(json[1]["status"], "created");
+
+        // Nor can an alias on an account they may use be moved onto one they may not
+        let alias_id = json[1]["alias"]["id"].as_str().unwrap().to_string();
+        let uri = format!("/api/aliases/{}", alias_id);
+        let repoint = serde_json::json!({ "accountId": theirs.id }).to_string();
+        let (status, json) = test_support::call(app.clone(), Method::PATCH, &uri, &stranger.token, repoint).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(json["code"], "account_forbidden");
+
+        // Once the account is shared with them, it works
+        sqlx::query("INSERT INTO user_accounts (user_id, account_id, created_at) VALUES ($1, $2, 0)")
+            .bind(&stranger.id)
+            .bind(&theirs.id)
+            .execute(&state.db)
+            .await
+            .unwrap();
+        let (status, _) =
+            test_support::call(app, Method::POST, "/api/aliases", &stranger.token, alias(&theirs.id).to_string()).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn unknown_and_forbidden_senders_look_the_same() {
+        let state = test_support::state().await;
+        let owner = test_support::user(&state.db, "dev").await;
+        let stranger = test_support::user(&state.db, "dev").await;
+        let private = test_support::account(&state.db, Some(&owner.id)).await;
+        let app = crate::router(state.clone(), 1024 * 1024);
+        let send = |from: &str| {
+            serde_json::json!({ "from": from, "to": "someone@example.com", "subject": "Hi", "body": "Hi" }).to_string()
+        };
+
+        let (status, forbidden) =
+            test_support::call(app.clone(), Method::POST, "/api/send", &stranger.token, send(&private.email)).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, unknown) =
+            test_support::call(app.clone(), Method::POST, "/api/send", &stranger.token, send("nobody@senders.test")).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(forbidden, unknown);
+        assert_eq!(unknown["code"], "sender_forbidden");
+
+        let by_id = serde_json::json!({
+            "fromAccountId": uuid::Uuid::new_v4().to_string(),
+            "to": "someone@example.com",
+            "subject": "Hi",
+            "body": "Hi"
+        })
+        .to_string();
+        let (status, json) = test_support::call(app, Method::POST, "/api/send", &stranger.token, by_id).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(json, unknown);
+    }
+}
@@ -0,0 +1,108 @@
+// Idempotency-Key support for /api/send, so a client retrying after a dropped
+// connection gets the first result back instead of sending the mail twice.
+//
+// A key is claimed before sending (response NULL) and completed with the JSON result.
+// Only sends that went out (or were skipped) are kept; failures release the key so
+// the retry can try again.
+
+use axum::http::{HeaderMap, StatusCode};
+use chrono::Utc;
+use sqlx::PgPool;
+
+use crate::error::ApiError;
+
+pub const HEADER: &str = "idempotency-key";
+const MAX_KEY_LEN: usize = 255;
+// A claim with no result after this long belongs to a request that died mid-send
+const ABANDONED_AFTER_SECS: i64 = 10 * 60;
+
+pub enum Claim {
+    // First use; the caller sends and then calls `complete` or `release`
+    New,
+    Replay(serde_json::Value),
+    InProgress,
+}
+
+/// The request's Idempotency-Key, if it sent one: 1-255 visible ASCII characters.
+pub fn key_from_headers(headers: &HeaderMap) -> Result<Option<String>, ApiError> {
+    let Some(value) = headers.get(HEADER) else {
+        return Ok(None);
+    };
+    let key = value.to_str().unwrap_or_default().trim();
+    if key.is_empty() || key.len() > MAX_KEY_LEN || !key.bytes().all(|b| b.is_ascii_graphic()) {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            "invalid_idempotency_key",
+            "Idempotency-Key must be 1-255 visible ASCII characters",
+        ));
+    }
+    Ok(Some(key.to_string()))
+}
+
+pub async fn claim(db: &PgPool, user_id: &str, key: &str, ttl_secs: i64) -> anyhow::Result<Claim> {
+    let now = Utc::now().timestamp();
+    // An expired key, or an abandoned claim, can be used afresh
+    sqlx::query(
+        r#"
+        DELETE FROM idempotency_keys
+        WHERE user_id = $1 AND key = $2 AND (created_at < $3 OR (response IS NULL AND created_at < $4))
+        "#,
+    )
+    .bind(user_id)
+    .bind(key)
+    .bind(now - ttl_secs)
+    .bind(now - ABANDONED_AFTER_SECS)
+    .execute(db)
+    .await?;
+
+    let inserted = sqlx::query(
+        r#"
+        INSERT INTO idempotency_keys (key, user_id, response, created_at)
+        VALUES ($1, $2, NULL, $3)
+        ON CONFLICT (user_id, key) DO NOTHING
+        "#,
+    )
+    .bind(key)
+    .bind(user_id)
+    .bind(now)
+    .execute(db)
+    .await?
+    .rows_affected();
+    if inserted > 0 {
+        return Ok(Claim::New);
+    }
+
+    let response: Option<Option<serde_json::Value>> =
+        sqlx::query_scalar("SELECT response FROM idempotency_keys WHERE user_id = $1 AND key = $2")
+            .bind(user_id)
+            .bind(key)
+            .fetch_optional(db)
+            .await?;
+    Ok(match response.flatten() {
+        Some(response) => Claim::Replay(response),
+        None => Claim::InProgress,
+    })
+}
+
+pub async fn complete(db: &PgPool, user_id: &str, key: &str, response: &serde_json::Value) {
+    let result = sqlx::query("UPDATE idempotency_keys SET response = $1 WHERE user_id = $2 AND key = $3")
+        .bind(response)
+        .bind(user_id)
+        .bind(key)
+        .execute(db)
+        .await;
+    if let Err(e) = result {
+        tracing::error!(error = %e, %user_id, "failed to store idempotent send result");
+    }
+}
+
+pub async fn release(db: &PgPool, user_id: &str, key: &str) {
+    let result = sqlx::query("DELETE FROM idempotency_keys WHERE user_id = $1 AND key = $2 AND response IS NULL")
+        .bind(user_id)
+        .bind(key)
+        .execute(db)
+        .await;
+    if let Err(e) = result {
+        tracing::error!(error = %e, %user_id, "failed to release idempotency key");
+    }
+}
@@ -0,0 +1,525 @@
+// IMAP access to account mailboxes (Microsoft 365 / Outlook by default)
+
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+use async_imap::{
+    error::Error as ImapError,
+    types::{Fetch, Flag, Mailbox, NameAttribute},
+    Authenticator, Client, Session,
+};
+use chrono::NaiveDate;
+use futures::TryStreamExt;
+use mail_parser::{Addr, Address, MessageParser, MimeHeaders};
+use serde::Serialize;
+use tokio::net::TcpStream;
+use tokio_native_tls::TlsStream;
+
+use crate::email::SmtpAuth;
+
+pub type ImapSession = Session<TlsStream<TcpStream>>;
+
+// Bodies above this size (usually attachments) are skipped for previews
+const PREVIEW_MAX_BYTES: u32 = 256 * 1024;
+const PREVIEW_CHARS: usize = 200;
+pub const DEFAULT_LIMIT: u32 = 20;
+pub const MAX_LIMIT: u32 = 100;
+pub const DEFAULT_FOLDER: &str = "INBOX";
+
+// The server refused to SELECT the folder, which for a well-formed name means it doesn't exist
+#[derive(Debug)]
+pub struct FolderNotFound(pub String);
+
+impl fmt::Display for FolderNotFound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "IMAP folder {:?} not found", self.0)
+    }
+}
+
+impl std::error::Error for FolderNotFound {}
+
+#[derive(Debug, Clone)]
+pub struct ImapConfig {
+    pub host: String,
+    pub port: u16,
+}
+
+impl ImapConfig {
+    // IMAP_HOST / IMAP_PORT, defaulting to Outlook over implicit TLS
+    pub fn from_env() -> anyhow::Result<Self> {
+        let host = std::env::var("IMAP_HOST")
+            .ok()
+            .filter(|v| !v.trim().is_empty())
+            .unwrap_or_else(|| "outlook.office365.com".to_string());
+        let port = match std::env::var("IMAP_PORT") {
+            Ok(v) if !v.trim().is_empty() => v
+                .trim()
+                .parse()
+                .map_err(|_| anyhow!("IMAP_PORT must be a port number, got {:?}", v))?,
+            _ => 993,
+        };
+        Ok(Self { host, port })
+    }
+}
+
+// (account, folder) -> unread count and when it was read
+type UnreadEntries = HashMap<(String, String), (u32, Instant)>;
+
+/// Per-account, per-folder unread counts kept for INBOX_UNREAD_CACHE_SECS (default 30, 0 disables),
+/// so a polling badge doesn't open an IMAP session on every request.
+#[derive(Clone)]
+pub struct UnreadCache {
+    entries: Arc<Mutex<UnreadEntries>>,
+    ttl: Duration,
+}
+
+impl UnreadCache {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let secs: u64 = match std::env::var("INBOX_UNREAD_CACHE_SECS") {
+            Ok(v) if !v.trim().is_empty() => v
+                .trim()
+                .parse()
+                .map_err(|_| anyhow!("INBOX_UNREAD_CACHE_SECS must be a number of seconds, got {:?}", v))?,
+            _ => 30,
+        };
+        Ok(Self {
+            entries: Arc::new(Mutex::new(HashMap::new())),
+            ttl: Duration::from_secs(secs),
+        })
+    }
+
+    pub fn get(&self, account: &str, folder: &str) -> Option<u32> {
+        let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries
+            .get(&(account.to_string(), folder.to_string()))
+            .filter(|(_, at)| at.elapsed() < self.ttl)
+            .map(|(count, _)| *count)
+    }
+
+    pub fn put(&self, account: &str, folder: &str, count: u32) {
+        if self.ttl.is_zero() {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.retain(|_, (_, at)| at.elapsed() < self.ttl);
+        entries.insert((account.to_string(), folder.to_string()), (count, Instant::now()));
+    }
+
+    // After a flag change, so the next poll sees it instead of a stale count
+    pub fn invalidate(&self, account: &str, folder: &str) {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.remove(&(account.to_string(), folder.to_string()));
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct InboxMessage {
+    pub uid: u32,
+    pub from: Option<String>,
+    pub subject: Option<String>,
+    pub date: Option<String>,
+    pub preview: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct InboxAttachment {
+    // Position among the message's attachments; used by the download route
+    pub index: usize,
+    pub filename: Option<String>,
+    #[serde(rename = "contentType")]
+    pub content_type: String,
+    pub size: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct InboxMessageDetail {
+    pub uid: u32,
+    pub from: Option<String>,
+    pub to: Vec<String>,
+    pub cc: Vec<String>,
+    pub subject: Option<String>,
+    pub date: Option<String>,
+    // For inReplyTo/references when answering
+    #[serde(rename = "messageId")]
+    pub message_id: Option<String>,
+    #[serde(rename = "textBody")]
+    pub text_body: Option<String>,
+    // As sent by the remote party; sanitize before rendering
+    #[serde(rename = "htmlBody")]
+    pub html_body: Option<String>,
+    pub attachments: Vec<InboxAttachment>,
+}
+
+struct XOAuth2 {
+    user: String,
+    access_token: String,
+}
+
+impl Authenticator for XOAuth2 {
+    type Response = String;
+
+    fn process(&mut self, _challenge: &[u8]) -> Self::Response {
+        format!("user={}\x01auth=Bearer {}\x01\x01", self.user, self.access_token)
+    }
+}
+
+pub async fn connect(config: &ImapConfig, user: &str, auth: &SmtpAuth) -> anyhow::Result<ImapSession> {
+    let tcp = TcpStream::connect((config.host.as_str(), config.port)).await?;
+    let tls = tokio_native_tls::TlsConnector::from(native_tls::TlsConnector::new()?);
+    let stream = tls.connect(&config.host, tcp).await?;
+    let client = Client::new(stream);
+
+    let session = match auth {
+        SmtpAuth::Password(password) => client
+            .login(user, password)
+            .await
+            .map_err(|(e, _)| anyhow!("IMAP login failed: {}", e))?,
+        SmtpAuth::OAuth2(token) => client
+            .authenticate(
+                "XOAUTH2",
+                XOAuth2 {
+                    user: user.to_string(),
+                    access_token: token.clone(),
+                },
+            )
+            .await
+            .map_err(|(e, _)| anyhow!("IMAP XOAUTH2 failed: {}", e))?,
+    };
+    Ok(session)
+}
+
+fn summarize(uid: u32, raw: &[u8]) -> InboxMessage {
+    let parsed = MessageParser::default().parse(raw);
+    let Some(message) = parsed else {
+        return InboxMessage {
+            uid,
+            from: None,
+            subject: None,
+            date: None,
+            preview: None,
+        };
+    };
+
+    InboxMessage {
+        uid,
+        from: message.from().and_then(|a| a.first()).map(format_addr).filter(|f| !f.is_empty()),
+        subject: message.subject().map(str::to_string),
+        date: message.date().map(|d| d.to_rfc3339()),
+        preview: message
+            .body_preview(PREVIEW_CHARS)
+            .map(|p| p.split_whitespace().collect::<Vec<_>>().join(" ")),
+    }
+}
+
+fn format_addr(addr: &Addr) -> String {
+    match (addr.name.as_deref(), addr.address.as_deref()) {
+        (Some(name), Some(email)) => format!("{} <{}>", name, email),
+        (None, Some(email)) => email.to_string(),
+        (Some(name), None) => name.to_string(),
+        (None, None) => String::new(),
+    }
+}
+
+fn format_addrs(address: Option<&Address>) -> Vec<String> {
+    address
+        .map(|a| a.iter().map(format_addr).filter(|f| !f.is_empty()).collect())
+        .unwrap_or_default()
+}
+
+fn attachment_meta(index: usize, part: &mail_parser::MessagePart) -> InboxAttachment {
+    let content_type = part
+        .content_type()
+        .map(|ct| match ct.subtype() {
+            Some(subtype) => format!("{}/{}", ct.ctype(), subtype),
+            None => ct.ctype().to_string(),
+        })
+        .unwrap_or_else(|| "application/octet-stream".to_string());
+    InboxAttachment {
+        index,
+        filename: part.attachment_name().map(str::to_string),
+        content_type,
+        size: part.len(),
+    }
+}
+
+/// Headers, bodies and attachment metadata of a full RFC 822 message.
+pub fn message_detail(uid: u32, raw: &[u8]) -> anyhow::Result<InboxMessageDetail> {
+    let message = MessageParser::default()
+        .parse(raw)
+        .ok_or_else(|| anyhow!("message {} could not be parsed", uid))?;
+
+    Ok(InboxMessageDetail {
+        uid,
+        from: message.from().and_then(|a| a.first()).map(format_addr).filter(|f| !f.is_empty()),
+        to: format_addrs(message.to()),
+        cc: format_addrs(message.cc()),
+        subject: message.subject().map(str::to_string),
+        date: message.date().map(|d| d.to_rfc3339()),
+        message_id: message.message_id().map(|id| format!("<{}>", id)),
+        text_body: message.body_text(0).map(|body| body.into_owned()),
+        // body_html would otherwise render the text part as HTML
+        html_body: message
+            .html_part(0)
+            .filter(|part| part.is_text_html())
+            .and(message.body_html(0))
+            .map(|body| body.into_owned()),
+        attachments: message
+            .attachments()
+            .enumerate()
+            .map(|(index, part)| attachment_meta(index, part))
+            .collect(),
+    })
+}
+
+/// The `index`th attachment's metadata and decoded bytes. The whole message has to be
+/// in memory for this: IMAP returns BODY[] as a single literal and part boundaries are
+/// only known once it is parsed.
+pub fn attachment(raw: &[u8], index: usize) -> Option<(InboxAttachment, Vec<u8>)> {
+    let message = MessageParser::default().parse(raw)?;
+    let part = message.attachment(index)?;
+    Some((attachment_meta(index, part), part.contents().to_vec()))
+}
+
+#[derive(Debug, Serialize)]
+pub struct Folder {
+    // As the server spells it (hierarchy separator and modified UTF-7 included);
+    // pass it back unchanged as the folder parameter
+    pub name: String,
+    pub delimiter: Option<String>,
+    // e.g. \Noselect, \HasChildren, and special-use flags such as \Sent
+    pub flags: Vec<String>,
+}
+
+/// Every folder the account can see, in the order the server lists them.
+pub async fn list_folders(session: &mut ImapSession) -> anyhow::Result<Vec<Folder>> {
+    let names: Vec<_> = session.list(Some(""), Some("*")).await?.try_collect().await?;
+    Ok(names
+        .iter()
+        .map(|name| Folder {
+            name: name.name().to_string(),
+            delimiter: name.delimiter().map(str::to_string),
+            flags: name
+                .attributes()
+                .iter()
+                .map(|attribute| match attribute {
+                    NameAttribute::NoInferiors => "\\Noinferiors".to_string(),
+                    NameAttribute::NoSelect => "\\Noselect".to_string(),
+                    NameAttribute::Marked => "\\Marked".to_string(),
+                    NameAttribute::Unmarked => "\\Unmarked".to_string(),
+                    NameAttribute::Custom(flag) => flag.to_string(),
+                })
+                .collect(),
+        })
+        .collect())
+}
+
+async fn select_folder(session: &mut ImapSession, folder: &str) -> anyhow::Result<Mailbox> {
+    session.select(folder).await.map_err(|e| match e {
+        ImapError::No(_) => anyhow::Error::new(FolderNotFound(folder.to_string())),
+        e => e.into(),
+    })
+}
+
+/// The full message for `uid` in `folder`, or None when no message has that UID.
+/// Uses BODY.PEEK so opening a message doesn't mark it read.
+pub async fn fetch_raw(session: &mut ImapSession, folder: &str, uid: u32) -> anyhow::Result<Option<Vec<u8>>> {
+    select_folder(session, folder).await?;
+    let fetched: Vec<_> = session
+        .uid_fetch(uid.to_string(), "(UID BODY.PEEK[])")
+        .await?
+        .try_collect()
+        .await?;
+    Ok(fetched
+        .iter()
+        .find(|fetch| fetch.uid == Some(uid))
+        .and_then(|fetch| fetch.body())
+        .map(<[u8]>::to_vec))
+}
+
+/// Newest `limit` messages in `folder`, most recent first.
+pub async fn fetch_recent(session: &mut ImapSession, folder: &str, limit: u32) -> anyhow::Result<Vec<InboxMessage>> {
+    let mailbox = select_folder(session, folder).await?;
+    if mailbox.exists == 0 || limit == 0 {
+        return Ok(Vec::new());
+    }
+
+    // Headers first, then bodies only for messages small enough to preview cheaply
+    let first = mailbox.exists.saturating_sub(limit) + 1;
+    let range = format!("{}:{}", first, mailbox.exists);
+    let headers: Vec<_> = session
+        .fetch(&range, "(UID RFC822.SIZE BODY.PEEK[HEADER])")
+        .await?
+        .try_collect()
+        .await?;
+    summarize_fetched(session, headers).await
+}
+
+// Which part of the message a search term is matched against
+#[derive(Debug, Clone, Copy)]
+pub enum SearchField {
+    Text,
+    Subject,
+    From,
+    To,
+    Body,
+}
+
+impl SearchField {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "" | "text" => Some(Self::Text),
+            "subject" => Some(Self::Subject),
+            "from" => Some(Self::From),
+            "to" => Some(Self::To),
+            "body" => Some(Self::Body),
+            _ => None,
+        }
+    }
+
+    fn keyword(self) -> &'static str {
+        match self {
+            Self::Text => "TEXT",
+            Self::Subject => "SUBJECT",
+            Self::From => "FROM",
+            Self::To => "TO",
+            Self::Body => "BODY",
+        }
+    }
+}
+
+pub struct SearchCriteria {
+    pub field: SearchField,
+    // Non-empty and free of control characters; checked by the handler
+    pub query: String,
+    // Inclusive lower and exclusive upper bound on the internal (received) date
+    pub since: Option<NaiveDate>,
+    pub before: Option<NaiveDate>,
+}
+
+impl SearchCriteria {
+    fn to_imap(&self) -> String {
+        let escaped = self.query.replace('\\', "\\\\").replace('"', "\\\"");
+        // Quoted strings are 7-bit unless the command names a charset
+        let mut command = if self.query.is_ascii() {
+            String::new()
+        } else {
+            "CHARSET UTF-8 ".to_string()
+        };
+        command.push_str(&format!("{} \"{}\"", self.field.keyword(), escaped));
+        if let Some(since) = self.since {
+            command.push_str(&format!(" SINCE {}", since.format("%-d-%b-%Y")));
+        }
+        if let Some(before) = self.before {
+            command.push_str(&format!(" BEFORE {}", before.format("%-d-%b-%Y")));
+        }
+        command
+    }
+}
+
+/// Newest `limit` messages in `folder` matching `criteria`, most recent first.
+pub async fn search(
+    session: &mut ImapSession,
+    folder: &str,
+    criteria: &SearchCriteria,
+    limit: u32,
+) -> anyhow::Result<Vec<InboxMessage>> {
+    select_folder(session, folder).await?;
+    let mut uids: Vec<u32> = session.uid_search(criteria.to_imap()).await?.into_iter().collect();
+    if uids.is_empty() || limit == 0 {
+        return Ok(Vec::new());
+    }
+
+    // UIDs only grow, so the highest ones are the most recent arrivals
+    uids.sort_unstable_by(|a, b| b.cmp(a));
+    uids.truncate(limit as usize);
+    let set = uids.iter().map(u32::to_string).collect::<Vec<_>>().join(",");
+    let headers: Vec<_> = session
+        .uid_fetch(&set, "(UID RFC822.SIZE BODY.PEEK[HEADER])")
+        .await?
+        .try_collect()
+        .await?;
+    summarize_fetched(session, headers).await
+}
+
+/// Number of messages in `folder` without `\Seen`.
+pub async fn unread_count(session: &mut ImapSession, folder: &str) -> anyhow::Result<u32> {
+    select_folder(session, folder).await?;
+    let unseen = session.search("UNSEEN").await?;
+    Ok(unseen.len() as u32)
+}
+
+/// Sets or clears `\Seen` on one message in `folder`. Returns whether it is seen
+/// afterwards, or None when no message has that UID.
+pub async fn set_seen(
+    session: &mut ImapSession,
+    folder: &str,
+    uid: u32,
+    seen: bool,
+) -> anyhow::Result<Option<bool>> {
+    select_folder(session, folder).await?;
+
+    // UID STORE on a missing UID succeeds silently, so check it exists first
+    let existing: Vec<_> = session
+        .uid_fetch(uid.to_string(), "(UID FLAGS)")
+        .await?
+        .try_collect()
+        .await?;
+    if !existing.iter().any(|fetch| fetch.uid == Some(uid)) {
+        return Ok(None);
+    }
+
+    let command = if seen { "+FLAGS (\\Seen)" } else { "-FLAGS (\\Seen)" };
+    let updated: Vec<_> = session
+        .uid_store(uid.to_string(), command)
+        .await?
+        .try_collect()
+        .await?;
+    // The non-silent STORE echoes the new flags; trust the request if the server doesn't
+    let now_seen = updated
+        .iter()
+        .find(|fetch| fetch.uid == Some(uid))
+        .map(|fetch| fetch.flags().any(|flag| flag == Flag::Seen))
+        .unwrap_or(seen);
+    Ok(Some(now_seen))
+}
+
+// Builds summaries from header fetches, pulling bodies for the ones small enough to preview
+async fn summarize_fetched(session: &mut ImapSession, headers: Vec<Fetch>) -> anyhow::Result<Vec<InboxMessage>> {
+    let mut raw: Vec<(u32, Vec<u8>)> = headers
+        .iter()
+        .filter_map(|fetch| Some((fetch.uid?, fetch.header().unwrap_or_default().to_vec())))
+        .collect();
+
+    let small: Vec<String> = headers
+        .iter()
+        .filter(|fetch| fetch.size.is_some_and(|size| size <= PREVIEW_MAX_BYTES))
+        .filter_map(|fetch| fetch.uid.map(|uid| uid.to_string()))
+        .collect();
+    if !small.is_empty() {
+        let bodies: Vec<_> = session
+            .uid_fetch(small.join(","), "(UID BODY.PEEK[TEXT])")
+            .await?
+            .try_collect()
+            .await?;
+        for fetch in &bodies {
+            let (Some(uid), Some(text)) = (fetch.uid, fetch.text()) else {
+                continue;
+            };
+            if let Some((_, message)) = raw.iter_mut().find(|(u, _)| *u == uid) {
+                message.extend_from_slice(text);
+            }
+        }
+    }
+
+    let mut messages: Vec<InboxMessage> = raw
+        .iter()
+        .map(|(uid, message)| summarize(*uid, message))
+        .collect();
+    messages.sort_by_key(|m| std::cmp::Reverse(m.uid));
+    Ok(messages)
+}
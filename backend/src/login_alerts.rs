@@ -0,0 +1,366 @@
+use std::net::IpAddr;
+
+use axum::{
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use chrono::Utc;
+use sha2::{Digest, Sha256};
+use sqlx::{PgPool, Row};
+
+use crate::{
+    auth::{build_system_email_html, send_system_email},
+    mailer, AppState,
+};
+
+// At most one "failed attempts" alert per user per window.
+const FAILED_ALERT_WINDOW_SECS: i64 = 60 * 60;
+
+// /24 for IPv4 and /48 for IPv6, so DHCP churn doesn't look like a new device.
+fn ip_prefix(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(v4) => {
+            let o = v4.octets();
+            format!("{}.{}.{}.0/24", o[0], o[1], o[2])
+        }
+        IpAddr::V6(v6) => {
+            let s = v6.segments();
+            format!("{:x}:{:x}:{:x}::/48", s[0], s[1], s[2])
+        }
+    }
+}
+
+fn user_agent_family(headers: &HeaderMap) -> String {
+    let ua = headers
+        .get("user-agent")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("");
+    // Order matters: Edge and Opera also advertise Chrome, Chrome advertises Safari.
+    for (needle, family) in [
+        ("Edg/", "Edge"),
+        ("OPR/", "Opera"),
+        ("Firefox/", "Firefox"),
+        ("Chrome/", "Chrome"),
+        ("Safari/", "Safari"),
+        ("curl/", "curl"),
+    ] {
+        if ua.contains(needle) {
+            return family.to_string();
+        }
+    }
+    ua.split('/').next().unwrap_or("").trim().to_string()
+}
+
+fn device_hash(user_id: &str, prefix: &str, family: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(user_id.as_bytes());
+    hasher.update(b"|");
+    hasher.update(prefix.as_bytes());
+    hasher.update(b"|");
+    hasher.update(family.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+pub fn failed_login_threshold() -> i64 {
+    env_i64("LOGIN_ALERT_FAILED_THRESHOLD", 5)
+}
+
+fn env_i64(key: &str, default: i64) -> i64 {
+    std::env::var(key)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|v: &i64| *v > 0)
+        .unwrap_or(default)
+}
+
+// Consecutive failures before sign-in is blocked, and for how long
+fn lockout_threshold() -> i64 {
+    env_i64("LOGIN_LOCKOUT_THRESHOLD", 5)
+}
+
+fn lockout_secs() -> i64 {
+    env_i64("LOGIN_LOCKOUT_MINUTES", 15) * 60
+}
+
+/// 429 for a locked account; checked before the password so guesses don't count.
+pub fn locked_out_response(retry_after_secs: i64) -> Response {
+    let minutes = (retry_after_secs + 59) / 60;
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        [(header::RETRY_AFTER, retry_after_secs.to_string())],
+        Json(serde_json::json!({
+            "status": "error",
+            "message": format!(
+                "Too many failed sign-in attempts. Try again in {} minute{}.",
+                minutes,
+                if minutes == 1 { "" } else { "s" }
+            )
+        })),
+    )
+        .into_response()
+}
+
+/// Bumps the failure counter, locks the account once the lockout threshold is reached,
+/// and mails the user once the alert threshold is crossed. The first failure after a
+/// lock has expired starts the count again, so one more wrong guess doesn't relock.
+pub async fn record_failed_login(state: &AppState, user_id: &str, email: &str) {
+    let now = Utc::now().timestamp();
+    let row = match sqlx::query(
+        r#"
+        UPDATE users SET
+            failed_login_count = CASE WHEN locked_until <= $4 THEN 1 ELSE failed_login_count + 1 END,
+            locked_until = CASE
+                WHEN (CASE WHEN locked_until <= $4 THEN 0 ELSE failed_login_count END) + 1 >= $2 THEN $3
+                WHEN locked_until <= $4 THEN NULL
+                ELSE locked_until
+            END
+        WHERE id = $1
+        RETURNING failed_login_count, failed_login_alert_at, security_alerts
+        "#,
+    )
+    .bind(user_id)
+    .bind(lockout_threshold())
+    .bind(now + lockout_secs())
+    .bind(now)
+    .fetch_one(&state.db)
+    .await
+    {
+        Ok(row) => row,
+        Err(e) => {
+            tracing::error!(error = %e, %user_id, %email, "failed to record failed login");
+            return;
+        }
+    };
+
+    let count = row.get::<i32, _>(0) as i64;
+    let last_alert = row.get::<Option<i64>, _>(1);
+    let enabled = row.get::<bool, _>(2);
+
+    if !enabled || count < failed_login_threshold() {
+        return;
+    }
+    if last_alert.is_some_and(|at| now - at < FAILED_ALERT_WINDOW_SECS) {
+        return;
+    }
+
+    // Claim the alert slot first so concurrent failures don't each send one.
+    let claimed = sqlx::query(
+        r#"
+        UPDATE users SET failed_login_alert_at = $1
+        WHERE id = $2 AND (failed_login_alert_at IS NULL OR failed_login_alert_at < $3)
+        "#,
+    )
+    .bind(now)
+    .bind(user_id)
+    .bind(now - FAILED_ALERT_WINDOW_SECS)
+    .execute(&state.db)
+    .await
+    .map(|r| r.rows_affected() == 1)
+    .unwrap_or(false);
+    if !claimed {
+        return;
+    }
+
+    let lines = vec![
+        format!(
+            "There have been {} failed sign-in attempts on {} since the last successful sign-in.",
+            count, email
+        ),
+        "If this wasn't you, someone may be guessing your password. Consider resetting it from the sign-in page.".to_string(),
+    ];
+    spawn_alert(state, email, "Multiple failed sign-in attempts", lines);
+}
+
+/// Resets the failure counter and alerts on the first sign-in from an unseen device.
+/// `ip` is the client address as resolved by TrustedProxies.
+pub async fn record_successful_login(
+    state: &AppState,
+    user_id: &str,
+    email: &str,
+    ip: IpAddr,
+    headers: &HeaderMap,
+) {
+    let family = user_agent_family(headers);
+    let hash = device_hash(user_id, &ip_prefix(ip), &family);
+    let now = Utc::now().timestamp();
+
+    if let Err(e) = sqlx::query("UPDATE users SET failed_login_count = 0, locked_until = NULL WHERE id = $1")
+        .bind(user_id)
+        .execute(&state.db)
+        .await
+    {
+        tracing::error!(error = %e, %user_id, %email, "failed to reset failed login count");
+    }
+
+    match is_new_device(&state.db, user_id, &hash, now).await {
+        Ok(true) => {}
+        Ok(false) => return,
+        Err(e) => {
+            tracing::error!(error = %e, %user_id, %email, "failed to record device");
+            return;
+        }
+    }
+
+    let enabled: bool = sqlx::query_scalar("SELECT security_alerts FROM users WHERE id = $1")
+        .bind(user_id)
+        .fetch_one(&state.db)
+        .await
+        .unwrap_or(true);
+    if !enabled {
+        return;
+    }
+
+    let origin = ip.to_string();
+    let client = if family.is_empty() { "an unknown client".to_string() } else { family };
+    let lines = vec![
+        format!("{} was just used to sign in from {} using {}.", email, origin, client),
+        "If this was you, no action is needed. If not, reset your password from the sign-in page right away.".to_string(),
+    ];
+    spawn_alert(
+        state,
+        email,
+        &format!("New sign-in from {}", origin),
+        lines,
+    );
+}
+
+/// Records a sign-in from the device and says whether it deserves an alert: only the
+/// request that first sees a device does, and a user's very first sign-in has nothing
+/// to compare against.
+async fn is_new_device(db: &PgPool, user_id: &str, hash: &str, now: i64) -> sqlx::Result<bool> {
+    let known_devices: i64 = sqlx::query_scalar("SELECT COUNT(1) FROM user_devices WHERE user_id = $1")
+        .bind(user_id)
+        .fetch_one(db)
+        .await?;
+
+    // The insert is the dedupe: only the request that creates the row gets true back
+    let inserted: bool = sqlx::query_scalar(
+        r#"
+        INSERT INTO user_devices (user_id, device_hash, first_seen_at, last_seen_at)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (user_id, device_hash) DO UPDATE SET last_seen_at = EXCLUDED.last_seen_at
+        RETURNING (xmax = 0)
+        "#,
+    )
+    .bind(user_id)
+    .bind(hash)
+    .bind(now)
+    .bind(now)
+    .fetch_one(db)
+    .await?;
+
+    Ok(inserted && known_devices > 0)
+}
+
+// Alerts never block or fail the login request itself.
+fn spawn_alert(state: &AppState, email: &str, subject: &str, mut lines: Vec<String>) {
+    let state = state.clone();
+    let email = email.to_string();
+    let subject = subject.to_string();
+
+    tokio::spawn(async move {
+        let default_sender = match mailer::get_default_sender_summary(&state.db, &state.secrets).await {
+            Ok(Some(summary)) => summary,
+            _ => {
+                tracing::warn!(%email, "no default sender configured; skipping security alert");
+                return;
+            }
+        };
+
+        let base_url = state.app_base_url.trim_end_matches('/');
+        lines.push(format!("Reset your password: {}/login", base_url));
+        lines.push("You can turn these alerts off from your profile.".to_string());
+        let body = build_system_email_html(
+            &subject,
+            &lines,
+            "Review account",
+            &format!("{}/profile", base_url),
+        );
+
+        if let Err(e) = send_system_email(&state, &default_sender, &email, &subject, &body).await {
+            tracing::error!(error = %e, %email, "failed to send security alert");
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+
+    use super::*;
+    use crate::test_support;
+
+    fn device(user_id: &str, ip: &str, user_agent: &str) -> String {
+        let mut headers = HeaderMap::new();
+        headers.insert("user-agent", HeaderValue::from_str(user_agent).unwrap());
+        device_hash(user_id, &ip_prefix(ip.parse().unwrap()), &user_agent_family(&headers))
+    }
+
+    #[test]
+    fn device_ignores_address_churn_within_a_network() {
+        let firefox = "Mozilla/5.0 (X11; Linux x86_64; rv:128.0) Gecko/20100101 Firefox/128.0";
+        let chrome = "Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/126.0 Safari/537.36";
+        let laptop = device("u1", "203.0.113.7", firefox);
+        assert_eq!(laptop, device("u1", "203.0.113.200", firefox));
+        assert_ne!(laptop, device("u1", "203.0.114.7", firefox));
+        assert_ne!(laptop, device("u1", "203.0.113.7", chrome));
+        assert_ne!(laptop, device("u2", "203.0.113.7", firefox));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn new_device_alert_fires_once_per_device() {
+        let db = test_support::db().await;
+        let user = test_support::user(&db, "user").await;
+        let laptop = device(&user.id, "203.0.113.7", "Firefox/128.0");
+        let phone = device(&user.id, "198.51.100.20", "Version/17.5 Mobile/15E148 Safari/604.1");
+        let tablet = device(&user.id, "192.0.2.44", "Chrome/126.0 Safari/537.36");
+
+        // The first device ever has nothing to compare against
+        assert!(!is_new_device(&db, &user.id, &laptop, 1).await.unwrap());
+        assert!(!is_new_device(&db, &user.id, &laptop, 2).await.unwrap());
+
+        assert!(is_new_device(&db, &user.id, &phone, 3).await.unwrap());
+        assert!(!is_new_device(&db, &user.id, &phone, 4).await.unwrap());
+
+        // Two simultaneous sign-ins from a new device alert once between them
+        let (a, b) = tokio::join!(
+            is_new_device(&db, &user.id, &tablet, 5),
+            is_new_device(&db, &user.id, &tablet, 5),
+        );
+        assert!(a.unwrap() ^ b.unwrap());
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn a_failure_after_the_lock_expires_does_not_relock() {
+        let state = test_support::state().await;
+        let user = test_support::user(&state.db, "user").await;
+        sqlx::query("UPDATE users SET security_alerts = FALSE WHERE id = $1")
+            .bind(&user.id)
+            .execute(&state.db)
+            .await
+            .unwrap();
+        let locked_until = || async {
+            sqlx::query_scalar::<_, Option<i64>>("SELECT locked_until FROM users WHERE id = $1")
+                .bind(&user.id)
+                .fetch_one(&state.db)
+                .await
+                .unwrap()
+        };
+
+        for _ in 0..lockout_threshold() {
+            record_failed_login(&state, &user.id, "locked@users.test").await;
+        }
+        assert!(locked_until().await.is_some());
+
+        sqlx::query("UPDATE users SET locked_until = $1 WHERE id = $2")
+            .bind(Utc::now().timestamp() - 1)
+            .bind(&user.id)
+            .execute(&state.db)
+            .await
+            .unwrap();
+        record_failed_login(&state, &user.id, "locked@users.test").await;
+        assert_eq!(locked_until().await, None);
+    }
+}
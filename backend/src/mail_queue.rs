@@ -0,0 +1,221 @@
+// Background delivery for system emails (signup verification, password reset), so the
+// request that triggers one returns without waiting on SMTP.
+
+use std::{sync::Arc, time::Duration};
+
+use anyhow::anyhow;
+use tokio::{
+    sync::{mpsc, watch, Mutex},
+    task::JoinHandle,
+};
+
+use crate::{
+    auth::send_system_email,
+    email::Throttled,
+    mailer::SenderSummary,
+    workers::{self, Heartbeat},
+    AppState,
+};
+
+// Enough to absorb a burst; past this, enqueueing waits for the worker
+const QUEUE_CAPACITY: usize = 256;
+// An idle worker still reports in this often. One delivery can take several SMTP
+// timeouts with retries, so it only counts as stale well after that.
+const HEARTBEAT_EVERY: Duration = Duration::from_secs(30);
+const STALE_AFTER: Duration = Duration::from_secs(300);
+
+pub struct SystemEmail {
+    pub sender: SenderSummary,
+    pub to: String,
+    pub subject: String,
+    pub html: String,
+}
+
+#[derive(Clone)]
+pub struct MailQueue {
+    tx: mpsc::Sender<SystemEmail>,
+}
+
+impl MailQueue {
+    /// The receiver goes to `spawn_worker` once the rest of AppState exists.
+    pub fn new() -> (Self, mpsc::Receiver<SystemEmail>) {
+        let (tx, rx) = mpsc::channel(QUEUE_CAPACITY);
+        (Self { tx }, rx)
+    }
+
+    pub async fn enqueue(&self, email: SystemEmail) -> anyhow::Result<()> {
+        self.tx
+            .send(email)
+            .await
+            .map_err(|_| anyhow!("system mail worker has stopped"))
+    }
+}
+
+/// Handle to the running worker, used at shutdown to let it finish the backlog.
+pub struct MailWorker {
+    stop: watch::Sender<bool>,
+    handle: JoinHandle<()>,
+}
+
+impl MailWorker {
+    /// Stops waiting for new mail, delivers whatever is already queued and returns
+    /// once that is done or `timeout` has passed, whichever comes first.
+    pub async fn shutdown(self, timeout: Duration) {
+        let _ = self.stop.send(true);
+        match tokio::time::timeout(timeout, self.handle).await {
+            Ok(_) => tracing::info!("system mail queue drained"),
+            Err(_) => tracing::warn!(
+                "system mail queue did not drain within {:?}; remaining mail is dropped",
+                timeout
+            ),
+        }
+    }
+}
+
+/// Drains the queue one message at a time. `send_system_email` already retries
+/// transient SMTP failures, so anything that reaches the error branch is permanent,
+/// except throttling, which puts the message back on the queue for later.
+///
+/// The worker holds a clone of AppState, and with it a sender, so the channel never
+/// closes on its own; `MailWorker::shutdown` is what ends the loop. A panic while
+/// delivering restarts the loop on the same receiver, losing only that message.
+pub fn spawn_worker(state: AppState, rx: mpsc::Receiver<SystemEmail>) -> MailWorker {
+    let (stop, stopped) = watch::channel(false);
+    let rx = Arc::new(Mutex::new(rx));
+    let heartbeat = state.workers.register("system_mail", STALE_AFTER, true);
+    let handle = workers::supervise(heartbeat, move |heartbeat| {
+        run(state.clone(), rx.clone(), stopped.clone(), heartbeat)
+    });
+    MailWorker { stop, handle }
+}
+
+async fn run(
+    state: AppState,
+    rx: Arc<Mutex<mpsc::Receiver<SystemEmail>>>,
+    mut stopped: watch::Receiver<bool>,
+    heartbeat: Heartbeat,
+) {
+    let mut rx = rx.lock().await;
+    let mut idle = tokio::time::interval(HEARTBEAT_EVERY);
+    loop {
+        let email = tokio::select! {
+            email = rx.recv() => email,
+            _ = idle.tick() => {
+                heartbeat.tick();
+                continue;
+            }
+            _ = stopped.changed() => {
+                // No new mail is coming in; deliver what was queued before the signal
+                rx.close();
+                rx.recv().await
+            }
+        };
+        let Some(email) = email else { break };
+        let (to, subject) = (email.to.clone(), email.subject.clone());
+        match deliver(&state, email).await {
+            Ok(()) => heartbeat.processed(1),
+            Err(e) => {
+                tracing::error!(error = %e, %to, %subject, "failed to deliver queued system email");
+                heartbeat.failed(&e);
+            }
+        }
+    }
+}
+
+// Ok once the message is sent or deferred until its sender's cooldown ends
+async fn deliver(state: &AppState, email: SystemEmail) -> anyhow::Result<()> {
+    // Throttled covers both a sender already cooling down and a server that just asked
+    // us to back off; either way the cooldown has been recorded on the account
+    let Err(e) =
+        send_system_email(state, &email.sender, &email.to, &email.subject, &email.html).await
+    else {
+        return Ok(());
+    };
+    if let Some(throttled) = e.downcast_ref::<Throttled>() {
+        let until = chrono::Utc::now().timestamp() + throttled.retry_after.as_secs().max(1) as i64;
+        requeue_at(state, email, until);
+        return Ok(());
+    }
+    Err(e)
+}
+
+// Puts the message back on the queue once the cooldown (unix seconds) has passed,
+// without holding up the rest of the queue. Mail still waiting at shutdown is dropped.
+fn requeue_at(state: &AppState, email: SystemEmail, until: i64) {
+    let wait = (until - chrono::Utc::now().timestamp()).max(1) as u64;
+    tracing::info!(to = %email.to, wait_secs = wait, "deferring system email while the sender cools down");
+    let queue = state.mail_queue.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_secs(wait)).await;
+        let to = email.to.clone();
+        if let Err(e) = queue.enqueue(email).await {
+            tracing::warn!(error = %e, %to, "dropped deferred system email");
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        mailer::{self, SenderKind},
+        test_support::{self, MockSmtp},
+    };
+
+    async fn queued_email(state: &AppState, sender_email: &str) -> SystemEmail {
+        let sender_id: String = sqlx::query_scalar("SELECT id FROM accounts WHERE email = $1")
+            .bind(sender_email)
+            .fetch_one(&state.db)
+            .await
+            .unwrap();
+        SystemEmail {
+            sender: mailer::summarize_sender(&state.db, &state.secrets, SenderKind::Account, &sender_id)
+                .await
+                .unwrap(),
+            to: "someone@example.com".to_string(),
+            subject: "Verify".to_string(),
+            html: "<p>Hello</p>".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn a_cooling_down_sender_defers_system_mail() {
+        let smtp = MockSmtp::start(&[]).await;
+        let mut state = test_support::state().await;
+        state.smtp = smtp.config();
+        let (queue, mut rx) = MailQueue::new();
+        state.mail_queue = queue;
+        let sender = test_support::account(&state.db, None).await;
+        mailer::record_throttle(&state.db, &sender.email, chrono::Utc::now().timestamp() + 1)
+            .await
+            .unwrap();
+
+        deliver(&state, queued_email(&state, &sender.email).await).await.unwrap();
+        assert!(smtp.commands().is_empty());
+
+        let requeued = tokio::time::timeout(Duration::from_secs(5), rx.recv()).await.unwrap();
+        assert_eq!(requeued.unwrap().to, "someone@example.com");
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn a_throttled_system_send_starts_a_cooldown_and_is_retried() {
+        let smtp = MockSmtp::start(&[(
+            "RCPT TO:",
+            "451 4.7.500 Server busy. Please try again later in 1 seconds",
+        )])
+        .await;
+        let mut state = test_support::state().await;
+        state.smtp = smtp.config();
+        let (queue, mut rx) = MailQueue::new();
+        state.mail_queue = queue;
+        let sender = test_support::account(&state.db, None).await;
+
+        deliver(&state, queued_email(&state, &sender.email).await).await.unwrap();
+        assert!(mailer::account_cooldown(&state.db, &sender.email).await.unwrap().is_some());
+
+        let requeued = tokio::time::timeout(Duration::from_secs(5), rx.recv()).await.unwrap();
+        assert!(requeued.is_some());
+    }
+}
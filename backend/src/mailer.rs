@@ -2,6 +2,12 @@ use anyhow::anyhow;
 use serde::{Deserialize, Serialize};
 use sqlx::{Row, PgPool};
 
+use crate::{email::ComplianceFooter, secrets::SecretBox, SenderOption};
+
+// Whether user $2 has been granted the account in `accounts`
+const GRANTED: &str =
+    "EXISTS (SELECT 1 FROM user_accounts WHERE user_accounts.account_id = accounts.id AND user_accounts.user_id = $2)";
+
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
 pub enum SenderKind {
@@ -30,11 +36,30 @@ impl TryFrom<String> for SenderKind {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct ResolvedSender {
     pub header_from: String,
+    // Name shown in From when the request gives a bare address; None sends the address alone
+    pub display_name: Option<String>,
     pub auth_email: String,
+    // Decrypted; never log or serialise it
     pub auth_password: String,
+    pub compliance_footer: ComplianceFooter,
+    // The account's flag; aliases follow the account they send through
+    pub branding_enabled: bool,
+}
+
+impl std::fmt::Debug for ResolvedSender {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ResolvedSender")
+            .field("header_from", &self.header_from)
+            .field("display_name", &self.display_name)
+            .field("auth_email", &self.auth_email)
+            .field("auth_password", &"<redacted>")
+            .field("compliance_footer", &self.compliance_footer)
+            .field("branding_enabled", &self.branding_enabled)
+            .finish()
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -48,12 +73,29 @@ pub struct SenderSummary {
     pub credentials: ResolvedSender,
 }
 
+/// Domain claimed by a catch-all alias address ("*@domain"), lowercased.
+pub fn catchall_domain(alias_email: &str) -> Option<String> {
+    alias_email
+        .strip_prefix("*@")
+        .filter(|domain| !domain.is_empty())
+        .map(str::to_ascii_lowercase)
+}
+
+// Blank names are treated as unset
+fn non_blank(name: Option<String>) -> Option<String> {
+    name.map(|name| name.trim().to_string()).filter(|name| !name.is_empty())
+}
+
+/// Finds the sender for a bare address, along with the display name to put in From
+/// when the caller doesn't give one: the account's sender name (or else its display name),
+/// or the alias's own name if it has one.
 pub async fn resolve_sender_by_email(
     db: &PgPool,
+    secrets: &SecretBox,
     email: &str,
 ) -> anyhow::Result<ResolvedSender> {
     if let Some(row) = sqlx::query(
-        "SELECT email, password FROM accounts WHERE email = ? AND is_active = 1",
+        "SELECT email, password, compliance_footer_html, compliance_footer_text, COALESCE(sender_name, display_name), branding_enabled FROM accounts WHERE email = $1 AND is_active = TRUE AND deleted_at IS NULL",
     )
     .bind(email)
     .fetch_optional(db)
@@ -61,8 +103,14 @@ pub async fn resolve_sender_by_email(
     {
         return Ok(ResolvedSender {
             header_from: row.get::<String, _>(0),
+            display_name: non_blank(row.get::<Option<String>, _>(4)),
             auth_email: row.get::<String, _>(0),
-            auth_password: row.get::<String, _>(1),
+            auth_password: secrets.open(&row.get::<String, _>(1))?,
+            compliance_footer: ComplianceFooter {
+                html: row.get::<Option<String>, _>(2),
+                text: row.get::<Option<String>, _>(3),
+            },
+            branding_enabled: row.get::<bool, _>(5),
         });
     }
 
@@ -72,10 +120,14 @@ pub async fn resolve_sender_by_email(
                accounts.email,
                accounts.password,
                aliases.is_active,
-               accounts.is_active
+               accounts.is_active,
+               COALESCE(aliases.compliance_footer_html, accounts.compliance_footer_html),
+               COALESCE(aliases.compliance_footer_text, accounts.compliance_footer_text),
+               aliases.display_name,
+               accounts.branding_enabled
         FROM aliases
         JOIN accounts ON aliases.account_id = accounts.id
-        WHERE aliases.alias_email = ?
+        WHERE aliases.alias_email = $1 AND aliases.is_catchall = FALSE AND accounts.deleted_at IS NULL
         "#,
     )
     .bind(email)
@@ -87,8 +139,52 @@ pub async fn resolve_sender_by_email(
         if alias_active && account_active {
             return Ok(ResolvedSender {
                 header_from: row.get::<String, _>(0),
+                display_name: non_blank(row.get::<Option<String>, _>(7)),
                 auth_email: row.get::<String, _>(1),
-                auth_password: row.get::<String, _>(2),
+                auth_password: secrets.open(&row.get::<String, _>(2))?,
+                compliance_footer: ComplianceFooter {
+                    html: row.get::<Option<String>, _>(5),
+                    text: row.get::<Option<String>, _>(6),
+                },
+                branding_enabled: row.get::<bool, _>(8),
+            });
+        }
+    }
+
+    // No exact match: a catch-all for the domain sends as the literal requested address
+    let domain = email
+        .rsplit_once('@')
+        .map(|(_, domain)| domain.to_ascii_lowercase())
+        .filter(|domain| !domain.is_empty());
+    if let Some(domain) = domain {
+        if let Some(row) = sqlx::query(
+            r#"
+            SELECT accounts.email,
+                   accounts.password,
+                   COALESCE(aliases.compliance_footer_html, accounts.compliance_footer_html),
+                   COALESCE(aliases.compliance_footer_text, accounts.compliance_footer_text),
+                   aliases.display_name,
+                   accounts.branding_enabled
+            FROM aliases
+            JOIN accounts ON aliases.account_id = accounts.id
+            WHERE aliases.is_catchall = TRUE AND aliases.catchall_domain = $1
+              AND aliases.is_active = TRUE AND accounts.is_active = TRUE AND accounts.deleted_at IS NULL
+            "#,
+        )
+        .bind(&domain)
+        .fetch_optional(db)
+        .await?
+        {
+            return Ok(ResolvedSender {
+                header_from: email.to_string(),
+                display_name: non_blank(row.get::<Option<String>, _>(4)),
+                auth_email: row.get::<String, _>(0),
+                auth_password: secrets.open(&row.get::<String, _>(1))?,
+                compliance_footer: ComplianceFooter {
+                    html: row.get::<Option<String>, _>(2),
+                    text: row.get::<Option<String>, _>(3),
+                },
+                branding_enabled: row.get::<bool, _>(5),
             });
         }
     }
@@ -99,9 +195,161 @@ pub async fn resolve_sender_by_email(
     ))
 }
 
-async fn summarize_account_by_id(db: &PgPool, account_id: &str) -> anyhow::Result<SenderSummary> {
+/// Whether `user_id` owns the account or was granted it in `user_accounts`, which is what
+/// pointing an alias at it takes. Being public isn't enough. Admins are checked by the caller.
+pub async fn has_account_access<'e>(
+    db: impl sqlx::PgExecutor<'e>,
+    user_id: &str,
+    account_id: &str,
+) -> sqlx::Result<bool> {
+    let allowed: Option<bool> = sqlx::query_scalar(&format!(
+        "SELECT COALESCE(owner_id = $2 OR {}, FALSE) FROM accounts WHERE id = $1",
+        GRANTED
+    ))
+    .bind(account_id)
+    .bind(user_id)
+    .fetch_optional(db)
+    .await?;
+    Ok(allowed.unwrap_or(false))
+}
+
+/// Whether `user_id` may send as `email`. Follows the same exact account, exact alias,
+/// catch-all order as `resolve_sender_by_email`. Owners, users granted the account in
+/// `user_accounts`, and anyone for public senders are allowed. Admins are checked by the caller.
+pub async fn may_send_as(db: &PgPool, user_id: &str, email: &str) -> anyhow::Result<bool> {
+    let account: Option<bool> = sqlx::query_scalar(&format!(
+        r#"
+        SELECT COALESCE(accounts.owner_id = $2 OR accounts.is_public OR {}, FALSE)
+        FROM accounts
+        WHERE email = $1 AND is_active = TRUE AND deleted_at IS NULL
+        "#,
+        GRANTED
+    ))
+    .bind(email)
+    .bind(user_id)
+    .fetch_optional(db)
+    .await?;
+    if let Some(allowed) = account {
+        return Ok(allowed);
+    }
+
+    // Access to an account covers its aliases; otherwise the alias's own owner/public flag applies
+    let alias_check = format!(
+        "aliases.owner_id = $2 OR aliases.is_public OR accounts.owner_id = $2 OR {}",
+        GRANTED
+    );
+    let alias: Option<bool> = sqlx::query_scalar(&format!(
+        r#"
+        SELECT COALESCE({}, FALSE)
+        FROM aliases
+        JOIN accounts ON aliases.account_id = accounts.id
+        WHERE aliases.alias_email = $1 AND aliases.is_catchall = FALSE
+          AND aliases.is_active = TRUE AND accounts.is_active = TRUE AND accounts.deleted_at IS NULL
+        "#,
+        alias_check
+    ))
+    .bind(email)
+    .bind(user_id)
+    .fetch_optional(db)
+    .await?;
+    if let Some(allowed) = alias {
+        return Ok(allowed);
+    }
+
+    let Some(domain) = email.rsplit_once('@').map(|(_, d)| d.to_ascii_lowercase()) else {
+        return Ok(false);
+    };
+    let catchall: Option<bool> = sqlx::query_scalar(&format!(
+        r#"
+        SELECT COALESCE({}, FALSE)
+        FROM aliases
+        JOIN accounts ON aliases.account_id = accounts.id
+        WHERE aliases.is_catchall = TRUE AND aliases.catchall_domain = $1
+          AND aliases.is_active = TRUE AND accounts.is_active = TRUE AND accounts.deleted_at IS NULL
+        "#,
+        alias_check
+    ))
+    .bind(&domain)
+    .bind(user_id)
+    .fetch_optional(db)
+    .await?;
+    Ok(catchall.unwrap_or(false))
+}
+
+/// Accounts and fixed-address aliases `user_id` may send from under the rules of
+/// `may_send_as`, or every one for `None` (admins). Catch-alls are left out since they
+/// have no single address. Inactive senders, including aliases of an inactive account,
+/// are only listed with `include_inactive`.
+pub async fn list_senders(
+    db: &PgPool,
+    user_id: Option<&str>,
+    include_inactive: bool,
+) -> anyhow::Result<Vec<SenderOption>> {
+    let rows = sqlx::query(&format!(
+        r#"
+        SELECT 'account', accounts.id, accounts.email, accounts.display_name, NULL, NULL, accounts.is_active
+        FROM accounts
+        WHERE accounts.deleted_at IS NULL
+          AND ($1::BOOLEAN OR accounts.is_active)
+          AND ($2::TEXT IS NULL OR accounts.owner_id = $2 OR accounts.is_public OR {granted})
+        UNION ALL
+        SELECT 'alias', aliases.id, aliases.alias_email, COALESCE(aliases.display_name, aliases.alias_email),
+               accounts.display_name, accounts.email, aliases.is_active AND accounts.is_active
+        FROM aliases
+        JOIN accounts ON aliases.account_id = accounts.id
+        WHERE accounts.deleted_at IS NULL AND aliases.is_catchall = FALSE
+          AND ($1::BOOLEAN OR (aliases.is_active AND accounts.is_active))
+          AND ($2::TEXT IS NULL OR aliases.owner_id = $2 OR aliases.is_public OR accounts.owner_id = $2 OR {granted})
+        ORDER BY 3 ASC
+        "#,
+        granted = GRANTED
+    ))
+    .bind(include_inactive)
+    .bind(user_id)
+    .fetch_all(db)
+    .await?;
+
+    rows.into_iter()
+        .map(|row| {
+            let via_display = row
+                .get::<Option<String>, _>(4)
+                .zip(row.get::<Option<String>, _>(5))
+                .map(|(name, email)| format!("{} ({})", name, email));
+            Ok(SenderOption {
+                sender_type: SenderKind::try_from(row.get::<String, _>(0))?,
+                id: row.get::<String, _>(1),
+                email: row.get::<String, _>(2),
+                display_label: row.get::<String, _>(3),
+                via_display,
+                is_active: row.get::<bool, _>(6),
+            })
+        })
+        .collect()
+}
+
+/// Whether `user_id` may read or change the mailbox that logs in as `account_email`:
+/// its owner or a user granted the account. Unlike sending, being public isn't enough.
+/// Admins are checked by the caller.
+pub async fn may_access_mailbox(db: &PgPool, user_id: &str, account_email: &str) -> anyhow::Result<bool> {
+    let allowed: Option<bool> = sqlx::query_scalar(
+        r#"
+        SELECT COALESCE(owner_id = $2 OR EXISTS (
+            SELECT 1 FROM user_accounts WHERE user_accounts.account_id = accounts.id AND user_accounts.user_id = $2
+        ), FALSE)
+        FROM accounts
+        WHERE email = $1 AND is_active = TRUE AND deleted_at IS NULL
+        "#,
+    )
+    .bind(account_email)
+    .bind(user_id)
+    .fetch_optional(db)
+    .await?;
+    Ok(allowed.unwrap_or(false))
+}
+
+async fn summarize_account_by_id(db: &PgPool, secrets: &SecretBox, account_id: &str) -> anyhow::Result<SenderSummary> {
     let row = sqlx::query(
-        "SELECT id, email, display_name, password, is_active FROM accounts WHERE id = ?",
+        "SELECT id, email, display_name, password, is_active, compliance_footer_html, compliance_footer_text, COALESCE(sender_name, display_name), branding_enabled FROM accounts WHERE id = $1 AND deleted_at IS NULL",
     )
     .bind(account_id)
     .fetch_optional(db)
@@ -115,7 +363,7 @@ async fn summarize_account_by_id(db: &PgPool, account_id: &str) -> anyhow::Resul
 
     let email = row.get::<String, _>(1);
     let display_name = row.get::<String, _>(2);
-    let password = row.get::<String, _>(3);
+    let password = secrets.open(&row.get::<String, _>(3))?;
 
     Ok(SenderSummary {
         sender_type: SenderKind::Account,
@@ -126,13 +374,19 @@ async fn summarize_account_by_id(db: &PgPool, account_id: &str) -> anyhow::Resul
         is_active,
         credentials: ResolvedSender {
             header_from: email.clone(),
+            display_name: non_blank(row.get::<Option<String>, _>(7)),
             auth_email: email,
             auth_password: password,
+            compliance_footer: ComplianceFooter {
+                html: row.get::<Option<String>, _>(5),
+                text: row.get::<Option<String>, _>(6),
+            },
+            branding_enabled: row.get::<bool, _>(8),
         },
     })
 }
 
-async fn summarize_alias_by_id(db: &PgPool, alias_id: &str) -> anyhow::Result<SenderSummary> {
+async fn summarize_alias_by_id(db: &PgPool, secrets: &SecretBox, alias_id: &str) -> anyhow::Result<SenderSummary> {
     let row = sqlx::query(
         r#"
         SELECT 
@@ -144,10 +398,14 @@ async fn summarize_alias_by_id(db: &PgPool, alias_id: &str) -> anyhow::Result<Se
             accounts.email,
             accounts.display_name,
             accounts.password,
-            accounts.is_active
+            accounts.is_active,
+            COALESCE(aliases.compliance_footer_html, accounts.compliance_footer_html),
+            COALESCE(aliases.compliance_footer_text, accounts.compliance_footer_text),
+            aliases.is_catchall,
+            accounts.branding_enabled
         FROM aliases
         JOIN accounts ON aliases.account_id = accounts.id
-        WHERE aliases.id = ?
+        WHERE aliases.id = $1 AND accounts.deleted_at IS NULL
         "#,
     )
     .bind(alias_id)
@@ -155,6 +413,11 @@ async fn summarize_alias_by_id(db: &PgPool, alias_id: &str) -> anyhow::Result<Se
     .await?
     .ok_or_else(|| anyhow!("Alias not found"))?;
 
+    // "*@domain" is not a sendable address on its own
+    if row.get::<bool, _>(11) {
+        return Err(anyhow!("Catch-all aliases can't be used as a fixed sender"));
+    }
+
     let alias_active = row.get::<bool, _>(3);
     let account_active = row.get::<bool, _>(8);
     if !alias_active {
@@ -168,36 +431,84 @@ async fn summarize_alias_by_id(db: &PgPool, alias_id: &str) -> anyhow::Result<Se
     let alias_display = row.get::<Option<String>, _>(2);
     let account_email = row.get::<String, _>(5);
     let account_display = row.get::<String, _>(6);
-    let password = row.get::<String, _>(7);
+    let password = secrets.open(&row.get::<String, _>(7))?;
 
     Ok(SenderSummary {
         sender_type: SenderKind::Alias,
         sender_id: row.get::<String, _>(0),
         email: alias_email.clone(),
-        display_label: alias_display.unwrap_or_else(|| alias_email.clone()),
+        display_label: alias_display.clone().unwrap_or_else(|| alias_email.clone()),
         via_display: Some(format!("{} ({})", account_display, account_email)),
         is_active: alias_active && account_active,
         credentials: ResolvedSender {
             header_from: alias_email,
+            display_name: non_blank(alias_display),
             auth_email: account_email,
             auth_password: password,
+            compliance_footer: ComplianceFooter {
+                html: row.get::<Option<String>, _>(9),
+                text: row.get::<Option<String>, _>(10),
+            },
+            branding_enabled: row.get::<bool, _>(12),
         },
     })
 }
 
 pub async fn summarize_sender(
     db: &PgPool,
+    secrets: &SecretBox,
     sender_type: SenderKind,
     sender_id: &str,
 ) -> anyhow::Result<SenderSummary> {
     match sender_type {
-        SenderKind::Account => summarize_account_by_id(db, sender_id).await,
-        SenderKind::Alias => summarize_alias_by_id(db, sender_id).await,
+        SenderKind::Account => summarize_account_by_id(db, secrets, sender_id).await,
+        SenderKind::Alias => summarize_alias_by_id(db, secrets, sender_id).await,
+    }
+}
+
+// Unix timestamp until which the account's SMTP server asked us to back off, if still in the future
+pub async fn account_cooldown(db: &PgPool, account_email: &str) -> anyhow::Result<Option<i64>> {
+    let until: Option<i64> =
+        sqlx::query_scalar("SELECT cooldown_until FROM accounts WHERE email = $1")
+            .bind(account_email)
+            .fetch_optional(db)
+            .await?
+            .flatten();
+    Ok(until.filter(|until| *until > chrono::Utc::now().timestamp()))
+}
+
+pub async fn record_throttle(db: &PgPool, account_email: &str, until: i64) -> anyhow::Result<()> {
+    sqlx::query(
+        "UPDATE accounts SET cooldown_until = $1, throttle_count = throttle_count + 1 WHERE email = $2",
+    )
+    .bind(until)
+    .bind(account_email)
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+// Longest SMTP error kept on the account; server replies can be long
+const MAX_SEND_ERROR_LEN: usize = 500;
+
+/// Stamps the account with the time and outcome of a send attempt. None clears the
+/// previous error. Failures here are logged, never surfaced to the sender.
+pub async fn record_send_outcome(db: &PgPool, account_email: &str, error: Option<&str>) {
+    let error = error.map(|e| e.chars().take(MAX_SEND_ERROR_LEN).collect::<String>());
+    let result = sqlx::query("UPDATE accounts SET last_send_at = $1, last_send_error = $2 WHERE email = $3")
+        .bind(chrono::Utc::now().timestamp())
+        .bind(error)
+        .bind(account_email)
+        .execute(db)
+        .await;
+    if let Err(e) = result {
+        tracing::error!(error = %e, sender = %account_email, "failed to record send outcome");
     }
 }
 
 pub async fn get_default_sender_summary(
     db: &PgPool,
+    secrets: &SecretBox,
 ) -> anyhow::Result<Option<SenderSummary>> {
     let row = sqlx::query("SELECT sender_type, sender_id FROM default_sender WHERE singleton = 1")
         .fetch_optional(db)
@@ -206,7 +517,7 @@ pub async fn get_default_sender_summary(
     if let Some(row) = row {
         let sender_type: SenderKind = row.get::<String, _>(0).try_into()?;
         let sender_id = row.get::<String, _>(1);
-        let summary = summarize_sender(db, sender_type, &sender_id).await?;
+        let summary = summarize_sender(db, secrets, sender_type, &sender_id).await?;
         Ok(Some(summary))
     } else {
         Ok(None)
@@ -215,15 +526,16 @@ pub async fn get_default_sender_summary(
 
 pub async fn upsert_default_sender(
     db: &PgPool,
+    secrets: &SecretBox,
     sender_type: SenderKind,
     sender_id: &str,
 ) -> anyhow::Result<SenderSummary> {
-    let summary = summarize_sender(db, sender_type, sender_id).await?;
+    let summary = summarize_sender(db, secrets, sender_type, sender_id).await?;
 
     sqlx::query(
         r#"
         INSERT INTO default_sender (singleton, sender_type, sender_id)
-        VALUES (1, ?, ?)
+        VALUES (1, $1, $2)
         ON CONFLICT(singleton) DO UPDATE SET sender_type = excluded.sender_type, sender_id = excluded.sender_id
         "#,
     )
@@ -235,19 +547,39 @@ pub async fn upsert_default_sender(
     Ok(summary)
 }
 
+/// Clears the default sender if it is this one. True when it was.
 pub async fn delete_default_if_matches(
     db: &PgPool,
     sender_type: SenderKind,
     sender_id: &str,
-) -> anyhow::Result<()> {
-    sqlx::query(
-        "DELETE FROM default_sender WHERE singleton = 1 AND sender_type = ? AND sender_id = ?",
+) -> anyhow::Result<bool> {
+    let cleared = sqlx::query(
+        "DELETE FROM default_sender WHERE singleton = 1 AND sender_type = $1 AND sender_id = $2",
     )
     .bind(sender_type.as_str())
     .bind(sender_id)
     .execute(db)
-    .await?;
-    Ok(())
+    .await?
+    .rows_affected();
+    Ok(cleared > 0)
+}
+
+/// Clears the default sender if it is this account or one of its aliases, which can't
+/// send once the account is inactive. True when it was.
+pub async fn delete_default_for_account(db: &PgPool, account_id: &str) -> anyhow::Result<bool> {
+    let cleared = sqlx::query(
+        r#"
+        DELETE FROM default_sender
+        WHERE singleton = 1
+          AND ((sender_type = 'account' AND sender_id = $1)
+            OR (sender_type = 'alias' AND sender_id IN (SELECT id FROM aliases WHERE account_id = $1)))
+        "#,
+    )
+    .bind(account_id)
+    .execute(db)
+    .await?
+    .rows_affected();
+    Ok(cleared > 0)
 }
 
 
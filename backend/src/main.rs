@@ -1,22 +1,59 @@
+use std::{collections::HashMap, net::SocketAddr};
+
 use axum::{
+    extract::{DefaultBodyLimit, State},
+    http::{header, HeaderName, HeaderValue, Method, StatusCode},
+    middleware,
     routing::{get, patch, post},
     Router,
 };
 use serde::{Deserialize, Serialize};
 use sqlx::{postgres::PgPoolOptions, PgPool};
-use tower_http::cors::CorsLayer;
+use tower_http::{
+    cors::{AllowOrigin, CorsLayer},
+    trace::{DefaultMakeSpan, DefaultOnResponse, TraceLayer},
+};
+use tracing::Level;
+use tracing_subscriber::EnvFilter;
 
 mod email;
+mod error;
 mod handlers;
 mod auth;
 mod mailer;
+mod config_bundle;
+mod login_alerts;
+mod send_limits;
+mod oauth;
+mod inbox;
+mod two_factor;
+mod secrets;
+mod rate_limit;
+mod mail_queue;
+mod audit;
+mod telemetry;
+mod unsubscribe;
+mod drafts;
+mod webhooks;
+mod cleanup;
+mod idempotency;
+mod smtp_pool;
+mod csv_export;
+mod sent_recipients;
+mod workers;
+mod web_view;
+#[cfg(test)]
+mod test_support;
 
 use handlers::*;
 use auth::{
-    change_password, confirm_password_reset, create_api_token, create_user, delete_api_token,
-    delete_user, ensure_default_admin, list_api_tokens, list_users, login, me,
-    request_password_reset, signup, update_user, verify_signup,
+    change_password, confirm_email_change, confirm_password_reset, create_api_token, create_user, delete_api_token,
+    delete_pending_signup, delete_user, ensure_default_admin, get_preferences, list_api_tokens,
+    list_pending_signups, list_users, login, logout, me, refresh_session, request_password_reset,
+    request_email_change, resend_verification, rotate_api_token, signup, update_preferences, update_user, verify_signup,
 };
+use config_bundle::{export_config, import_config};
+use oauth::{delete_account_oauth, microsoft_callback, start_account_oauth};
 use mailer::SenderKind;
 
 #[derive(Clone)]
@@ -34,8 +71,30 @@ pub struct AppState {
     pub db: PgPool,
     pub microsoft_oauth: MicrosoftOAuthConfig,
     pub jwt_secret: String,
+    pub jwt_ttl_hours: i64,
+    pub refresh_ttl_days: i64,
     pub app_base_url: String,
     pub turnstile_secret: Option<String>,
+    // Proxies whose forwarded client addresses are believed (TRUSTED_PROXIES)
+    pub trusted_proxies: rate_limit::TrustedProxies,
+    pub send_limiter: send_limits::SendLimiter,
+    pub send_quota: send_limits::DailyQuota,
+    pub smtp: email::SmtpConfig,
+    pub smtp_pool: smtp_pool::SmtpPool,
+    pub imap: inbox::ImapConfig,
+    pub unread_cache: inbox::UnreadCache,
+    pub max_attachment_bytes: usize,
+    // Addresses allowed across to, cc and bcc of one send
+    pub max_recipients: usize,
+    pub secrets: secrets::SecretBox,
+    pub metrics: telemetry::Metrics,
+    pub mail_queue: mail_queue::MailQueue,
+    // How long an Idempotency-Key on /api/send is remembered
+    pub idempotency_ttl_secs: i64,
+    // Heartbeats of the background tasks, for /api/admin/workers and readiness
+    pub workers: workers::Workers,
+    // How long a webVersion link and its stored copy last
+    pub web_version_ttl_secs: i64,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -50,6 +109,36 @@ pub struct EmailAccount {
     pub owner_id: Option<String>,
     #[serde(rename = "isPublic")]
     pub is_public: bool,
+    #[serde(rename = "complianceFooterHtml")]
+    pub compliance_footer_html: Option<String>,
+    #[serde(rename = "complianceFooterText")]
+    pub compliance_footer_text: Option<String>,
+    // Set while the SMTP server is throttling this account (unix seconds)
+    #[serde(rename = "cooldownUntil")]
+    pub cooldown_until: Option<i64>,
+    #[serde(rename = "throttleEvents")]
+    pub throttle_count: i64,
+    // Unix seconds; only admins listing with includeDeleted ever see a non-null value
+    #[serde(rename = "deletedAt")]
+    pub deleted_at: Option<i64>,
+    // Sends allowed per UTC day; null means DAILY_SEND_LIMIT applies
+    #[serde(rename = "dailySendLimit")]
+    pub daily_send_limit: Option<i64>,
+    // Name used in From; null means displayName is used
+    #[serde(rename = "senderName")]
+    pub sender_name: Option<String>,
+    // False drops the W9 Mail header and sign-off from templated mail
+    #[serde(rename = "brandingEnabled")]
+    pub branding_enabled: bool,
+    // Outcome of the latest send through the account (unix seconds); the error is null
+    // when that send worked
+    #[serde(rename = "lastSendAt")]
+    pub last_send_at: Option<i64>,
+    #[serde(rename = "lastSendError")]
+    pub last_send_error: Option<String>,
+    // Unix seconds
+    #[serde(rename = "createdAt")]
+    pub created_at: i64,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -73,6 +162,75 @@ pub struct EmailAlias {
     pub owner_id: Option<String>,
     #[serde(rename = "isPublic")]
     pub is_public: bool,
+    #[serde(rename = "isCatchall")]
+    pub is_catchall: bool,
+    #[serde(rename = "complianceFooterHtml")]
+    pub compliance_footer_html: Option<String>,
+    #[serde(rename = "complianceFooterText")]
+    pub compliance_footer_text: Option<String>,
+    // Unix seconds
+    #[serde(rename = "createdAt")]
+    pub created_at: i64,
+}
+
+// An account or alias after an activate/deactivate call. defaultSenderCleared is true
+// when deactivating it unset the default sender.
+#[derive(Serialize)]
+pub struct ActivationResponse<T> {
+    #[serde(flatten)]
+    pub item: T,
+    #[serde(rename = "defaultSenderCleared")]
+    pub default_sender_cleared: bool,
+}
+
+// One entry per imported account, in request order. status is created, skipped (the
+// email is taken) or error.
+#[derive(Serialize)]
+pub struct BulkAccountResult {
+    pub index: usize,
+    pub status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub account: Option<EmailAccount>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+impl BulkAccountResult {
+    pub fn failed(index: usize, status: &'static str, message: String) -> Self {
+        Self {
+            index,
+            status,
+            account: None,
+            message: Some(message),
+        }
+    }
+}
+
+// One entry per submitted alias, in request order
+#[derive(Serialize)]
+pub struct BulkAliasResult {
+    pub index: usize,
+    pub status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alias: Option<EmailAlias>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+// A user allowed to send from an account they don't own
+#[derive(Serialize)]
+pub struct AccountUser {
+    #[serde(rename = "userId")]
+    pub user_id: String,
+    pub email: String,
+    #[serde(rename = "grantedAt")]
+    pub granted_at: i64,
+}
+
+#[derive(Deserialize)]
+pub struct GrantAccountRequest {
+    #[serde(rename = "userId")]
+    pub user_id: String,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -90,6 +248,21 @@ pub struct DefaultSenderResponse {
     pub is_active: bool,
 }
 
+// One "from" identity in GET /api/senders
+#[derive(Serialize, Debug)]
+pub struct SenderOption {
+    #[serde(rename = "type")]
+    pub sender_type: SenderKind,
+    pub id: String,
+    pub email: String,
+    #[serde(rename = "displayLabel")]
+    pub display_label: String,
+    #[serde(rename = "viaDisplay")]
+    pub via_display: Option<String>,
+    #[serde(rename = "isActive")]
+    pub is_active: bool,
+}
+
 #[derive(Deserialize)]
 pub struct UpdateDefaultSenderRequest {
     #[serde(rename = "senderType")]
@@ -98,6 +271,11 @@ pub struct UpdateDefaultSenderRequest {
     pub sender_id: String,
 }
 
+#[derive(Deserialize)]
+pub struct TestDefaultSenderRequest {
+    pub to: String,
+}
+
 #[derive(Deserialize)]
 pub struct CreateAccountRequest {
     pub email: String,
@@ -108,6 +286,8 @@ pub struct CreateAccountRequest {
     pub is_active: bool,
     #[serde(rename = "isPublic", default)]
     pub is_public: bool,
+    #[serde(rename = "senderName", default)]
+    pub sender_name: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -119,6 +299,19 @@ pub struct UpdateAccountRequest {
     pub owner_id: Option<String>,
     #[serde(rename = "isPublic")]
     pub is_public: Option<bool>,
+    #[serde(rename = "complianceFooterHtml")]
+    pub compliance_footer_html: Option<String>,
+    #[serde(rename = "complianceFooterText")]
+    pub compliance_footer_text: Option<String>,
+    // Admin only; 0 removes the account's own limit
+    #[serde(rename = "dailySendLimit")]
+    pub daily_send_limit: Option<i64>,
+    // An empty string clears it, so From goes back to the display name
+    #[serde(rename = "senderName")]
+    pub sender_name: Option<String>,
+    // Admin only, like the compliance footers
+    #[serde(rename = "brandingEnabled")]
+    pub branding_enabled: Option<bool>,
 }
 
 #[derive(Deserialize)]
@@ -133,6 +326,9 @@ pub struct CreateAliasRequest {
     pub is_active: bool,
     #[serde(rename = "isPublic", default)]
     pub is_public: bool,
+    // aliasEmail is then "*@domain" (or just "domain") and matches any address there
+    #[serde(rename = "isCatchall", default)]
+    pub is_catchall: bool,
 }
 
 #[derive(Deserialize)]
@@ -147,32 +343,320 @@ pub struct UpdateAliasRequest {
     pub owner_id: Option<String>,
     #[serde(rename = "isPublic")]
     pub is_public: Option<bool>,
+    #[serde(rename = "complianceFooterHtml")]
+    pub compliance_footer_html: Option<String>,
+    #[serde(rename = "complianceFooterText")]
+    pub compliance_footer_text: Option<String>,
 }
 
 #[derive(Deserialize)]
 pub struct SendEmailRequest {
+    // from, to, subject and body may be left out when draftId supplies them
+    #[serde(default)]
     pub from: String,
-    pub to: String,
+    // Alternatives to from that survive an email change; the sender's current address is
+    // used, and a from given alongside must match it
+    #[serde(default, rename = "fromAccountId")]
+    pub from_account_id: Option<String>,
+    #[serde(default, rename = "fromAliasId")]
+    pub from_alias_id: Option<String>,
+    // Recipients: a comma-separated string or a list with one address per item
+    #[serde(default)]
+    pub to: StringOrList,
+    #[serde(default)]
     pub subject: String,
+    #[serde(default)]
     pub body: String,
     #[serde(default)]
-    pub cc: Option<String>,
+    pub cc: Option<StringOrList>,
     #[serde(default)]
-    pub bcc: Option<String>,
+    pub bcc: Option<StringOrList>,
     #[serde(default, rename = "isHtml")]
     pub is_html: bool,
+    #[serde(default, rename = "allOrNothing")]
+    pub all_or_nothing: bool,
+    #[serde(default)]
+    pub attachments: Vec<AttachmentRequest>,
+    #[serde(default, rename = "replyTo")]
+    pub reply_to: Option<String>,
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    // HTML bodies get the branding template unless this is false
+    #[serde(default, rename = "useTemplate")]
+    pub use_template: Option<bool>,
+    // Threading: message ids like "<abc@example.com>"
+    #[serde(default, rename = "inReplyTo")]
+    pub in_reply_to: Option<String>,
+    #[serde(default)]
+    pub references: Option<StringOrList>,
+    // Adds List-Unsubscribe headers with a signed link for the (single) recipient
+    #[serde(default, rename = "listUnsubscribe")]
+    pub list_unsubscribe: bool,
+    // Drops recipients who have unsubscribed instead of mailing them
+    #[serde(default, rename = "honorUnsubscribe")]
+    pub honor_unsubscribe: bool,
+    // Sends one of the caller's drafts; fields given here override the draft's, and the
+    // draft is deleted once the mail is delivered
+    #[serde(default, rename = "draftId")]
+    pub draft_id: Option<String>,
+    // Bcc the from address so the sender's mailbox keeps a copy
+    #[serde(default, rename = "bccSelf")]
+    pub bcc_self: bool,
+    // Resolve, validate and build the message, but don't hand it to the SMTP server
+    #[serde(default, rename = "dryRun")]
+    pub dry_run: bool,
+    // HTML bodies sent as written are stripped of scripts and the like unless this is false
+    #[serde(default, rename = "sanitizeHtml")]
+    pub sanitize_html: Option<bool>,
+    // HTML only: adds a "View in browser" link to a stored copy of the message
+    #[serde(default, rename = "webVersion")]
+    pub web_version: bool,
+}
+
+// Accepts either "a b c" or ["a", "b", "c"]
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum StringOrList {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl StringOrList {
+    // Whitespace-separated entries of either form, flattened
+    pub fn into_items(self) -> Vec<String> {
+        let items = match self {
+            StringOrList::One(value) => vec![value],
+            StringOrList::Many(values) => values,
+        };
+        items
+            .iter()
+            .flat_map(|item| item.split_whitespace())
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// As a comma-separated recipient list. A string is taken as written; list items are
+    /// single addresses, so a comma in a display name is quoted rather than split on.
+    /// Err holds the first list item that isn't a valid address.
+    pub fn into_recipient_list(self) -> Result<String, String> {
+        match self {
+            StringOrList::One(value) => Ok(value),
+            StringOrList::Many(values) => values
+                .iter()
+                .filter(|item| !item.trim().is_empty())
+                .map(|item| email::single_recipient(item).ok_or_else(|| item.clone()))
+                .collect::<Result<Vec<_>, _>>()
+                .map(|items| items.join(", ")),
+        }
+    }
+}
+
+impl Default for StringOrList {
+    fn default() -> Self {
+        StringOrList::One(String::new())
+    }
+}
+
+#[derive(Deserialize)]
+pub struct EmailPreviewRequest {
+    pub body: String,
+    #[serde(default, rename = "asHtml")]
+    pub as_html: bool,
+    #[serde(default, rename = "useTemplate")]
+    pub use_template: Option<bool>,
+    #[serde(default, rename = "sanitizeHtml")]
+    pub sanitize_html: Option<bool>,
+}
+
+#[derive(Deserialize)]
+pub struct AttachmentRequest {
+    pub filename: String,
+    #[serde(default, rename = "contentType")]
+    pub content_type: Option<String>,
+    #[serde(rename = "contentBase64")]
+    pub content_base64: String,
+    // Referenced from an HTML body as cid:<contentId> to show the file inline
+    #[serde(default, rename = "contentId")]
+    pub content_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ListParams {
+    pub limit: Option<u32>,
+    pub offset: Option<u32>,
+}
+
+#[derive(Deserialize)]
+pub struct AccountListParams {
+    pub limit: Option<u32>,
+    pub offset: Option<u32>,
+    pub search: Option<String>,
+    pub active: Option<bool>,
+    #[serde(rename = "includeDeleted")]
+    pub include_deleted: Option<bool>,
+    pub sort: Option<ListSort>,
+}
+
+#[derive(Deserialize)]
+pub struct AliasListParams {
+    pub limit: Option<u32>,
+    pub offset: Option<u32>,
+    pub active: Option<bool>,
+    #[serde(rename = "accountId")]
+    pub account_id: Option<String>,
+    pub sort: Option<ListSort>,
+}
+
+#[derive(Deserialize)]
+pub struct SenderListParams {
+    #[serde(rename = "includeInactive")]
+    pub include_inactive: Option<bool>,
+}
+
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum ListSort {
+    #[default]
+    Email,
+    // Newest first
+    Created,
+}
+
+#[derive(Serialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub total: i64,
+    pub limit: i64,
+    pub offset: i64,
+}
+
+#[derive(Deserialize)]
+pub struct SentQuery {
+    pub limit: Option<u32>,
+    // Only messages with this address in to, cc or bcc; case-insensitive
+    pub recipient: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct SentMessage {
+    pub id: String,
+    pub from: String,
+    pub to: String,
+    pub cc: Option<String>,
+    pub bcc: Option<String>,
+    pub subject: String,
+    pub body: String,
+    #[serde(rename = "sentAt")]
+    pub sent_at: String,
+    #[serde(rename = "messageId")]
+    pub message_id: Option<String>,
+}
+
+// GET /api/sent/:id: the message plus its recipients grouped by kind
+#[derive(Serialize)]
+pub struct SentMessageDetail {
+    #[serde(flatten)]
+    pub message: SentMessage,
+    pub recipients: sent_recipients::Recipients,
 }
 
 #[derive(Deserialize)]
 pub struct InboxQuery {
     pub account: String,
     pub limit: Option<u32>,
+    // Raw IMAP name from /api/inbox/folders; INBOX when omitted
+    pub folder: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct InboxMessageQuery {
+    pub account: String,
+    pub folder: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateInboxMessageRequest {
+    pub seen: bool,
+}
+
+#[derive(Deserialize)]
+pub struct InboxSearchQuery {
+    pub account: String,
+    pub folder: Option<String>,
+    pub q: Option<String>,
+    // text (default), subject, from, to or body
+    pub field: Option<String>,
+    pub limit: Option<u32>,
+    // YYYY-MM-DD; since is inclusive, before exclusive
+    pub since: Option<String>,
+    pub before: Option<String>,
+}
+
+// Unset or blank falls back to the default; anything else must be a positive integer
+fn positive_env(key: &str, default: i64) -> anyhow::Result<i64> {
+    match std::env::var(key) {
+        Ok(v) if !v.trim().is_empty() => v
+            .trim()
+            .parse::<i64>()
+            .ok()
+            .filter(|n| *n > 0)
+            .ok_or_else(|| anyhow::anyhow!("{} must be a positive integer, got {:?}", key, v)),
+        _ => Ok(default),
+    }
+}
+
+// CORS_ALLOWED_ORIGINS is a comma-separated list of exact origins
+// (e.g. "https://w9.nu,https://admin.w9.nu"). Only those get credentialed CORS
+// responses. Unset or blank stays permissive for local development.
+fn cors_layer() -> anyhow::Result<CorsLayer> {
+    let raw = std::env::var("CORS_ALLOWED_ORIGINS").unwrap_or_default();
+    if raw.trim().is_empty() {
+        tracing::warn!("CORS_ALLOWED_ORIGINS is not set; allowing requests from any origin");
+        return Ok(CorsLayer::permissive());
+    }
+
+    let origins = raw
+        .split(',')
+        .map(str::trim)
+        .filter(|origin| !origin.is_empty())
+        .map(|origin| {
+            HeaderValue::from_str(origin.trim_end_matches('/'))
+                .map_err(|_| anyhow::anyhow!("CORS_ALLOWED_ORIGINS has an invalid origin {:?}", origin))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    tracing::info!(origins = origins.len(), "CORS restricted to CORS_ALLOWED_ORIGINS");
+
+    Ok(CorsLayer::new()
+        .allow_origin(AllowOrigin::list(origins))
+        .allow_methods([
+            Method::GET,
+            Method::POST,
+            Method::PUT,
+            Method::PATCH,
+            Method::DELETE,
+        ])
+        .allow_headers([
+            header::AUTHORIZATION,
+            header::CONTENT_TYPE,
+            HeaderName::from_static(idempotency::HEADER),
+        ])
+        .allow_credentials(true))
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     // Load .env file for local development (ignored if not present)
     dotenv::dotenv().ok();
+
+    // RUST_LOG overrides the default, e.g. RUST_LOG=w9_mail_backend=debug,tower_http=debug.
+    // Blank counts as unset, since docker-compose passes it through empty.
+    let log_filter = std::env::var("RUST_LOG")
+        .ok()
+        .filter(|v| !v.trim().is_empty())
+        .unwrap_or_else(|| "info".to_string());
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::new(log_filter))
+        .init();
     
     let host = std::env::var("HOST").unwrap_or_else(|_| "0.0.0.0".to_string());
     let port = std::env::var("PORT")
@@ -180,128 +664,40 @@ async fn main() -> anyhow::Result<()> {
         .parse::<u16>()?;
     
     let db_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    if !(db_url.starts_with("postgres://") || db_url.starts_with("postgresql://")) {
+        anyhow::bail!(
+            "DATABASE_URL must be a postgres:// URL; other database backends are not supported"
+        );
+    }
     let db = PgPoolOptions::new()
         .max_connections(5)
         .connect(&db_url)
         .await
         .map_err(|e| anyhow::anyhow!("Failed to connect to Postgres: {}", e))?;
     
-    // Check if table exists before trying to create users table to avoid errors or rely on IF NOT EXISTS
-    // Postgres dialect updates:
-    // - TEXT PRIMARY KEY is valid
-    // - BOOLEAN defaults TRUE/FALSE
-    // - DATETIME -> TIMESTAMPTZ
-
-    sqlx::query(
-        r#"
-        CREATE TABLE IF NOT EXISTS users (
-            id TEXT PRIMARY KEY,
-            email TEXT UNIQUE NOT NULL,
-            password_hash TEXT NOT NULL,
-            role TEXT NOT NULL CHECK(role IN ('admin','dev','user')),
-            must_change_password BOOLEAN NOT NULL DEFAULT FALSE,
-            created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP
-        )
-        "#,
-    )
-    .execute(&db)
-    .await?;
-
-    sqlx::query(
-        r#"
-        CREATE TABLE IF NOT EXISTS accounts (
-            id TEXT PRIMARY KEY,
-            email TEXT UNIQUE NOT NULL,
-            display_name TEXT NOT NULL,
-            password TEXT NOT NULL,
-            is_active BOOLEAN NOT NULL DEFAULT TRUE,
-            owner_id TEXT,
-            is_public BOOLEAN NOT NULL DEFAULT FALSE,
-            FOREIGN KEY(owner_id) REFERENCES users(id) ON DELETE SET NULL
-        )
-        "#,
-    )
-    .execute(&db)
-    .await?;
-
-    sqlx::query(
-        r#"
-        CREATE TABLE IF NOT EXISTS aliases (
-            id TEXT PRIMARY KEY,
-            alias_email TEXT UNIQUE NOT NULL,
-            display_name TEXT,
-            is_active BOOLEAN NOT NULL DEFAULT TRUE,
-            account_id TEXT NOT NULL,
-            owner_id TEXT,
-            is_public BOOLEAN NOT NULL DEFAULT FALSE,
-            FOREIGN KEY(account_id) REFERENCES accounts(id) ON DELETE CASCADE,
-            FOREIGN KEY(owner_id) REFERENCES users(id) ON DELETE SET NULL
-        )
-        "#,
-    )
-    .execute(&db)
-    .await?;
-
-    // Postgres doesn't support 'singleton' constraint check in quite the same way as sqlite nicely inside create, 
-    // but we can just use a unique index or similar. For simplicity, we keep it as is, Postgres supports CHECK.
-    sqlx::query(
-        r#"
-        CREATE TABLE IF NOT EXISTS default_sender (
-            singleton INTEGER PRIMARY KEY CHECK (singleton = 1),
-            sender_type TEXT NOT NULL CHECK(sender_type IN ('account','alias')),
-            sender_id TEXT NOT NULL
-        )
-        "#,
-    )
-    .execute(&db)
-    .await?;
-
-    sqlx::query(
-        r#"
-        CREATE TABLE IF NOT EXISTS pending_users (
-            id TEXT PRIMARY KEY,
-            email TEXT UNIQUE NOT NULL,
-            password_hash TEXT NOT NULL,
-            verification_token TEXT UNIQUE NOT NULL,
-            expires_at BIGINT NOT NULL
-        )
-        "#,
-    )
-    .execute(&db)
-    .await?;
-
-    sqlx::query(
-        r#"
-        CREATE TABLE IF NOT EXISTS password_reset_tokens (
-            id TEXT PRIMARY KEY,
-            user_id TEXT NOT NULL,
-            token TEXT UNIQUE NOT NULL,
-            expires_at BIGINT NOT NULL,
-            FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
-        )
-        "#,
-    )
-    .execute(&db)
-    .await?;
-
-    sqlx::query(
-        r#"
-        CREATE TABLE IF NOT EXISTS api_tokens (
-            id TEXT PRIMARY KEY,
-            user_id TEXT NOT NULL,
-            token_hash TEXT UNIQUE NOT NULL,
-            name TEXT,
-            created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP,
-            last_used_at TIMESTAMPTZ,
-            FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
-        )
-        "#,
-    )
-    .execute(&db)
-    .await?;
+    // Schema lives in backend/migrations; applied versions are tracked in _sqlx_migrations
+    sqlx::migrate!()
+        .run(&db)
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to run database migrations: {}", e))?;
 
     ensure_default_admin(&db).await?;
 
+    let indexed = sent_recipients::backfill(&db).await?;
+    if indexed > 0 {
+        tracing::info!(count = indexed, "indexed recipients of previously sent messages");
+    }
+
+    let secrets = secrets::SecretBox::from_env()?;
+    if secrets.is_enabled() {
+        let encrypted = secrets::encrypt_plaintext_passwords(&db, &secrets).await?;
+        if encrypted > 0 {
+            tracing::info!(count = encrypted, "encrypted stored account passwords with ACCOUNT_ENC_KEY");
+        }
+    } else {
+        tracing::warn!("ACCOUNT_ENC_KEY is not set; account passwords are stored in plaintext");
+    }
+
     // Load Microsoft OAuth2 configuration
     let microsoft_oauth = MicrosoftOAuthConfig {
         client_id: std::env::var("MICROSOFT_CLIENT_ID")
@@ -320,34 +716,164 @@ async fn main() -> anyhow::Result<()> {
 
     let jwt_secret =
         std::env::var("JWT_SECRET").unwrap_or_else(|_| "change-me-in-production".to_string());
+    let jwt_ttl_hours = positive_env("JWT_TTL_HOURS", 12)?;
+    let refresh_ttl_days = positive_env("REFRESH_TOKEN_TTL_DAYS", 30)?;
+    tracing::info!(jwt_ttl_hours, refresh_ttl_days, "session lifetimes configured");
     let app_base_url =
         std::env::var("APP_WEB_BASE_URL").unwrap_or_else(|_| "https://w9.nu".to_string());
 
     let turnstile_secret = std::env::var("TURNSTILE_SECRET_KEY").ok().filter(|v| !v.trim().is_empty());
+    let smtp = email::SmtpConfig::from_env()?;
+    tracing::info!(host = %smtp.host, port = smtp.port, tls = ?smtp.tls, "SMTP relay configured");
+    let smtp_pool = smtp_pool::SmtpPool::from_env()?;
+    let imap = inbox::ImapConfig::from_env()?;
+    let unread_cache = inbox::UnreadCache::from_env()?;
+    let max_attachment_bytes = std::env::var("MAX_ATTACHMENT_BYTES")
+        .ok()
+        .and_then(|v| v.trim().parse::<usize>().ok())
+        .unwrap_or(25 * 1024 * 1024);
+    let max_body_bytes = positive_env("MAX_BODY_BYTES", DEFAULT_MAX_BODY_BYTES)? as usize;
+    // Base64 inflates attachments by 4/3
+    if max_body_bytes < max_attachment_bytes / 3 * 4 {
+        tracing::warn!(
+            max_body_bytes,
+            max_attachment_bytes,
+            "MAX_BODY_BYTES is below the encoded size of MAX_ATTACHMENT_BYTES; large attachments will be rejected with 413"
+        );
+    }
     
+    let cors = cors_layer()?;
+
+    let idempotency_ttl_secs = positive_env("IDEMPOTENCY_KEY_TTL_HOURS", 24)? * 3600;
+    let max_recipients = positive_env("MAX_RECIPIENTS", 100)? as usize;
+    let web_version_ttl_secs = positive_env("WEB_VERSION_TTL_DAYS", 30)? * 86400;
+
+    let (mail_queue, mail_rx) = mail_queue::MailQueue::new();
     let state = AppState {
         db,
         microsoft_oauth,
         jwt_secret,
+        jwt_ttl_hours,
+        refresh_ttl_days,
         app_base_url,
         turnstile_secret,
+        trusted_proxies: rate_limit::TrustedProxies::from_env()?,
+        send_limiter: send_limits::SendLimiter::from_env(),
+        send_quota: send_limits::DailyQuota::from_env()?,
+        smtp,
+        smtp_pool,
+        imap,
+        unread_cache,
+        max_attachment_bytes,
+        max_recipients,
+        secrets,
+        metrics: telemetry::Metrics::install()?,
+        mail_queue,
+        idempotency_ttl_secs,
+        workers: workers::Workers::from_env(),
+        web_version_ttl_secs,
     };
+    let mail_worker = mail_queue::spawn_worker(state.clone(), mail_rx);
+    let cleanup_minutes = positive_env("TOKEN_CLEANUP_INTERVAL_MINUTES", 60)?;
+    cleanup::spawn(
+        &state.workers,
+        state.db.clone(),
+        std::time::Duration::from_secs(cleanup_minutes as u64 * 60),
+        idempotency_ttl_secs,
+    );
+    let db = state.db.clone();
 
-    let app = Router::new()
-        .route("/health", get(health_check))
+    let app = router(state, max_body_bytes)
+        .layer(cors)
+        // One span per request (method + path); handler events nest under it, and the
+        // response is logged with status and latency
+        .layer(
+            TraceLayer::new_for_http()
+                .make_span_with(DefaultMakeSpan::new().level(Level::INFO))
+                .on_response(DefaultOnResponse::new().level(Level::INFO)),
+        );
+
+    let addr = format!("{}:{}", host, port);
+    let listener = tokio::net::TcpListener::bind(&addr).await?;
+    tracing::info!("Server running on http://{}", addr);
+    // In-flight requests (including sends already talking to SMTP) finish before
+    // serve returns; new connections are refused once the signal arrives
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+        .with_graceful_shutdown(shutdown_signal())
+        .await?;
+
+    mail_worker.shutdown(SHUTDOWN_DRAIN_TIMEOUT).await;
+    db.close().await;
+    tracing::info!("shutdown complete");
+
+    Ok(())
+}
+
+// Every route with its body limits; main adds CORS and request tracing on top
+fn router(state: AppState, max_body_bytes: usize) -> Router {
+    // Unauthenticated entry points, throttled per client IP
+    let public_auth = Router::new()
         .route("/api/auth/login", post(login))
+        .route("/api/auth/refresh", post(refresh_session))
         .route("/api/auth/signup", post(signup))
         .route("/api/auth/signup/verify", post(verify_signup))
+        .route("/api/auth/signup/resend", post(resend_verification))
         .route("/api/auth/password-reset", post(request_password_reset))
         .route(
             "/api/auth/password-reset/confirm",
             post(confirm_password_reset),
         )
+        .route("/api/auth/change-email/confirm", post(confirm_email_change))
+        .route_layer(middleware::from_fn_with_state(
+            rate_limit::AuthRateLimiter::from_env(state.trusted_proxies.clone()),
+            rate_limit::limit_auth,
+        ));
+
+    Router::new()
+        .route("/health", get(health_check))
+        .route("/health/live", get(liveness_check))
+        .route("/health/ready", get(health_check))
+        .route("/metrics", get(telemetry::render))
+        .merge(public_auth)
+        // Linked from List-Unsubscribe headers; the signed token is the only credential
+        .route(
+            "/api/unsubscribe",
+            get(unsubscribe::confirm_unsubscribe).post(unsubscribe::unsubscribe),
+        )
+        // "View in browser" links in sent mail; likewise authorized by the token alone
+        .route("/api/view/:token", get(web_view::view_web_version))
+        .route("/api/auth/logout", post(logout))
         .route("/api/auth/change-password", post(change_password))
+        .route("/api/auth/change-email", post(request_email_change))
         .route("/api/auth/me", get(me))
+        // Both paths have been used as the Azure redirect URI
+        .route("/api/auth/callback", get(microsoft_callback))
+        .route("/api/auth/microsoft/callback", get(microsoft_callback))
+        .route("/api/auth/preferences", get(get_preferences).put(update_preferences))
+        .route(
+            "/api/auth/2fa",
+            get(two_factor::get_status).delete(two_factor::disable),
+        )
+        .route("/api/auth/2fa/enroll", post(two_factor::enroll))
+        .route("/api/auth/2fa/verify", post(two_factor::verify_enrollment))
+        .route("/api/tokens", get(list_api_tokens).post(create_api_token))
+        .route("/api/tokens/:id", axum::routing::delete(delete_api_token))
+        .route("/api/tokens/:id/rotate", post(rotate_api_token))
+        // Original paths, kept for existing clients
         .route("/api/api-tokens", get(list_api_tokens).post(create_api_token))
         .route("/api/api-tokens/:id", axum::routing::delete(delete_api_token))
         .route("/api/users", get(list_users).post(create_user))
+        .route("/api/users/pending", get(list_pending_signups))
+        .route(
+            "/api/users/pending/:id",
+            axum::routing::delete(delete_pending_signup),
+        )
+        .route("/api/audit", get(audit::list_audit))
+        .route("/api/webhooks", get(webhooks::list_webhooks).post(webhooks::create_webhook))
+        .route(
+            "/api/webhooks/:id",
+            patch(webhooks::update_webhook).delete(webhooks::delete_webhook),
+        )
         .route(
             "/api/users/:id",
             patch(update_user).delete(delete_user),
@@ -355,32 +881,181 @@ async fn main() -> anyhow::Result<()> {
         .route("/api/accounts", get(get_accounts).post(create_account))
         .route(
             "/api/accounts/:id",
-            patch(update_account).delete(delete_account),
+            get(get_account).patch(update_account).delete(delete_account),
         )
         .route("/api/accounts/public", get(get_public_accounts))
+        .route("/api/accounts/export", get(csv_export::export_accounts))
+        .route("/api/accounts/import", post(import_accounts))
+        .route("/api/accounts/:id/test", post(test_account))
+        .route("/api/accounts/:id/restore", post(restore_account))
+        .route("/api/accounts/:id/activate", post(activate_account))
+        .route("/api/accounts/:id/deactivate", post(deactivate_account))
+        .route("/api/accounts/:id/users", get(list_account_users).post(grant_account_user))
+        .route(
+            "/api/accounts/:id/users/:user_id",
+            axum::routing::delete(revoke_account_user),
+        )
+        .route(
+            "/api/accounts/:id/oauth",
+            post(start_account_oauth).delete(delete_account_oauth),
+        )
         .route("/api/aliases", get(get_aliases).post(create_alias))
+        .route("/api/aliases/bulk", post(create_aliases_bulk))
+        .route("/api/aliases/:id/activate", post(activate_alias))
+        .route("/api/aliases/:id/deactivate", post(deactivate_alias))
         .route(
             "/api/aliases/:id",
-            patch(update_alias).delete(delete_alias),
+            get(get_alias).patch(update_alias).delete(delete_alias),
         )
         .route("/api/aliases/public", get(get_public_aliases))
+        .route("/api/aliases/export", get(csv_export::export_aliases))
+        .route("/api/senders", get(list_senders))
         .route(
             "/api/settings/default-sender",
             get(get_default_sender).put(update_default_sender),
         )
-        .route("/api/send", post(send_email))
+        .route("/api/settings/default-sender/test", post(test_default_sender))
+        .route("/api/admin/config/export", get(export_config))
+        .route("/api/admin/config/import", post(import_config))
+        .route("/api/admin/workers", get(workers::list_workers))
+        // The only routes that carry attachments; everything else keeps the small default
+        .route("/api/send", post(send_email).layer(DefaultBodyLimit::max(max_body_bytes)))
+        .route("/api/email/preview", post(preview_email).layer(DefaultBodyLimit::max(max_body_bytes)))
         .route("/api/inbox", get(get_inbox))
-        .layer(CorsLayer::permissive())
-        .with_state(state);
+        .route("/api/inbox/folders", get(list_inbox_folders))
+        .route("/api/inbox/search", get(search_inbox))
+        .route("/api/inbox/unread-count", get(get_unread_count))
+        .route(
+            "/api/inbox/:uid",
+            get(get_inbox_message).patch(update_inbox_message),
+        )
+        .route("/api/inbox/:uid/attachments/:index", get(download_inbox_attachment))
+        .route("/api/sent", get(list_sent_messages))
+        .route("/api/sent/:id", get(get_sent_message))
+        .route("/api/drafts", get(drafts::list_drafts).post(drafts::create_draft))
+        .route(
+            "/api/drafts/:id",
+            get(drafts::get_draft)
+                .patch(drafts::update_draft)
+                .delete(drafts::delete_draft),
+        )
+        .layer(DefaultBodyLimit::max(DEFAULT_BODY_LIMIT))
+        .layer(middleware::from_fn(error::payload_too_large_as_json))
+        .with_state(state)
+}
 
-    let addr = format!("{}:{}", host, port);
-    let listener = tokio::net::TcpListener::bind(&addr).await?;
-    println!("Server running on http://{}", addr);
-    axum::serve(listener, app).await?;
+// Body limit for every route except send and preview, which take MAX_BODY_BYTES
+const DEFAULT_BODY_LIMIT: usize = 2 * 1024 * 1024;
 
-    Ok(())
+// MAX_BODY_BYTES when unset
+const DEFAULT_MAX_BODY_BYTES: i64 = 30 * 1024 * 1024;
+
+// Kubernetes sends SIGTERM and waits 30s by default before SIGKILL
+const SHUTDOWN_DRAIN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(20);
+
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::error!(error = %e, "failed to listen for SIGINT");
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                tracing::error!(error = %e, "failed to listen for SIGTERM");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => tracing::info!("received SIGINT, shutting down"),
+        _ = terminate => tracing::info!("received SIGTERM, shutting down"),
+    }
 }
 
-async fn health_check() -> &'static str {
+const HEALTH_DB_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
+
+// Readiness: only "ok" when the database answers, so load balancers stop routing to
+// an instance that can't serve requests. With HEALTH_REQUIRE_WORKERS, a stale critical
+// background worker fails it too.
+async fn health_check(State(state): State<AppState>) -> (StatusCode, &'static str) {
+    let ping = sqlx::query_scalar::<_, i32>("SELECT 1").fetch_one(&state.db);
+    match tokio::time::timeout(HEALTH_DB_TIMEOUT, ping).await {
+        Ok(Ok(_)) => {
+            let stale = state.workers.blocking_readiness();
+            if stale.is_empty() {
+                (StatusCode::OK, "ok")
+            } else {
+                tracing::warn!(workers = ?stale, "health check: background workers are stale");
+                (StatusCode::SERVICE_UNAVAILABLE, "workers stale")
+            }
+        }
+        Ok(Err(e)) => {
+            tracing::warn!(error = %e, "health check: database query failed");
+            (StatusCode::SERVICE_UNAVAILABLE, "database unavailable")
+        }
+        Err(_) => {
+            tracing::warn!("health check: database did not answer within {:?}", HEALTH_DB_TIMEOUT);
+            (StatusCode::SERVICE_UNAVAILABLE, "database timeout")
+        }
+    }
+}
+
+// Liveness: the process is up and serving HTTP, whatever the database is doing
+async fn liveness_check() -> &'static str {
     "ok"
 }
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{Method, StatusCode};
+
+    use crate::{email, test_support, StringOrList};
+
+    #[test]
+    fn recipient_arrays_keep_commas_in_display_names() {
+        let list: StringOrList =
+            serde_json::from_str(r#"["Doe, Jane <jane@x.com>", "\"Roe, Rick\" <rick@x.com>"]"#).unwrap();
+        let list = list.into_recipient_list().unwrap();
+        assert_eq!(
+            email::split_recipients(&list),
+            [r#""Doe, Jane" <jane@x.com>"#, r#""Roe, Rick" <rick@x.com>"#]
+        );
+
+        let list: StringOrList = serde_json::from_str(r#"["ok@x.com", "not an address"]"#).unwrap();
+        assert_eq!(list.into_recipient_list(), Err("not an address".to_string()));
+
+        // The string form is still split on commas
+        let list: StringOrList = serde_json::from_str(r#""a@x.com, b@x.com""#).unwrap();
+        assert_eq!(email::split_recipients(&list.into_recipient_list().unwrap()), ["a@x.com", "b@x.com"]);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn oversized_send_body_gets_a_json_413() {
+        let state = test_support::state().await;
+        let user = test_support::user(&state.db, "dev").await;
+        let max_body_bytes = 64 * 1024;
+        let body = serde_json::json!({
+            "to": "someone@example.com",
+            "subject": "Too big",
+            "body": "x".repeat(max_body_bytes)
+        })
+        .to_string();
+
+        let app = super::router(state, max_body_bytes);
+        let (status, json) = test_support::call(app, Method::POST, "/api/send", &user.token, body).await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(json["status"], "error");
+        assert_eq!(json["code"], "payload_too_large");
+    }
+}
@@ -0,0 +1,325 @@
+// Microsoft OAuth2 for SMTP (XOAUTH2). Accounts with a stored refresh token send with a
+// fresh access token; everything else keeps using the stored password.
+
+use anyhow::anyhow;
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{Json, Redirect},
+};
+use chrono::Utc;
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+use sqlx::Row;
+
+use crate::{
+    auth::{AuthUser, UserRole, SCOPE_ACCOUNTS_WRITE},
+    email::SmtpAuth,
+    AppState, MicrosoftOAuthConfig,
+};
+
+// Refresh a little early so a token doesn't expire mid-conversation
+const EXPIRY_SKEW_SECS: i64 = 120;
+const CONSENT_TTL_SECS: i64 = 15 * 60;
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: i64,
+    refresh_token: Option<String>,
+}
+
+// Carried through the consent round-trip so the callback knows which account it is for
+#[derive(Serialize, Deserialize)]
+struct ConsentState {
+    account_id: String,
+    exp: usize,
+}
+
+fn token_endpoint(config: &MicrosoftOAuthConfig) -> String {
+    let tenant = if config.tenant_id.is_empty() { "common" } else { config.tenant_id.as_str() };
+    format!("https://login.microsoftonline.com/{}/oauth2/v2.0/token", tenant)
+}
+
+// Azure shows both a secret ID and a secret value; only the value authenticates.
+fn client_secret(config: &MicrosoftOAuthConfig) -> &str {
+    config
+        .client_value
+        .as_deref()
+        .filter(|v| !v.is_empty())
+        .unwrap_or(&config.client_secret)
+}
+
+fn scope_with_offline_access(config: &MicrosoftOAuthConfig) -> String {
+    if config.scope.split_whitespace().any(|s| s == "offline_access") {
+        config.scope.clone()
+    } else {
+        format!("{} offline_access", config.scope)
+    }
+}
+
+async fn request_token(
+    config: &MicrosoftOAuthConfig,
+    params: &[(&str, &str)],
+) -> anyhow::Result<TokenResponse> {
+    let scope = scope_with_offline_access(config);
+    let mut form = vec![
+        ("client_id", config.client_id.as_str()),
+        ("client_secret", client_secret(config)),
+        ("scope", scope.as_str()),
+    ];
+    form.extend_from_slice(params);
+
+    let response = reqwest::Client::new()
+        .post(token_endpoint(config))
+        .form(&form)
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        return Err(anyhow!("Token request failed ({}): {}", status, body));
+    }
+    Ok(response.json::<TokenResponse>().await?)
+}
+
+/// Picks XOAUTH2 when the account has OAuth credentials, refreshing the access token
+/// if needed, and falls back to the stored password otherwise.
+pub async fn smtp_auth(state: &AppState, account_email: &str, password: &str) -> anyhow::Result<SmtpAuth> {
+    let row = sqlx::query(
+        r#"
+        SELECT account_oauth.account_id, account_oauth.refresh_token,
+               account_oauth.access_token, account_oauth.expires_at
+        FROM account_oauth
+        JOIN accounts ON accounts.id = account_oauth.account_id
+        WHERE accounts.email = $1
+        "#,
+    )
+    .bind(account_email)
+    .fetch_optional(&state.db)
+    .await?;
+
+    let Some(row) = row else {
+        return Ok(SmtpAuth::Password(password.to_string()));
+    };
+
+    let now = Utc::now().timestamp();
+    let cached = row.get::<Option<String>, _>(2);
+    let expires_at = row.get::<Option<i64>, _>(3).unwrap_or(0);
+    if let Some(token) = cached {
+        if expires_at - EXPIRY_SKEW_SECS > now {
+            return Ok(SmtpAuth::OAuth2(token));
+        }
+    }
+
+    let account_id = row.get::<String, _>(0);
+    let refresh_token = row.get::<String, _>(1);
+    let token = request_token(
+        &state.microsoft_oauth,
+        &[("grant_type", "refresh_token"), ("refresh_token", refresh_token.as_str())],
+    )
+    .await?;
+
+    // Microsoft rotates refresh tokens; keep the newest one
+    sqlx::query(
+        r#"
+        UPDATE account_oauth
+        SET access_token = $1, expires_at = $2, refresh_token = $3, updated_at = $4
+        WHERE account_id = $5
+        "#,
+    )
+    .bind(&token.access_token)
+    .bind(now + token.expires_in)
+    .bind(token.refresh_token.as_deref().unwrap_or(&refresh_token))
+    .bind(now)
+    .bind(&account_id)
+    .execute(&state.db)
+    .await?;
+
+    Ok(SmtpAuth::OAuth2(token.access_token))
+}
+
+async fn store_refresh_token(state: &AppState, account_id: &str, token: &TokenResponse, refresh_token: &str) -> anyhow::Result<()> {
+    let now = Utc::now().timestamp();
+    sqlx::query(
+        r#"
+        INSERT INTO account_oauth (account_id, refresh_token, access_token, expires_at, updated_at)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (account_id) DO UPDATE SET
+            refresh_token = EXCLUDED.refresh_token,
+            access_token = EXCLUDED.access_token,
+            expires_at = EXCLUDED.expires_at,
+            updated_at = EXCLUDED.updated_at
+        "#,
+    )
+    .bind(account_id)
+    .bind(refresh_token)
+    .bind(&token.access_token)
+    .bind(now + token.expires_in)
+    .bind(now)
+    .execute(&state.db)
+    .await?;
+    Ok(())
+}
+
+async fn ensure_account_exists(state: &AppState, account_id: &str) -> Result<(), StatusCode> {
+    let exists: i64 = sqlx::query_scalar("SELECT COUNT(1) FROM accounts WHERE id = $1 AND deleted_at IS NULL")
+        .bind(account_id)
+        .fetch_one(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if exists == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    Ok(())
+}
+
+fn ensure_admin(user: &AuthUser) -> Result<(), StatusCode> {
+    user.ensure_password_updated()?;
+    user.ensure_scope(SCOPE_ACCOUNTS_WRITE)?;
+    if !matches!(user.role, UserRole::Admin) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    Ok(())
+}
+
+// Start the Microsoft consent flow for an account; the admin opens the returned URL
+pub async fn start_account_oauth(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(account_id): Path<String>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    ensure_admin(&user)?;
+    ensure_account_exists(&state, &account_id).await?;
+
+    let config = &state.microsoft_oauth;
+    if config.client_id.is_empty() {
+        return Ok(Json(serde_json::json!({
+            "status": "error",
+            "message": "Microsoft OAuth is not configured"
+        })));
+    }
+
+    let claims = ConsentState {
+        account_id,
+        exp: (Utc::now().timestamp() + CONSENT_TTL_SECS) as usize,
+    };
+    let consent_state = encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(state.jwt_secret.as_bytes()),
+    )
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let tenant = if config.tenant_id.is_empty() { "common" } else { config.tenant_id.as_str() };
+    let url = reqwest::Url::parse_with_params(
+        &format!("https://login.microsoftonline.com/{}/oauth2/v2.0/authorize", tenant),
+        &[
+            ("client_id", config.client_id.as_str()),
+            ("response_type", "code"),
+            ("redirect_uri", config.redirect_uri.as_str()),
+            ("response_mode", "query"),
+            ("scope", scope_with_offline_access(config).as_str()),
+            ("state", consent_state.as_str()),
+        ],
+    )
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(serde_json::json!({
+        "status": "ok",
+        "authorizeUrl": url.to_string()
+    })))
+}
+
+#[derive(Deserialize)]
+pub struct OAuthCallbackQuery {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    pub error: Option<String>,
+    pub error_description: Option<String>,
+}
+
+// Microsoft redirects here after consent; exchange the code and keep the refresh token
+pub async fn microsoft_callback(
+    State(state): State<AppState>,
+    Query(query): Query<OAuthCallbackQuery>,
+) -> Redirect {
+    let manage_url = format!("{}/manage", state.app_base_url.trim_end_matches('/'));
+    let fail = |reason: &str| {
+        let url = reqwest::Url::parse_with_params(&manage_url, &[("oauth", "error"), ("reason", reason)])
+            .map(|u| u.to_string())
+            .unwrap_or_else(|_| manage_url.clone());
+        Redirect::to(&url)
+    };
+
+    if let Some(error) = query.error {
+        tracing::warn!(
+            %error,
+            description = %query.error_description.unwrap_or_default(),
+            "Microsoft OAuth consent failed"
+        );
+        return fail(&error);
+    }
+    let (Some(code), Some(consent_state)) = (query.code, query.state) else {
+        return fail("missing_code");
+    };
+
+    let claims = match decode::<ConsentState>(
+        &consent_state,
+        &DecodingKey::from_secret(state.jwt_secret.as_bytes()),
+        &Validation::default(),
+    ) {
+        Ok(data) => data.claims,
+        Err(_) => return fail("invalid_state"),
+    };
+
+    let token = match request_token(
+        &state.microsoft_oauth,
+        &[
+            ("grant_type", "authorization_code"),
+            ("code", code.as_str()),
+            ("redirect_uri", state.microsoft_oauth.redirect_uri.as_str()),
+        ],
+    )
+    .await
+    {
+        Ok(token) => token,
+        Err(e) => {
+            tracing::error!(error = %e, account_id = %claims.account_id, "Microsoft OAuth code exchange failed");
+            return fail("token_exchange");
+        }
+    };
+    let Some(refresh_token) = token.refresh_token.clone() else {
+        return fail("no_refresh_token");
+    };
+
+    if let Err(e) = store_refresh_token(&state, &claims.account_id, &token, &refresh_token).await {
+        tracing::error!(error = %e, account_id = %claims.account_id, "failed to store OAuth token");
+        return fail("storage");
+    }
+
+    Redirect::to(&format!("{}?oauth=connected", manage_url))
+}
+
+// Drop the account's OAuth credentials; it goes back to password auth
+pub async fn delete_account_oauth(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(account_id): Path<String>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    ensure_admin(&user)?;
+
+    let result = sqlx::query("DELETE FROM account_oauth WHERE account_id = $1")
+        .bind(&account_id)
+        .execute(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if result.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    Ok(Json(serde_json::json!({
+        "status": "success",
+        "message": "OAuth credentials removed; the account will use its password"
+    })))
+}
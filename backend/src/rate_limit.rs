@@ -0,0 +1,236 @@
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Json, Response},
+};
+use ipnet::IpNet;
+
+/// Reverse proxies whose X-Forwarded-For / X-Real-IP headers are believed
+/// (TRUSTED_PROXIES: comma-separated addresses or CIDR ranges). With none configured
+/// the connecting peer is taken as the client, since anyone can send those headers.
+#[derive(Clone, Default)]
+pub struct TrustedProxies(Arc<Vec<IpNet>>);
+
+impl TrustedProxies {
+    pub fn from_env() -> anyhow::Result<Self> {
+        Self::parse(&std::env::var("TRUSTED_PROXIES").unwrap_or_default())
+    }
+
+    fn parse(raw: &str) -> anyhow::Result<Self> {
+        let nets = raw
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| {
+                entry
+                    .parse::<IpNet>()
+                    .or_else(|_| entry.parse::<IpAddr>().map(IpNet::from))
+                    .map_err(|_| anyhow::anyhow!("TRUSTED_PROXIES has an invalid address or range {:?}", entry))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(Self(Arc::new(nets)))
+    }
+
+    fn contains(&self, ip: IpAddr) -> bool {
+        self.0.iter().any(|net| net.contains(&ip))
+    }
+
+    /// The client's address. Forwarded headers only count when the peer is a trusted
+    /// proxy, and then the rightmost X-Forwarded-For hop that isn't one of ours is the
+    /// client: everything to its left was written by the client and can be anything.
+    pub fn client_ip(&self, headers: &HeaderMap, peer: IpAddr) -> IpAddr {
+        if !self.contains(peer) {
+            return peer;
+        }
+        let hops: Vec<&str> = headers
+            .get_all("x-forwarded-for")
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .collect();
+        if hops.is_empty() {
+            return headers
+                .get("x-real-ip")
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.trim().parse().ok())
+                .unwrap_or(peer);
+        }
+        // Walking left from the peer, stop at a hop that isn't a trusted proxy; if the
+        // chain turns to garbage first, the last proxy we could read is the best we have
+        let mut client = peer;
+        for hop in hops.iter().rev() {
+            let Ok(ip) = hop.trim().parse::<IpAddr>() else {
+                break;
+            };
+            client = ip;
+            if !self.contains(ip) {
+                break;
+            }
+        }
+        client
+    }
+}
+
+// A bucket that has been idle this long is full again and can be forgotten
+const IDLE_EVICT: Duration = Duration::from_secs(60);
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Per-IP token bucket for the unauthenticated auth endpoints (login, signup,
+/// password reset), so they can't be used to brute-force accounts or send spam.
+#[derive(Clone)]
+pub struct AuthRateLimiter {
+    buckets: Arc<Mutex<HashMap<IpAddr, Bucket>>>,
+    per_minute: f64,
+    trusted_proxies: TrustedProxies,
+}
+
+impl AuthRateLimiter {
+    pub fn from_env(trusted_proxies: TrustedProxies) -> Self {
+        let per_minute = std::env::var("AUTH_RATE_LIMIT_PER_MIN")
+            .ok()
+            .and_then(|v| v.trim().parse().ok())
+            .filter(|v: &u32| *v > 0)
+            .unwrap_or(20);
+        Self::new(per_minute, trusted_proxies)
+    }
+
+    fn new(per_minute: u32, trusted_proxies: TrustedProxies) -> Self {
+        Self {
+            buckets: Arc::new(Mutex::new(HashMap::new())),
+            per_minute: per_minute as f64,
+            trusted_proxies,
+        }
+    }
+
+    /// Takes a token for `ip`, or returns how many seconds until one is available.
+    fn check(&self, ip: IpAddr) -> Result<(), u64> {
+        let now = Instant::now();
+        let refill_per_sec = self.per_minute / 60.0;
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        buckets.retain(|_, b| now.duration_since(b.updated) < IDLE_EVICT);
+
+        let bucket = buckets.entry(ip).or_insert(Bucket {
+            tokens: self.per_minute,
+            updated: now,
+        });
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * refill_per_sec).min(self.per_minute);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(((1.0 - bucket.tokens) / refill_per_sec).ceil().max(1.0) as u64)
+        }
+    }
+}
+
+/// Keys on the client address as resolved by TrustedProxies.
+pub async fn limit_auth(
+    State(limiter): State<AuthRateLimiter>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let ip = limiter.trusted_proxies.client_ip(request.headers(), peer.ip());
+    match limiter.check(ip) {
+        Ok(()) => next.run(request).await,
+        Err(retry_after) => (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(header::RETRY_AFTER, retry_after.to_string())],
+            Json(serde_json::json!({
+                "status": "error",
+                "message": "Too many requests. Please wait a moment and try again.",
+                "retryAfter": retry_after
+            })),
+        )
+            .into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, middleware, routing::post, Router};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    fn app(limiter: AuthRateLimiter) -> Router {
+        Router::new()
+            .route("/api/auth/login", post(|| async { "ok" }))
+            .route_layer(middleware::from_fn_with_state(limiter, limit_auth))
+    }
+
+    async fn login(app: &Router, peer: &str, forwarded: &str) -> StatusCode {
+        let mut request = Request::post("/api/auth/login")
+            .header("x-forwarded-for", forwarded)
+            .body(Body::empty())
+            .unwrap();
+        request
+            .extensions_mut()
+            .insert(ConnectInfo(SocketAddr::new(peer.parse().unwrap(), 40000)));
+        app.clone().oneshot(request).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn spoofed_forwarded_for_does_not_reset_the_bucket() {
+        let app = app(AuthRateLimiter::new(2, TrustedProxies::default()));
+        assert_eq!(login(&app, "203.0.113.7", "198.51.100.1").await, StatusCode::OK);
+        assert_eq!(login(&app, "203.0.113.7", "198.51.100.2").await, StatusCode::OK);
+        assert_eq!(login(&app, "203.0.113.7", "198.51.100.3").await, StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(login(&app, "203.0.113.8", "198.51.100.3").await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn behind_a_trusted_proxy_the_rightmost_untrusted_hop_is_the_client() {
+        let proxies = TrustedProxies::parse("10.0.0.0/8, 192.0.2.1").unwrap();
+        let app = app(AuthRateLimiter::new(2, proxies));
+        // The client can prepend anything; our proxies append what they saw
+        assert_eq!(login(&app, "10.0.0.5", "1.1.1.1, 203.0.113.7").await, StatusCode::OK);
+        assert_eq!(login(&app, "10.0.0.5", "2.2.2.2, 203.0.113.7, 192.0.2.1").await, StatusCode::OK);
+        assert_eq!(login(&app, "10.0.0.6", "3.3.3.3, 203.0.113.7").await, StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(login(&app, "10.0.0.5", "203.0.113.8").await, StatusCode::OK);
+    }
+
+    #[test]
+    fn forwarded_headers_are_ignored_unless_the_peer_is_trusted() {
+        let proxies = TrustedProxies::parse("10.0.0.1").unwrap();
+        let peer: IpAddr = "10.0.0.1".parse().unwrap();
+        let headers = |pairs: &[(&'static str, &str)]| {
+            let mut headers = HeaderMap::new();
+            for (name, value) in pairs {
+                headers.append(*name, value.parse().unwrap());
+            }
+            headers
+        };
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+
+        let real_ip = headers(&[("x-real-ip", "203.0.113.7")]);
+        assert_eq!(proxies.client_ip(&real_ip, peer), ip("203.0.113.7"));
+        assert_eq!(proxies.client_ip(&real_ip, ip("198.51.100.1")), ip("198.51.100.1"));
+        assert_eq!(TrustedProxies::default().client_ip(&real_ip, peer), peer);
+
+        // A hop we can't read ends the walk at the last proxy before it
+        let garbage = headers(&[("x-forwarded-for", "203.0.113.7, unknown")]);
+        assert_eq!(proxies.client_ip(&garbage, peer), peer);
+
+        // Split across several header lines, the last line is still the nearest hop
+        let split = headers(&[("x-forwarded-for", "1.1.1.1"), ("x-forwarded-for", "203.0.113.7")]);
+        assert_eq!(proxies.client_ip(&split, peer), ip("203.0.113.7"));
+
+        assert!(TrustedProxies::parse("10.0.0.0/8, not-an-ip").is_err());
+    }
+}
@@ -0,0 +1,130 @@
+// At-rest encryption for mailbox passwords (AES-256-GCM). Stored values look like
+// "enc:v1:<base64(nonce || ciphertext || tag)>"; anything without the prefix is legacy plaintext.
+
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use ring::{
+    aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN},
+    rand::{SecureRandom, SystemRandom},
+};
+use sqlx::{PgPool, Row};
+
+const PREFIX: &str = "enc:v1:";
+
+#[derive(Clone)]
+pub struct SecretBox {
+    key: Option<Arc<[u8; 32]>>,
+}
+
+impl SecretBox {
+    // ACCOUNT_ENC_KEY is 32 random bytes, base64-encoded (`openssl rand -base64 32`)
+    pub fn from_env() -> anyhow::Result<Self> {
+        let Some(raw) = std::env::var("ACCOUNT_ENC_KEY").ok().filter(|v| !v.trim().is_empty()) else {
+            return Ok(Self { key: None });
+        };
+        let bytes = STANDARD
+            .decode(raw.trim())
+            .map_err(|_| anyhow!("ACCOUNT_ENC_KEY must be base64"))?;
+        let key: [u8; 32] = bytes
+            .try_into()
+            .map_err(|_| anyhow!("ACCOUNT_ENC_KEY must decode to exactly 32 bytes"))?;
+        Ok(Self {
+            key: Some(Arc::new(key)),
+        })
+    }
+
+    #[cfg(test)]
+    pub fn with_key(key: [u8; 32]) -> Self {
+        Self {
+            key: Some(Arc::new(key)),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.key.is_some()
+    }
+
+    fn cipher(&self) -> Option<LessSafeKey> {
+        let key = self.key.as_ref()?;
+        let unbound = UnboundKey::new(&AES_256_GCM, key.as_slice()).expect("key is 32 bytes");
+        Some(LessSafeKey::new(unbound))
+    }
+
+    /// Encrypts for storage; without a key the value is stored as-is.
+    pub fn seal(&self, plaintext: &str) -> anyhow::Result<String> {
+        let Some(cipher) = self.cipher() else {
+            return Ok(plaintext.to_string());
+        };
+        let mut nonce = [0u8; NONCE_LEN];
+        SystemRandom::new()
+            .fill(&mut nonce)
+            .map_err(|_| anyhow!("Failed to generate nonce"))?;
+
+        let mut in_out = plaintext.as_bytes().to_vec();
+        cipher
+            .seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::empty(), &mut in_out)
+            .map_err(|_| anyhow!("Failed to encrypt secret"))?;
+
+        let mut payload = nonce.to_vec();
+        payload.extend_from_slice(&in_out);
+        Ok(format!("{}{}", PREFIX, STANDARD.encode(payload)))
+    }
+
+    /// Decrypts a stored value; legacy plaintext passes through unchanged.
+    pub fn open(&self, stored: &str) -> anyhow::Result<String> {
+        let Some(encoded) = stored.strip_prefix(PREFIX) else {
+            return Ok(stored.to_string());
+        };
+        let cipher = self
+            .cipher()
+            .ok_or_else(|| anyhow!("Stored secret is encrypted but ACCOUNT_ENC_KEY is not set"))?;
+        let mut payload = STANDARD
+            .decode(encoded)
+            .map_err(|_| anyhow!("Stored secret is not valid base64"))?;
+        if payload.len() < NONCE_LEN {
+            return Err(anyhow!("Stored secret is truncated"));
+        }
+        let mut in_out = payload.split_off(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(&payload).map_err(|_| anyhow!("Invalid nonce"))?;
+        let plaintext = cipher
+            .open_in_place(nonce, Aad::empty(), &mut in_out)
+            .map_err(|_| anyhow!("Failed to decrypt secret; is ACCOUNT_ENC_KEY correct?"))?;
+        String::from_utf8(plaintext.to_vec()).map_err(|_| anyhow!("Decrypted secret is not UTF-8"))
+    }
+
+    /// Like `open`, but refuses plaintext: for values that claim to be sealed, such as
+    /// passwords in an imported config bundle.
+    pub fn open_sealed(&self, sealed: &str) -> anyhow::Result<String> {
+        if !sealed.starts_with(PREFIX) {
+            return Err(anyhow!("Value is not sealed"));
+        }
+        self.open(sealed)
+    }
+}
+
+/// One-off upgrade run at startup: encrypts any account passwords still stored in plaintext.
+pub async fn encrypt_plaintext_passwords(db: &PgPool, secrets: &SecretBox) -> anyhow::Result<u64> {
+    if !secrets.is_enabled() {
+        return Ok(0);
+    }
+
+    let rows = sqlx::query("SELECT id, password FROM accounts WHERE password NOT LIKE 'enc:v1:%'")
+        .fetch_all(db)
+        .await?;
+
+    let mut updated = 0;
+    for row in rows {
+        let id = row.get::<String, _>(0);
+        let sealed = secrets.seal(&row.get::<String, _>(1))?;
+        // The LIKE guard keeps a concurrent write from being double-encrypted
+        updated += sqlx::query("UPDATE accounts SET password = $1 WHERE id = $2 AND password NOT LIKE 'enc:v1:%'")
+            .bind(sealed)
+            .bind(&id)
+            .execute(db)
+            .await?
+            .rows_affected();
+    }
+    Ok(updated)
+}
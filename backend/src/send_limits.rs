@@ -0,0 +1,199 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use chrono::{Duration as ChronoDuration, Utc};
+use sqlx::PgPool;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::auth::UserRole;
+
+// How long an excess request waits for a slot before giving up with 429.
+const ACQUIRE_WAIT: Duration = Duration::from_secs(2);
+pub const RETRY_AFTER_SECS: u64 = 2;
+
+/// Caps simultaneous synchronous sends per user so one client can't exhaust
+/// the SMTP connection allowance for everyone.
+#[derive(Clone)]
+pub struct SendLimiter {
+    slots: Arc<Mutex<HashMap<String, Arc<Semaphore>>>>,
+    admin_limit: usize,
+    dev_limit: usize,
+    user_limit: usize,
+}
+
+impl SendLimiter {
+    pub fn from_env() -> Self {
+        let limit = |key: &str| {
+            std::env::var(key)
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .filter(|v: &usize| *v > 0)
+                .unwrap_or(3)
+        };
+        Self {
+            slots: Arc::new(Mutex::new(HashMap::new())),
+            admin_limit: limit("SEND_CONCURRENCY_ADMIN"),
+            dev_limit: limit("SEND_CONCURRENCY_DEV"),
+            user_limit: limit("SEND_CONCURRENCY_USER"),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn with_limit(limit: usize) -> Self {
+        Self {
+            slots: Arc::new(Mutex::new(HashMap::new())),
+            admin_limit: limit,
+            dev_limit: limit,
+            user_limit: limit,
+        }
+    }
+
+    fn limit_for(&self, role: &UserRole) -> usize {
+        match role {
+            UserRole::Admin => self.admin_limit,
+            UserRole::Dev => self.dev_limit,
+            UserRole::User => self.user_limit,
+        }
+    }
+
+    /// Waits briefly for a slot. The permit is released when dropped, which also
+    /// covers early returns and panics in the handler.
+    pub async fn acquire(&self, user_id: &str, role: &UserRole) -> Option<OwnedSemaphorePermit> {
+        let semaphore = {
+            let mut slots = self.slots.lock().unwrap_or_else(|e| e.into_inner());
+            // Held permits and waiters keep a clone; anything else is idle and can go.
+            slots.retain(|_, s| Arc::strong_count(s) > 1);
+            slots
+                .entry(user_id.to_string())
+                .or_insert_with(|| Arc::new(Semaphore::new(self.limit_for(role))))
+                .clone()
+        };
+
+        tokio::time::timeout(ACQUIRE_WAIT, semaphore.acquire_owned())
+            .await
+            .ok()
+            .and_then(Result::ok)
+    }
+}
+
+/// Caps sends per account per UTC day, so a leaked token can't empty a mailbox's
+/// reputation in one go. An account's own `daily_send_limit` wins over DAILY_SEND_LIMIT;
+/// with neither set, sends are unlimited.
+#[derive(Clone, Copy)]
+pub struct DailyQuota {
+    default_limit: Option<i64>,
+}
+
+pub enum QuotaCheck {
+    Allowed,
+    Exceeded { limit: i64, retry_after_secs: u64 },
+}
+
+impl DailyQuota {
+    /// Blank or 0 leaves accounts without their own limit unlimited.
+    pub fn from_env() -> anyhow::Result<Self> {
+        let default_limit = match std::env::var("DAILY_SEND_LIMIT") {
+            Ok(v) if !v.trim().is_empty() => {
+                let limit = v.trim().parse::<i64>().ok().filter(|n| *n >= 0).ok_or_else(|| {
+                    anyhow::anyhow!("DAILY_SEND_LIMIT must be a non-negative integer, got {:?}", v)
+                })?;
+                (limit > 0).then_some(limit)
+            }
+            _ => None,
+        };
+        Ok(Self { default_limit })
+    }
+
+    /// Counts one send against the account that authenticates it, unless today's limit
+    /// is already used up. The check and increment are one statement, so concurrent
+    /// sends can't both take the last slot.
+    pub async fn try_consume(&self, db: &PgPool, account_email: &str) -> anyhow::Result<QuotaCheck> {
+        let account: Option<(String, Option<i64>)> =
+            sqlx::query_as("SELECT id, daily_send_limit FROM accounts WHERE email = $1")
+                .bind(account_email)
+                .fetch_optional(db)
+                .await?;
+        let Some((account_id, own_limit)) = account else {
+            return Ok(QuotaCheck::Allowed);
+        };
+        let Some(limit) = own_limit.or(self.default_limit) else {
+            return Ok(QuotaCheck::Allowed);
+        };
+
+        let now = Utc::now();
+        let counted: Option<i64> = sqlx::query_scalar(
+            r#"
+            INSERT INTO send_counters (account_id, day, count)
+            SELECT $1, $2, 1 WHERE $3 > 0
+            ON CONFLICT (account_id, day) DO UPDATE SET count = send_counters.count + 1
+            WHERE send_counters.count < $3
+            RETURNING count
+            "#,
+        )
+        .bind(&account_id)
+        .bind(now.date_naive())
+        .bind(limit)
+        .fetch_optional(db)
+        .await?;
+        if counted.is_some() {
+            return Ok(QuotaCheck::Allowed);
+        }
+
+        let midnight = (now.date_naive() + ChronoDuration::days(1))
+            .and_hms_opt(0, 0, 0)
+            .map(|t| t.and_utc())
+            .unwrap_or(now);
+        Ok(QuotaCheck::Exceeded {
+            limit,
+            retry_after_secs: (midnight - now).num_seconds().max(1) as u64,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(limit: usize) -> SendLimiter {
+        SendLimiter {
+            slots: Arc::new(Mutex::new(HashMap::new())),
+            admin_limit: limit,
+            dev_limit: limit,
+            user_limit: limit,
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn excess_sends_wait_for_a_slot() {
+        let limiter = limiter(2);
+        let role = UserRole::User;
+        let first = limiter.acquire("alice", &role).await.unwrap();
+        let second = limiter.acquire("alice", &role).await.unwrap();
+
+        // The third waits out ACQUIRE_WAIT, then gives up
+        let started = tokio::time::Instant::now();
+        assert!(limiter.acquire("alice", &role).await.is_none());
+        assert!(started.elapsed() >= ACQUIRE_WAIT);
+
+        // Other users have slots of their own
+        let other = limiter.acquire("bob", &role).await.unwrap();
+
+        // A waiting send gets the slot as soon as one is released
+        let waiter = tokio::spawn({
+            let limiter = limiter.clone();
+            async move { limiter.acquire("alice", &UserRole::User).await }
+        });
+        tokio::task::yield_now().await;
+        drop(first);
+        let third = waiter.await.unwrap().unwrap();
+
+        drop((second, third, other));
+        // Idle entries are swept on the next acquire
+        let _carol = limiter.acquire("carol", &role).await.unwrap();
+        let slots = limiter.slots.lock().unwrap();
+        assert_eq!(slots.keys().collect::<Vec<_>>(), ["carol"]);
+    }
+}
@@ -0,0 +1,149 @@
+// Recipients of sent messages, one lowercased address per row, so GET /api/sent can
+// filter by recipient through an index instead of a LIKE over the comma-joined columns.
+
+use serde::Serialize;
+use sqlx::{PgExecutor, PgPool, Row};
+
+use crate::{email::split_recipients, handlers::bare_address};
+
+#[derive(Serialize, Default, Debug, PartialEq)]
+pub struct Recipients {
+    pub to: Vec<String>,
+    pub cc: Vec<String>,
+    pub bcc: Vec<String>,
+}
+
+// (address, kind) pairs in list order; an address repeated within one kind is kept once
+fn entries(to: &str, cc: Option<&str>, bcc: Option<&str>) -> Vec<(String, String)> {
+    let mut rows: Vec<(String, String)> = Vec::new();
+    for (list, kind) in [(Some(to), "to"), (cc, "cc"), (bcc, "bcc")] {
+        for entry in list.map(split_recipients).unwrap_or_default() {
+            let address = bare_address(entry);
+            if !rows.iter().any(|(a, k)| a == &address && k == kind) {
+                rows.push((address, kind.to_string()));
+            }
+        }
+    }
+    rows
+}
+
+/// Indexes the recipients of one sent message. Run it in the transaction that inserts
+/// the message, so a message is never visible without its recipients.
+pub async fn record(
+    db: impl PgExecutor<'_>,
+    sent_message_id: &str,
+    to: &str,
+    cc: Option<&str>,
+    bcc: Option<&str>,
+) -> sqlx::Result<()> {
+    let (addresses, kinds): (Vec<String>, Vec<String>) = entries(to, cc, bcc).into_iter().unzip();
+    if addresses.is_empty() {
+        return Ok(());
+    }
+    sqlx::query(
+        r#"
+        INSERT INTO sent_message_recipients (sent_message_id, address, kind)
+        SELECT $1, address, kind FROM UNNEST($2::text[], $3::text[]) AS r(address, kind)
+        ON CONFLICT DO NOTHING
+        "#,
+    )
+    .bind(sent_message_id)
+    .bind(addresses)
+    .bind(kinds)
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+/// The recipients of one sent message, grouped by kind and sorted by address.
+pub async fn for_message(db: impl PgExecutor<'_>, sent_message_id: &str) -> sqlx::Result<Recipients> {
+    let rows = sqlx::query(
+        "SELECT address, kind FROM sent_message_recipients WHERE sent_message_id = $1 ORDER BY address",
+    )
+    .bind(sent_message_id)
+    .fetch_all(db)
+    .await?;
+
+    let mut recipients = Recipients::default();
+    for row in rows {
+        let address = row.get::<String, _>(0);
+        match row.get::<String, _>(1).as_str() {
+            "to" => recipients.to.push(address),
+            "cc" => recipients.cc.push(address),
+            _ => recipients.bcc.push(address),
+        }
+    }
+    Ok(recipients)
+}
+
+/// One-off upgrade run at startup: indexes messages sent before sent_message_recipients
+/// existed. Every message has at least one `to` address, so one without rows is unindexed.
+pub async fn backfill(db: &PgPool) -> anyhow::Result<u64> {
+    let rows = sqlx::query(
+        r#"
+        SELECT id, to_recipients, cc_recipients, bcc_recipients
+        FROM sent_messages m
+        WHERE NOT EXISTS (SELECT 1 FROM sent_message_recipients r WHERE r.sent_message_id = m.id)
+        "#,
+    )
+    .fetch_all(db)
+    .await?;
+
+    let mut indexed = 0;
+    for row in rows {
+        let cc = row.get::<Option<String>, _>(2);
+        let bcc = row.get::<Option<String>, _>(3);
+        record(db, &row.get::<String, _>(0), &row.get::<String, _>(1), cc.as_deref(), bcc.as_deref()).await?;
+        indexed += 1;
+    }
+    Ok(indexed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    #[test]
+    fn entries_are_lowercased_bare_addresses_per_kind() {
+        let rows = entries(
+            "\"Doe, Jane\" <Jane@Example.com>, bob@example.com, BOB@example.com",
+            Some("bob@example.com"),
+            None,
+        );
+        let rows: Vec<(&str, &str)> = rows.iter().map(|(a, k)| (a.as_str(), k.as_str())).collect();
+        assert_eq!(
+            rows,
+            [("jane@example.com", "to"), ("bob@example.com", "to"), ("bob@example.com", "cc")]
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn backfill_indexes_messages_sent_before_the_table_existed() {
+        let db = test_support::db().await;
+        let user = test_support::user(&db, "dev").await;
+        let id = uuid::Uuid::new_v4().to_string();
+        sqlx::query(
+            r#"
+            INSERT INTO sent_messages (id, user_id, from_email, to_recipients, cc_recipients, bcc_recipients, subject, body)
+            VALUES ($1, $2, 'sender@example.com', 'A@Example.com, b@example.com', 'Carol <carol@example.com>', NULL, 'Hi', 'Hi')
+            "#,
+        )
+        .bind(&id)
+        .bind(&user.id)
+        .execute(&db)
+        .await
+        .unwrap();
+
+        assert!(backfill(&db).await.unwrap() >= 1);
+        let recipients = for_message(&db, &id).await.unwrap();
+        assert_eq!(recipients.to, ["a@example.com", "b@example.com"]);
+        assert_eq!(recipients.cc, ["carol@example.com"]);
+        assert!(recipients.bcc.is_empty());
+
+        // Already indexed rows are left alone on the next startup
+        backfill(&db).await.unwrap();
+        assert_eq!(for_message(&db, &id).await.unwrap(), recipients);
+    }
+}
@@ -0,0 +1,79 @@
+// Authenticated SMTP connections kept open between sends, so repeated sends from the
+// same account skip the TCP connect, TLS handshake and AUTH round trips.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+use lettre::transport::smtp::client::AsyncSmtpConnection;
+
+// Spare connections kept per account; a burst beyond this connects as usual
+const MAX_IDLE_PER_KEY: usize = 2;
+
+// (host, port, login) - the login is part of the key since a session is authenticated as it
+pub type PoolKey = (String, u16, String);
+
+struct IdleConnection {
+    conn: AsyncSmtpConnection,
+    since: Instant,
+}
+
+/// Idle connections are dropped after SMTP_POOL_IDLE_SECS (default 60, 0 disables
+/// pooling). Servers close idle sessions on their own too, so a connection is checked
+/// with NOOP before it is reused.
+#[derive(Clone)]
+pub struct SmtpPool {
+    idle: Arc<Mutex<HashMap<PoolKey, Vec<IdleConnection>>>>,
+    ttl: Duration,
+}
+
+impl SmtpPool {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let secs: u64 = match std::env::var("SMTP_POOL_IDLE_SECS") {
+            Ok(v) if !v.trim().is_empty() => v
+                .trim()
+                .parse()
+                .map_err(|_| anyhow!("SMTP_POOL_IDLE_SECS must be a number of seconds, got {:?}", v))?,
+            _ => 60,
+        };
+        Ok(Self {
+            idle: Arc::new(Mutex::new(HashMap::new())),
+            ttl: Duration::from_secs(secs),
+        })
+    }
+
+    /// The most recently used idle connection for `key` that hasn't outlived the TTL.
+    pub fn take(&self, key: &PoolKey) -> Option<AsyncSmtpConnection> {
+        let mut idle = self.idle.lock().unwrap_or_else(|e| e.into_inner());
+        let conns = idle.get_mut(key)?;
+        conns.retain(|entry| entry.since.elapsed() < self.ttl);
+        let conn = conns.pop().map(|entry| entry.conn);
+        if conns.is_empty() {
+            idle.remove(key);
+        }
+        conn
+    }
+
+    /// Keeps `conn` for the next send, unless pooling is off, the connection is broken
+    /// or enough are already waiting. Expired connections of every key are dropped here.
+    pub fn put(&self, key: PoolKey, conn: AsyncSmtpConnection) {
+        if self.ttl.is_zero() || conn.has_broken() {
+            return;
+        }
+        let mut idle = self.idle.lock().unwrap_or_else(|e| e.into_inner());
+        idle.retain(|_, conns| {
+            conns.retain(|entry| entry.since.elapsed() < self.ttl);
+            !conns.is_empty()
+        });
+        let conns = idle.entry(key).or_default();
+        if conns.len() < MAX_IDLE_PER_KEY {
+            conns.push(IdleConnection {
+                conn,
+                since: Instant::now(),
+            });
+        }
+    }
+}
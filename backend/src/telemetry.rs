@@ -0,0 +1,108 @@
+// Prometheus metrics for send volume and sign-ins, scraped from GET /metrics
+
+use std::time::Duration;
+
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use metrics::{counter, describe_counter, describe_histogram, histogram, Unit};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+
+use crate::AppState;
+
+const EMAILS_SENT: &str = "w9_mail_emails_sent_total";
+const SEND_FAILURES: &str = "w9_mail_send_failures_total";
+const SMTP_SEND_SECONDS: &str = "w9_mail_smtp_send_duration_seconds";
+const LOGINS: &str = "w9_mail_logins_total";
+const FAILED_LOGINS: &str = "w9_mail_failed_logins_total";
+
+const SMTP_BUCKETS: &[f64] = &[0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0];
+const UPKEEP_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Clone)]
+pub struct Metrics {
+    handle: PrometheusHandle,
+    // METRICS_TOKEN; when set, scrapes must send it as a bearer token
+    token: Option<String>,
+}
+
+impl Metrics {
+    pub fn install() -> anyhow::Result<Self> {
+        let handle = PrometheusBuilder::new()
+            .set_buckets_for_metric(Matcher::Full(SMTP_SEND_SECONDS.to_string()), SMTP_BUCKETS)?
+            .install_recorder()?;
+
+        describe_counter!(EMAILS_SENT, "Emails accepted by the SMTP server");
+        describe_counter!(SEND_FAILURES, "Sends that did not go out, by reason");
+        describe_histogram!(SMTP_SEND_SECONDS, Unit::Seconds, "Time spent in the SMTP conversation per send");
+        describe_counter!(LOGINS, "Successful sign-ins");
+        describe_counter!(FAILED_LOGINS, "Rejected sign-ins, by reason");
+
+        // The exporter only trims histogram buffers when asked to
+        let upkeep = handle.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(UPKEEP_INTERVAL);
+            loop {
+                interval.tick().await;
+                upkeep.run_upkeep();
+            }
+        });
+
+        let token = std::env::var("METRICS_TOKEN")
+            .ok()
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty());
+        Ok(Self { handle, token })
+    }
+
+    // A recorder that isn't installed globally, so each test can build its own state
+    #[cfg(test)]
+    pub fn detached() -> Self {
+        Self {
+            handle: PrometheusBuilder::new().build_recorder().handle(),
+            token: None,
+        }
+    }
+}
+
+pub fn email_sent() {
+    counter!(EMAILS_SENT).increment(1);
+}
+
+// reason: auth, quota, rejected, throttled, timeout or smtp
+pub fn send_failed(reason: &'static str) {
+    counter!(SEND_FAILURES, "reason" => reason).increment(1);
+}
+
+pub fn smtp_latency(elapsed: Duration) {
+    histogram!(SMTP_SEND_SECONDS).record(elapsed.as_secs_f64());
+}
+
+pub fn login_succeeded() {
+    counter!(LOGINS).increment(1);
+}
+
+// reason: unknown_user, locked, password or totp
+pub fn login_failed(reason: &'static str) {
+    counter!(FAILED_LOGINS, "reason" => reason).increment(1);
+}
+
+pub async fn render(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    if let Some(expected) = &state.metrics.token {
+        let provided = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "));
+        if provided != Some(expected.as_str()) {
+            return StatusCode::UNAUTHORIZED.into_response();
+        }
+    }
+
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.metrics.handle.render(),
+    )
+        .into_response()
+}
@@ -0,0 +1,262 @@
+// Fixtures shared by the unit tests.
+//
+// Tests that need Postgres are #[ignore]d and run against TEST_DATABASE_URL, a scratch
+// database the migrations are applied to:
+//   TEST_DATABASE_URL=postgres://localhost/w9_mail_test cargo test -- --include-ignored
+// Every fixture row gets a fresh id, so tests can share the database and run in parallel.
+// MockSmtp needs nothing external.
+
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use axum::{
+    body::Body,
+    extract::ConnectInfo,
+    http::{header, Method, Request, StatusCode},
+    Router,
+};
+use sha2::{Digest, Sha256};
+use sqlx::{postgres::PgPoolOptions, PgPool};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+};
+use tower::ServiceExt;
+
+use crate::{
+    email, inbox, mail_queue, rate_limit, secrets, send_limits, smtp_pool, telemetry, workers, AppState,
+    MicrosoftOAuthConfig,
+};
+
+pub async fn db() -> PgPool {
+    let url = std::env::var("TEST_DATABASE_URL")
+        .expect("TEST_DATABASE_URL must point at a scratch Postgres database");
+    let db = PgPoolOptions::new()
+        .max_connections(5)
+        .connect(&url)
+        .await
+        .expect("failed to connect to TEST_DATABASE_URL");
+    sqlx::migrate!().run(&db).await.expect("failed to run migrations");
+    db
+}
+
+pub async fn state() -> AppState {
+    // Nothing in the tests drains the system mail queue
+    let (mail_queue, _mail_rx) = mail_queue::MailQueue::new();
+    AppState {
+        db: db().await,
+        microsoft_oauth: MicrosoftOAuthConfig {
+            client_id: String::new(),
+            client_secret: String::new(),
+            client_value: None,
+            tenant_id: String::new(),
+            redirect_uri: String::new(),
+            scope: String::new(),
+        },
+        jwt_secret: "test-secret".to_string(),
+        jwt_ttl_hours: 1,
+        refresh_ttl_days: 1,
+        app_base_url: "https://mail.test".to_string(),
+        turnstile_secret: None,
+        trusted_proxies: rate_limit::TrustedProxies::default(),
+        send_limiter: send_limits::SendLimiter::from_env(),
+        send_quota: send_limits::DailyQuota::from_env().unwrap(),
+        smtp: email::SmtpConfig::from_env().unwrap(),
+        smtp_pool: smtp_pool::SmtpPool::from_env().unwrap(),
+        imap: inbox::ImapConfig::from_env().unwrap(),
+        unread_cache: inbox::UnreadCache::from_env().unwrap(),
+        max_attachment_bytes: 25 * 1024 * 1024,
+        max_recipients: 100,
+        secrets: secrets::SecretBox::from_env().unwrap(),
+        metrics: telemetry::Metrics::detached(),
+        mail_queue,
+        idempotency_ttl_secs: 3600,
+        workers: workers::Workers::default(),
+        web_version_ttl_secs: 3600,
+    }
+}
+
+fn new_id() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
+
+pub struct TestUser {
+    pub id: String,
+    // Raw API token for the Authorization header
+    pub token: String,
+}
+
+/// A user with an unscoped API token. `role` is admin, dev or user.
+pub async fn user(db: &PgPool, role: &str) -> TestUser {
+    let id = new_id();
+    let email = format!("{}@users.test", id);
+    sqlx::query("INSERT INTO users (id, email, password_hash, role) VALUES ($1, $2, 'unused', $3)")
+        .bind(&id)
+        .bind(&email)
+        .bind(role)
+        .execute(db)
+        .await
+        .unwrap();
+
+    let token = new_id();
+    let token_hash = format!("{:x}", Sha256::digest(token.as_bytes()));
+    sqlx::query("INSERT INTO api_tokens (id, user_id, token_hash, name) VALUES ($1, $2, $3, 'test')")
+        .bind(new_id())
+        .bind(&id)
+        .bind(&token_hash)
+        .execute(db)
+        .await
+        .unwrap();
+
+    TestUser { id, token }
+}
+
+pub struct TestAccount {
+    pub id: String,
+    pub email: String,
+}
+
+/// An active, private sender account with a plaintext password.
+pub async fn account(db: &PgPool, owner_id: Option<&str>) -> TestAccount {
+    let id = new_id();
+    let email = format!("{}@senders.test", id);
+    sqlx::query(
+        "INSERT INTO accounts (id, email, display_name, password, owner_id) VALUES ($1, $2, 'Test', 'secret', $3)",
+    )
+    .bind(&id)
+    .bind(&email)
+    .bind(owner_id)
+    .execute(db)
+    .await
+    .unwrap();
+    TestAccount { id, email }
+}
+
+/// Sends one authenticated JSON request through the router, as if from 127.0.0.1.
+pub async fn call(
+    app: Router,
+    method: Method,
+    uri: &str,
+    token: &str,
+    body: impl Into<Body>,
+) -> (StatusCode, serde_json::Value) {
+    let mut request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header(header::AUTHORIZATION, format!("Bearer {}", token))
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(body.into())
+        .unwrap();
+    // What into_make_service_with_connect_info provides in main
+    request
+        .extensions_mut()
+        .insert(ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 40000))));
+    let response = app.oneshot(request).await.unwrap();
+    let status = response.status();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let json = serde_json::from_slice(&bytes).unwrap_or(serde_json::Value::Null);
+    (status, json)
+}
+
+/// A scripted SMTP server on localhost, speaking just enough of the protocol for
+/// EmailService over a plain connection. A command starting with one of the scripted
+/// prefixes gets that reply; otherwise EHLO offers AUTH PLAIN LOGIN, AUTH succeeds, DATA
+/// takes the message and everything else gets a 250. `start_slow` holds each message
+/// for a while before accepting it.
+pub struct MockSmtp {
+    pub port: u16,
+    commands: Arc<Mutex<Vec<String>>>,
+}
+
+impl MockSmtp {
+    pub async fn start(script: &[(&str, &str)]) -> Self {
+        Self::start_slow(script, Duration::ZERO).await
+    }
+
+    pub async fn start_slow(script: &[(&str, &str)], data_delay: Duration) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let script: Arc<Vec<(String, String)>> = Arc::new(
+            script
+                .iter()
+                .map(|(prefix, reply)| (prefix.to_string(), reply.to_string()))
+                .collect(),
+        );
+        let commands = Arc::new(Mutex::new(Vec::new()));
+        let log = commands.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve_smtp(stream, script.clone(), log.clone(), data_delay));
+            }
+        });
+        Self { port, commands }
+    }
+
+    /// Every command received so far on any connection, without the message data.
+    pub fn commands(&self) -> Vec<String> {
+        self.commands.lock().unwrap().clone()
+    }
+
+    /// No TLS, one attempt per send, and a short timeout.
+    pub fn config(&self) -> email::SmtpConfig {
+        email::SmtpConfig {
+            host: "127.0.0.1".to_string(),
+            port: self.port,
+            tls: email::SmtpTlsMode::None,
+            max_attempts: 1,
+            timeout: Duration::from_secs(5),
+            dkim: None,
+        }
+    }
+}
+
+async fn serve_smtp(
+    stream: TcpStream,
+    script: Arc<Vec<(String, String)>>,
+    log: Arc<Mutex<Vec<String>>>,
+    data_delay: Duration,
+) {
+    let (read, mut write) = stream.into_split();
+    let mut lines = BufReader::new(read).lines();
+    if write.write_all(b"220 mock ESMTP\r\n").await.is_err() {
+        return;
+    }
+    while let Ok(Some(line)) = lines.next_line().await {
+        log.lock().unwrap().push(line.clone());
+        let scripted = script
+            .iter()
+            .find(|(prefix, _)| line.starts_with(prefix.as_str()))
+            .map(|(_, reply)| reply.clone());
+        let verb = line.split_whitespace().next().unwrap_or("").to_ascii_uppercase();
+        let reply = match (scripted, verb.as_str()) {
+            (Some(reply), _) => reply,
+            (None, "EHLO") => "250-mock\r\n250-AUTH PLAIN LOGIN\r\n250 8BITMIME".to_string(),
+            (None, "AUTH") => "235 2.7.0 Authentication successful".to_string(),
+            (None, "DATA") => {
+                if write.write_all(b"354 End data with <CR><LF>.<CR><LF>\r\n").await.is_err() {
+                    return;
+                }
+                loop {
+                    match lines.next_line().await {
+                        Ok(Some(data)) if data == "." => break,
+                        Ok(Some(_)) => {}
+                        _ => return,
+                    }
+                }
+                tokio::time::sleep(data_delay).await;
+                "250 2.0.0 Queued".to_string()
+            }
+            (None, "QUIT") => {
+                let _ = write.write_all(b"221 2.0.0 Bye\r\n").await;
+                return;
+            }
+            (None, _) => "250 2.0.0 OK".to_string(),
+        };
+        if write.write_all(format!("{}\r\n", reply).as_bytes()).await.is_err() {
+            return;
+        }
+    }
+}
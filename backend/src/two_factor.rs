@@ -0,0 +1,302 @@
+// TOTP two-factor authentication (RFC 6238: HMAC-SHA1, 6 digits, 30 second steps),
+// compatible with Google Authenticator, 1Password, Authy and friends.
+
+use axum::{extract::State, http::StatusCode, response::Json};
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+
+use crate::{auth::AuthUser, AppState};
+
+const STEP_SECS: i64 = 30;
+const DIGITS: u32 = 6;
+// Accept the neighbouring step either side to tolerate clock drift
+const SKEW_STEPS: i64 = 1;
+const SECRET_BYTES: usize = 20;
+const ISSUER: &str = "W9 Mail";
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+fn base32_encode(data: &[u8]) -> String {
+    let mut out = String::new();
+    for chunk in data.chunks(5) {
+        let mut buf = [0u8; 5];
+        buf[..chunk.len()].copy_from_slice(chunk);
+        let bits = u64::from_be_bytes([0, 0, 0, buf[0], buf[1], buf[2], buf[3], buf[4]]);
+        let chars = (chunk.len() * 8).div_ceil(5);
+        for i in 0..chars {
+            let index = (bits >> (35 - i * 5)) & 0x1f;
+            out.push(BASE32_ALPHABET[index as usize] as char);
+        }
+    }
+    out
+}
+
+fn base32_decode(input: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for c in input.chars().filter(|c| !c.is_whitespace() && *c != '=') {
+        let value = BASE32_ALPHABET
+            .iter()
+            .position(|&a| a as char == c.to_ascii_uppercase())? as u32;
+        buffer = (buffer << 5) | value;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    Some(out)
+}
+
+fn code_at(secret: &[u8], step: i64) -> u32 {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(&(step as u64).to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([hash[offset], hash[offset + 1], hash[offset + 2], hash[offset + 3]])
+        & 0x7fff_ffff;
+    binary % 10u32.pow(DIGITS)
+}
+
+/// Returns the matching time step, so callers can refuse a code that was already used.
+fn verify_code(secret_base32: &str, code: &str, now: i64) -> Option<i64> {
+    let code = code.trim().replace(' ', "");
+    if code.len() != DIGITS as usize || !code.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let expected: u32 = code.parse().ok()?;
+    let secret = base32_decode(secret_base32)?;
+    let current = now / STEP_SECS;
+    (current - SKEW_STEPS..=current + SKEW_STEPS).find(|&step| code_at(&secret, step) == expected)
+}
+
+fn otpauth_url(email: &str, secret: &str) -> String {
+    let label = format!("{}:{}", ISSUER, email);
+    let mut url = reqwest::Url::parse("otpauth://totp/").expect("static URL parses");
+    url.set_path(&label);
+    url.query_pairs_mut()
+        .append_pair("secret", secret)
+        .append_pair("issuer", ISSUER)
+        .append_pair("algorithm", "SHA1")
+        .append_pair("digits", &DIGITS.to_string())
+        .append_pair("period", &STEP_SECS.to_string());
+    url.to_string()
+}
+
+pub enum LoginCheck {
+    NotEnrolled,
+    Passed,
+    Missing,
+    Failed,
+}
+
+/// Second factor for `login`; only enabled enrolments are enforced.
+pub async fn check_login(state: &AppState, user_id: &str, code: Option<&str>) -> Result<LoginCheck, sqlx::Error> {
+    let secret: Option<String> = sqlx::query_scalar("SELECT secret FROM user_totp WHERE user_id = $1 AND enabled = TRUE")
+        .bind(user_id)
+        .fetch_optional(&state.db)
+        .await?;
+    let Some(secret) = secret else {
+        return Ok(LoginCheck::NotEnrolled);
+    };
+    let Some(code) = code.filter(|c| !c.trim().is_empty()) else {
+        return Ok(LoginCheck::Missing);
+    };
+
+    match verify_code(&secret, code, Utc::now().timestamp()) {
+        Some(step) if claim_step(state, user_id, step).await? => Ok(LoginCheck::Passed),
+        _ => Ok(LoginCheck::Failed),
+    }
+}
+
+// Records the step as used unless it (or a later one) already was, in one statement so
+// two logins racing with the same code can't both get through
+async fn claim_step(state: &AppState, user_id: &str, step: i64) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        "UPDATE user_totp SET last_used_step = $1 WHERE user_id = $2 AND (last_used_step IS NULL OR last_used_step < $1)",
+    )
+    .bind(step)
+    .bind(user_id)
+    .execute(&state.db)
+    .await?;
+    Ok(result.rows_affected() == 1)
+}
+
+#[derive(Serialize)]
+pub struct TwoFactorStatus {
+    pub enabled: bool,
+}
+
+#[derive(Serialize)]
+pub struct EnrollResponse {
+    pub secret: String,
+    #[serde(rename = "otpauthUrl")]
+    pub otpauth_url: String,
+}
+
+#[derive(Deserialize)]
+pub struct TwoFactorCodeRequest {
+    pub code: String,
+}
+
+pub async fn get_status(
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> Result<Json<TwoFactorStatus>, StatusCode> {
+    let enabled: Option<bool> = sqlx::query_scalar("SELECT enabled FROM user_totp WHERE user_id = $1")
+        .bind(&user.id)
+        .fetch_optional(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(TwoFactorStatus {
+        enabled: enabled.unwrap_or(false),
+    }))
+}
+
+// Starts (or restarts) enrolment; 2FA is only enforced once /verify confirms a code
+pub async fn enroll(
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> Result<Json<EnrollResponse>, StatusCode> {
+    user.ensure_full_access()?;
+
+    let mut bytes = [0u8; SECRET_BYTES];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let secret = base32_encode(&bytes);
+
+    let result = sqlx::query(
+        r#"
+        INSERT INTO user_totp (user_id, secret, enabled)
+        VALUES ($1, $2, FALSE)
+        ON CONFLICT (user_id) DO UPDATE SET secret = EXCLUDED.secret, last_used_step = NULL
+        WHERE user_totp.enabled = FALSE
+        "#,
+    )
+    .bind(&user.id)
+    .bind(&secret)
+    .execute(&state.db)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    // Re-enrolling over an active secret would silently lock out the old authenticator
+    if result.rows_affected() == 0 {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    Ok(Json(EnrollResponse {
+        otpauth_url: otpauth_url(&user.email, &secret),
+        secret,
+    }))
+}
+
+pub async fn verify_enrollment(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(payload): Json<TwoFactorCodeRequest>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    user.ensure_full_access()?;
+
+    let secret: String = sqlx::query_scalar("SELECT secret FROM user_totp WHERE user_id = $1 AND enabled = FALSE")
+        .bind(&user.id)
+        .fetch_optional(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::BAD_REQUEST)?;
+
+    let step = verify_code(&secret, &payload.code, Utc::now().timestamp()).ok_or(StatusCode::UNAUTHORIZED)?;
+
+    sqlx::query("UPDATE user_totp SET enabled = TRUE, last_used_step = $1, enabled_at = CURRENT_TIMESTAMP WHERE user_id = $2")
+        .bind(step)
+        .bind(&user.id)
+        .execute(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(serde_json::json!({
+        "status": "success",
+        "message": "Two-factor authentication enabled"
+    })))
+}
+
+// Turning 2FA off needs a current code, not just a (possibly stolen) session
+pub async fn disable(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(payload): Json<TwoFactorCodeRequest>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    user.ensure_full_access()?;
+
+    match check_login(&state, &user.id, Some(&payload.code))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    {
+        LoginCheck::Passed => {}
+        LoginCheck::NotEnrolled => return Err(StatusCode::NOT_FOUND),
+        LoginCheck::Missing | LoginCheck::Failed => return Err(StatusCode::UNAUTHORIZED),
+    }
+
+    sqlx::query("DELETE FROM user_totp WHERE user_id = $1")
+        .bind(&user.id)
+        .execute(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(serde_json::json!({
+        "status": "success",
+        "message": "Two-factor authentication disabled"
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    #[test]
+    fn base32_matches_rfc_4648() {
+        let vectors = [
+            ("", ""),
+            ("f", "MY"),
+            ("fo", "MZXQ"),
+            ("foo", "MZXW6"),
+            ("foob", "MZXW6YQ"),
+            ("fooba", "MZXW6YTB"),
+            ("foobar", "MZXW6YTBOI"),
+        ];
+        for (plain, encoded) in vectors {
+            assert_eq!(base32_encode(plain.as_bytes()), encoded);
+            assert_eq!(base32_decode(encoded).unwrap(), plain.as_bytes());
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn a_code_gets_one_login_even_when_raced() {
+        let state = test_support::state().await;
+        let user = test_support::user(&state.db, "user").await;
+        let secret = base32_encode(b"12345678901234567890");
+        sqlx::query("INSERT INTO user_totp (user_id, secret, enabled) VALUES ($1, $2, TRUE)")
+            .bind(&user.id)
+            .bind(&secret)
+            .execute(&state.db)
+            .await
+            .unwrap();
+        let code = format!("{:06}", code_at(b"12345678901234567890", Utc::now().timestamp() / STEP_SECS));
+
+        let (a, b) = tokio::join!(
+            check_login(&state, &user.id, Some(&code)),
+            check_login(&state, &user.id, Some(&code)),
+        );
+        let passed = [a.unwrap(), b.unwrap()]
+            .iter()
+            .filter(|check| matches!(check, LoginCheck::Passed))
+            .count();
+        assert_eq!(passed, 1);
+        assert!(matches!(check_login(&state, &user.id, Some(&code)).await.unwrap(), LoginCheck::Failed));
+    }
+}
@@ -0,0 +1,172 @@
+// List-Unsubscribe links for bulk sends and the suppression list they feed.
+// Tokens are JWTs signed with the app secret, so a link only ever unsubscribes
+// the address it was minted for.
+
+use std::collections::HashSet;
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{Html, IntoResponse, Response},
+};
+use chrono::Utc;
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+use crate::{email::html_escape, AppState};
+
+const TOKEN_PURPOSE: &str = "unsubscribe";
+
+// No expiry: an unsubscribe link in an old message should keep working
+#[derive(Serialize, Deserialize)]
+struct UnsubscribeClaims {
+    sub: String,
+    purpose: String,
+}
+
+fn normalize(address: &str) -> String {
+    address.trim().to_ascii_lowercase()
+}
+
+pub fn token_for(jwt_secret: &str, recipient: &str) -> anyhow::Result<String> {
+    let claims = UnsubscribeClaims {
+        sub: normalize(recipient),
+        purpose: TOKEN_PURPOSE.to_string(),
+    };
+    Ok(encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(jwt_secret.as_bytes()),
+    )?)
+}
+
+fn recipient_from_token(jwt_secret: &str, token: &str) -> Option<String> {
+    let mut validation = Validation::default();
+    validation.required_spec_claims.clear();
+    validation.validate_exp = false;
+    let claims = decode::<UnsubscribeClaims>(
+        token,
+        &DecodingKey::from_secret(jwt_secret.as_bytes()),
+        &validation,
+    )
+    .ok()?
+    .claims;
+    (claims.purpose == TOKEN_PURPOSE).then_some(claims.sub)
+}
+
+/// Value for the List-Unsubscribe header: a mailto back to the sender and the
+/// one-click https link (RFC 8058).
+pub fn list_unsubscribe_header(state: &AppState, sender: &str, recipient: &str) -> anyhow::Result<String> {
+    let url = reqwest::Url::parse_with_params(
+        &format!("{}/api/unsubscribe", state.app_base_url.trim_end_matches('/')),
+        &[("token", token_for(&state.jwt_secret, recipient)?)],
+    )?;
+    Ok(format!("<mailto:{}?subject=unsubscribe>, <{}>", sender.trim(), url))
+}
+
+/// The subset of `addresses` (lowercased) that have unsubscribed.
+pub async fn unsubscribed_among(db: &PgPool, addresses: &[String]) -> sqlx::Result<HashSet<String>> {
+    if addresses.is_empty() {
+        return Ok(HashSet::new());
+    }
+    let normalized: Vec<String> = addresses.iter().map(|a| normalize(a)).collect();
+    let rows: Vec<String> = sqlx::query_scalar("SELECT email FROM unsubscribes WHERE email = ANY($1)")
+        .bind(&normalized)
+        .fetch_all(db)
+        .await?;
+    Ok(rows.into_iter().collect())
+}
+
+#[derive(Deserialize)]
+pub struct UnsubscribeQuery {
+    pub token: Option<String>,
+}
+
+fn page(status: StatusCode, title: &str, body_html: &str) -> Response {
+    let html = format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head><meta charset="utf-8"><meta name="viewport" content="width=device-width, initial-scale=1"><title>{title}</title></head>
+<body style="font-family: sans-serif; max-width: 480px; margin: 64px auto; padding: 0 16px; color: #222;">
+<h1 style="font-size: 22px;">{title}</h1>
+{body_html}
+</body>
+</html>"#,
+        title = html_escape(title),
+        body_html = body_html
+    );
+    (status, Html(html)).into_response()
+}
+
+fn invalid_link() -> Response {
+    page(
+        StatusCode::BAD_REQUEST,
+        "Invalid unsubscribe link",
+        "<p>This link is incomplete or has been altered. Use the unsubscribe link from the email exactly as it was sent.</p>",
+    )
+}
+
+/// GET only asks for confirmation: link scanners and prefetchers follow GETs,
+/// and shouldn't be able to unsubscribe anyone.
+pub async fn confirm_unsubscribe(
+    State(state): State<AppState>,
+    Query(query): Query<UnsubscribeQuery>,
+) -> Response {
+    let Some(token) = query.token else {
+        return invalid_link();
+    };
+    let Some(recipient) = recipient_from_token(&state.jwt_secret, &token) else {
+        return invalid_link();
+    };
+    page(
+        StatusCode::OK,
+        "Unsubscribe",
+        &format!(
+            r#"<p>Stop sending email to <strong>{}</strong>?</p>
+<form method="post" action="?token={}"><button type="submit" style="padding: 8px 16px;">Unsubscribe</button></form>"#,
+            html_escape(&recipient),
+            html_escape(&token)
+        ),
+    )
+}
+
+/// Records the unsubscribe. Mail clients POST here directly for one-click
+/// unsubscribe (body `List-Unsubscribe=One-Click`), which needs no confirmation.
+pub async fn unsubscribe(
+    State(state): State<AppState>,
+    Query(query): Query<UnsubscribeQuery>,
+) -> Response {
+    let Some(token) = query.token else {
+        return invalid_link();
+    };
+    let Some(recipient) = recipient_from_token(&state.jwt_secret, &token) else {
+        return invalid_link();
+    };
+
+    if let Err(e) = sqlx::query(
+        "INSERT INTO unsubscribes (email, created_at) VALUES ($1, $2) ON CONFLICT (email) DO NOTHING",
+    )
+    .bind(&recipient)
+    .bind(Utc::now().timestamp())
+    .execute(&state.db)
+    .await
+    {
+        tracing::error!(error = %e, %recipient, "failed to record unsubscribe");
+        return page(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Something went wrong",
+            "<p>We couldn't record your request. Please try the link again in a few minutes.</p>",
+        );
+    }
+
+    tracing::info!(%recipient, "recipient unsubscribed");
+    page(
+        StatusCode::OK,
+        "You're unsubscribed",
+        &format!(
+            "<p><strong>{}</strong> won't receive these emails any more.</p>",
+            html_escape(&recipient)
+        ),
+    )
+}
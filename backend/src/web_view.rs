@@ -0,0 +1,242 @@
+// "View in browser" copies of sent mail (webVersion on /api/send). The link carries a
+// JWT signed with the app secret that names one sent message and expires with its
+// stored copy, so links can't be guessed, enumerated or pointed at another message.
+
+use axum::{
+    extract::{Path, State},
+    http::{header, HeaderName, StatusCode},
+    response::{Html, IntoResponse, Response},
+};
+use chrono::Utc;
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::{email::html_escape, AppState};
+
+const TOKEN_PURPOSE: &str = "web_view";
+
+#[derive(Serialize, Deserialize)]
+struct ViewClaims {
+    sub: String,
+    purpose: String,
+    exp: i64,
+}
+
+/// What record_sent_message stores for a webVersion send.
+pub struct WebCopy {
+    pub html: String,
+    // Unix seconds; the link stops working and the cleanup task drops the copy after this
+    pub expires_at: i64,
+}
+
+fn token_for(jwt_secret: &str, sent_message_id: &str, expires_at: i64) -> anyhow::Result<String> {
+    let claims = ViewClaims {
+        sub: sent_message_id.to_string(),
+        purpose: TOKEN_PURPOSE.to_string(),
+        exp: expires_at,
+    };
+    Ok(encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(jwt_secret.as_bytes()),
+    )?)
+}
+
+fn sent_message_from_token(jwt_secret: &str, token: &str) -> Option<String> {
+    let mut validation = Validation::default();
+    validation.leeway = 0;
+    let claims = decode::<ViewClaims>(token, &DecodingKey::from_secret(jwt_secret.as_bytes()), &validation)
+        .ok()?
+        .claims;
+    (claims.purpose == TOKEN_PURPOSE).then_some(claims.sub)
+}
+
+/// Adds a "View in browser" link to `html` for the sent message `sent_message_id` and
+/// returns the copy to store with it: the HTML without that link or tracking pixels.
+pub fn prepare(state: &AppState, sent_message_id: &str, html: &str) -> anyhow::Result<(String, WebCopy)> {
+    let expires_at = Utc::now().timestamp() + state.web_version_ttl_secs;
+    let url = format!(
+        "{}/api/view/{}",
+        state.app_base_url.trim_end_matches('/'),
+        token_for(&state.jwt_secret, sent_message_id, expires_at)?
+    );
+    let copy = WebCopy {
+        html: strip_tracking_pixels(html),
+        expires_at,
+    };
+    Ok((with_view_link(html, &url), copy))
+}
+
+// Right after <body>, or at the very top when the HTML has no body tag
+fn with_view_link(html: &str, url: &str) -> String {
+    let link = format!(
+        r#"<p style="margin: 0 0 12px; text-align: center; font-size: 12px;"><a href="{}" style="color: #666666;">View in browser</a></p>"#,
+        html_escape(url)
+    );
+    let body = Regex::new(r"(?i)<body\b[^>]*>").unwrap();
+    match body.find(html) {
+        Some(tag) => format!("{}{}{}", &html[..tag.end()], link, &html[tag.end()..]),
+        None => format!("{}{}", link, html),
+    }
+}
+
+// Images at most 1x1 pixel are open-tracking beacons; loading one from the browser copy
+// would count as the recipient opening the mail again
+fn strip_tracking_pixels(html: &str) -> String {
+    let images = Regex::new(r"(?is)<img\b[^>]*>").unwrap();
+    let size = Regex::new(r#"(?i)\b(width|height)\s*[=:]\s*["']?\s*(\d+)"#).unwrap();
+    images
+        .replace_all(html, |caps: &regex::Captures| {
+            let tag = &caps[0];
+            let (mut width, mut height) = (None, None);
+            for dim in size.captures_iter(tag) {
+                let value = dim[2].parse::<u32>().unwrap_or(u32::MAX);
+                if dim[1].eq_ignore_ascii_case("width") {
+                    width = Some(value);
+                } else {
+                    height = Some(value);
+                }
+            }
+            match (width, height) {
+                (Some(w), Some(h)) if w <= 1 && h <= 1 => String::new(),
+                _ => tag.to_string(),
+            }
+        })
+        .into_owned()
+}
+
+fn not_found() -> Response {
+    (
+        StatusCode::NOT_FOUND,
+        [(HeaderName::from_static("x-robots-tag"), "noindex, nofollow")],
+        Html("<!DOCTYPE html><html lang=\"en\"><head><meta charset=\"utf-8\"><title>Link expired</title></head><body style=\"font-family: sans-serif; max-width: 480px; margin: 64px auto; padding: 0 16px;\"><p>This link is invalid or has expired.</p></body></html>"),
+    )
+        .into_response()
+}
+
+/// A tampered, expired or pruned link is a plain 404 either way.
+pub async fn view_web_version(State(state): State<AppState>, Path(token): Path<String>) -> Response {
+    let Some(id) = sent_message_from_token(&state.jwt_secret, &token) else {
+        return not_found();
+    };
+    let html: Option<String> = match sqlx::query_scalar(
+        "SELECT web_html FROM sent_messages WHERE id = $1 AND web_expires_at > $2",
+    )
+    .bind(&id)
+    .bind(Utc::now().timestamp())
+    .fetch_optional(&state.db)
+    .await
+    {
+        Ok(html) => html.flatten(),
+        Err(e) => {
+            tracing::error!(error = %e, sent_message_id = %id, "failed to load web version");
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    let Some(html) = html else {
+        return not_found();
+    };
+    (
+        [
+            (HeaderName::from_static("x-robots-tag"), "noindex, nofollow"),
+            (header::REFERRER_POLICY, "no-referrer"),
+            (header::CACHE_CONTROL, "private, no-store"),
+            // The stored HTML may not have been sanitized (sanitizeHtml: false)
+            (header::CONTENT_SECURITY_POLICY, "script-src 'none'; object-src 'none'"),
+        ],
+        Html(html),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::Request};
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::test_support;
+
+    #[test]
+    fn the_link_goes_inside_the_body() {
+        let html = with_view_link("<html><BODY class=\"x\"><p>Hi</p></body></html>", "https://mail.test/api/view/t?a&b");
+        assert!(html.starts_with("<html><BODY class=\"x\"><p style="));
+        assert!(html.contains(r#"href="https://mail.test/api/view/t?a&amp;b""#));
+        assert!(with_view_link("<p>Hi</p>", "u").ends_with("View in browser</a></p><p>Hi</p>"));
+    }
+
+    #[test]
+    fn only_pixel_sized_images_are_stripped() {
+        let html = r#"<img src="logo.png" width="120" height="40"><img src="https://t.example/o.gif" width="1" height="1" alt=""><img style="width:0px;height:0px" src="b.gif"><img src="photo.jpg">"#;
+        assert_eq!(
+            strip_tracking_pixels(html),
+            r#"<img src="logo.png" width="120" height="40"><img src="photo.jpg">"#
+        );
+    }
+
+    #[test]
+    fn tokens_only_open_the_message_they_were_signed_for() {
+        let future = Utc::now().timestamp() + 60;
+        let token = token_for("secret", "message-1", future).unwrap();
+        assert_eq!(sent_message_from_token("secret", &token).as_deref(), Some("message-1"));
+        assert!(sent_message_from_token("other-secret", &token).is_none());
+
+        // Swap in a payload naming another message, keeping the original signature
+        let parts: Vec<&str> = token.split('.').collect();
+        let forged = token_for("attacker", "message-2", future).unwrap();
+        let forged_payload = forged.split('.').nth(1).unwrap();
+        assert!(sent_message_from_token("secret", &format!("{}.{}.{}", parts[0], forged_payload, parts[2])).is_none());
+
+        let expired = token_for("secret", "message-1", Utc::now().timestamp() - 1).unwrap();
+        assert!(sent_message_from_token("secret", &expired).is_none());
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn the_stored_copy_is_served_until_it_expires() {
+        let state = test_support::state().await;
+        let user = test_support::user(&state.db, "dev").await;
+        let id = uuid::Uuid::new_v4().to_string();
+        let expires_at = Utc::now().timestamp() + 60;
+        sqlx::query(
+            r#"
+            INSERT INTO sent_messages (id, user_id, from_email, to_recipients, subject, body, web_html, web_expires_at)
+            VALUES ($1, $2, 'sender@example.com', 'a@example.com', 'Hi', 'sent body', '<p>Hello</p>', $3)
+            "#,
+        )
+        .bind(&id)
+        .bind(&user.id)
+        .bind(expires_at)
+        .execute(&state.db)
+        .await
+        .unwrap();
+        let app = crate::router(state.clone(), 1024 * 1024);
+        let view = |token: String| {
+            let app = app.clone();
+            async move {
+                let request = Request::get(format!("/api/view/{}", token)).body(Body::empty()).unwrap();
+                let response = app.oneshot(request).await.unwrap();
+                let noindex = response.headers().get("x-robots-tag").cloned();
+                let status = response.status();
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+                (status, noindex, String::from_utf8(body.to_vec()).unwrap())
+            }
+        };
+
+        let (status, noindex, body) = view(token_for(&state.jwt_secret, &id, expires_at).unwrap()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(noindex.unwrap(), "noindex, nofollow");
+        assert_eq!(body, "<p>Hello</p>");
+
+        // The token is still valid, but the stored copy has passed its expiry
+        sqlx::query("UPDATE sent_messages SET web_expires_at = $1 WHERE id = $2")
+            .bind(Utc::now().timestamp() - 1)
+            .bind(&id)
+            .execute(&state.db)
+            .await
+            .unwrap();
+        let (status, _, _) = view(token_for(&state.jwt_secret, &id, expires_at).unwrap()).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+}
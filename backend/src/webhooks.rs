@@ -0,0 +1,334 @@
+// Outbound notifications about sends. Each registered URL gets a JSON POST per matching
+// event, signed with its secret:
+//   X-W9-Signature: sha256=<hex HMAC-SHA256 of the raw body>
+
+use std::time::Duration;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+};
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use sqlx::{postgres::PgRow, Row};
+use uuid::Uuid;
+
+use crate::{
+    audit,
+    auth::{AuthUser, UserRole, SCOPE_ADMIN},
+    error::ApiError,
+    AppState,
+};
+
+pub const EVENT_SENT: &str = "email.sent";
+pub const EVENT_FAILED: &str = "email.failed";
+const EVENTS: &[&str] = &[EVENT_SENT, EVENT_FAILED];
+
+const DELIVERY_ATTEMPTS: u32 = 3;
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+const SIGNATURE_HEADER: &str = "X-W9-Signature";
+
+#[derive(Serialize)]
+pub struct Webhook {
+    pub id: String,
+    pub url: String,
+    pub events: Vec<String>,
+    #[serde(rename = "createdAt")]
+    pub created_at: i64,
+}
+
+#[derive(Deserialize)]
+pub struct CreateWebhookRequest {
+    pub url: String,
+    pub events: Vec<String>,
+    // Generated when omitted; either way it's only shown in the create response
+    pub secret: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateWebhookRequest {
+    pub url: Option<String>,
+    pub events: Option<Vec<String>>,
+    pub secret: Option<String>,
+}
+
+/// What happened to one /api/send request that reached the SMTP server.
+pub struct SendEvent {
+    pub event: &'static str,
+    pub from: String,
+    pub to: String,
+    pub subject: String,
+    // sent, partial or failed
+    pub status: &'static str,
+}
+
+fn webhook_from_row(row: &PgRow) -> Webhook {
+    Webhook {
+        id: row.get(0),
+        url: row.get(1),
+        events: row.get(2),
+        created_at: row.get(3),
+    }
+}
+
+fn ensure_admin(user: &AuthUser) -> Result<(), ApiError> {
+    user.ensure_password_updated()?;
+    user.ensure_scope(SCOPE_ADMIN)?;
+    if !matches!(user.role, UserRole::Admin) {
+        return Err(StatusCode::FORBIDDEN.into());
+    }
+    Ok(())
+}
+
+fn validate_url(url: &str) -> Result<String, ApiError> {
+    let url = url.trim();
+    match reqwest::Url::parse(url) {
+        Ok(parsed) if matches!(parsed.scheme(), "http" | "https") => Ok(url.to_string()),
+        _ => Err(ApiError::invalid("url", "invalid_url", format!("{:?} is not an http(s) URL", url))),
+    }
+}
+
+// Deduplicated and sorted so the stored list is stable
+fn validate_events(events: Vec<String>) -> Result<Vec<String>, ApiError> {
+    let mut events: Vec<String> = events.into_iter().map(|e| e.trim().to_string()).collect();
+    events.sort();
+    events.dedup();
+    if events.is_empty() {
+        return Err(ApiError::invalid(
+            "events",
+            "events_required",
+            format!("At least one event is required: {}", EVENTS.join(", ")),
+        ));
+    }
+    if let Some(unknown) = events.iter().find(|e| !EVENTS.contains(&e.as_str())) {
+        return Err(ApiError::invalid(
+            "events",
+            "unknown_event",
+            format!("Unknown event {:?}; expected {}", unknown, EVENTS.join(", ")),
+        ));
+    }
+    Ok(events)
+}
+
+fn generate_secret() -> String {
+    let bytes: [u8; 32] = rand::thread_rng().gen();
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn signature(secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(body);
+    format!("sha256={:x}", mac.finalize().into_bytes())
+}
+
+pub async fn list_webhooks(
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> Result<Json<Vec<Webhook>>, ApiError> {
+    ensure_admin(&user)?;
+
+    let rows = sqlx::query("SELECT id, url, events, created_at FROM webhooks ORDER BY created_at, id")
+        .fetch_all(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(rows.iter().map(webhook_from_row).collect()))
+}
+
+pub async fn create_webhook(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(req): Json<CreateWebhookRequest>,
+) -> Result<Response, ApiError> {
+    ensure_admin(&user)?;
+
+    let url = validate_url(&req.url)?;
+    let events = validate_events(req.events)?;
+    let secret = req
+        .secret
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .unwrap_or_else(generate_secret);
+    let sealed = state.secrets.seal(&secret).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let row = sqlx::query(
+        r#"
+        INSERT INTO webhooks (id, url, secret, events, created_at)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING id, url, events, created_at
+        "#,
+    )
+    .bind(Uuid::new_v4().to_string())
+    .bind(&url)
+    .bind(&sealed)
+    .bind(&events)
+    .bind(Utc::now().timestamp())
+    .fetch_one(&state.db)
+    .await
+    .map_err(|e| {
+        tracing::error!(error = %e, user_id = %user.id, "failed to create webhook");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let webhook = webhook_from_row(&row);
+
+    audit::record(&state.db, &user, "webhook.create", "webhook", &webhook.id, serde_json::json!({ "url": url, "events": events })).await;
+    Ok((
+        StatusCode::CREATED,
+        Json(serde_json::json!({ "webhook": webhook, "secret": secret })),
+    )
+        .into_response())
+}
+
+pub async fn update_webhook(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+    Json(req): Json<UpdateWebhookRequest>,
+) -> Result<Json<Webhook>, ApiError> {
+    ensure_admin(&user)?;
+
+    if req.url.is_none() && req.events.is_none() && req.secret.is_none() {
+        return Err(ApiError::new(StatusCode::BAD_REQUEST, "nothing_to_update", "No webhook fields were provided"));
+    }
+    let url = req.url.as_deref().map(validate_url).transpose()?;
+    let events = req.events.map(validate_events).transpose()?;
+    let secret = req.secret.map(|s| s.trim().to_string());
+    if secret.as_deref() == Some("") {
+        return Err(ApiError::invalid("secret", "invalid_secret", "Secret can't be empty"));
+    }
+    let sealed = secret
+        .as_deref()
+        .map(|s| state.secrets.seal(s))
+        .transpose()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let row = sqlx::query(
+        r#"
+        UPDATE webhooks SET
+            url = COALESCE($2, url),
+            events = COALESCE($3, events),
+            secret = COALESCE($4, secret)
+        WHERE id = $1
+        RETURNING id, url, events, created_at
+        "#,
+    )
+    .bind(&id)
+    .bind(&url)
+    .bind(&events)
+    .bind(&sealed)
+    .fetch_optional(&state.db)
+    .await
+    .map_err(|e| {
+        tracing::error!(error = %e, user_id = %user.id, webhook_id = %id, "failed to update webhook");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?
+    .ok_or(StatusCode::NOT_FOUND)?;
+
+    let detail = serde_json::json!({ "url": url, "events": events, "secretChanged": sealed.is_some() });
+    audit::record(&state.db, &user, "webhook.update", "webhook", &id, detail).await;
+    Ok(Json(webhook_from_row(&row)))
+}
+
+pub async fn delete_webhook(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    ensure_admin(&user)?;
+
+    let result = sqlx::query("DELETE FROM webhooks WHERE id = $1")
+        .bind(&id)
+        .execute(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if result.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND.into());
+    }
+
+    audit::record(&state.db, &user, "webhook.delete", "webhook", &id, serde_json::json!({})).await;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Queues delivery of `event` to every webhook subscribed to it and returns at once.
+/// Failures are logged; the send itself is already settled.
+pub fn notify(state: &AppState, event: SendEvent) {
+    let state = state.clone();
+    tokio::spawn(async move {
+        let rows = match sqlx::query("SELECT id, url, secret FROM webhooks WHERE $1 = ANY(events)")
+            .bind(event.event)
+            .fetch_all(&state.db)
+            .await
+        {
+            Ok(rows) => rows,
+            Err(e) => {
+                tracing::error!(error = %e, event = event.event, "failed to load webhooks");
+                return;
+            }
+        };
+        if rows.is_empty() {
+            return;
+        }
+
+        let body = serde_json::json!({
+            "event": event.event,
+            "from": event.from,
+            "to": event.to,
+            "subject": event.subject,
+            "status": event.status,
+            "timestamp": Utc::now().timestamp(),
+        })
+        .to_string();
+        let client = match reqwest::Client::builder().timeout(DELIVERY_TIMEOUT).build() {
+            Ok(client) => client,
+            Err(e) => {
+                tracing::error!(error = %e, "failed to build webhook client");
+                return;
+            }
+        };
+
+        for row in rows {
+            let id: String = row.get(0);
+            let url: String = row.get(1);
+            let secret = match state.secrets.open(&row.get::<String, _>(2)) {
+                Ok(secret) => secret,
+                Err(e) => {
+                    tracing::error!(error = %e, webhook_id = %id, "failed to decrypt webhook secret");
+                    continue;
+                }
+            };
+            let client = client.clone();
+            let body = body.clone();
+            let event = event.event;
+            // Each endpoint retries on its own schedule, so one slow relay doesn't hold up the rest
+            tokio::spawn(async move { deliver(&client, &id, &url, &secret, event, body).await });
+        }
+    });
+}
+
+// Retries on network errors and non-2xx responses, backing off 1s then 2s
+async fn deliver(client: &reqwest::Client, id: &str, url: &str, secret: &str, event: &str, body: String) {
+    let signature = signature(secret, body.as_bytes());
+    for attempt in 1..=DELIVERY_ATTEMPTS {
+        let result = client
+            .post(url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header("X-W9-Event", event)
+            .header(SIGNATURE_HEADER, &signature)
+            .body(body.clone())
+            .send()
+            .await;
+        let error = match result {
+            Ok(response) if response.status().is_success() => return,
+            Ok(response) => format!("HTTP {}", response.status()),
+            Err(e) => e.to_string(),
+        };
+        if attempt == DELIVERY_ATTEMPTS {
+            tracing::warn!(webhook_id = %id, %event, %error, attempts = attempt, "webhook delivery failed");
+            return;
+        }
+        tokio::time::sleep(Duration::from_secs(1 << (attempt - 1))).await;
+    }
+}
@@ -0,0 +1,302 @@
+// Heartbeats from the long-running background tasks (system mail queue, token cleanup),
+// so one that died or hung shows up from outside: GET /api/admin/workers lists them, and
+// with HEALTH_REQUIRE_WORKERS set /health/ready fails while a critical one is stale.
+
+use std::{
+    any::Any,
+    collections::BTreeMap,
+    fmt::Display,
+    future::Future,
+    panic::AssertUnwindSafe,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{extract::State, http::StatusCode, response::Json};
+use chrono::Utc;
+use futures::FutureExt;
+use serde::Serialize;
+use tokio::task::JoinHandle;
+
+use crate::{
+    auth::{env_flag, AuthUser, UserRole, SCOPE_ADMIN},
+    AppState,
+};
+
+// Delay before restarting a panicked task; doubles on each panic up to the cap
+const RESTART_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_RESTART_BACKOFF: Duration = Duration::from_secs(300);
+
+struct Entry {
+    stale_after: Duration,
+    critical: bool,
+    started_at: i64,
+    last_tick: i64,
+    last_error: Option<String>,
+    last_error_at: Option<i64>,
+    items_processed: u64,
+    restarts: u64,
+}
+
+#[derive(Serialize)]
+pub struct WorkerStatus {
+    pub name: &'static str,
+    pub critical: bool,
+    // Unix seconds
+    #[serde(rename = "startedAt")]
+    pub started_at: i64,
+    #[serde(rename = "lastTick")]
+    pub last_tick: i64,
+    #[serde(rename = "lastError")]
+    pub last_error: Option<String>,
+    #[serde(rename = "lastErrorAt")]
+    pub last_error_at: Option<i64>,
+    // Since the process started, across restarts
+    #[serde(rename = "itemsProcessed")]
+    pub items_processed: u64,
+    pub restarts: u64,
+    #[serde(rename = "staleAfterSecs")]
+    pub stale_after_secs: u64,
+    // No heartbeat for longer than staleAfterSecs
+    pub stale: bool,
+}
+
+#[derive(Clone, Default)]
+pub struct Workers {
+    entries: Arc<Mutex<BTreeMap<&'static str, Entry>>>,
+    // HEALTH_REQUIRE_WORKERS
+    required_for_ready: bool,
+}
+
+impl Workers {
+    pub fn from_env() -> Self {
+        Self {
+            entries: Arc::default(),
+            required_for_ready: env_flag("HEALTH_REQUIRE_WORKERS"),
+        }
+    }
+
+    /// Adds a worker that reports in at least every `stale_after` while it is healthy.
+    /// Critical workers are the ones readiness can depend on.
+    pub fn register(&self, name: &'static str, stale_after: Duration, critical: bool) -> Heartbeat {
+        let now = Utc::now().timestamp();
+        self.entries.lock().unwrap().insert(
+            name,
+            Entry {
+                stale_after,
+                critical,
+                started_at: now,
+                last_tick: now,
+                last_error: None,
+                last_error_at: None,
+                items_processed: 0,
+                restarts: 0,
+            },
+        );
+        Heartbeat {
+            name,
+            entries: self.entries.clone(),
+        }
+    }
+
+    pub fn statuses(&self) -> Vec<WorkerStatus> {
+        let now = Utc::now().timestamp();
+        self.entries
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, entry)| WorkerStatus {
+                name,
+                critical: entry.critical,
+                started_at: entry.started_at,
+                last_tick: entry.last_tick,
+                last_error: entry.last_error.clone(),
+                last_error_at: entry.last_error_at,
+                items_processed: entry.items_processed,
+                restarts: entry.restarts,
+                stale_after_secs: entry.stale_after.as_secs(),
+                stale: now - entry.last_tick > entry.stale_after.as_secs() as i64,
+            })
+            .collect()
+    }
+
+    /// Stale critical workers that should fail readiness; always empty unless
+    /// HEALTH_REQUIRE_WORKERS is set.
+    pub fn blocking_readiness(&self) -> Vec<&'static str> {
+        if !self.required_for_ready {
+            return Vec::new();
+        }
+        self.statuses()
+            .into_iter()
+            .filter(|status| status.critical && status.stale)
+            .map(|status| status.name)
+            .collect()
+    }
+
+    #[cfg(test)]
+    pub fn required_for_ready() -> Self {
+        Self {
+            entries: Arc::default(),
+            required_for_ready: true,
+        }
+    }
+}
+
+/// A worker's handle on its registry entry. Every call counts as a heartbeat.
+#[derive(Clone)]
+pub struct Heartbeat {
+    name: &'static str,
+    entries: Arc<Mutex<BTreeMap<&'static str, Entry>>>,
+}
+
+impl Heartbeat {
+    pub fn tick(&self) {
+        self.update(|_| {});
+    }
+
+    pub fn processed(&self, items: u64) {
+        self.update(|entry| entry.items_processed += items);
+    }
+
+    pub fn failed(&self, error: impl Display) {
+        let error = error.to_string();
+        self.update(|entry| {
+            entry.last_error = Some(error);
+            entry.last_error_at = Some(entry.last_tick);
+        });
+    }
+
+    fn restarted(&self, panic: &str) {
+        self.failed(format!("panicked: {}", panic));
+        self.update(|entry| entry.restarts += 1);
+    }
+
+    fn update(&self, change: impl FnOnce(&mut Entry)) {
+        if let Some(entry) = self.entries.lock().unwrap().get_mut(self.name) {
+            entry.last_tick = Utc::now().timestamp();
+            change(entry);
+        }
+    }
+}
+
+/// Runs the task `run` builds, and builds a fresh one with increasing delays whenever it
+/// panics; the panic is recorded on the heartbeat. The handle resolves once a run returns.
+pub fn supervise<F, Fut>(heartbeat: Heartbeat, run: F) -> JoinHandle<()>
+where
+    F: Fn(Heartbeat) -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    tokio::spawn(async move {
+        let mut backoff = RESTART_BACKOFF;
+        loop {
+            let started = Instant::now();
+            let Err(panic) = AssertUnwindSafe(run(heartbeat.clone())).catch_unwind().await else {
+                return;
+            };
+            let message = panic_message(panic.as_ref());
+            // One that had been running fine for a while starts over from the shortest delay
+            if started.elapsed() > MAX_RESTART_BACKOFF {
+                backoff = RESTART_BACKOFF;
+            }
+            tracing::error!(worker = heartbeat.name, panic = %message, "background task panicked; restarting in {:?}", backoff);
+            heartbeat.restarted(&message);
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(MAX_RESTART_BACKOFF);
+        }
+    })
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> String {
+    panic
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| panic.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string())
+}
+
+pub async fn list_workers(
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> Result<Json<Vec<WorkerStatus>>, StatusCode> {
+    user.ensure_password_updated()?;
+    user.ensure_scope(SCOPE_ADMIN)?;
+    if !matches!(user.role, UserRole::Admin) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    Ok(Json(state.workers.statuses()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use axum::http::{Method, StatusCode};
+
+    use super::*;
+    use crate::test_support;
+
+    #[tokio::test(start_paused = true)]
+    async fn a_panicking_task_is_recorded_and_restarted() {
+        let workers = Workers::default();
+        let heartbeat = workers.register("flaky", Duration::from_secs(60), true);
+        let runs = Arc::new(AtomicU32::new(0));
+        let handle = supervise(heartbeat, {
+            let runs = runs.clone();
+            move |heartbeat| {
+                let runs = runs.clone();
+                async move {
+                    heartbeat.processed(1);
+                    if runs.fetch_add(1, Ordering::SeqCst) < 2 {
+                        panic!("boom");
+                    }
+                }
+            }
+        });
+
+        handle.await.unwrap();
+        assert_eq!(runs.load(Ordering::SeqCst), 3);
+        let status = &workers.statuses()[0];
+        assert_eq!(status.restarts, 2);
+        assert_eq!(status.items_processed, 3);
+        assert_eq!(status.last_error.as_deref(), Some("panicked: boom"));
+    }
+
+    #[test]
+    fn only_stale_critical_workers_block_readiness() {
+        let workers = Workers::required_for_ready();
+        workers.register("mail", Duration::from_secs(60), true);
+        workers.register("cleanup", Duration::from_secs(60), false);
+        assert!(workers.blocking_readiness().is_empty());
+
+        for entry in workers.entries.lock().unwrap().values_mut() {
+            entry.last_tick -= 120;
+        }
+        assert_eq!(workers.blocking_readiness(), ["mail"]);
+        assert!(Workers::default().blocking_readiness().is_empty());
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn readiness_fails_while_a_critical_worker_is_stale() {
+        let mut state = test_support::state().await;
+        state.workers = Workers::required_for_ready();
+        let heartbeat = state.workers.register("system_mail", Duration::from_secs(60), true);
+        let admin = test_support::user(&state.db, "admin").await;
+        let app = crate::router(state.clone(), 1024 * 1024);
+
+        let (status, _) = test_support::call(app.clone(), Method::GET, "/health/ready", "", "").await;
+        assert_eq!(status, StatusCode::OK);
+
+        state.workers.entries.lock().unwrap().get_mut("system_mail").unwrap().last_tick -= 120;
+        let (status, _) = test_support::call(app.clone(), Method::GET, "/health/ready", "", "").await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        let (status, json) = test_support::call(app.clone(), Method::GET, "/api/admin/workers", &admin.token, "").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(json[0]["name"], "system_mail");
+        assert_eq!(json[0]["stale"], true);
+
+        heartbeat.tick();
+        let (status, _) = test_support::call(app, Method::GET, "/health/ready", "", "").await;
+        assert_eq!(status, StatusCode::OK);
+    }
+}
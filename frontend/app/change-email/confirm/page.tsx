@@ -0,0 +1,96 @@
+'use client'
+
+import { Suspense, useEffect, useState } from 'react'
+import Link from 'next/link'
+import { useSearchParams } from 'next/navigation'
+
+type ViewState = 'idle' | 'loading' | 'success' | 'error'
+
+function ChangeEmailConfirmContent() {
+  const searchParams = useSearchParams()
+  const token = searchParams.get('token') || ''
+  const [state, setState] = useState<ViewState>('idle')
+  const [message, setMessage] = useState('Paste the confirmation link from your inbox.')
+
+  useEffect(() => {
+    if (!token) {
+      setState('error')
+      setMessage('Missing confirmation token.')
+      return
+    }
+
+    const confirm = async () => {
+      setState('loading')
+      try {
+        const apiUrl = process.env.NEXT_PUBLIC_API_URL || '/api'
+        const response = await fetch(`${apiUrl}/auth/change-email/confirm`, {
+          method: 'POST',
+          headers: { 'Content-Type': 'application/json' },
+          body: JSON.stringify({ token })
+        })
+        const data = await response.json().catch(() => ({ message: 'Confirmation failed' }))
+        if (response.ok && data.status === 'success') {
+          setState('success')
+          setMessage(data.message || 'Email updated.')
+        } else {
+          setState('error')
+          setMessage(data.message || 'Confirmation failed.')
+        }
+      } catch (error) {
+        console.error('Email change confirmation error:', error)
+        setState('error')
+        setMessage('Network error. Try again.')
+      }
+    }
+
+    confirm()
+  }, [token])
+
+  return (
+    <section className="box">
+      <h2 className="section-title">Status</h2>
+      <p className={`status ${state === 'error' ? 'error' : state === 'success' ? 'success' : 'warning'}`}>{message}</p>
+      <div className="actions">
+        <Link className="button" href="/login">
+          Go to login
+        </Link>
+        <Link className="button ghost" href="/profile">
+          Profile
+        </Link>
+      </div>
+    </section>
+  )
+}
+
+export default function ChangeEmailConfirmPage() {
+  return (
+    <main className="app">
+      <header className="header">
+        <h1>W9 Mail / Confirm email</h1>
+        <p>Switch your sign-in email to the new address.</p>
+      </header>
+
+      <nav className="nav">
+        <Link className="nav-link" href="/">
+          Composer
+        </Link>
+        <Link className="nav-link" href="/manage">
+          Manage
+        </Link>
+        <Link className="nav-link" href="/docs">
+          Docs
+        </Link>
+        <Link className="nav-link" href="/profile">
+          Profile
+        </Link>
+        <Link className="nav-link" href="/login">
+          Login
+        </Link>
+      </nav>
+
+      <Suspense fallback={<section className="box"><p>Loading…</p></section>}>
+        <ChangeEmailConfirmContent />
+      </Suspense>
+    </main>
+  )
+}
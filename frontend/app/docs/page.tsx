@@ -35,6 +35,17 @@ export default function DocsPage() {
             <p>Replace host with your deployed domain (e.g., https://w9.nu/api).</p>
           </section>
 
+          <section className="box">
+            <h2 className="section-title">Errors</h2>
+            <p>Errors come back as JSON with a readable <code>message</code>, a machine-readable <code>code</code>, and the offending <code>field</code> when there is one. Branch on <code>code</code>, not the message.</p>
+            <pre>{`{
+  "status": "error",
+  "code": "invalid_expiry",
+  "message": "expiresInDays must be between 1 and 3650",
+  "field": "expiresInDays"
+}`}</pre>
+          </section>
+
           <section className="box">
             <h2 className="section-title">Endpoints</h2>
 
@@ -44,14 +55,65 @@ export default function DocsPage() {
               <pre>{`REQUEST:
 {
   "email": "user@domain.com",
-  "password": "string"
+  "password": "string",
+  "totpCode": "123456"   // only when two-factor is enabled
 }
 
 RESPONSE:
 {
   "token": "jwt",
+  "refreshToken": "opaque-string",
   "role": "user|dev|admin",
   "mustChangePassword": false
+}
+
+401 WHEN A CODE IS MISSING OR WRONG:
+{
+  "status": "error",
+  "message": "Two-factor code required",
+  "totpRequired": true
+}
+
+429 AFTER TOO MANY FAILED ATTEMPTS (with Retry-After header):
+{
+  "status": "error",
+  "message": "Too many failed sign-in attempts. Try again in 15 minutes."
+}`}</pre>
+            </article>
+
+            <article>
+              <h3>POST /api/auth/refresh · POST /api/auth/logout</h3>
+              <p>
+                Refresh exchanges a refresh token for a new JWT and a new refresh token; each refresh token works
+                once. Logout revokes it. Neither needs an Authorization header.
+              </p>
+              <pre>{`BODY:
+{
+  "refreshToken": "opaque-string"
+}
+
+REFRESH RESPONSE: same shape as login. Unknown, used or expired tokens get 401.
+LOGOUT RESPONSE: 204 No Content`}</pre>
+            </article>
+
+            <article>
+              <h3>POST /api/auth/2fa/enroll · POST /api/auth/2fa/verify · DELETE /api/auth/2fa</h3>
+              <p>
+                Enroll returns a TOTP secret and otpauth URL; verify confirms it with a code and turns two-factor on.
+                DELETE turns it off and also needs a current code. GET /api/auth/2fa reports whether it is enabled.
+              </p>
+              <pre>{`HEADERS:
+Authorization: Bearer &lt;jwt&gt;
+
+ENROLL RESPONSE:
+{
+  "secret": "BASE32SECRET",
+  "otpauthUrl": "otpauth://totp/W9%20Mail:user@domain.com?secret=..."
+}
+
+VERIFY / DELETE BODY:
+{
+  "code": "123456"
 }`}</pre>
             </article>
 
@@ -68,6 +130,21 @@ BODY:
 }`}</pre>
             </article>
 
+            <article>
+              <h3>POST /api/auth/change-email · POST /api/auth/change-email/confirm</h3>
+              <p>Changes the caller&apos;s sign-in email after the current password is checked. A confirmation link goes to the new address through the default sender; the old email keeps working until the token from that link is confirmed. Taken addresses return 409 <code>email_taken</code>. Links expire after 30 minutes.</p>
+              <pre>{`REQUEST:
+{
+  "newEmail": "new@domain.com",
+  "currentPassword": "string"
+}
+
+CONFIRM (no auth):
+{
+  "token": "from-the-link"
+}`}</pre>
+            </article>
+
             <article>
               <h3>POST /api/auth/signup</h3>
               <p>Register a normal user and trigger the verification email.</p>
@@ -99,6 +176,21 @@ RESPONSE:
 }`}</pre>
             </article>
 
+            <article>
+              <h3>POST /api/auth/signup/resend</h3>
+              <p>Send a fresh verification link for a signup that hasn&apos;t expired yet. The old link stops working and the new one is good for 30 minutes. At most one email per minute per address. The response is the same whether or not a signup is pending.</p>
+              <pre>{`REQUEST:
+{
+  "email": "user@domain.com"
+}
+
+RESPONSE:
+{
+  "status": "pending",
+  "message": "If a signup is waiting for verification, a new link was sent."
+}`}</pre>
+            </article>
+
             <article>
               <h3>POST /api/auth/password-reset</h3>
               <p>Send a reset link via the default sender. Hides whether the email exists.</p>
@@ -132,19 +224,35 @@ RESPONSE:
 
             <article>
               <h3>GET /api/accounts</h3>
-              <p>List all sender profiles (auth required). Normal users only see status, admins can mutate.</p>
+              <p>List sender profiles (auth required), ordered by email. Normal users only see status, admins can mutate. Paginated with <code>limit</code> (default 100, max 200) and <code>offset</code>; <code>search</code> matches email or display name, <code>active</code> filters on status; <code>sort</code> is <code>email</code> (default) or <code>created</code> (newest first, by <code>createdAt</code>). <code>lastSendAt</code> and <code>lastSendError</code> record the latest send through the account; a non-null error means it failed.</p>
               <pre>{`HEADERS:
 Authorization: Bearer &lt;jwt&gt;
 
+QUERY (optional):
+?limit=100&offset=0&search=ops&active=true&sort=created
+
 RESPONSE:
-[
-  {
-    "id": "uuid",
-    "email": "ops@domain.com",
-    "displayName": "Ops Bot",
-    "isActive": true
-  }
-]`}</pre>
+{
+  "items": [
+    {
+      "id": "uuid",
+      "email": "ops@domain.com",
+      "displayName": "Ops Bot",
+      "isActive": true,
+      "lastSendAt": 1767225600,
+      "lastSendError": null,
+      "createdAt": 1764547200
+    }
+  ],
+  "total": 1,
+  "limit": 100,
+  "offset": 0
+}`}</pre>
+            </article>
+
+            <article>
+              <h3>GET /api/accounts/:id</h3>
+              <p>One sender profile, shaped like a list item. Non-admins only see their own and public accounts; anything else, like an unknown id, returns 404. Admins also see soft-deleted accounts.</p>
             </article>
 
             <article>
@@ -157,24 +265,131 @@ REQUEST:
 {
   "email": "string",
   "displayName": "string",
+  "senderName": "string?",
   "password": "string",
   "isActive": boolean
 }`}</pre>
+              <p><code>displayName</code> labels the account in the UI. Mail sent as the account uses <code>senderName</code> in <code>From</code> when it is set, and <code>displayName</code> otherwise.</p>
             </article>
 
             <article>
               <h3>PATCH /api/accounts/:id</h3>
-              <p>Toggle activation and/or rotate password.</p>
+              <p>Toggle activation and/or rotate password. Admins can also set <code>dailySendLimit</code>, the account&apos;s sends per UTC day; <code>0</code> falls back to the server default. <code>senderName</code> changes the <code>From</code> name without touching the label; an empty string clears it. Admins can set <code>brandingEnabled: false</code> to drop the W9 Mail header and sign-off from the template for this account and its aliases.</p>
               <pre>{`HEADERS:
 Authorization: Bearer &lt;admin jwt&gt;
 
 REQUEST:
 {
   "isActive": boolean?,
-  "password": "string?"
+  "password": "string?",
+  "senderName": "string?",
+  "brandingEnabled": boolean?,
+  "dailySendLimit": number?
+}`}</pre>
+            </article>
+
+            <article>
+              <h3>POST /api/accounts/:id/test</h3>
+              <p>Connect to the SMTP server and sign in with the account's stored credentials, then disconnect without sending. <code>kind</code> is <code>connection</code>, <code>tls</code>, <code>auth</code> or <code>timeout</code>.</p>
+              <pre>{`HEADERS:
+Authorization: Bearer &lt;owner or admin jwt&gt;
+
+RESPONSE:
+{ "ok": true }
+
+{
+  "ok": false,
+  "error": { "kind": "auth", "message": "permanent error (535): 5.7.3 Authentication unsuccessful" }
 }`}</pre>
             </article>
 
+            <article>
+              <h3>DELETE /api/accounts/:id · POST /api/accounts/:id/restore</h3>
+              <p>Delete is a soft delete: the account and its aliases are hidden and can't send, but history is kept. Admins list deleted accounts with <code>GET /api/accounts?includeDeleted=true</code> and undelete with <code>restore</code>, which returns the account.</p>
+            </article>
+
+            <article>
+              <h3>POST /api/accounts/:id/activate · /deactivate · POST /api/aliases/:id/activate · /deactivate</h3>
+              <p>Admin-only shortcuts that set <code>isActive</code> without touching any other field. They return the updated account or alias with <code>defaultSenderCleared</code>, which is true when deactivating it also unset the default sender. For an account, that happens if the default was the account or one of its aliases.</p>
+              <pre>{`RESPONSE:
+{
+  "id": "uuid",
+  "email": "ops@domain.com",
+  "isActive": false,
+  ...,
+  "defaultSenderCleared": true
+}`}</pre>
+            </article>
+
+            <article>
+              <h3>GET · POST /api/accounts/:id/users · DELETE /api/accounts/:id/users/:userId</h3>
+              <p>Owner or admin. Lists, grants or revokes other users' permission to send from the account and its aliases. <code>POST /api/send</code> returns 403 when a non-admin uses a <code>from</code> address they don't own, haven't been granted, and that isn't public.</p>
+              <pre>{`REQUEST (POST):
+{ "userId": "uuid" }
+
+RESPONSE (GET):
+[{ "userId": "uuid", "email": "dev@domain.com", "grantedAt": 1700000000 }]`}</pre>
+            </article>
+
+            <article>
+              <h3>GET /api/aliases</h3>
+              <p>Paged like <code>GET /api/accounts</code>. Filter with <code>active</code> and <code>accountId</code>; <code>sort</code> is <code>email</code> (default) or <code>created</code> (newest first).</p>
+              <pre>{`QUERY (optional):
+?limit=100&offset=0&active=true&accountId=uuid&sort=created`}</pre>
+            </article>
+
+            <article>
+              <h3>GET /api/aliases/:id</h3>
+              <p>One alias with its account&apos;s email, name and status, shaped like a <code>GET /api/aliases</code> item. Non-admins only see their own and public aliases. Hidden aliases, aliases of deleted accounts and unknown ids return 404.</p>
+            </article>
+
+            <article>
+              <h3>POST /api/aliases/bulk</h3>
+              <p>Create up to 100 aliases in one request. Each alias is checked like <code>POST /api/aliases</code>; a failing item (duplicate, unknown account, bad address) is skipped and reported while the rest are created.</p>
+              <pre>{`HEADERS:
+Authorization: Bearer &lt;admin or dev jwt&gt;
+
+REQUEST:
+[
+  { "accountId": "uuid", "aliasEmail": "sales@domain.com", "displayName": "Sales", "isActive": true },
+  { "accountId": "uuid", "aliasEmail": "billing@domain.com", "isActive": true }
+]
+
+RESPONSE:
+[
+  { "index": 0, "status": "created", "alias": { "id": "uuid", "aliasEmail": "sales@domain.com", ... } },
+  { "index": 1, "status": "error", "message": "Alias email already exists" }
+]`}</pre>
+            </article>
+
+            <article>
+              <h3>GET /api/accounts/export · GET /api/aliases/export</h3>
+              <p>Admin-only CSV download (<code>accounts.csv</code>, <code>aliases.csv</code>) of every account or alias that isn&apos;t deleted, streamed row by row. Alias rows carry their account&apos;s email, name and status. Cells starting with <code>=</code>, <code>+</code>, <code>-</code> or <code>@</code> are prefixed with <code>&apos;</code> so spreadsheets don&apos;t run them as formulas.</p>
+              <pre>{`accounts.csv:
+id,email,displayName,senderName,isActive,isPublic,ownerEmail,dailySendLimit,brandingEnabled,createdAt
+
+aliases.csv:
+id,aliasEmail,displayName,isActive,isPublic,isCatchall,ownerEmail,accountEmail,accountDisplayName,accountIsActive,createdAt`}</pre>
+            </article>
+
+            <article>
+              <h3>POST /api/accounts/import</h3>
+              <p>Admin-only bulk onboarding of up to 100 accounts. Send a JSON array of <code>POST /api/accounts</code> bodies, or CSV with a header row naming <code>email</code>, <code>displayName</code>, <code>password</code> and optionally <code>isActive</code>, <code>isPublic</code>, <code>senderName</code>. Existing emails are skipped and bad rows are reported without stopping the rest.</p>
+              <pre>{`HEADERS:
+Authorization: Bearer &lt;admin jwt&gt;
+Content-Type: text/csv
+
+REQUEST:
+email,displayName,password,isActive
+ops@domain.com,Ops,app-password,true
+
+RESPONSE:
+[
+  { "index": 0, "status": "created", "account": { ... } },
+  { "index": 1, "status": "skipped", "message": "Email address already exists" }
+]`}</pre>
+            </article>
+
             <article>
               <h3>POST /api/send</h3>
               <p>Send email using any registered account or alias. Available to user, dev, and admin roles. The <code>from</code> address must match a registered account email or alias email that is active.</p>
@@ -187,36 +402,95 @@ REQUEST:
   "to": "to@domain.com",
   "subject": "string",
   "body": "string",
-  "cc": "optional string (comma-separated)",
-  "bcc": "optional string (comma-separated)",
-  "isHtml": false
+  "cc": "optional string (comma-separated) or list",
+  "bcc": "optional string (comma-separated) or list",
+  "replyTo": "optional single address",
+  "headers": { "X-Campaign-Id": "optional custom headers" },
+  "inReplyTo": "optional &lt;message-id@domain&gt; being replied to",
+  "references": "optional &lt;id@domain&gt; string or list of ids",
+  "listUnsubscribe": false,
+  "honorUnsubscribe": false,
+  "bccSelf": false,
+  "dryRun": false,
+  "useTemplate": true,
+  "sanitizeHtml": true,
+  "webVersion": false,
+  "isHtml": false,
+  "allOrNothing": false,
+  "draftId": "optional; send a saved draft",
+  "attachments": [
+    {
+      "filename": "invoice.pdf",
+      "contentType": "application/pdf",
+      "contentBase64": "JVBERi0xLjQK...",
+      "contentId": "optional; reference as cid:&lt;contentId&gt; in HTML"
+    }
+  ]
 }
 
 RESPONSE:
 {
   "status": "sent",
-  "message": "Email sent successfully"
+  "message": "Email sent successfully",
+  "messageId": "<3f2b6c1e-…@domain.com>"
 }
 
 ERROR RESPONSE:
 {
   "status": "error",
-  "message": "Sender account or alias not found or inactive"
+  "code": "unknown_sender",
+  "message": "Sender account or alias not found or inactive",
+  "field": "from"
 }`}</pre>
               <p><strong>Notes:</strong></p>
               <ul style={{ marginLeft: '20px', marginTop: '8px' }}>
                 <li>The <code>from</code> field accepts either a base account email or an alias email. Aliases will send via their associated account credentials.</li>
+                <li>A bare <code>from</code> goes out with the account&apos;s (or alias&apos;s) display name. Write <code>"Help Desk &lt;sender@domain.com&gt;"</code> to use another name; an invalid address returns 400.</li>
+                <li><code>fromAccountId</code> or <code>fromAliasId</code> can replace <code>from</code>; the sender&apos;s current email is used. A <code>from</code> sent alongside must be the same address (400 <code>sender_mismatch</code> otherwise).</li>
+                <li><code>bccSelf: true</code> Bccs the <code>from</code> address so the sender keeps a copy, unless it is already a recipient.</li>
+                <li><code>dryRun: true</code> validates and builds the message without sending it. The response has status <code>validated</code>, the would-be <code>messageId</code>, the envelope <code>recipients</code> and any <code>warnings</code>; a message that can&apos;t be built returns 400 with code <code>invalid_message</code>. Dry runs don&apos;t use the daily limit.</li>
                 <li>Set <code>isHtml</code> to <code>true</code> to send HTML-formatted emails. When <code>false</code> or omitted, emails are sent as plain text.</li>
-                <li>Multiple recipients in <code>to</code>, <code>cc</code>, or <code>bcc</code> should be comma-separated.</li>
+                <li>HTML bodies are wrapped in the branding template. Set <code>useTemplate</code> to <code>false</code> to send the HTML provided without it (the sender's compliance footer is still appended).</li>
+                <li>HTML sent as written (<code>useTemplate: false</code>, or a full <code>&lt;html&gt;</code> document) is sanitized: scripts, event handlers, forms and <code>javascript:</code> links are removed, while formatting, inline styles and images are kept. Set <code>sanitizeHtml</code> to <code>false</code> to skip this.</li>
+                <li>Multiple recipients in <code>to</code>, <code>cc</code>, or <code>bcc</code> are comma-separated, or given as a list with one address per item. Use the list form (or quote the name) when a display name contains a comma: <code>["Doe, Jane &lt;jane@domain.com&gt;"]</code>. An invalid list item returns 400.</li>
+                <li>To thread a reply, set <code>inReplyTo</code> to the original&apos;s <code>messageId</code> and <code>references</code> to the thread&apos;s ids (a string or a list). Ids must look like <code>&lt;id@domain&gt;</code>.</li>
+                <li><code>listUnsubscribe</code> adds one-click <code>List-Unsubscribe</code> headers for a single-recipient send. <code>honorUnsubscribe</code> drops recipients who have used such a link and lists them in <code>skipped</code>; if none are left the response status is <code>skipped</code>.</li>
+                <li><code>webVersion</code> (HTML only) adds a &quot;View in browser&quot; link to a stored copy of the message at <code>/api/view/:token</code>. The signed link expires after 30 days by default; an expired or altered one returns 404.</li>
+                <li>One send may address at most 100 recipients across <code>to</code>, <code>cc</code> and <code>bcc</code> by default; more returns 400 with the count.</li>
+                <li><code>attachments</code> is optional. Decoded files may total up to 25 MB by default; larger requests get <code>413</code>.</li>
+                <li>An HTML body can show an attachment inline with <code>&lt;img src="cid:logo"&gt;</code>, where <code>logo</code> is the attachment&apos;s <code>contentId</code> (or its filename if no <code>contentId</code> is set). Referenced attachments are embedded inline; the rest stay regular attachments.</li>
                 <li>The sender account or alias must be active for the email to be sent.</li>
+                <li>A failed SMTP send returns <code>502</code> with code <code>smtp_error</code> (or <code>sender_auth_failed</code> when the account&apos;s login can&apos;t be used). A sender the mail server is throttling gets <code>503</code> <code>sender_throttled</code> with <code>Retry-After</code>.</li>
+                <li>Each account may send a limited number of emails per UTC day, aliases included. Past the limit the response is <code>429</code> with <code>Retry-After</code> set to the seconds until midnight UTC.</li>
+                <li>Send an <code>Idempotency-Key</code> header to make retries safe: a repeat of a key whose send went out in the last 24 hours returns the stored response (with <code>Idempotent-Replayed: true</code>) instead of sending again, and a repeat while the first is still running gets 409. Failed sends don&apos;t keep the key.</li>
+                <li><code>draftId</code> sends a saved draft. <code>from</code>, <code>to</code>, <code>subject</code>, <code>body</code>, <code>cc</code> and <code>bcc</code> then default to the draft&apos;s values. The draft is deleted once the mail is delivered.</li>
               </ul>
             </article>
 
+            <article>
+              <h3>POST /api/email/preview</h3>
+              <p>Renders a body through the branding template and inline-image handling used by <code>/api/send</code> and returns the result as <code>text/html</code>, without sending. Plain-text bodies come back unchanged as <code>text/plain</code>. The <code>X-Inline-Images</code> header counts the embedded images.</p>
+              <pre>{`REQUEST:
+{
+  "body": "&lt;p&gt;Hello&lt;/p&gt;",
+  "asHtml": true,
+  "useTemplate": true,
+  "sanitizeHtml": true
+}`}</pre>
+            </article>
+
             <article>
               <h3>GET /api/users</h3>
               <p>Admin-only snapshot of every auth profile.</p>
             </article>
 
+            <article>
+              <h3>GET /api/users/pending · DELETE /api/users/pending/:id</h3>
+              <p>Admin-only. Signups still waiting for verification (expired ones are left out). Deleting one cancels it and invalidates its link; unknown ids return 404.</p>
+              <pre>{`RESPONSE (GET):
+[{ "id": "uuid", "email": "user@domain.com", "expiresAt": 1700001800 }]`}</pre>
+            </article>
+
             <article>
               <h3>POST /api/users</h3>
               <p>Admin-only invite. Defaults to creating a normal user.</p>
@@ -224,7 +498,7 @@ ERROR RESPONSE:
 
           <article>
             <h3>PATCH /api/users/:id</h3>
-            <p>Admin-only mutation for rotating roles or forcing password resets.</p>
+            <p>Admin-only mutation for rotating roles or forcing password resets. Only admins can assign the <code>admin</code> role, and demoting the last remaining admin returns 400 <code>cannot_remove_last_admin</code>.</p>
             <pre>{`HEADERS:
 Authorization: Bearer &lt;admin jwt&gt;
 
@@ -238,7 +512,23 @@ REQUEST:
 
           <article>
             <h3>DELETE /api/users/:id</h3>
-            <p>Admin-only removal. Backend blocks deleting the currently authenticated admin.</p>
+            <p>Admin-only removal. Backend blocks deleting the currently authenticated admin or the last remaining admin.</p>
+          </article>
+
+          <article>
+            <h3>GET /api/senders</h3>
+            <p>The accounts and aliases the caller may send from, in one list sorted by email. Admins see all of them. Dev users see their own, public and granted ones, the same set <code>/api/send</code> accepts. Catch-alls are not listed. Inactive senders are only included with <code>?includeInactive=true</code>.</p>
+            <pre>{`RESPONSE:
+[
+  {
+    "type": "account|alias",
+    "id": "uuid",
+    "email": "alias@domain.com",
+    "displayLabel": "Marketing Bot",
+    "viaDisplay": "Ops Bot (ops@domain.com)",
+    "isActive": true
+  }
+]`}</pre>
           </article>
 
           <article>
@@ -265,13 +555,215 @@ REQUEST:
 }`}</pre>
           </article>
 
+          <article>
+            <h3>POST /api/settings/default-sender/test</h3>
+            <p>Admin-only. Sends a branded test message through the default sender and returns the SMTP result, so a broken sender is caught before signups and password resets depend on it. SMTP failures return <code>502</code> with the server&apos;s error; <code>409</code> means there is no usable default sender.</p>
+            <pre>{`REQUEST:
+{ "to": "you@domain.com" }
+
+RESPONSE:
+{ "status": "sent", "message": "Test email sent from noreply@domain.com to you@domain.com", "from": "noreply@domain.com", "to": "you@domain.com" }`}</pre>
+          </article>
+
+          <article>
+            <h3>GET · POST /api/webhooks · PATCH · DELETE /api/webhooks/:id</h3>
+            <p>Admin-only. URLs notified after <code>POST /api/send</code> reaches the SMTP server: <code>email.sent</code> (status <code>sent</code> or <code>partial</code>) or <code>email.failed</code>. Each POST is signed with <code>X-W9-Signature: sha256=&lt;hex HMAC-SHA256 of the body&gt;</code> using the webhook&apos;s secret, which is generated if omitted and only returned on create. Failed deliveries are retried twice in the background.</p>
+            <pre>{`REQUEST:
+{
+  "url": "https://relay.example.com/w9",
+  "events": ["email.sent", "email.failed"],
+  "secret": "optional"
+}
+
+PAYLOAD:
+{
+  "event": "email.sent",
+  "from": "sender@domain.com",
+  "to": "user@example.com",
+  "subject": "Hello",
+  "status": "sent",
+  "timestamp": 1700000000
+}`}</pre>
+          </article>
+
+          <article>
+            <h3>GET /api/admin/workers</h3>
+            <p>Admin-only heartbeat of each background task. <code>stale</code> is true once a worker has not reported in for <code>staleAfterSecs</code>; a panicked task is restarted and counted in <code>restarts</code>.</p>
+            <pre>{`RESPONSE:
+[{
+  "name": "system_mail",
+  "critical": true,
+  "startedAt": 1700000000,
+  "lastTick": 1700000030,
+  "lastError": null,
+  "lastErrorAt": null,
+  "itemsProcessed": 12,
+  "restarts": 0,
+  "staleAfterSecs": 300,
+  "stale": false
+}]`}</pre>
+          </article>
+
+          <article>
+            <h3>GET /api/audit</h3>
+            <p>Admin-only log of account, alias, user, default-sender and webhook changes, newest first. Accepts <code>limit</code> and <code>offset</code>.</p>
+            <pre>{`RESPONSE:
+{
+  "items": [{
+    "id": "uuid",
+    "actorUserId": "uuid",
+    "actorEmail": "admin@domain.com",
+    "action": "account.create",
+    "targetType": "account",
+    "targetId": "uuid",
+    "timestamp": 1700000000,
+    "detail": { "email": "ops@domain.com" }
+  }],
+  "total": 1,
+  "limit": 100,
+  "offset": 0
+}`}</pre>
+          </article>
+
+            <article>
+              <h3>GET /api/sent</h3>
+              <p>Messages the caller sent successfully, newest first. Failed sends are not recorded. <code>recipient</code> matches an address in to, cc or bcc regardless of case or display name. <code>GET /api/sent/:id</code> returns one message with a <code>recipients</code> object grouping the addresses into <code>to</code>, <code>cc</code> and <code>bcc</code>; another user&apos;s message returns 404.</p>
+              <pre>{`QUERY:
+limit=50            (default 50, max 200)
+recipient=customer@example.com   (optional)
+
+RESPONSE:
+[
+  {
+    "id": "uuid",
+    "from": "sender@domain.com",
+    "to": "user@example.com",
+    "cc": null,
+    "bcc": null,
+    "subject": "Hello",
+    "body": "<html>…</html>",
+    "sentAt": "2024-05-02T09:14:00+00:00",
+    "messageId": "<3f2b6c1e-…@domain.com>"
+  }
+]`}</pre>
+            </article>
+
+            <article>
+              <h3>GET · POST /api/drafts · GET · PATCH · DELETE /api/drafts/:id</h3>
+              <p>Dev/Admin. Drafts saved from the compose form, visible only to the user who saved them. Every field is optional. <code>PATCH</code> changes only the fields sent, and an empty <code>cc</code> or <code>bcc</code> clears it. The list is paged (<code>limit</code>, <code>offset</code>) and newest first. Another user&apos;s draft returns 404. Send a draft with <code>draftId</code> on <code>POST /api/send</code>.</p>
+              <pre>{`REQUEST (POST / PATCH):
+{
+  "from": "sender@domain.com",
+  "to": "user@example.com",
+  "cc": "",
+  "bcc": null,
+  "subject": "Quarterly report",
+  "body": "Hi team, ..."
+}
+
+RESPONSE:
+{
+  "id": "uuid",
+  "from": "sender@domain.com",
+  "to": "user@example.com",
+  "cc": null,
+  "bcc": null,
+  "subject": "Quarterly report",
+  "body": "Hi team, ...",
+  "updatedAt": 1714641240
+}`}</pre>
+            </article>
+
             <article>
               <h3>GET /api/inbox</h3>
-              <p>IMAP bridge (placeholder for future work).</p>
+              <p>Dev/Admin. Reads the newest messages from the account's INBOX over IMAP. Non-admins need to own the account or have been granted it (403 otherwise). Unknown or inactive accounts return 404; IMAP failures return 502.</p>
+              <p>Every inbox endpoint takes an optional <code>folder</code> (default <code>INBOX</code>), named exactly as <code>GET /api/inbox/folders</code> lists it. UIDs are only unique within a folder, so pass the same folder to the message routes. A folder the server doesn&apos;t have returns 404.</p>
               <pre>{`QUERY:
 account=sender@domain.com
-limit=50
-              `}</pre>
+folder=Archive      (optional, default INBOX)
+limit=50            (default 20, max 100)
+
+RESPONSE:
+[
+  {
+    "uid": 4211,
+    "from": "Jane Doe <jane@example.com>",
+    "subject": "Quarterly report",
+    "date": "2024-05-02T09:14:00Z",
+    "preview": "Hi team, attached is..."
+  }
+]`}</pre>
+            </article>
+
+            <article>
+              <h3>GET /api/inbox/folders</h3>
+              <p>Dev/Admin. The account&apos;s folders from IMAP <code>LIST</code>. Names are returned as the server reports them, with its hierarchy delimiter (<code>/</code> or <code>.</code>), and can be passed straight back as <code>folder</code>. Folders flagged <code>\Noselect</code> can&apos;t be opened. Same access rules as <code>GET /api/inbox</code>.</p>
+              <pre>{`QUERY:
+account=sender@domain.com
+
+RESPONSE:
+[
+  { "name": "INBOX", "delimiter": "/", "flags": [] },
+  { "name": "Archive/2024", "delimiter": "/", "flags": ["\\HasNoChildren"] }
+]`}</pre>
+            </article>
+
+            <article>
+              <h3>GET /api/inbox/search</h3>
+              <p>Dev/Admin. Runs an IMAP SEARCH on the account&apos;s INBOX and returns the newest matches in the same shape as <code>GET /api/inbox</code>. An empty query, unknown field or bad date returns 400.</p>
+              <pre>{`QUERY:
+account=sender@domain.com
+q=invoice
+field=subject       (text (default), subject, from, to, body)
+since=2024-01-01    (optional, inclusive)
+before=2024-02-01   (optional, exclusive)
+limit=50            (default 20, max 100)`}</pre>
+            </article>
+
+            <article>
+              <h3>GET /api/inbox/:uid · GET /api/inbox/:uid/attachments/:index</h3>
+              <p>Dev/Admin. The full message for one UID, without marking it read. <code>htmlBody</code> is the sender&apos;s HTML as received, so sanitize it before rendering. Attachment bytes are downloaded from the second route using the <code>index</code> listed here. Unknown UIDs or indexes return 404. Same access rules as <code>GET /api/inbox</code>.</p>
+              <pre>{`QUERY:
+account=sender@domain.com
+
+RESPONSE:
+{
+  "uid": 4211,
+  "from": "Jane Doe <jane@example.com>",
+  "to": ["sender@domain.com"],
+  "cc": [],
+  "subject": "Quarterly report",
+  "date": "2024-05-02T09:14:00Z",
+  "messageId": "<abc123@example.com>",
+  "textBody": "Hi team, attached is...",
+  "htmlBody": "<p>Hi team, attached is...</p>",
+  "attachments": [
+    { "index": 0, "filename": "report.pdf", "contentType": "application/pdf", "size": 48213 }
+  ]
+}`}</pre>
+            </article>
+
+            <article>
+              <h3>GET /api/inbox/unread-count</h3>
+              <p>Dev/Admin. Number of unread INBOX messages (IMAP <code>SEARCH UNSEEN</code>), for a badge. Counts are cached per account and folder for 30 seconds by default and refreshed after <code>PATCH /api/inbox/:uid</code>. Same access rules as <code>GET /api/inbox</code>; IMAP failures return 502.</p>
+              <pre>{`QUERY:
+account=sender@domain.com
+
+RESPONSE:
+{ "unread": 3 }`}</pre>
+            </article>
+
+            <article>
+              <h3>PATCH /api/inbox/:uid</h3>
+              <p>Dev/Admin. Marks one INBOX message read or unread by setting or clearing its <code>\Seen</code> flag. Same access rules as <code>GET /api/inbox</code>; an unknown UID returns 404.</p>
+              <pre>{`QUERY:
+account=sender@domain.com
+
+REQUEST:
+{ "seen": true }
+
+RESPONSE:
+{ "uid": 4211, "seen": true }`}</pre>
             </article>
           </section>
       </>
@@ -11,6 +11,8 @@ export default function LoginPage() {
   const router = useRouter()
   const { session, saveSession, updateSession, logout } = useSession()
   const [form, setForm] = useState({ email: '', password: '' })
+  const [totpRequired, setTotpRequired] = useState(false)
+  const [totpCode, setTotpCode] = useState('')
   const [message, setMessage] = useState<{ type: 'success' | 'error'; text: string } | null>(null)
   const [loading, setLoading] = useState(false)
   const [changingPassword, setChangingPassword] = useState(false)
@@ -49,15 +51,31 @@ export default function LoginPage() {
       const response = await fetch(`${apiUrl}/auth/login`, {
         method: 'POST',
         headers: { 'Content-Type': 'application/json' },
-        body: JSON.stringify({ ...form, turnstile_token: turnstileToken })
+        body: JSON.stringify({
+          ...form,
+          turnstile_token: turnstileToken,
+          totpCode: totpRequired ? totpCode : undefined
+        })
       })
       if (!response.ok) {
+        const error = await response.json().catch(() => null)
+        if (error?.totpRequired) {
+          setTotpRequired(true)
+          setTotpCode('')
+          setMessage({ type: 'error', text: error.message })
+          return
+        }
+        if (response.status === 429) {
+          setMessage({ type: 'error', text: error?.message || 'Too many attempts. Try again later.' })
+          return
+        }
         setMessage({ type: 'error', text: 'Invalid credentials' })
         return
       }
       const data = await response.json()
       saveSession({
         token: data.token,
+        refreshToken: data.refreshToken,
         id: data.id,
         email: data.email,
         role: data.role,
@@ -179,6 +197,20 @@ export default function LoginPage() {
                 required
               />
             </div>
+            {totpRequired && (
+              <div className="row">
+                <label>Authenticator code</label>
+                <input
+                  type="text"
+                  inputMode="numeric"
+                  autoComplete="one-time-code"
+                  value={totpCode}
+                  onChange={(e) => setTotpCode(e.target.value)}
+                  placeholder="123456"
+                  required
+                />
+              </div>
+            )}
             <Turnstile 
               onVerify={(token) => setTurnstileToken(token)}
               onError={() => setTurnstileToken(null)}
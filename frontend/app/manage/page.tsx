@@ -25,6 +25,7 @@ interface EmailAlias {
   accountIsActive: boolean
   ownerId?: string | null
   isPublic?: boolean
+  isCatchall?: boolean
 }
 
 interface DefaultSender {
@@ -67,7 +68,8 @@ export default function ManagePage() {
     aliasEmail: '',
     displayName: '',
     isActive: true,
-    isPublic: false
+    isPublic: false,
+    isCatchall: false
   })
   const [defaultSelection, setDefaultSelection] = useState('')
   const [savingDefault, setSavingDefault] = useState(false)
@@ -100,18 +102,32 @@ export default function ManagePage() {
     // eslint-disable-next-line react-hooks/exhaustive-deps
   }, [session?.token])
 
+  // List endpoints return { items, total, limit, offset }; walk the pages so the tables stay complete
+  const fetchAllPages = async <T,>(url: string): Promise<T[] | null> => {
+    if (!session?.token) return null
+    const pageSize = 200
+    const items: T[] = []
+    for (let offset = 0; ; offset += pageSize) {
+      const response = await fetch(`${url}?limit=${pageSize}&offset=${offset}`, {
+        headers: { Authorization: `Bearer ${session.token}` }
+      })
+      if (!response.ok) {
+        if (response.status === 401) logout()
+        return null
+      }
+      const page: { items: T[]; total: number } = await response.json()
+      items.push(...page.items)
+      if (page.items.length === 0 || items.length >= page.total) return items
+    }
+  }
+
   const fetchAccounts = async () => {
     if (!session?.token) return
     try {
       const apiUrl = process.env.NEXT_PUBLIC_API_URL || '/api'
-      const response = await fetch(`${apiUrl}/accounts`, {
-        headers: { Authorization: `Bearer ${session.token}` }
-      })
-      if (response.ok) {
-        const data = await response.json()
-        setAccounts(data)
-      } else if (response.status === 401) {
-        logout()
+      const items = await fetchAllPages<EmailAccount>(`${apiUrl}/accounts`)
+      if (items) {
+        setAccounts(items)
       }
     } catch (error) {
       console.error('Failed to fetch accounts:', error)
@@ -123,14 +139,9 @@ export default function ManagePage() {
     setLoadingAliases(true)
     try {
       const apiUrl = process.env.NEXT_PUBLIC_API_URL || '/api'
-      const response = await fetch(`${apiUrl}/aliases`, {
-        headers: { Authorization: `Bearer ${session.token}` }
-      })
-      if (response.ok) {
-        const data = await response.json()
-        setAliases(data)
-      } else if (response.status === 401) {
-        logout()
+      const items = await fetchAllPages<EmailAlias>(`${apiUrl}/aliases`)
+      if (items) {
+        setAliases(items)
       }
     } catch (error) {
       console.error('Failed to fetch aliases:', error)
@@ -232,7 +243,8 @@ export default function ManagePage() {
         aliasEmail: aliasForm.aliasEmail.trim(),
         displayName: aliasForm.displayName.trim() ? aliasForm.displayName.trim() : undefined,
         isActive: aliasForm.isActive,
-        isPublic: aliasForm.isPublic
+        isPublic: aliasForm.isPublic,
+        isCatchall: aliasForm.isCatchall
       }
       const response = await fetch(`${apiUrl}/aliases`, {
         method: 'POST',
@@ -494,6 +506,49 @@ export default function ManagePage() {
     }
   }
 
+  const handleTestAccount = async (id: string) => {
+    if (!session?.token) return
+    setMessage(null)
+    try {
+      const apiUrl = process.env.NEXT_PUBLIC_API_URL || '/api'
+      const response = await fetch(`${apiUrl}/accounts/${id}/test`, {
+        method: 'POST',
+        headers: { Authorization: `Bearer ${session.token}` }
+      })
+      const data = await response.json().catch(() => ({}))
+      if (response.ok && data.ok) {
+        setMessage({ type: 'success', text: 'SMTP sign-in succeeded' })
+      } else if (data.error) {
+        setMessage({ type: 'error', text: `SMTP ${data.error.kind} error: ${data.error.message}` })
+      } else {
+        setMessage({ type: 'error', text: 'Failed to test account' })
+      }
+    } catch (error) {
+      console.error('Failed to test account:', error)
+      setMessage({ type: 'error', text: 'Network error. Please try again.' })
+    }
+  }
+
+  const handleConnectMicrosoft = async (id: string) => {
+    if (!session?.token) return
+    try {
+      const apiUrl = process.env.NEXT_PUBLIC_API_URL || '/api'
+      const response = await fetch(`${apiUrl}/accounts/${id}/oauth`, {
+        method: 'POST',
+        headers: { Authorization: `Bearer ${session.token}` }
+      })
+      const data = await response.json().catch(() => ({}))
+      if (response.ok && data.authorizeUrl) {
+        window.location.href = data.authorizeUrl
+      } else {
+        setMessage({ type: 'error', text: data.message || 'Failed to start Microsoft sign-in' })
+      }
+    } catch (error) {
+      console.error('Failed to start Microsoft sign-in:', error)
+      setMessage({ type: 'error', text: 'Network error. Please try again.' })
+    }
+  }
+
   const handleUserCreate = async (e: React.FormEvent) => {
     e.preventDefault()
     if (!session?.token) return
@@ -897,10 +952,10 @@ export default function ManagePage() {
           <div className="row">
             <label>Alias email</label>
             <input
-              type="email"
+              type={aliasForm.isCatchall ? 'text' : 'email'}
               value={aliasForm.aliasEmail}
               onChange={(e) => setAliasForm({ ...aliasForm, aliasEmail: e.target.value })}
-              placeholder="alias@domain.com"
+              placeholder={aliasForm.isCatchall ? '*@team.example.com' : 'alias@domain.com'}
               required
             />
           </div>
@@ -929,6 +984,14 @@ export default function ManagePage() {
             />{' '}
             public (visible to other users)
           </label>
+          <label>
+            <input
+              type="checkbox"
+              checked={aliasForm.isCatchall}
+              onChange={(e) => setAliasForm({ ...aliasForm, isCatchall: e.target.checked })}
+            />{' '}
+            catch-all (send as any address at this domain)
+          </label>
           <button className="button" type="submit" disabled={!accounts.length}>
             Add alias
           </button>
@@ -1016,6 +1079,9 @@ export default function ManagePage() {
                       <td>
                         {alias.isActive ? 'Active' : 'Inactive'}
                         {!alias.accountIsActive && <span className="status error">Credential inactive</span>}
+                        {alias.isCatchall && (
+                          <span style={{ marginLeft: '8px', fontSize: '12px', color: '#74c0fc' }}>Catch-all</span>
+                        )}
                         {alias.isPublic !== undefined && (
                           <span style={{ marginLeft: '8px', fontSize: '12px', color: alias.isPublic ? '#51cf66' : '#999' }}>
                             {alias.isPublic ? 'Public' : 'Private'}
@@ -1149,6 +1215,10 @@ export default function ManagePage() {
                               </button>
                             )
                           )}
+                          <button onClick={() => handleTestAccount(account.id)}>Test sign-in</button>
+                          {isAdmin && (
+                            <button onClick={() => handleConnectMicrosoft(account.id)}>Connect Microsoft</button>
+                          )}
                           <button onClick={() => handleDeleteAccount(account.id)}>Delete</button>
                         </div>
                       ) : (
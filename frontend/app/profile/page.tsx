@@ -11,18 +11,29 @@ interface ApiToken {
   name?: string | null
   createdAt: string
   lastUsedAt?: string | null
+  expiresAt?: string | null
+  scopes?: string[] | null
 }
 
+const TOKEN_SCOPES = ['send', 'sent:read', 'inbox:read', 'accounts:read', 'accounts:write', 'admin']
+
 export default function ProfilePage() {
   const { session, logout } = useSession()
   const [message, setMessage] = useState<{ type: 'success' | 'error'; text: string } | null>(null)
   const [changingPassword, setChangingPassword] = useState(false)
   const [passwordForm, setPasswordForm] = useState({ old: '', new: '', confirm: '' })
   const [changing, setChanging] = useState(false)
+  const [emailForm, setEmailForm] = useState({ newEmail: '', password: '' })
+  const [requestingEmail, setRequestingEmail] = useState(false)
   const [apiTokens, setApiTokens] = useState<ApiToken[]>([])
   const [loadingTokens, setLoadingTokens] = useState(false)
   const [creatingToken, setCreatingToken] = useState(false)
   const [newTokenName, setNewTokenName] = useState('')
+  const [newTokenExpiry, setNewTokenExpiry] = useState('')
+  const [newTokenScopes, setNewTokenScopes] = useState<string[]>([])
+  const [twoFactorEnabled, setTwoFactorEnabled] = useState(false)
+  const [enrollment, setEnrollment] = useState<{ secret: string; otpauthUrl: string } | null>(null)
+  const [twoFactorCode, setTwoFactorCode] = useState('')
   const [newlyCreatedToken, setNewlyCreatedToken] = useState<{ id: string; token: string; name?: string | null } | null>(null)
 
   const fetchApiTokens = async () => {
@@ -30,7 +41,7 @@ export default function ProfilePage() {
     setLoadingTokens(true)
     try {
       const apiUrl = process.env.NEXT_PUBLIC_API_URL || '/api'
-      const response = await fetch(`${apiUrl}/api-tokens`, {
+      const response = await fetch(`${apiUrl}/tokens`, {
         headers: { Authorization: `Bearer ${session.token}` }
       })
       if (response.ok) {
@@ -53,18 +64,24 @@ export default function ProfilePage() {
     setMessage(null)
     try {
       const apiUrl = process.env.NEXT_PUBLIC_API_URL || '/api'
-      const response = await fetch(`${apiUrl}/api-tokens`, {
+      const response = await fetch(`${apiUrl}/tokens`, {
         method: 'POST',
         headers: {
           'Content-Type': 'application/json',
           Authorization: `Bearer ${session.token}`
         },
-        body: JSON.stringify({ name: newTokenName || null })
+        body: JSON.stringify({
+          name: newTokenName || null,
+          expiresInDays: newTokenExpiry ? Number(newTokenExpiry) : null,
+          scopes: newTokenScopes.length > 0 ? newTokenScopes : null
+        })
       })
       const data = await response.json()
       if (response.ok) {
         setNewlyCreatedToken({ id: data.id, token: data.token, name: data.name })
         setNewTokenName('')
+        setNewTokenExpiry('')
+        setNewTokenScopes([])
         setMessage({ type: 'success', text: data.message || 'API token created successfully' })
         fetchApiTokens()
       } else {
@@ -85,7 +102,7 @@ export default function ProfilePage() {
     }
     try {
       const apiUrl = process.env.NEXT_PUBLIC_API_URL || '/api'
-      const response = await fetch(`${apiUrl}/api-tokens/${tokenId}`, {
+      const response = await fetch(`${apiUrl}/tokens/${tokenId}`, {
         method: 'DELETE',
         headers: { Authorization: `Bearer ${session.token}` }
       })
@@ -102,9 +119,101 @@ export default function ProfilePage() {
     }
   }
 
+  const handleRotateToken = async (tokenId: string) => {
+    if (!session?.token) return
+    if (!confirm('Rotate this API token? The current token stops working immediately.')) {
+      return
+    }
+    try {
+      const apiUrl = process.env.NEXT_PUBLIC_API_URL || '/api'
+      const response = await fetch(`${apiUrl}/tokens/${tokenId}/rotate`, {
+        method: 'POST',
+        headers: { Authorization: `Bearer ${session.token}` }
+      })
+      const data = await response.json().catch(() => ({}))
+      if (response.ok) {
+        setNewlyCreatedToken({ id: data.id, token: data.token, name: data.name })
+        setMessage({ type: 'success', text: data.message || 'API token rotated successfully' })
+        fetchApiTokens()
+      } else {
+        setMessage({ type: 'error', text: data.message || data.error || 'Failed to rotate API token' })
+      }
+    } catch (error) {
+      console.error('Failed to rotate API token:', error)
+      setMessage({ type: 'error', text: 'Network error. Please try again.' })
+    }
+  }
+
+  const fetchTwoFactorStatus = async () => {
+    if (!session?.token) return
+    try {
+      const apiUrl = process.env.NEXT_PUBLIC_API_URL || '/api'
+      const response = await fetch(`${apiUrl}/auth/2fa`, {
+        headers: { Authorization: `Bearer ${session.token}` }
+      })
+      if (response.ok) {
+        const data = await response.json()
+        setTwoFactorEnabled(data.enabled)
+      }
+    } catch (error) {
+      console.error('Failed to load two-factor status:', error)
+    }
+  }
+
+  const handleEnrollTwoFactor = async () => {
+    if (!session?.token) return
+    setMessage(null)
+    try {
+      const apiUrl = process.env.NEXT_PUBLIC_API_URL || '/api'
+      const response = await fetch(`${apiUrl}/auth/2fa/enroll`, {
+        method: 'POST',
+        headers: { Authorization: `Bearer ${session.token}` }
+      })
+      if (response.ok) {
+        setEnrollment(await response.json())
+        setTwoFactorCode('')
+      } else {
+        setMessage({ type: 'error', text: 'Failed to start two-factor enrollment' })
+      }
+    } catch (error) {
+      console.error('Failed to enroll two-factor:', error)
+      setMessage({ type: 'error', text: 'Network error. Please try again.' })
+    }
+  }
+
+  const handleTwoFactorCode = async (e: React.FormEvent) => {
+    e.preventDefault()
+    if (!session?.token) return
+    setMessage(null)
+    try {
+      const apiUrl = process.env.NEXT_PUBLIC_API_URL || '/api'
+      const response = await fetch(twoFactorEnabled ? `${apiUrl}/auth/2fa` : `${apiUrl}/auth/2fa/verify`, {
+        method: twoFactorEnabled ? 'DELETE' : 'POST',
+        headers: {
+          'Content-Type': 'application/json',
+          Authorization: `Bearer ${session.token}`
+        },
+        body: JSON.stringify({ code: twoFactorCode })
+      })
+      const data = await response.json().catch(() => ({}))
+      if (response.ok) {
+        setMessage({ type: 'success', text: data.message })
+        setEnrollment(null)
+        setTwoFactorCode('')
+        fetchTwoFactorStatus()
+      } else {
+        setMessage({ type: 'error', text: 'Invalid code. Check your authenticator app and try again.' })
+      }
+    } catch (error) {
+      console.error('Failed to submit two-factor code:', error)
+      setMessage({ type: 'error', text: 'Network error. Please try again.' })
+    }
+  }
+
   React.useEffect(() => {
     if (session) {
       fetchApiTokens()
+      fetchTwoFactorStatus()
     }
   }, [session])
 
@@ -152,6 +261,40 @@ export default function ProfilePage() {
     }
   }
 
+  const handleChangeEmail = async (e: React.FormEvent) => {
+    e.preventDefault()
+    if (!session?.token) return
+
+    setRequestingEmail(true)
+    setMessage(null)
+    try {
+      const apiUrl = process.env.NEXT_PUBLIC_API_URL || '/api'
+      const response = await fetch(`${apiUrl}/auth/change-email`, {
+        method: 'POST',
+        headers: {
+          'Content-Type': 'application/json',
+          Authorization: `Bearer ${session.token}`
+        },
+        body: JSON.stringify({
+          newEmail: emailForm.newEmail,
+          currentPassword: emailForm.password
+        })
+      })
+      const data = await response.json().catch(() => ({ message: 'Failed to request email change' }))
+      if (response.ok && data.status === 'pending') {
+        setMessage({ type: 'success', text: data.message || 'Check your new inbox for a confirmation link' })
+        setEmailForm({ newEmail: '', password: '' })
+      } else {
+        setMessage({ type: 'error', text: data.message || data.error || 'Failed to request email change' })
+      }
+    } catch (error) {
+      console.error('Failed to request email change:', error)
+      setMessage({ type: 'error', text: 'Network error. Please try again.' })
+    } finally {
+      setRequestingEmail(false)
+    }
+  }
+
   return (
     <main className="app">
       <header className="header">
@@ -248,6 +391,75 @@ export default function ProfilePage() {
             </p>
           </section>
 
+          <section className="box">
+            <h2 className="section-title">Change Email</h2>
+            <p>We&apos;ll send a confirmation link to the new address. Keep signing in with {session.email} until you follow it.</p>
+            <form className="form" onSubmit={handleChangeEmail}>
+              <div className="row">
+                <label>New Email</label>
+                <input
+                  type="email"
+                  value={emailForm.newEmail}
+                  onChange={(e) => setEmailForm({ ...emailForm, newEmail: e.target.value })}
+                  required
+                />
+              </div>
+              <div className="row">
+                <label>Current Password</label>
+                <input
+                  type="password"
+                  value={emailForm.password}
+                  onChange={(e) => setEmailForm({ ...emailForm, password: e.target.value })}
+                  required
+                />
+              </div>
+              <button className="button" type="submit" disabled={requestingEmail}>
+                {requestingEmail ? 'Sending…' : 'Send confirmation link'}
+              </button>
+            </form>
+          </section>
+
+          <section className="box">
+            <h2 className="section-title">Two-factor authentication</h2>
+            <p>
+              {twoFactorEnabled
+                ? 'Sign-in requires a code from your authenticator app.'
+                : 'Require a code from an authenticator app (TOTP) when signing in.'}
+            </p>
+            {enrollment && (
+              <div style={{ marginBottom: '1rem' }}>
+                <p>Add this key to your authenticator app, then enter the code it shows.</p>
+                <code style={{ display: 'block', wordBreak: 'break-all' }}>{enrollment.secret}</code>
+                <p className="hint">
+                  Or open <a href={enrollment.otpauthUrl}>this link</a> on a device with an authenticator app.
+                </p>
+              </div>
+            )}
+            {twoFactorEnabled || enrollment ? (
+              <form className="form" onSubmit={handleTwoFactorCode}>
+                <div className="row">
+                  <label>Authenticator code</label>
+                  <input
+                    type="text"
+                    inputMode="numeric"
+                    autoComplete="one-time-code"
+                    value={twoFactorCode}
+                    onChange={(e) => setTwoFactorCode(e.target.value)}
+                    placeholder="123456"
+                    required
+                  />
+                </div>
+                <button className="button" type="submit">
+                  {twoFactorEnabled ? 'Disable two-factor' : 'Confirm and enable'}
+                </button>
+              </form>
+            ) : (
+              <button className="button" onClick={handleEnrollTwoFactor}>
+                Set up two-factor
+              </button>
+            )}
+          </section>
+
           <section className="box">
             <h2 className="section-title">API Tokens</h2>
             <p>Create API tokens to authenticate API requests. Tokens are only shown once when created.</p>
@@ -291,6 +503,34 @@ export default function ProfilePage() {
                   placeholder="e.g., Production API, Development"
                 />
               </div>
+              <div className="row">
+                <label>Expires</label>
+                <select value={newTokenExpiry} onChange={(e) => setNewTokenExpiry(e.target.value)}>
+                  <option value="">Never</option>
+                  <option value="30">In 30 days</option>
+                  <option value="90">In 90 days</option>
+                  <option value="365">In 1 year</option>
+                </select>
+              </div>
+              <div className="row">
+                <label>Scopes (none selected = full access)</label>
+                <div style={{ display: 'flex', flexWrap: 'wrap', gap: '0.75rem' }}>
+                  {TOKEN_SCOPES.map((scope) => (
+                    <label key={scope} style={{ display: 'flex', alignItems: 'center', gap: '0.25rem' }}>
+                      <input
+                        type="checkbox"
+                        checked={newTokenScopes.includes(scope)}
+                        onChange={(e) =>
+                          setNewTokenScopes((prev) =>
+                            e.target.checked ? [...prev, scope] : prev.filter((s) => s !== scope)
+                          )
+                        }
+                      />
+                      {scope}
+                    </label>
+                  ))}
+                </div>
+              </div>
               <button className="button" type="submit" disabled={creatingToken}>
                 {creatingToken ? 'Creating…' : 'Create API Token'}
               </button>
@@ -308,6 +548,8 @@ export default function ProfilePage() {
                     <th style={{ textAlign: 'left', padding: '0.5rem' }}>Name</th>
                     <th style={{ textAlign: 'left', padding: '0.5rem' }}>Created</th>
                     <th style={{ textAlign: 'left', padding: '0.5rem' }}>Last Used</th>
+                    <th style={{ textAlign: 'left', padding: '0.5rem' }}>Expires</th>
+                    <th style={{ textAlign: 'left', padding: '0.5rem' }}>Scopes</th>
                     <th style={{ textAlign: 'right', padding: '0.5rem' }}>Actions</th>
                   </tr>
                 </thead>
@@ -319,7 +561,22 @@ export default function ProfilePage() {
                       <td style={{ padding: '0.5rem' }}>
                         {token.lastUsedAt ? new Date(token.lastUsedAt).toLocaleString() : 'Never'}
                       </td>
+                      <td style={{ padding: '0.5rem' }}>
+                        {!token.expiresAt
+                          ? 'Never'
+                          : new Date(token.expiresAt) <= new Date()
+                            ? 'Expired'
+                            : new Date(token.expiresAt).toLocaleString()}
+                      </td>
+                      <td style={{ padding: '0.5rem' }}>{token.scopes ? token.scopes.join(', ') : 'Full access'}</td>
                       <td style={{ padding: '0.5rem', textAlign: 'right' }}>
+                        <button
+                          className="button subtle"
+                          onClick={() => handleRotateToken(token.id)}
+                          style={{ marginRight: '0.5rem' }}
+                        >
+                          Rotate
+                        </button>
                         <button
                           className="button subtle"
                           onClick={() => handleDeleteToken(token.id)}
@@ -10,6 +10,8 @@ export default function SignupPage() {
   const [message, setMessage] = useState<{ type: 'success' | 'error'; text: string } | null>(null)
   const [loading, setLoading] = useState(false)
   const [turnstileToken, setTurnstileToken] = useState<string | null>(null)
+  const [pendingEmail, setPendingEmail] = useState<string | null>(null)
+  const [resending, setResending] = useState(false)
 
   const handleSubmit = async (e: React.FormEvent) => {
     e.preventDefault()
@@ -30,6 +32,7 @@ export default function SignupPage() {
       const data = await response.json().catch(() => ({ message: 'Failed to register' }))
       if (response.ok && data.status === 'pending') {
         setMessage({ type: 'success', text: data.message || 'Verification email sent.' })
+        setPendingEmail(form.email)
         setForm({ email: '', password: '' })
       } else {
         setMessage({ type: 'error', text: data.message || 'Signup failed' })
@@ -42,6 +45,30 @@ export default function SignupPage() {
     }
   }
 
+  const handleResend = async () => {
+    if (!pendingEmail) return
+    setResending(true)
+    try {
+      const apiUrl = process.env.NEXT_PUBLIC_API_URL || '/api'
+      const response = await fetch(`${apiUrl}/auth/signup/resend`, {
+        method: 'POST',
+        headers: { 'Content-Type': 'application/json' },
+        body: JSON.stringify({ email: pendingEmail })
+      })
+      const data = await response.json().catch(() => ({ message: 'Failed to resend' }))
+      if (response.ok && data.status === 'pending') {
+        setMessage({ type: 'success', text: data.message || 'Verification email sent.' })
+      } else {
+        setMessage({ type: 'error', text: data.message || 'Failed to resend' })
+      }
+    } catch (error) {
+      console.error('Failed to resend verification:', error)
+      setMessage({ type: 'error', text: 'Network error. Please try again.' })
+    } finally {
+      setResending(false)
+    }
+  }
+
   return (
     <main className="app">
       <header className="header">
@@ -84,6 +111,14 @@ export default function SignupPage() {
             {loading ? 'Submitting…' : 'Create account'}
           </button>
         </form>
+        {pendingEmail && (
+          <p className="hint">
+            No email for {pendingEmail}?{' '}
+            <button className="button subtle" type="button" onClick={handleResend} disabled={resending}>
+              {resending ? 'Sending…' : 'Resend verification'}
+            </button>
+          </p>
+        )}
         <p className="hint">
           Already registered? <Link href="/login">Return to login</Link>.
         </p>
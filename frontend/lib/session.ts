@@ -4,6 +4,7 @@ export type SessionRole = 'admin' | 'dev' | 'user'
 
 export interface SessionPayload {
   token: string
+  refreshToken?: string
   id: string
   email: string
   role: SessionRole
@@ -35,6 +36,68 @@ function persistSession(session: SessionPayload | null) {
   window.dispatchEvent(new Event(SESSION_EVENT))
 }
 
+// Refresh the JWT this long before it expires
+const REFRESH_MARGIN_MS = 5 * 60 * 1000
+// setTimeout overflows past ~24.8 days
+const MAX_TIMER_MS = 2 ** 31 - 1
+
+let refreshTimer: ReturnType<typeof setTimeout> | null = null
+let scheduledFor: string | null = null
+
+function apiUrl() {
+  return process.env.NEXT_PUBLIC_API_URL || '/api'
+}
+
+function tokenExpiry(token: string): number | null {
+  try {
+    const payload = JSON.parse(atob(token.split('.')[1].replace(/-/g, '+').replace(/_/g, '/')))
+    return typeof payload.exp === 'number' ? payload.exp * 1000 : null
+  } catch {
+    return null
+  }
+}
+
+async function refreshSession(current: SessionPayload) {
+  try {
+    const response = await fetch(`${apiUrl()}/auth/refresh`, {
+      method: 'POST',
+      headers: { 'Content-Type': 'application/json' },
+      body: JSON.stringify({ refreshToken: current.refreshToken })
+    })
+    // Another tab may have rotated the token first; it will have stored the new session
+    if (!response.ok) return
+    const data = await response.json()
+    const latest = loadSession()
+    if (!latest || latest.token !== current.token) return
+    persistSession({
+      ...latest,
+      token: data.token,
+      refreshToken: data.refreshToken,
+      role: data.role,
+      mustChangePassword: data.mustChangePassword
+    })
+  } catch (error) {
+    console.error('Failed to refresh session:', error)
+  }
+}
+
+// One timer per tab, however many components use the hook
+function scheduleRefresh(session: SessionPayload | null) {
+  if (!isBrowser() || session?.token === scheduledFor) return
+  if (refreshTimer) clearTimeout(refreshTimer)
+  refreshTimer = null
+  scheduledFor = null
+  if (!session?.refreshToken) return
+  const expiry = tokenExpiry(session.token)
+  if (!expiry) return
+  scheduledFor = session.token
+  const delay = Math.min(Math.max(expiry - Date.now() - REFRESH_MARGIN_MS, 0), MAX_TIMER_MS)
+  refreshTimer = setTimeout(() => {
+    refreshTimer = null
+    refreshSession(session)
+  }, delay)
+}
+
 export function useSession() {
   const [session, setSession] = useState<SessionPayload | null>(() => loadSession())
 
@@ -54,12 +117,25 @@ export function useSession() {
     }
   }, [])
 
+  useEffect(() => {
+    scheduleRefresh(session)
+  }, [session])
+
   const save = useCallback((next: SessionPayload | null) => {
     persistSession(next)
     setSession(next)
   }, [])
 
   const logout = useCallback(() => {
+    const refreshToken = loadSession()?.refreshToken
+    if (refreshToken) {
+      fetch(`${apiUrl()}/auth/logout`, {
+        method: 'POST',
+        headers: { 'Content-Type': 'application/json' },
+        body: JSON.stringify({ refreshToken }),
+        keepalive: true
+      }).catch(() => {})
+    }
     persistSession(null)
     setSession(null)
   }, [])